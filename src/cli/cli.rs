@@ -3,8 +3,12 @@ mod comms;
 use clap::{error::ErrorKind, CommandFactory, Parser, Subcommand, ValueEnum};
 
 #[derive(Parser)]
-#[command(version="0.5.0", about="razer laptop configuration for linux", name="razer-cli")]
+#[command(version="0.5.0", about="razer laptop configuration for linux", name="razer-cli", arg_required_else_help = true)]
 struct Cli {
+    /// send the command to this device, by its index in list-devices or its
+    /// serial number, instead of the selected one
+    #[arg(long, global = true)]
+    device: Option<String>,
     #[command(subcommand)]
     args: Args,
 }
@@ -21,6 +25,11 @@ enum Args {
         #[command(subcommand)]
         attr: WriteAttr,
     },
+    /// Step an attribute up or down, for keyboard shortcuts
+    Adjust {
+        #[command(subcommand)]
+        attr: AdjustAttr,
+    },
     /// Write a standard effect
     StandardEffect {
         #[command(subcommand)]
@@ -31,6 +40,229 @@ enum Args {
         #[command(subcommand)]
         effect: Effect,
     },
+    /// Make the daemon read its configuration file again
+    ReloadConfig,
+    /// Print every setting and reading of the daemon as JSON
+    Dump,
+    /// Light a single key over the running effects
+    KeyColour(KeyColourParams),
+    /// Remove the colour of a single key
+    ClearKeyColour(KeyIndexParams),
+    /// List the custom effects the daemon knows
+    ListEffects,
+    /// Save, load or list named sets of effect layers
+    Preset {
+        #[command(subcommand)]
+        action: PresetAction,
+    },
+    /// Save, switch to or list named sets of the power, fan, brightness and logo settings and the effects
+    Profile {
+        #[command(subcommand)]
+        action: ProfileAction,
+    },
+    /// Set the keyboard brightness, e.g. `brightness --ac 80`
+    Brightness(BrightnessShortParams),
+    /// Set the fan speed, e.g. `fan --ac auto`, or run a fan action that doesn't change it
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+    Fan {
+        #[command(subcommand)]
+        action: Option<FanAction>,
+        #[command(flatten)]
+        speed: FanShortParams,
+    },
+    /// Set the power mode of the current power source, or of --ac/--bat, e.g. `power gaming`
+    Power(PowerShortParams),
+    /// Show a raw RGB frame instead of the effects, until resume-effect
+    Frame(FrameParams),
+    /// Show the effects again after frame or import-profile
+    ResumeEffect,
+    /// Stop the effect animations, the keyboard keeps its current colours
+    PauseEffects,
+    /// Go on with the effect animations from where pause-effects stopped them
+    ResumeEffects,
+    /// List the connected devices, * marks the one the commands go to
+    ListDevices,
+    /// Send the following commands to another connected device
+    SelectDevice(SelectDeviceParams),
+    /// Turn the keyboard black right away, the effects keep running unseen
+    Blank,
+    /// Show the keyboard again after blank
+    Unblank,
+    /// Save what the keyboard shows as a raw RGB frame, the format frame reads
+    Snapshot(SnapshotParams),
+    /// Pulse a colour over the effects a few times, e.g. for notifications
+    Flash(FlashParams),
+    /// Show the per key colours of an OpenRGB style JSON profile, until resume-effect
+    ImportProfile(ImportProfileParams),
+    /// Apply a profile of ~/.local/share/razercontrol/profiles while a program runs
+    AppProfile(AppProfileParams),
+    /// Light the key of Caps, Num or Scroll Lock while the lock is on
+    LockIndicator(LockIndicatorParams),
+    /// Replace the effect with a random one, surprise!
+    RandomEffect(RandomEffectParams),
+}
+
+#[derive(Subcommand)]
+enum PresetAction {
+    /// Store the current effect layers under a name
+    Save(PresetNameParams),
+    /// Replace the current effect layers with a stored preset
+    Load(PresetNameParams),
+    /// List the stored presets
+    List,
+}
+
+#[derive(Subcommand)]
+enum ProfileAction {
+    /// Store the power, fan, brightness and logo settings of both power sources and the effect layers under a name
+    Save(PresetNameParams),
+    /// Replace those settings and the effect layers with a stored profile
+    Switch(PresetNameParams),
+    /// List the stored profiles
+    List,
+}
+
+#[derive(Parser)]
+struct FrameParams {
+    /// path to the raw RGB frame, 3 bytes per key of every zone, - for stdin
+    file: String,
+}
+
+#[derive(Parser)]
+struct ImportProfileParams {
+    /// JSON file with the LED names and colours, - for stdin
+    file: String,
+}
+
+#[derive(Parser)]
+struct AppProfileParams {
+    /// process name, as shown by `ps -e`, or the .exe of a Wine game
+    process: String,
+    /// profile name, leave out to stop watching the process
+    profile: Option<String>,
+}
+
+#[derive(Parser)]
+struct RandomEffectParams {
+    /// seed of the random choice, the same seed gives the same effect
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+#[derive(Parser)]
+struct LockIndicatorParams {
+    /// capslock, numlock or scrolllock
+    lock: String,
+    /// RRGGBB hex colour (e.g. ffffff), or red green blue (0-255), black turns it off
+    #[arg(num_args = 1..=3, required = true)]
+    colour: Vec<String>,
+}
+
+#[derive(Parser)]
+struct FlashParams {
+    /// RRGGBB hex colour (e.g. ff0000), or red green blue (0-255)
+    #[arg(num_args = 1..=3, required = true)]
+    colour: Vec<String>,
+    /// length of a pulse in milliseconds (50-2000)
+    #[arg(long, default_value_t = 300)]
+    duration: u32,
+    /// number of pulses (1-20)
+    #[arg(long, default_value_t = 2)]
+    count: u8,
+    /// keys to flash: all, wasd, arrows, function_row or number_row
+    #[arg(long, default_value = "all")]
+    region: String,
+}
+
+#[derive(Parser)]
+struct SnapshotParams {
+    /// path to write the raw RGB frame to, - for stdout
+    file: String,
+    /// only this effect layer (0 = bottom), not blended or dimmed
+    #[arg(long)]
+    layer: Option<u16>,
+}
+
+#[derive(Parser)]
+struct SelectDeviceParams {
+    /// index shown by list-devices, or serial number
+    device: String,
+}
+
+#[derive(Subcommand)]
+enum FanAction {
+    /// Run the fans at full speed for a while, then go back to the current setting
+    Turbo(FanTurboParams),
+}
+
+#[derive(Parser)]
+#[group(required = true, multiple = true)]
+struct BrightnessShortParams {
+    /// brightness when plugged in (0-100)
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+    ac: Option<u8>,
+    /// brightness on battery (0-100)
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+    bat: Option<u8>,
+}
+
+#[derive(Parser)]
+#[group(required = true, multiple = true)]
+struct FanShortParams {
+    /// fan speed when plugged in, in RPM or auto
+    #[arg(long, value_parser = parse_fan_speed)]
+    ac: Option<i32>,
+    /// fan speed on battery, in RPM or auto
+    #[arg(long, value_parser = parse_fan_speed)]
+    bat: Option<i32>,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum PowerModeName {
+    Balanced,
+    Gaming,
+    Creator,
+    Silent,
+}
+
+#[derive(Parser)]
+struct PowerShortParams {
+    /// power mode, `write power` sets the custom mode
+    mode: PowerModeName,
+    /// set the mode for plugged in
+    #[arg(long)]
+    ac: bool,
+    /// set the mode for battery
+    #[arg(long)]
+    bat: bool,
+}
+
+#[derive(Parser)]
+struct FanTurboParams {
+    /// seconds at full speed (1-600)
+    #[arg(value_parser = clap::value_parser!(u32).range(1..=600))]
+    duration: u32,
+}
+
+#[derive(Parser)]
+struct PresetNameParams {
+    /// letters, digits, '-' and '_'
+    name: String,
+}
+
+#[derive(Parser)]
+struct KeyColourParams {
+    /// key (0-89 on the Blade layout), row by row from the top left
+    index: u8,
+    /// RRGGBB hex colour or red, green, blue (0-255)
+    #[arg(num_args = 1..=3, required = true)]
+    colour: Vec<String>,
+}
+
+#[derive(Parser)]
+struct KeyIndexParams {
+    /// key (0-89 on the Blade layout), row by row from the top left
+    index: u8,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -59,12 +291,66 @@ enum ReadAttr {
     Sync,
     /// Read the current bho mode
     Bho,
+    /// Read the current custom effect
+    Effect,
+    /// Read the fan curve
+    FanCurve(AcStateParam),
+    /// Read the CPU and GPU temperatures
+    Temperature,
+    /// Read the battery charge
+    Battery,
+    /// Read the wear of the battery: capacity left and charge cycles
+    BatteryHealth,
+    /// Read the features of the laptop
+    Features,
+    /// Read the logo breathing speed
+    LogoSpeed(AcStateParam),
+    /// Read whether the settings follow the power source
+    AutoSwitch,
+    /// Read the measured fan speeds
+    FanRpm,
+    /// Read the stored frame the keyboard was copied to last
+    CustomFrame,
+    /// Read how long the keyboard waits for a key before dimming
+    IdleTimeout,
+    /// Read the gamma correction of the custom effect colours
+    Gamma,
+    /// Read the white balance of the custom effect colours
+    WhiteBalance,
+    /// Read the temperature margin of the fan curve
+    FanHysteresis,
+    /// Read how long a new effect fades in over the old one
+    EffectTransition,
+    /// Read the frame rate the effects actually run at
+    AnimationStats,
+    /// List the power modes of the laptop
+    PowerProfiles,
+    /// Read the brightness the effects are never dimmed below
+    MinEffectBrightness,
+    /// Read the version of the daemon and the driver of the laptop, for bug reports
+    Version,
+    /// Read the keyboard layout the key names and regions follow
+    Layout,
+    /// Read which entry of the laptops file the device was matched with
+    MatchInfo,
+    /// Read where the effects the keyboard supports run
+    EffectBackend,
+    /// Read whether the brightness follows a perceptual curve
+    BrightnessCurve,
+    /// Read the colour of the keyboard without effects
+    IdleColour,
+    /// Read whether a breathing logo follows the keyboard
+    LogoSync,
+    /// Read whether the animations stop on battery
+    BatterySaverLighting,
 }
 
 #[derive(Subcommand)]
 enum WriteAttr {
     /// Set the fan speed
     Fan(FanParams),
+    /// Set the fan speed in percent of the fan range of the device
+    FanPercent(FanPercentParams),
     /// Set the power mode
     Power(PowerParams),
     /// Set the brightness of the keyboard
@@ -75,6 +361,44 @@ enum WriteAttr {
     Sync(SyncParams),
     /// Set battery health optimization
     Bho(BhoParams),
+    /// Set the frame rate of the custom effects
+    Fps(FpsParams),
+    /// Set a fan curve following the CPU temperature (setting a fan speed disables it)
+    FanCurve(FanCurveParams),
+    /// Set the logo breathing speed
+    LogoSpeed(LogoSpeedParams),
+    /// Apply the settings of the power source when plugging in or out
+    AutoSwitch(AutoSwitchParams),
+    /// Copy what the keyboard shows to a frame stored on the keyboard controller
+    CustomFrame(CustomFrameIndexParams),
+    /// Dim the keyboard when no key was pressed for a while
+    IdleTimeout(IdleTimeoutParams),
+    /// Set only the CPU boost of the custom power mode
+    CpuBoost(CpuBoostParams),
+    /// Set only the GPU boost of the custom power mode
+    GpuBoost(GpuBoostParams),
+    /// Dim one of the stacked effect layers
+    LayerBrightness(LayerBrightnessParams),
+    /// Set the gamma correction of the custom effect colours
+    Gamma(GammaParams),
+    /// Set how much of red, green and blue reaches the keyboard, for a neutral white
+    WhiteBalance(WhiteBalanceParams),
+    /// Set how far the temperature has to move before the fan curve changes the speed
+    FanHysteresis(FanHysteresisParams),
+    /// Set how long a new effect fades in over the old one
+    EffectTransition(EffectTransitionParams),
+    /// Set the brightness the effects are never dimmed below, unless it is 0
+    MinEffectBrightness(MinEffectBrightnessParams),
+    /// Run the effects the keyboard supports on its controller or in the daemon
+    EffectBackend(EffectBackendParams),
+    /// Write the brightness through a perceptual curve, for finer low levels
+    BrightnessCurve(BrightnessCurveParams),
+    /// Set the colour of the keyboard once the last effect is removed
+    IdleColour(IdleColourParams),
+    /// Let a breathing logo pulse together with the breathing keyboard effect
+    LogoSync(LogoSyncParams),
+    /// Stop the animations on battery, keeping their last frame until plugged in
+    BatterySaverLighting(BatterySaverLightingParams),
 }
 
 #[derive(Parser)]
@@ -83,12 +407,30 @@ struct PowerParams {
     ac_state: AcState,
     /// power mode (0, 1, 2, 3 or 4)
     pwr: u8,
-    /// cpu boost (0, 1, 2 or 3)
+    /// cpu boost (0, 1, 2 or 3), only with power mode 4
     cpu_mode: Option<u8>,
-    /// gpu boost (0, 1 or 2)
+    /// gpu boost (0, 1 or 2), only with power mode 4
     gpu_mode: Option<u8>,
 }
 
+#[derive(Subcommand)]
+enum AdjustAttr {
+    /// Change the brightness by some percent, stopping at 0 and 100
+    Brightness(AdjustParams),
+    /// Change the fan speed by some RPM within the range of the laptop,
+    /// starting from the slowest speed when automatic
+    Fan(AdjustParams),
+}
+
+#[derive(Parser)]
+struct AdjustParams {
+    /// battery/plugged in
+    ac_state: AcState,
+    /// step, negative to go down (e.g. -10)
+    #[arg(allow_negative_numbers = true)]
+    delta: i32,
+}
+
 #[derive(Parser)]
 struct FanParams {
     /// battery/plugged in
@@ -97,12 +439,24 @@ struct FanParams {
     speed: i32,
 }
 
+#[derive(Parser)]
+struct FanPercentParams {
+    /// battery/plugged in
+    ac_state: AcState,
+    /// percent of the fan range, 0 = Auto
+    #[arg(value_parser = clap::value_parser!(u8).range(0..=100))]
+    percent: u8,
+}
+
 #[derive(Parser)]
 struct BrightnessParams {
     /// battery/plugged in
     ac_state: AcState,
     /// brightness
     brightness: i32,
+    /// fade to the new brightness over this many milliseconds
+    #[arg(long, default_value_t = 0)]
+    fade: u32,
 }
 
 #[derive(Parser)]
@@ -118,6 +472,124 @@ struct SyncParams {
     sync_state: OnOff,
 }
 
+#[derive(Parser)]
+struct AutoSwitchParams {
+    state: OnOff,
+}
+
+#[derive(Parser)]
+struct BrightnessCurveParams {
+    state: OnOff,
+}
+
+#[derive(Parser)]
+struct LogoSyncParams {
+    state: OnOff,
+}
+
+#[derive(Parser)]
+struct BatterySaverLightingParams {
+    state: OnOff,
+}
+
+#[derive(Parser)]
+struct IdleColourParams {
+    /// RRGGBB hex colour (e.g. ff0000), or red green blue (0-255), 000000 for black
+    #[arg(num_args = 1..=3, required = true)]
+    colour: Vec<String>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+enum EffectBackend {
+    Software,
+    Hardware,
+}
+
+#[derive(Parser)]
+struct EffectBackendParams {
+    backend: EffectBackend,
+}
+
+#[derive(Parser)]
+struct CustomFrameIndexParams {
+    /// stored frame (0-15), 255 stores nothing
+    #[arg(value_parser = parse_custom_frame_index)]
+    frame: u8,
+}
+
+#[derive(Parser)]
+struct CpuBoostParams {
+    /// battery/plugged in
+    ac_state: AcState,
+    /// cpu boost (0, 1, 2 or 3)
+    #[arg(value_parser = clap::value_parser!(u8).range(0..=3))]
+    cpu_mode: u8,
+}
+
+#[derive(Parser)]
+struct GpuBoostParams {
+    /// battery/plugged in
+    ac_state: AcState,
+    /// gpu boost (0, 1 or 2)
+    #[arg(value_parser = clap::value_parser!(u8).range(0..=2))]
+    gpu_mode: u8,
+}
+
+#[derive(Parser)]
+struct LayerBrightnessParams {
+    /// layer, 0 being the bottom one
+    layer: usize,
+    /// brightness in percent (0-100)
+    #[arg(value_parser = clap::value_parser!(u8).range(0..=100))]
+    brightness: u8,
+}
+
+#[derive(Parser)]
+struct MinEffectBrightnessParams {
+    /// percent, 0 lets the effects go fully dark
+    #[arg(value_parser = clap::value_parser!(u8).range(0..=100))]
+    value: u8,
+}
+
+#[derive(Parser)]
+struct EffectTransitionParams {
+    /// milliseconds, 0 switches at once
+    #[arg(value_parser = clap::value_parser!(u32).range(0..=10000))]
+    duration_ms: u32,
+}
+
+#[derive(Parser)]
+struct FanHysteresisParams {
+    /// degrees celsius, 0 to follow every change
+    #[arg(value_parser = clap::value_parser!(u8).range(0..=20))]
+    celsius: u8,
+}
+
+#[derive(Parser)]
+struct GammaParams {
+    /// gamma (0.1-5.0), 1.0 to send the colours uncorrected
+    value: f32,
+}
+
+#[derive(Parser)]
+struct WhiteBalanceParams {
+    /// red in per mille (0-1000)
+    #[arg(value_parser = clap::value_parser!(u16).range(0..=1000))]
+    red: u16,
+    /// green in per mille (0-1000)
+    #[arg(value_parser = clap::value_parser!(u16).range(0..=1000))]
+    green: u16,
+    /// blue in per mille (0-1000)
+    #[arg(value_parser = clap::value_parser!(u16).range(0..=1000))]
+    blue: u16,
+}
+
+#[derive(Parser)]
+struct IdleTimeoutParams {
+    /// seconds without a key press, 0 to never dim
+    seconds: u32,
+}
+
 #[derive(Parser)]
 struct BhoParams {
     state: OnOff,
@@ -125,6 +597,30 @@ struct BhoParams {
     threshold: Option<u8>,
 }
 
+#[derive(Parser)]
+struct FpsParams {
+    /// frames per second (1-60)
+    fps: u8,
+}
+
+#[derive(Parser)]
+struct FanCurveParams {
+    /// battery/plugged in
+    ac_state: AcState,
+    /// temperature:rpm points, e.g. 50:2000 70:3500 (none to disable the curve)
+    #[arg(value_parser = parse_fan_curve_point)]
+    points: Vec<(u8, i32)>,
+}
+
+#[derive(Parser)]
+struct LogoSpeedParams {
+    /// battery/plugged in
+    ac_state: AcState,
+    /// 0 for the hardware rate, 1 (slow) to 10 (fast) otherwise
+    #[arg(value_parser = clap::value_parser!(u8).range(0..=10))]
+    speed: u8,
+}
+
 #[derive(ValueEnum, Clone)]
 enum AcState {
     /// battery
@@ -212,16 +708,39 @@ enum Effect {
     StaticGradient(StaticGradientParams),
     WaveGradient(WaveGradientParams),
     BreathingSingle(BreathingSingleParams),
+    Ripple(RippleParams),
+    Reactive(ReactiveEffectParams),
+    /// Per key colours, read from a raw RGB file with every key of every LED zone (270 bytes per zone on the Blade layout)
+    CustomFrame(CustomFrameParams),
+    /// Spectrum of the system audio, recorded with parec
+    AudioVisualizer(AudioVisualizerParams),
+    /// Whole keyboard going through the rainbow colours
+    SpectrumCycle(SpectrumCycleParams),
+    /// Several colours fading in and out one after the other
+    BreathingMulti(BreathingMultiParams),
+    /// Random keys twinkling like stars over a background colour
+    Starlight(StarlightEffectParams),
+    /// Two colours sweeping the keyboard in a direction
+    Wave(WaveEffectParams),
+    /// Drops with fading trails falling down the columns
+    Rain(RainParams),
+    /// Colours of the screen, captured with grim (wlroots) or ImageMagick (X11)
+    Ambilight(AmbilightParams),
+    /// Rainbow disc around the centre of the keyboard, turning over time
+    ColorWheel(ColorWheelParams),
+    /// One row of keys as a bar graph of the CPU load
+    LoadMeter(LoadMeterParams),
+    /// Bright circle following the mouse pointer, read with xdotool (X11)
+    Spotlight(SpotlightParams),
+    /// Keys coloured by how often they were pressed lately
+    Heatmap(HeatmapParams),
 }
 
 #[derive(Parser)]
 struct StaticParams {
-    /// red (0-255)
-    red: u8,
-    /// green (0-255)
-    green: u8,
-    /// blue (0-255)
-    blue: u8,
+    /// RRGGBB hex colour (e.g. ff0000), or red green blue (0-255)
+    #[arg(num_args = 1..=3, required = true)]
+    colour: Vec<String>,
 }
 
 #[derive(Parser)]
@@ -238,6 +757,23 @@ struct StaticGradientParams {
     green2: u8,
     /// blue2 (0-255)
     blue2: u8,
+    /// further colours after the second one, RRGGBB hex (e.g. --stop 0000ff)
+    #[arg(long = "stop", value_name = "RRGGBB")]
+    stops: Vec<String>,
+    /// how the colours blend across the keyboard
+    #[arg(long, value_enum, default_value_t = Easing::Linear)]
+    easing: Easing,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum Easing {
+    Linear,
+    /// slow at both colours, fast in the middle
+    Smoothstep,
+    /// stays near the first colour longer
+    EaseIn,
+    /// reaches the second colour sooner
+    EaseOut,
 }
 
 #[derive(Parser)]
@@ -254,6 +790,9 @@ struct WaveGradientParams {
     green2: u8,
     /// blue2 (0-255)
     blue2: u8,
+    /// further colours after the second one, RRGGBB hex (e.g. --stop 0000ff)
+    #[arg(long = "stop", value_name = "RRGGBB")]
+    stops: Vec<String>,
 }
 
 #[derive(Parser)]
@@ -264,17 +803,189 @@ struct BreathingSingleParams {
     green: u8,
     /// blue (0-255)
     blue: u8,
-    /// duration (0-255)
+    /// duration in tenths of a second (1-255)
+    duration: u8,
+    /// brightness in percent at the bottom of a breath
+    #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=100))]
+    min: u8,
+    /// brightness in percent at the top of a breath
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u8).range(0..=100))]
+    max: u8,
+}
+
+#[derive(Parser)]
+struct RippleParams {
+    /// red (0-255)
+    red: u8,
+    /// green (0-255)
+    green: u8,
+    /// blue (0-255)
+    blue: u8,
+    /// radius in keys (1-255)
+    radius: u8,
+}
+
+#[derive(Parser)]
+struct ReactiveEffectParams {
+    /// red (0-255)
+    red: u8,
+    /// green (0-255)
+    green: u8,
+    /// blue (0-255)
+    blue: u8,
+    /// fade duration in tenths of a second (1-255)
+    duration: u8,
+}
+
+#[derive(Parser)]
+struct AudioVisualizerParams {
+    /// hue of the bars (0-255, going around the colour wheel)
+    hue: u8,
+    /// sensitivity (1-255)
+    sensitivity: u8,
+}
+
+#[derive(Parser)]
+struct SpectrumCycleParams {
+    /// duration of a full cycle in tenths of a second (1-255)
+    period: u8,
+}
+
+#[derive(Parser)]
+struct BreathingMultiParams {
+    /// duration of each colour in tenths of a second (1-255)
     duration: u8,
+    /// RRGGBB hex colours (1-85)
+    #[arg(required = true, num_args = 1..=85)]
+    colours: Vec<String>,
+}
+
+#[derive(Parser)]
+struct StarlightEffectParams {
+    /// how many keys twinkle at once (1-255)
+    density: u8,
+    /// RRGGBB hex colour of the stars, or "random" for a random hue per star
+    colour: String,
+    /// RRGGBB hex colour of the background
+    #[arg(default_value = "000000")]
+    background: String,
+}
+
+#[derive(Parser)]
+struct WaveEffectParams {
+    /// direction of the wave
+    direction: WaveDirection,
+    /// speed in keys per second (1-60, faster is clamped)
+    speed: u8,
+    /// RRGGBB hex colours of the wave
+    colour1: String,
+    colour2: String,
+}
+
+#[derive(Parser)]
+struct RainParams {
+    /// fall speed in rows per second (1-60, faster is clamped)
+    speed: u8,
+    /// RRGGBB hex colour of the drops
+    #[arg(default_value = "00ff00")]
+    colour: String,
+}
+
+#[derive(Parser)]
+struct AmbilightParams {
+    /// whole keyboard in the average colour, or every key following its part of the screen
+    mode: AmbilightMode,
+    /// RRGGBB hex colour shown when the screen can't be captured
+    #[arg(default_value = "ffffff")]
+    fallback: String,
+}
+
+#[derive(Parser)]
+struct ColorWheelParams {
+    /// rotation speed in degrees per second, 0 keeps the wheel still
+    #[arg(default_value_t = 60)]
+    speed: u8,
+    /// saturation in percent (0-100)
+    #[arg(default_value_t = 100, value_parser = clap::value_parser!(u8).range(0..=100))]
+    saturation: u8,
+}
+
+#[derive(Parser)]
+struct LoadMeterParams {
+    /// row of the bar (0 = top row, 1 = number row)
+    #[arg(default_value_t = 1, value_parser = clap::value_parser!(u8).range(0..=5))]
+    row: u8,
+    /// hue of the first key (0-255 around the colour wheel), green by default
+    #[arg(default_value_t = 85)]
+    low_hue: u8,
+    /// hue of the last key, red by default
+    #[arg(default_value_t = 0)]
+    high_hue: u8,
+}
+
+#[derive(Parser)]
+struct SpotlightParams {
+    /// RRGGBB hex colour of the spotlight
+    #[arg(default_value = "ffffff")]
+    colour: String,
+    /// radius in keys (1-255)
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u8).range(1..))]
+    radius: u8,
+    /// brightness of the other keys in percent (0-100)
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u8).range(0..=100))]
+    background: u8,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum HeatmapRamp {
+    /// blue for the cold keys to red for the hot ones
+    Thermal,
+    /// black over red and yellow to white
+    Fire,
+}
+
+#[derive(Parser)]
+struct HeatmapParams {
+    #[arg(long, value_enum, default_value_t = HeatmapRamp::Thermal)]
+    ramp: HeatmapRamp,
+    /// minutes it takes for the count of a key to halve (1-255)
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u8).range(1..))]
+    half_life: u8,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum AmbilightMode {
+    Average,
+    PerKey,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum WaveDirection {
+    LeftToRight,
+    RightToLeft,
+    TopToBottom,
+    BottomToTop,
 }
 
+#[derive(Parser)]
+struct CustomFrameParams {
+    /// path to the raw RGB frame
+    file: String,
+}
+
+/// Device given with --device, every command is sent to it
+static TARGET_DEVICE: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+
 fn main() {
-    if std::fs::metadata(comms::SOCKET_PATH).is_err() {
+    let cli = Cli::parse();
+    if std::fs::metadata(comms::socket_path()).is_err() {
         eprintln!("Error. Socket doesn't exit. Is daemon running?");
         std::process::exit(1);
     }
 
-    let cli = Cli::parse();
+    if let Some(device) = cli.device {
+        let _ = TARGET_DEVICE.set(device);
+    }
 
     match cli.args {
         Args::Read { attr } => match attr {
@@ -284,11 +995,46 @@ fn main() {
             ReadAttr::Logo(AcStateParam { ac_state }) => read_logo_mode(ac_state as usize),
             ReadAttr::Sync => read_sync(),
             ReadAttr::Bho => read_bho(),
+            ReadAttr::Effect => read_effect(),
+            ReadAttr::FanCurve(AcStateParam { ac_state }) => read_fan_curve(ac_state as usize),
+            ReadAttr::Temperature => read_temperatures(),
+            ReadAttr::Battery => read_battery(),
+            ReadAttr::BatteryHealth => read_battery_health(),
+            ReadAttr::Features => read_features(),
+            ReadAttr::LogoSpeed(AcStateParam { ac_state }) => read_logo_speed(ac_state as usize),
+            ReadAttr::AutoSwitch => read_auto_switch(),
+            ReadAttr::IdleTimeout => read_idle_timeout(),
+            ReadAttr::Gamma => read_gamma(),
+            ReadAttr::WhiteBalance => read_white_balance(),
+            ReadAttr::FanHysteresis => read_fan_hysteresis(),
+            ReadAttr::EffectTransition => read_effect_transition(),
+            ReadAttr::AnimationStats => read_animation_stats(),
+            ReadAttr::PowerProfiles => read_power_profiles(),
+            ReadAttr::MinEffectBrightness => read_min_effect_brightness(),
+            ReadAttr::Version => read_version(),
+            ReadAttr::Layout => read_layout(),
+            ReadAttr::MatchInfo => read_match_info(),
+            ReadAttr::FanRpm => read_actual_fan_rpm(),
+            ReadAttr::CustomFrame => read_custom_frame(),
+            ReadAttr::EffectBackend => read_effect_backend(),
+            ReadAttr::BrightnessCurve => read_brightness_curve(),
+            ReadAttr::IdleColour => read_idle_colour(),
+            ReadAttr::LogoSync => read_logo_sync(),
+            ReadAttr::BatterySaverLighting => read_battery_saver_lighting(),
+        },
+        Args::Adjust { attr } => match attr {
+            AdjustAttr::Brightness(AdjustParams { ac_state, delta }) => {
+                adjust_brightness(ac_state as usize, delta)
+            }
+            AdjustAttr::Fan(AdjustParams { ac_state, delta }) => adjust_fan_speed(ac_state as usize, delta),
         },
         Args::Write { attr } => match attr {
             WriteAttr::Fan(FanParams { ac_state, speed }) => {
                 write_fan_speed(ac_state as usize, speed)
             }
+            WriteAttr::FanPercent(FanPercentParams { ac_state, percent }) => {
+                write_fan_percent(ac_state as usize, percent)
+            }
             WriteAttr::Power(PowerParams {
                 ac_state,
                 pwr,
@@ -298,7 +1044,13 @@ fn main() {
             WriteAttr::Brightness(BrightnessParams {
                 ac_state,
                 brightness,
+                fade: 0,
             }) => write_brightness(ac_state as usize, brightness as u8),
+            WriteAttr::Brightness(BrightnessParams {
+                ac_state,
+                brightness,
+                fade,
+            }) => write_brightness_smooth(ac_state as usize, brightness as u8, fade),
             WriteAttr::Sync(SyncParams { sync_state }) => write_sync(sync_state.is_on()),
             WriteAttr::Logo(LogoParams {
                 ac_state,
@@ -307,38 +1059,151 @@ fn main() {
             WriteAttr::Bho(BhoParams { state, threshold }) => {
                 validate_and_write_bho(threshold, state)
             }
+            WriteAttr::Fps(FpsParams { fps }) => write_animation_fps(fps),
+            WriteAttr::FanCurve(FanCurveParams { ac_state, points }) => {
+                write_fan_curve(ac_state as usize, points)
+            }
+            WriteAttr::LogoSpeed(LogoSpeedParams { ac_state, speed }) => {
+                write_logo_speed(ac_state as usize, speed)
+            }
+            WriteAttr::AutoSwitch(AutoSwitchParams { state }) => write_auto_switch(state.is_on()),
+            WriteAttr::IdleTimeout(IdleTimeoutParams { seconds }) => write_idle_timeout(seconds),
+            WriteAttr::CpuBoost(CpuBoostParams { ac_state, cpu_mode }) => {
+                write_cpu_boost(ac_state as usize, cpu_mode)
+            }
+            WriteAttr::GpuBoost(GpuBoostParams { ac_state, gpu_mode }) => {
+                write_gpu_boost(ac_state as usize, gpu_mode)
+            }
+            WriteAttr::LayerBrightness(LayerBrightnessParams { layer, brightness }) => {
+                write_layer_brightness(layer, brightness)
+            }
+            WriteAttr::Gamma(GammaParams { value }) => write_gamma(value),
+            WriteAttr::WhiteBalance(WhiteBalanceParams { red, green, blue }) => {
+                write_white_balance(red, green, blue)
+            }
+            WriteAttr::FanHysteresis(FanHysteresisParams { celsius }) => write_fan_hysteresis(celsius),
+            WriteAttr::EffectTransition(EffectTransitionParams { duration_ms }) => {
+                write_effect_transition(duration_ms)
+            }
+            WriteAttr::MinEffectBrightness(MinEffectBrightnessParams { value }) => write_min_effect_brightness(value),
+            WriteAttr::CustomFrame(CustomFrameIndexParams { frame }) => write_custom_frame(frame),
+            WriteAttr::EffectBackend(EffectBackendParams { backend }) => {
+                write_effect_backend(backend == EffectBackend::Hardware)
+            }
+            WriteAttr::BrightnessCurve(BrightnessCurveParams { state }) => {
+                write_brightness_curve(state.is_on())
+            }
+            WriteAttr::IdleColour(IdleColourParams { colour }) => {
+                write_idle_colour(parse_colour(&colour))
+            }
+            WriteAttr::LogoSync(LogoSyncParams { state }) => write_logo_sync(state.is_on()),
+            WriteAttr::BatterySaverLighting(BatterySaverLightingParams { state }) => {
+                write_battery_saver_lighting(state.is_on())
+            }
         },
         Args::Effect { effect } => match effect {
-            Effect::Static(params) => send_effect(
-                "static".to_string(),
-                vec![params.red, params.green, params.blue],
+            Effect::Static(params) => {
+                send_effect("static".to_string(), parse_colour(&params.colour))
+            }
+            Effect::StaticGradient(params) => {
+                let mut args = gradient_args(
+                    [params.red1, params.green1, params.blue1, params.red2, params.green2, params.blue2],
+                    &params.stops,
+                );
+                args.push(params.easing as u8);
+                send_effect("static_gradient".to_string(), args)
+            }
+            Effect::WaveGradient(params) => send_effect(
+                "wave_gradient".to_string(),
+                gradient_args(
+                    [params.red1, params.green1, params.blue1, params.red2, params.green2, params.blue2],
+                    &params.stops,
+                ),
             ),
-            Effect::StaticGradient(params) => send_effect(
-                "static_gradient".to_string(),
-                vec![
-                    params.red1,
-                    params.green1,
-                    params.blue1,
-                    params.red2,
-                    params.green2,
-                    params.blue2,
-                ],
-            ),
-            Effect::WaveGradient(params) => send_effect(
-                "wave_gradient".to_string(),
-                vec![
-                    params.red1,
-                    params.green1,
-                    params.blue1,
-                    params.red2,
-                    params.green2,
-                    params.blue2,
-                ],
+            Effect::BreathingSingle(params) => {
+                if params.min > params.max {
+                    exit_with_error("--min must not be above --max");
+                }
+                let mut args = vec![params.red, params.green, params.blue, params.duration];
+                // The 4 argument form still runs on the keyboard controller
+                if (params.min, params.max) != (0, 100) {
+                    args.extend([params.min, params.max]);
+                }
+                send_effect("breathing_single".to_string(), args)
+            }
+            Effect::Ripple(params) => send_effect(
+                "ripple".to_string(),
+                vec![params.red, params.green, params.blue, params.radius],
             ),
-            Effect::BreathingSingle(params) => send_effect(
-                "breathing_single".to_string(),
+            Effect::Reactive(params) => send_effect(
+                "reactive".to_string(),
                 vec![params.red, params.green, params.blue, params.duration],
             ),
+            Effect::CustomFrame(params) => match std::fs::read(&params.file) {
+                Ok(frame) => send_effect("custom_frame".to_string(), frame),
+                Err(error) => eprintln!("Could not read {}: {}", params.file, error),
+            },
+            Effect::AudioVisualizer(params) => send_effect(
+                "audio_visualizer".to_string(),
+                vec![params.hue, params.sensitivity],
+            ),
+            Effect::SpectrumCycle(params) => {
+                send_effect("spectrum_cycle".to_string(), vec![params.period])
+            }
+            Effect::BreathingMulti(params) => {
+                let mut args = vec![params.colours.len() as u8];
+                for colour in &params.colours {
+                    args.extend(parse_colour(std::slice::from_ref(colour)));
+                }
+                args.push(params.duration);
+                send_effect("breathing_multi".to_string(), args)
+            }
+            Effect::Starlight(params) => {
+                let random_hue = params.colour == "random";
+                let mut args = if random_hue {
+                    vec![0, 0, 0]
+                } else {
+                    parse_colour(std::slice::from_ref(&params.colour))
+                };
+                args.push(params.density);
+                args.push(random_hue as u8);
+                args.extend(parse_colour(std::slice::from_ref(&params.background)));
+                send_effect("starlight".to_string(), args)
+            }
+            Effect::Wave(params) => {
+                let mut args = parse_colour(std::slice::from_ref(&params.colour1));
+                args.extend(parse_colour(std::slice::from_ref(&params.colour2)));
+                args.push(params.direction as u8);
+                args.push(params.speed);
+                send_effect("wave".to_string(), args)
+            }
+            Effect::ColorWheel(params) => {
+                send_effect("color_wheel".to_string(), vec![params.speed, params.saturation])
+            }
+            Effect::Ambilight(params) => {
+                let mut args = vec![params.mode as u8];
+                args.extend(parse_colour(std::slice::from_ref(&params.fallback)));
+                send_effect("ambilight".to_string(), args)
+            }
+            Effect::Rain(params) => {
+                let mut args = parse_colour(std::slice::from_ref(&params.colour));
+                args.push(params.speed);
+                send_effect("rain".to_string(), args)
+            }
+            Effect::LoadMeter(params) => send_effect(
+                "load_meter".to_string(),
+                vec![params.row, params.low_hue, params.high_hue],
+            ),
+            Effect::Spotlight(params) => {
+                let mut args = parse_colour(std::slice::from_ref(&params.colour));
+                args.push(params.radius);
+                args.push(params.background);
+                send_effect("spotlight".to_string(), args)
+            }
+            Effect::Heatmap(params) => send_effect(
+                "heatmap".to_string(),
+                vec![params.ramp as u8, params.half_life],
+            ),
         },
         Args::StandardEffect { effect } => match effect {
             StandardEffect::Off => send_standard_effect("off".to_string(), vec![]),
@@ -372,14 +1237,72 @@ fn main() {
                     params.blue2,
                 ],
             ),
-            StandardEffect::Static(params) => send_standard_effect(
-                "static".to_string(),
-                vec![params.red, params.green, params.blue],
-            ),
+            StandardEffect::Static(params) => {
+                send_standard_effect("static".to_string(), parse_colour(&params.colour))
+            }
             StandardEffect::Wave(params) => {
                 send_standard_effect("wave".to_string(), vec![params.direction])
             }
         },
+        Args::ReloadConfig => reload_config(),
+        Args::Dump => dump(),
+        Args::KeyColour(KeyColourParams { index, colour }) => {
+            write_key_colour(index as usize, parse_colour(&colour))
+        }
+        Args::ClearKeyColour(KeyIndexParams { index }) => clear_key_colour(index as usize),
+        Args::ListEffects => list_effects(),
+        Args::Preset { action } => match action {
+            PresetAction::Save(PresetNameParams { name }) => save_preset(name),
+            PresetAction::Load(PresetNameParams { name }) => load_preset(name),
+            PresetAction::List => list_presets(),
+        },
+        Args::Profile { action } => match action {
+            ProfileAction::Save(PresetNameParams { name }) => save_profile(name),
+            ProfileAction::Switch(PresetNameParams { name }) => switch_profile(name),
+            ProfileAction::List => list_profiles(),
+        },
+        Args::Brightness(BrightnessShortParams { ac, bat }) => {
+            if let Some(val) = bat {
+                write_brightness(0, val);
+            }
+            if let Some(val) = ac {
+                write_brightness(1, val);
+            }
+        }
+        Args::Fan { action: Some(FanAction::Turbo(FanTurboParams { duration })), .. } => fan_turbo(duration),
+        Args::Fan { action: None, speed: FanShortParams { ac, bat } } => {
+            if let Some(rpm) = bat {
+                write_fan_speed(0, rpm);
+            }
+            if let Some(rpm) = ac {
+                write_fan_speed(1, rpm);
+            }
+        }
+        Args::Power(PowerShortParams { mode, ac, bat }) => {
+            let sources = match (ac, bat) {
+                (false, false) => vec![current_power_source()],
+                (ac, bat) => [(bat, 0), (ac, 1)].iter().filter(|s| s.0).map(|s| s.1).collect(),
+            };
+            for source in sources {
+                write_pwr_mode(source, mode as u8, None, None);
+            }
+        }
+        Args::Frame(FrameParams { file }) => write_full_frame(&file),
+        Args::ResumeEffect => resume_effect(),
+        Args::PauseEffects => pause_effects(),
+        Args::ResumeEffects => resume_effects(),
+        Args::ListDevices => list_devices(),
+        Args::SelectDevice(SelectDeviceParams { device }) => select_device(&device),
+        Args::Blank => blank_keyboard(),
+        Args::Unblank => restore_keyboard(),
+        Args::Snapshot(SnapshotParams { file, layer }) => write_snapshot(&file, layer),
+        Args::ImportProfile(ImportProfileParams { file }) => import_profile(&file),
+        Args::AppProfile(AppProfileParams { process, profile }) => {
+            write_app_profile(process, profile.unwrap_or_default())
+        }
+        Args::RandomEffect(RandomEffectParams { seed }) => random_effect(seed),
+        Args::LockIndicator(LockIndicatorParams { lock, colour }) => write_lock_indicator(lock, parse_colour(&colour)),
+        Args::Flash(params) => write_flash(parse_colour(&params.colour), params.duration, params.count, params.region),
     }
 }
 
@@ -412,7 +1335,7 @@ fn validate_and_write_bho(threshold: Option<u8>, state: OnOff) {
 
 fn read_bho() {
     send_data(comms::DaemonCommand::GetBatteryHealthOptimizer()).map_or_else(
-        || eprintln!("Unknown error occured when getting bho"),
+        || exit_with_error("Unknown error occured when getting bho"),
         |result| {
             if let comms::DaemonResponse::GetBatteryHealthOptimizer { is_on, threshold } = result {
                 match is_on {
@@ -442,8 +1365,7 @@ fn write_bho(on: bool, threshold: u8) {
 
 fn bho_toggle_on(threshold: u8) {
     if !valid_bho_threshold(threshold) {
-        eprintln!("Threshold value must be a multiple of five between 50 and 80");
-        return;
+        exit_with_error("Threshold value must be a multiple of five between 50 and 80");
     }
 
     send_data(comms::DaemonCommand::SetBatteryHealthOptimizer {
@@ -451,7 +1373,7 @@ fn bho_toggle_on(threshold: u8) {
         threshold: threshold,
     })
     .map_or_else(
-        || eprintln!("Unknown error occured when toggling bho"),
+        || exit_with_error("Unknown error occured when toggling bho"),
         |result| {
             if let comms::DaemonResponse::SetBatteryHealthOptimizer { result } = result {
                 match result {
@@ -462,7 +1384,7 @@ fn bho_toggle_on(threshold: u8) {
                         );
                     }
                     false => {
-                        eprintln!("Failed to turn on bho with threshold of {}", threshold);
+                        exit_with_error(&format!("Failed to turn on bho with threshold of {}", threshold));
                     }
                 }
             }
@@ -488,7 +1410,7 @@ fn bho_toggle_off() {
         threshold: 80,
     })
     .map_or_else(
-        || eprintln!("Unknown error occured when toggling bho"),
+        || exit_with_error("Unknown error occured when toggling bho"),
         |result| {
             if let comms::DaemonResponse::SetBatteryHealthOptimizer { result } = result {
                 match result {
@@ -496,7 +1418,7 @@ fn bho_toggle_off() {
                         println!("Successfully turned off bho");
                     }
                     false => {
-                        eprintln!("Failed to turn off bho");
+                        exit_with_error("Failed to turn off bho");
                     }
                 }
             }
@@ -510,35 +1432,62 @@ fn send_standard_effect(name: String, params: Vec<u8>) {
             if result {
                 println!("Effect set OK!");
             } else {
-                eprintln!("Effect set FAIL!");
+                exit_with_error("Effect set FAIL!");
             }
         },
-        Some(_) => eprintln!("Unexpected response from daemon!"),
-        None => eprintln!("Unknown daemon error!"),
+        Some(_) => exit_with_error("Unexpected response from daemon!"),
+        None => exit_with_error("Unknown daemon error!"),
     }
 }
 
 fn send_effect(name: String, params: Vec<u8>) {
-    match send_data(comms::DaemonCommand::SetEffect { name, params }) {
-        Some(comms::DaemonResponse::SetEffect { result }) => {
+    match send_data(comms::DaemonCommand::SetEffect { name, params: params.clone() }) {
+        Some(comms::DaemonResponse::SetEffect { result, params: effective }) => {
             if result {
                 println!("Effect set OK!");
+                // Timing args out of range are clamped by the daemon, not rejected
+                if effective != params {
+                    println!("Arguments clamped to the supported range: {:?}", effective);
+                }
             } else {
-                eprintln!("Effect set FAIL!");
+                exit_with_error("Effect set FAIL!");
             }
         },
-        Some(_) => eprintln!("Unexpected response from daemon!"),
-        None => eprintln!("Unknown daemon error!"),
+        Some(_) => exit_with_error("Unexpected response from daemon!"),
+        None => exit_with_error("Unknown daemon error!"),
+    }
+}
+
+fn read_effect() {
+    match send_data(comms::DaemonCommand::GetEffect) {
+        Some(comms::DaemonResponse::GetEffect { name, params }) => {
+            println!("Current effect: {} {:?}", name, params);
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
     }
 }
 
 fn send_data(opt: comms::DaemonCommand) -> Option<comms::DaemonResponse> {
+    match send_data_or_error(opt) {
+        Some(comms::DaemonResponse::Error { message }) => exit_with_error(&message),
+        response => response,
+    }
+}
+
+/// Like `send_data` but hands `Error` responses back instead of exiting
+fn send_data_or_error(opt: comms::DaemonCommand) -> Option<comms::DaemonResponse> {
+    let opt = match TARGET_DEVICE.get() {
+        Some(device) => comms::DaemonCommand::OnDevice { device: device.clone(), command: Box::new(opt) },
+        None => opt,
+    };
     match comms::bind() {
-        Some(socket) => comms::send_to_daemon(opt, socket),
-        None => {
-            eprintln!("Error. Cannot bind to socket");
-            None
+        Some(socket) => match comms::send_to_daemon(opt, socket) {
+            Ok(response) => Some(response),
+            Err(comms::SendError::Timeout) => exit_with_error("The daemon did not answer in time"),
+            Err(comms::SendError::Failed) => None,
         },
+        None => exit_with_error("Error. Cannot bind to socket"),
     }
 }
 
@@ -552,8 +1501,8 @@ fn read_fan_rpm(ac: usize) {
             };
             println!("Current fan setting: {}", rpm_desc);
         },
-        Some(_) => eprintln!("Daemon responded with invalid data!"),
-        None => eprintln!("Unknown daemon error!"),
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
     }
 }
 
@@ -568,8 +1517,8 @@ fn read_logo_mode(ac: usize) {
             };
             println!("Current logo setting: {}", logo_state_desc);
         },
-        Some(_) => eprintln!("Daemon responded with invalid data!"),
-        None => eprintln!("Unknown daemon error!"),
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
     }
 }
 
@@ -623,6 +1572,12 @@ fn write_pwr_mode(ac: usize, pwr_mode: u8, cpu_mode: Option<u8>, gpu_mode: Optio
             .exit()
     }
 
+    if pwr_mode != 4 && (cpu_mode.unwrap_or(0) != 0 || gpu_mode.unwrap_or(0) != 0) {
+        Cli::command()
+            .error(ErrorKind::InvalidValue, "CPU and GPU mode can only be set with power mode 4")
+            .exit()
+    }
+
     let cm = if pwr_mode == 4 {
         cpu_mode.expect("CPU mode must be provided when power mode is 4")
     } else {
@@ -653,6 +1608,9 @@ fn write_pwr_mode(ac: usize, pwr_mode: u8, cpu_mode: Option<u8>, gpu_mode: Optio
         cpu: cm,
         gpu: gm,
     }) {
+        Some(comms::DaemonResponse::SetPowerMode { result: false }) => {
+            exit_with_error("The daemon could not apply the setting!")
+        },
         Some(_) => read_power_mode(ac),
         None => {
             Cli::command()
@@ -670,8 +1628,8 @@ fn read_brightness(ac: usize) {
         Some(comms::DaemonResponse::GetBrightness { result }) => {
             println!("Current brightness: {}", result);
         },
-        Some(_) => eprintln!("Daemon responded with invalid data!"),
-        None => eprintln!("Unknown daemon error!"),
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
     }
 }
 
@@ -680,35 +1638,1114 @@ fn read_sync() {
         Some(comms::DaemonResponse::GetSync { sync }) => {
             println!("Current sync: {:?}", sync);
         },
-        Some(_) => eprintln!("Daemon responded with invalid data!"),
-        None => eprintln!("Unknown daemon error!"),
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
     }
 }
 
 fn write_brightness(ac: usize, val: u8) {
     match send_data(comms::DaemonCommand::SetBrightness { ac, val }) {
+        Some(comms::DaemonResponse::SetBrightness { result: false }) => {
+            exit_with_error("The daemon could not apply the setting!")
+        },
         Some(_) => read_brightness(ac),
-        None => eprintln!("Unknown error!"),
+        None => exit_with_error("Unknown error!"),
     }
 }
 
 fn write_fan_speed(ac: usize, x: i32) {
     match send_data(comms::DaemonCommand::SetFanSpeed { ac, rpm: x }) {
+        Some(comms::DaemonResponse::SetFanSpeed { result: false }) => {
+            exit_with_error("The daemon could not apply the setting!")
+        },
         Some(_) => read_fan_rpm(ac),
-        None => eprintln!("Unknown error!"),
+        None => exit_with_error("Unknown error!"),
     }
 }
 
 fn write_logo_mode(ac: usize, x: u8) {
     match send_data(comms::DaemonCommand::SetLogoLedState { ac, logo_state: x }) {
+        Some(comms::DaemonResponse::SetLogoLedState { result: false }) => {
+            exit_with_error("The daemon could not apply the setting!")
+        },
         Some(_) => read_logo_mode(ac),
-        None => eprintln!("Unknown error!"),
+        None => exit_with_error("Unknown error!"),
     }
 }
 
 fn write_sync(sync: bool) {
     match send_data(comms::DaemonCommand::SetSync { sync }) {
+        Some(comms::DaemonResponse::SetSync { result: false }) => {
+            exit_with_error("The daemon could not apply the setting!")
+        },
         Some(_) => read_sync(),
-        None => eprintln!("Unknown error!"),
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn write_animation_fps(fps: u8) {
+    match send_data(comms::DaemonCommand::SetAnimationFps { fps }) {
+        Some(comms::DaemonResponse::SetAnimationFps { fps }) => {
+            println!("Animation frame rate: {} fps", fps);
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn parse_fan_curve_point(point: &str) -> Result<(u8, i32), String> {
+    let (temp, rpm) = point
+        .split_once(':')
+        .ok_or(format!("{} is not a temperature:rpm pair", point))?;
+    let temp = temp.parse::<u8>().map_err(|e| format!("invalid temperature {}: {}", temp, e))?;
+    let rpm = rpm.parse::<i32>().map_err(|e| format!("invalid rpm {}: {}", rpm, e))?;
+    Ok((temp, rpm))
+}
+
+fn read_fan_curve(ac: usize) {
+    match send_data(comms::DaemonCommand::GetFanCurve { ac }) {
+        Some(comms::DaemonResponse::GetFanCurve { points }) => {
+            if points.is_empty() {
+                println!("No fan curve, using the fan speed setting");
+            } else {
+                for (temp, rpm) in points {
+                    println!("{} °C: {} RPM", temp, rpm);
+                }
+            }
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
+    }
+}
+
+fn write_fan_curve(ac: usize, points: Vec<(u8, i32)>) {
+    match send_data(comms::DaemonCommand::SetFanCurve { ac, points }) {
+        Some(comms::DaemonResponse::SetFanCurve { result: false }) => {
+            exit_with_error("The daemon could not apply the setting!")
+        },
+        Some(_) => read_fan_curve(ac),
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn read_temperatures() {
+    for (label, cmd) in [
+        ("CPU", comms::DaemonCommand::GetCpuTemperature),
+        ("GPU", comms::DaemonCommand::GetGpuTemperature),
+    ] {
+        match send_data_or_error(cmd) {
+            Some(comms::DaemonResponse::GetTemperature { celsius }) => {
+                println!("{} temperature: {:.1} °C", label, celsius);
+            },
+            Some(comms::DaemonResponse::GetGpuTemperature { celsius: Some(celsius), source }) => {
+                println!("{} temperature: {:.1} °C ({})", label, celsius, source);
+            },
+            Some(comms::DaemonResponse::GetGpuTemperature { celsius: None, source }) if source == "powered-down" => {
+                println!("{} temperature: unavailable, the GPU is powered down", label);
+            },
+            Some(comms::DaemonResponse::GetGpuTemperature { celsius: None, .. }) => {
+                eprintln!("No {} temperature sensor was found", label);
+            },
+            Some(comms::DaemonResponse::Error { message }) => eprintln!("{}", message),
+            Some(_) => exit_with_error("Daemon responded with invalid data!"),
+            None => eprintln!("{} temperature not available", label),
+        }
+    }
+}
+
+/// Prints the error and exits with a non-zero status so scripts can detect it
+/// 0 on battery and 1 when plugged in, like `AcState`
+fn current_power_source() -> usize {
+    match send_data(comms::DaemonCommand::GetPowerSource) {
+        Some(comms::DaemonResponse::GetPowerSource { source }) if source == "BAT" => 0,
+        Some(comms::DaemonResponse::GetPowerSource { source }) if source == "AC" => 1,
+        Some(_) => exit_with_error("The power source is unknown, pass --ac or --bat"),
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+/// `auto` or a speed in RPM, 0 is the automatic fan control of the daemon
+fn parse_fan_speed(arg: &str) -> Result<i32, String> {
+    match arg {
+        "auto" => Ok(0),
+        rpm => rpm.parse().map_err(|_| format!("'{}' is neither auto nor a speed in RPM", rpm)),
+    }
+}
+
+fn exit_with_error(msg: &str) -> ! {
+    eprintln!("{}", msg);
+    std::process::exit(1);
+}
+
+/// Parses either a single RRGGBB hex colour (with an optional #) or three
+/// decimal red, green and blue values into effect params
+fn parse_colour(args: &[String]) -> Vec<u8> {
+    let parsed: Option<Vec<u8>> = match args {
+        [hex] => comms::parse_hex_colour(hex).map(|rgb| rgb.to_vec()),
+        [red, green, blue] => [red, green, blue].iter().map(|c| c.parse::<u8>().ok()).collect(),
+        _ => None,
+    };
+
+    match parsed {
+        Some(colour) => colour,
+        None => Cli::command()
+            .error(
+                ErrorKind::InvalidValue,
+                "Colour must be RRGGBB hex or three values between 0 and 255",
+            )
+            .exit(),
+    }
+}
+
+/// Arguments of the gradient effects, the number of colours followed by
+/// the colours. Also used for 2 colours, the daemon only reads 6 arguments
+/// without a count
+fn gradient_args(colours: [u8; 6], stops: &[String]) -> Vec<u8> {
+    if stops.len() > 253 {
+        exit_with_error("At most 255 colours are supported");
+    }
+    let mut args = vec![stops.len() as u8 + 2];
+    args.extend_from_slice(&colours);
+    for stop in stops {
+        args.extend(parse_colour(std::slice::from_ref(stop)));
+    }
+    args
+}
+
+fn read_battery() {
+    match send_data(comms::DaemonCommand::GetBatteryState) {
+        Some(comms::DaemonResponse::GetBatteryState { capacity, charging }) => {
+            let state = if charging { "charging" } else { "not charging" };
+            println!("Battery: {}% ({})", capacity, state);
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Battery state not available"),
+    }
+}
+
+fn read_features() {
+    match send_data(comms::DaemonCommand::GetSupportedFeatures) {
+        Some(comms::DaemonResponse::GetSupportedFeatures { features, fan_min, fan_max, fan_step, has_logo, can_boost }) => {
+            println!("Features: {}", features.join(", "));
+            println!("Fan speed: {} - {} RPM in steps of {}", fan_min, fan_max, fan_step);
+            println!("Logo: {}", has_logo);
+            println!("CPU boost: {}", can_boost);
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
+    }
+}
+
+fn read_logo_speed(ac: usize) {
+    match send_data(comms::DaemonCommand::GetLogoBreathingSpeed { ac }) {
+        Some(comms::DaemonResponse::GetLogoBreathingSpeed { speed: 0 }) => {
+            println!("Logo breathing speed: hardware default");
+        },
+        Some(comms::DaemonResponse::GetLogoBreathingSpeed { speed }) => {
+            println!("Logo breathing speed: {}", speed);
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
+    }
+}
+
+fn write_logo_speed(ac: usize, speed: u8) {
+    match send_data(comms::DaemonCommand::SetLogoBreathingSpeed { ac, speed }) {
+        Some(comms::DaemonResponse::SetLogoBreathingSpeed { result: false }) => {
+            exit_with_error("The daemon could not apply the setting!")
+        },
+        Some(_) => read_logo_speed(ac),
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn reload_config() {
+    match send_data(comms::DaemonCommand::ReloadConfig) {
+        Some(comms::DaemonResponse::ReloadConfig { result: true }) => {
+            println!("Configuration reloaded");
+        },
+        Some(comms::DaemonResponse::ReloadConfig { result: false }) => {
+            exit_with_error("The daemon could not read its configuration file!")
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
+    }
+}
+
+fn read_auto_switch() {
+    match send_data(comms::DaemonCommand::GetAutoSwitch) {
+        Some(comms::DaemonResponse::GetAutoSwitch { enabled }) => {
+            println!("Automatic switching: {}", if enabled { "on" } else { "off" });
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
+    }
+}
+
+fn write_auto_switch(enabled: bool) {
+    match send_data(comms::DaemonCommand::SetAutoSwitch { enabled }) {
+        Some(comms::DaemonResponse::SetAutoSwitch { result: false }) => {
+            exit_with_error("The daemon could not apply the setting!")
+        },
+        Some(_) => read_auto_switch(),
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn read_actual_fan_rpm() {
+    match send_data(comms::DaemonCommand::GetActualFanRpm) {
+        Some(comms::DaemonResponse::GetActualFanRpm { rpm1: -1, rpm2: -1 }) => {
+            exit_with_error("No fan tachometer found")
+        },
+        Some(comms::DaemonResponse::GetActualFanRpm { rpm1, rpm2 }) => {
+            for (fan, rpm) in [rpm1, rpm2].iter().enumerate() {
+                if *rpm >= 0 {
+                    println!("Fan {}: {} RPM", fan + 1, rpm);
+                }
+            }
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
+    }
+}
+
+fn parse_custom_frame_index(arg: &str) -> Result<u8, String> {
+    match arg.parse::<u8>() {
+        Ok(frame) if frame <= 15 || frame == 255 => Ok(frame),
+        _ => Err(String::from("Frame must be between 0 and 15, or 255")),
+    }
+}
+
+fn read_custom_frame() {
+    match send_data(comms::DaemonCommand::GetCustomFrame) {
+        Some(comms::DaemonResponse::GetCustomFrame { frame: 255 }) => {
+            println!("Custom frame: none stored");
+        },
+        Some(comms::DaemonResponse::GetCustomFrame { frame }) => {
+            println!("Custom frame: {}", frame);
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
+    }
+}
+
+fn write_custom_frame(frame: u8) {
+    match send_data(comms::DaemonCommand::SetCustomFrame { frame }) {
+        Some(comms::DaemonResponse::SetCustomFrame { result: false }) => {
+            exit_with_error("The daemon could not apply the setting!")
+        },
+        Some(_) => read_custom_frame(),
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn write_brightness_smooth(ac: usize, val: u8, duration_ms: u32) {
+    match send_data(comms::DaemonCommand::SetBrightnessSmooth { ac, val, duration_ms }) {
+        Some(comms::DaemonResponse::SetBrightnessSmooth { result: false }) => {
+            exit_with_error("The daemon could not apply the setting!")
+        },
+        Some(_) => println!("Fading the brightness to {}% over {} ms", val, duration_ms),
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn read_idle_timeout() {
+    match send_data(comms::DaemonCommand::GetIdleTimeout) {
+        Some(comms::DaemonResponse::GetIdleTimeout { seconds: 0 }) => {
+            println!("Idle timeout: off");
+        },
+        Some(comms::DaemonResponse::GetIdleTimeout { seconds }) => {
+            println!("Idle timeout: {} seconds", seconds);
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
+    }
+}
+
+fn write_idle_timeout(seconds: u32) {
+    match send_data(comms::DaemonCommand::SetIdleTimeout { seconds }) {
+        Some(comms::DaemonResponse::SetIdleTimeout { result: false }) => {
+            exit_with_error("The daemon could not apply the setting!")
+        },
+        Some(_) => read_idle_timeout(),
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn write_cpu_boost(ac: usize, cpu: u8) {
+    match send_data(comms::DaemonCommand::SetCPUBoost { ac, cpu }) {
+        Some(comms::DaemonResponse::SetCPUBoost { result: false }) => {
+            exit_with_error("The daemon could not apply the setting!")
+        },
+        Some(_) => read_power_mode(ac),
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn write_gpu_boost(ac: usize, gpu: u8) {
+    match send_data(comms::DaemonCommand::SetGPUBoost { ac, gpu }) {
+        Some(comms::DaemonResponse::SetGPUBoost { result: false }) => {
+            exit_with_error("The daemon could not apply the setting!")
+        },
+        Some(_) => read_power_mode(ac),
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn dump() {
+    match send_data(comms::DaemonCommand::GetFullState) {
+        Some(comms::DaemonResponse::GetFullState { json }) => {
+            // Sent compact to stay small on the socket, pretty printed for people
+            match serde_json::from_str::<serde_json::Value>(&json) {
+                Ok(state) => println!("{:#}", state),
+                Err(_) => println!("{}", json),
+            }
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
+    }
+}
+
+fn write_layer_brightness(layer: usize, brightness: u8) {
+    match send_data(comms::DaemonCommand::SetLayerBrightness { layer, brightness }) {
+        Some(comms::DaemonResponse::SetLayerBrightness { result: false }) => {
+            exit_with_error("There is no effect layer with this number!")
+        },
+        Some(_) => println!("Layer {} brightness: {}%", layer, brightness),
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn write_key_colour(index: usize, colour: Vec<u8>) {
+    let (r, g, b) = (colour[0], colour[1], colour[2]);
+    match send_data(comms::DaemonCommand::SetKeyColor { index, r, g, b }) {
+        Some(comms::DaemonResponse::SetKeyColor { result: false }) => {
+            exit_with_error("The daemon could not apply the setting!")
+        },
+        Some(_) => {},
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn clear_key_colour(index: usize) {
+    match send_data(comms::DaemonCommand::ClearKeyColor { index }) {
+        Some(comms::DaemonResponse::ClearKeyColor { result: false }) => {
+            exit_with_error("This key has no colour set!")
+        },
+        Some(_) => {},
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn read_gamma() {
+    match send_data(comms::DaemonCommand::GetGamma) {
+        Some(comms::DaemonResponse::GetGamma { value }) => {
+            println!("Gamma: {:.1}", value);
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
+    }
+}
+
+fn write_gamma(value: f32) {
+    match send_data(comms::DaemonCommand::SetGamma { value }) {
+        Some(comms::DaemonResponse::SetGamma { result: false }) => {
+            exit_with_error("Gamma must be between 0.1 and 5.0")
+        },
+        Some(_) => read_gamma(),
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn read_white_balance() {
+    match send_data(comms::DaemonCommand::GetWhiteBalance) {
+        Some(comms::DaemonResponse::GetWhiteBalance { r_mul, g_mul, b_mul }) => {
+            println!("White balance: red {}‰, green {}‰, blue {}‰", r_mul, g_mul, b_mul);
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
+    }
+}
+
+fn write_white_balance(r_mul: u16, g_mul: u16, b_mul: u16) {
+    match send_data(comms::DaemonCommand::SetWhiteBalance { r_mul, g_mul, b_mul }) {
+        Some(comms::DaemonResponse::SetWhiteBalance { result: false }) => {
+            exit_with_error("The white balance values must be between 0 and 1000")
+        },
+        Some(_) => read_white_balance(),
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn read_fan_hysteresis() {
+    match send_data(comms::DaemonCommand::GetFanHysteresis) {
+        Some(comms::DaemonResponse::GetFanHysteresis { celsius }) => {
+            println!("Fan curve hysteresis: {} °C", celsius);
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
+    }
+}
+
+fn write_fan_hysteresis(celsius: u8) {
+    match send_data(comms::DaemonCommand::SetFanHysteresis { celsius }) {
+        Some(comms::DaemonResponse::SetFanHysteresis { result: false }) => {
+            exit_with_error("The daemon could not apply the setting!")
+        },
+        Some(_) => read_fan_hysteresis(),
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn save_preset(name: String) {
+    match send_data(comms::DaemonCommand::SaveEffectPreset { name }) {
+        Some(comms::DaemonResponse::SaveEffectPreset { result: false }) => {
+            exit_with_error("The daemon could not write the preset!")
+        },
+        Some(_) => {},
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn load_preset(name: String) {
+    match send_data(comms::DaemonCommand::LoadEffectPreset { name }) {
+        Some(comms::DaemonResponse::LoadEffectPreset { result: false }) => {
+            exit_with_error("The daemon could not apply the preset!")
+        },
+        Some(_) => {},
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn list_presets() {
+    match send_data(comms::DaemonCommand::ListEffectPresets) {
+        Some(comms::DaemonResponse::ListEffectPresets { names }) => {
+            for name in names {
+                println!("{}", name);
+            }
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
+    }
+}
+
+fn save_profile(name: String) {
+    match send_data(comms::DaemonCommand::SaveCurrentAsProfile { name }) {
+        Some(comms::DaemonResponse::SaveCurrentAsProfile { result: false }) => {
+            exit_with_error("The daemon could not write the profile!")
+        },
+        Some(_) => {},
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn switch_profile(name: String) {
+    match send_data(comms::DaemonCommand::SwitchProfile { name }) {
+        Some(comms::DaemonResponse::SwitchProfile { result: false }) => {
+            exit_with_error("The daemon could not apply the profile!")
+        },
+        Some(_) => {},
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn list_profiles() {
+    match send_data(comms::DaemonCommand::ListProfiles) {
+        Some(comms::DaemonResponse::ListProfiles { names }) => {
+            for name in names {
+                println!("{}", name);
+            }
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
+    }
+}
+
+fn list_effects() {
+    match send_data(comms::DaemonCommand::ListEffects) {
+        Some(comms::DaemonResponse::ListEffects { effects }) => {
+            for (name, command) in effects {
+                println!("{} ({})", name, command);
+            }
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
+    }
+}
+
+fn write_full_frame(file: &str) {
+    let frame = if file == "-" {
+        let mut frame = Vec::new();
+        std::io::Read::read_to_end(&mut std::io::stdin(), &mut frame).map(|_| frame)
+    } else {
+        std::fs::read(file)
+    };
+    let rgb = match frame {
+        Ok(rgb) => rgb,
+        Err(error) => exit_with_error(&format!("Could not read {}: {}", file, error)),
+    };
+    match send_data(comms::DaemonCommand::SetFullFrame { rgb }) {
+        Some(comms::DaemonResponse::SetFullFrame { result: false }) => {
+            exit_with_error("The frame must have 3 bytes per key of every keyboard zone!")
+        },
+        Some(_) => {},
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn resume_effect() {
+    match send_data(comms::DaemonCommand::ResumeEffect) {
+        Some(comms::DaemonResponse::ResumeEffect { result: false }) => {
+            exit_with_error("No frame is shown!")
+        },
+        Some(_) => {},
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn pause_effects() {
+    match send_data(comms::DaemonCommand::PauseEffects) {
+        Some(comms::DaemonResponse::PauseEffects { result: false }) => {
+            exit_with_error("The effects are already paused!")
+        },
+        Some(_) => {},
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn resume_effects() {
+    match send_data(comms::DaemonCommand::ResumeEffects) {
+        Some(comms::DaemonResponse::ResumeEffects { result: false }) => {
+            exit_with_error("The effects are not paused!")
+        },
+        Some(_) => {},
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn read_effect_backend() {
+    match send_data(comms::DaemonCommand::GetEffectBackend) {
+        Some(comms::DaemonResponse::GetEffectBackend { hardware }) => {
+            println!("Effect backend: {}", if hardware { "hardware" } else { "software" });
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
+    }
+}
+
+fn write_effect_backend(hardware: bool) {
+    match send_data(comms::DaemonCommand::SetEffectBackend { hardware }) {
+        Some(comms::DaemonResponse::SetEffectBackend { result: false }) => {
+            exit_with_error("The daemon could not apply the setting!")
+        },
+        Some(_) => read_effect_backend(),
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn read_brightness_curve() {
+    match send_data(comms::DaemonCommand::GetBrightnessCurve) {
+        Some(comms::DaemonResponse::GetBrightnessCurve { enabled }) => {
+            println!("Brightness curve: {}", if enabled { "on" } else { "off" });
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
+    }
+}
+
+fn write_brightness_curve(enabled: bool) {
+    match send_data(comms::DaemonCommand::SetBrightnessCurve { enabled }) {
+        Some(comms::DaemonResponse::SetBrightnessCurve { result: false }) => {
+            exit_with_error("The daemon could not apply the setting!")
+        },
+        Some(_) => read_brightness_curve(),
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn get_devices() -> (Vec<(String, String)>, usize) {
+    match send_data(comms::DaemonCommand::ListDevices) {
+        Some(comms::DaemonResponse::ListDevices { devices, selected }) => (devices, selected),
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
+    }
+}
+
+fn list_devices() {
+    let (devices, selected) = get_devices();
+    for (index, (name, serial)) in devices.iter().enumerate() {
+        let mark = if index == selected { "*" } else { " " };
+        println!("{} {}: {} ({})", mark, index, name, serial);
+    }
+}
+
+fn select_device(device: &str) {
+    let (devices, _) = get_devices();
+    let index = match device.parse::<usize>() {
+        Ok(index) if index < devices.len() => index,
+        _ => match devices.iter().position(|(_, serial)| serial == device) {
+            Some(index) => index,
+            None => exit_with_error("No connected device has this index or serial number!"),
+        },
+    };
+    match send_data(comms::DaemonCommand::SelectDevice { index }) {
+        Some(comms::DaemonResponse::SelectDevice { result: false }) => {
+            exit_with_error("The device is not connected anymore!")
+        },
+        Some(_) => list_devices(),
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn blank_keyboard() {
+    match send_data(comms::DaemonCommand::BlankKeyboard) {
+        Some(comms::DaemonResponse::BlankKeyboard { result: false }) => {
+            exit_with_error("The daemon could not blank the keyboard!")
+        },
+        Some(_) => {},
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn restore_keyboard() {
+    match send_data(comms::DaemonCommand::RestoreKeyboard) {
+        Some(comms::DaemonResponse::RestoreKeyboard { result: false }) => {
+            exit_with_error("The keyboard is not blanked!")
+        },
+        Some(_) => {},
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn read_idle_colour() {
+    match send_data(comms::DaemonCommand::GetIdleColor) {
+        Some(comms::DaemonResponse::GetIdleColor { r, g, b, last_static }) => {
+            print!("Idle colour: {:02x}{:02x}{:02x}", r, g, b);
+            if last_static {
+                print!(" (the last static effect when there was one)");
+            }
+            println!();
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
+    }
+}
+
+fn write_idle_colour(rgb: Vec<u8>) {
+    match send_data(comms::DaemonCommand::SetIdleColor { r: rgb[0], g: rgb[1], b: rgb[2] }) {
+        Some(comms::DaemonResponse::SetIdleColor { result: false }) => {
+            exit_with_error("The daemon could not apply the setting!")
+        },
+        Some(_) => read_idle_colour(),
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn read_logo_sync() {
+    match send_data(comms::DaemonCommand::GetLogoSync) {
+        Some(comms::DaemonResponse::GetLogoSync { enabled }) => {
+            println!("Logo synced with the keyboard: {}", if enabled { "on" } else { "off" });
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
+    }
+}
+
+fn write_logo_sync(enabled: bool) {
+    match send_data(comms::DaemonCommand::SetLogoSync { enabled }) {
+        Some(comms::DaemonResponse::SetLogoSync { result: false }) => {
+            exit_with_error("The daemon could not apply the setting!")
+        },
+        Some(_) => read_logo_sync(),
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn adjust_brightness(ac: usize, delta: i32) {
+    match send_data(comms::DaemonCommand::AdjustBrightness { ac, delta }) {
+        Some(comms::DaemonResponse::AdjustBrightness { result: false, .. }) => {
+            exit_with_error("The daemon could not apply the setting!")
+        },
+        Some(comms::DaemonResponse::AdjustBrightness { val, .. }) => {
+            println!("Current brightness: {}", val);
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn adjust_fan_speed(ac: usize, delta: i32) {
+    match send_data(comms::DaemonCommand::AdjustFanSpeed { ac, delta }) {
+        Some(comms::DaemonResponse::AdjustFanSpeed { result: false, .. }) => {
+            exit_with_error("The daemon could not apply the setting!")
+        },
+        Some(comms::DaemonResponse::AdjustFanSpeed { rpm, .. }) => {
+            println!("Current fan setting: {} RPM", rpm);
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn write_snapshot(file: &str, layer: Option<u16>) {
+    let layer_id = layer.map_or(-1, i32::from);
+    let rgb = match send_data(comms::DaemonCommand::GetRenderedFrame { layer_id }) {
+        Some(comms::DaemonResponse::GetRenderedFrame { rgbdata, .. }) if rgbdata.is_empty() => {
+            exit_with_error("No such effect layer!")
+        },
+        Some(comms::DaemonResponse::GetRenderedFrame { rgbdata, .. }) => rgbdata,
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
+    };
+    let written = if file == "-" {
+        std::io::Write::write_all(&mut std::io::stdout(), &rgb)
+    } else {
+        std::fs::write(file, &rgb)
+    };
+    if let Err(error) = written {
+        exit_with_error(&format!("Could not write {}: {}", file, error));
+    }
+}
+
+fn write_flash(colour: Vec<u8>, duration_ms: u32, count: u8, region: String) {
+    let command = comms::DaemonCommand::Flash { r: colour[0], g: colour[1], b: colour[2], duration_ms, count, region };
+    match send_data(command) {
+        Some(comms::DaemonResponse::Flash { result: false, .. }) => exit_with_error("Flash FAIL!"),
+        Some(comms::DaemonResponse::Flash { duration_ms: shown_ms, count: shown_count, .. }) => {
+            if (shown_ms, shown_count) != (duration_ms, count) {
+                println!("Clamped to {} pulses of {} ms", shown_count, shown_ms);
+            }
+        }
+        Some(comms::DaemonResponse::Error { message }) => exit_with_error(&message),
+        Some(_) => {},
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+/// Colour of a profile LED: "#rrggbb", [r, g, b], {"red", "green", "blue"}
+/// or a number laid out like the RGBColor of OpenRGB (0x00BBGGRR)
+fn profile_colour(value: &serde_json::Value) -> Option<[u8; 3]> {
+    let channel = |value: &serde_json::Value| value.as_u64().and_then(|c| u8::try_from(c).ok());
+    match value {
+        serde_json::Value::String(hex) => comms::parse_hex_colour(hex),
+        serde_json::Value::Array(rgb) if rgb.len() == 3 => {
+            Some([channel(&rgb[0])?, channel(&rgb[1])?, channel(&rgb[2])?])
+        }
+        serde_json::Value::Object(rgb) => {
+            let get = |long: &str, short: &str| rgb.get(long).or_else(|| rgb.get(short)).and_then(channel);
+            Some([get("red", "r")?, get("green", "g")?, get("blue", "b")?])
+        }
+        serde_json::Value::Number(number) => {
+            let bgr = number.as_u64()?;
+            Some([bgr as u8, (bgr >> 8) as u8, (bgr >> 16) as u8])
+        }
+        _ => None,
+    }
+}
+
+/// Reads the (name, colour) pairs of a profile. Takes {"leds": [{"name",
+/// "color"}]}, with the colours in a "colors" list next to "leds" like the
+/// OpenRGB SDK sends them, or an object of names and colours
+fn read_profile(profile: &serde_json::Value) -> Result<Vec<(String, [u8; 3])>, String> {
+    let Some(leds) = profile["leds"].as_array() else {
+        let Some(keys) = profile.as_object() else {
+            return Err(String::from("Expected an object with a \"leds\" list or LED names"));
+        };
+        return keys
+            .iter()
+            .map(|(name, colour)| match profile_colour(colour) {
+                Some(rgb) => Ok((name.clone(), rgb)),
+                None => Err(format!("Invalid colour for {}: {}", name, colour)),
+            })
+            .collect();
+    };
+    let colours = profile["colors"].as_array();
+    leds.iter()
+        .enumerate()
+        .map(|(i, led)| {
+            let name = led["name"].as_str().ok_or(format!("LED {} has no name", i))?;
+            let colour = match colours {
+                Some(colours) => colours.get(i).and_then(profile_colour),
+                None => profile_colour(&led["color"]),
+            };
+            match colour {
+                Some(rgb) => Ok((name.to_string(), rgb)),
+                None => Err(format!("Invalid or missing colour for {}", name)),
+            }
+        })
+        .collect()
+}
+
+fn import_profile(file: &str) {
+    let json = if file == "-" {
+        let mut json = String::new();
+        std::io::Read::read_to_string(&mut std::io::stdin(), &mut json).map(|_| json)
+    } else {
+        std::fs::read_to_string(file)
+    };
+    let json = match json {
+        Ok(json) => json,
+        Err(error) => exit_with_error(&format!("Could not read {}: {}", file, error)),
+    };
+    let keys = match serde_json::from_str(&json).map_err(|e| e.to_string()).and_then(|p| read_profile(&p)) {
+        Ok(keys) => keys,
+        Err(error) => exit_with_error(&format!("Invalid profile {}: {}", file, error)),
+    };
+    let count = keys.len();
+    match send_data(comms::DaemonCommand::ImportKeyColors { keys }) {
+        Some(comms::DaemonResponse::ImportKeyColors { result: false, .. }) => {
+            exit_with_error("The daemon could not show the profile!")
+        },
+        Some(comms::DaemonResponse::ImportKeyColors { unmapped, .. }) => {
+            println!("Imported {} of {} keys", count - unmapped.len(), count);
+            if !unmapped.is_empty() {
+                eprintln!("Not on this keyboard: {}", unmapped.join(", "));
+            }
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn read_battery_saver_lighting() {
+    match send_data(comms::DaemonCommand::GetBatterySaverLighting) {
+        Some(comms::DaemonResponse::GetBatterySaverLighting { enabled }) => {
+            println!("Animations stop on battery: {}", if enabled { "on" } else { "off" });
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
+    }
+}
+
+fn write_battery_saver_lighting(enabled: bool) {
+    match send_data(comms::DaemonCommand::SetBatterySaverLighting { enabled }) {
+        Some(comms::DaemonResponse::SetBatterySaverLighting { result: false }) => {
+            exit_with_error("The daemon could not apply the setting!")
+        },
+        Some(_) => read_battery_saver_lighting(),
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn write_fan_percent(ac: usize, percent: u8) {
+    match send_data(comms::DaemonCommand::SetFanPercent { ac, percent }) {
+        Some(comms::DaemonResponse::SetFanPercent { result: false, .. }) => {
+            exit_with_error("The daemon could not apply the setting!")
+        },
+        Some(comms::DaemonResponse::SetFanPercent { rpm: 0, .. }) => println!("Current fan setting: Auto"),
+        Some(comms::DaemonResponse::SetFanPercent { rpm, .. }) => {
+            println!("Current fan setting: {} RPM", rpm);
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn write_app_profile(process_name: String, profile_name: String) {
+    let removed = profile_name.is_empty();
+    match send_data(comms::DaemonCommand::SetAppProfile { process_name, profile_name }) {
+        Some(comms::DaemonResponse::SetAppProfile { result: true }) if removed => {
+            println!("The process is not watched anymore");
+        },
+        Some(comms::DaemonResponse::SetAppProfile { result: true }) => {
+            println!("The profile is applied while the process runs");
+        },
+        Some(comms::DaemonResponse::SetAppProfile { result: false }) => {
+            exit_with_error("The daemon could not apply the setting!")
+        },
+        Some(comms::DaemonResponse::Error { message }) => exit_with_error(&message),
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn read_effect_transition() {
+    match send_data(comms::DaemonCommand::GetEffectTransition) {
+        Some(comms::DaemonResponse::GetEffectTransition { duration_ms: 0 }) => println!("Effect transition: off"),
+        Some(comms::DaemonResponse::GetEffectTransition { duration_ms }) => {
+            println!("Effect transition: {} ms", duration_ms);
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
+    }
+}
+
+fn write_effect_transition(duration_ms: u32) {
+    match send_data(comms::DaemonCommand::SetEffectTransition { duration_ms }) {
+        Some(comms::DaemonResponse::SetEffectTransition { result: false }) => {
+            exit_with_error("The daemon could not apply the setting!")
+        },
+        Some(_) => read_effect_transition(),
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn read_animation_stats() {
+    match send_data(comms::DaemonCommand::GetAnimationStats) {
+        Some(comms::DaemonResponse::GetAnimationStats { target_fps, measured_fps, dropped_frames }) => {
+            println!("Target frame rate: {} fps", target_fps);
+            println!("Measured frame rate: {:.1} fps", measured_fps);
+            println!("Dropped frames: {}", dropped_frames);
+        },
+        Some(comms::DaemonResponse::Error { message }) => exit_with_error(&message),
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
+    }
+}
+
+fn read_power_profiles() {
+    match send_data(comms::DaemonCommand::ListPowerProfiles) {
+        Some(comms::DaemonResponse::ListPowerProfiles { profiles }) => {
+            for (mode, name) in profiles {
+                println!("{} = {}", mode, name);
+            }
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
+    }
+}
+
+fn read_min_effect_brightness() {
+    match send_data(comms::DaemonCommand::GetMinEffectBrightness) {
+        Some(comms::DaemonResponse::GetMinEffectBrightness { value }) => {
+            println!("Minimum effect brightness: {}%", value);
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
+    }
+}
+
+fn write_min_effect_brightness(value: u8) {
+    match send_data(comms::DaemonCommand::SetMinEffectBrightness { value }) {
+        Some(comms::DaemonResponse::SetMinEffectBrightness { result: false }) => {
+            exit_with_error("The daemon could not apply the setting!")
+        },
+        Some(_) => read_min_effect_brightness(),
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn read_version() {
+    match send_data(comms::DaemonCommand::GetVersion) {
+        Some(comms::DaemonResponse::GetVersion { version, device_path, driver, attributes }) => {
+            println!("Daemon version: {}", version);
+            if device_path.is_empty() {
+                println!("Device: simulated");
+                return;
+            }
+            println!("Device: {}", device_path);
+            println!("Kernel driver: {}", if driver.is_empty() { "none" } else { &driver });
+            println!("Driver attributes: {}", attributes.join(", "));
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
+    }
+}
+
+fn write_lock_indicator(lock: String, colour: Vec<u8>) {
+    let off = colour.iter().all(|c| *c == 0);
+    match send_data(comms::DaemonCommand::SetLockIndicator { lock, r: colour[0], g: colour[1], b: colour[2] }) {
+        Some(comms::DaemonResponse::SetLockIndicator { result: true }) if off => {
+            println!("The lock indicator is off");
+        },
+        Some(comms::DaemonResponse::SetLockIndicator { result: true }) => {
+            println!("The key is lit while the lock is on");
+        },
+        Some(comms::DaemonResponse::SetLockIndicator { result: false }) => {
+            exit_with_error("The daemon could not apply the setting!")
+        },
+        Some(comms::DaemonResponse::Error { message }) => exit_with_error(&message),
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn read_match_info() {
+    match send_data(comms::DaemonCommand::GetMatchInfo) {
+        Some(comms::DaemonResponse::GetMatchInfo { name, vendor_id, product_id, simulated, features, unmatched }) => {
+            match name {
+                Some(name) => {
+                    let ids = match (vendor_id, product_id) {
+                        (Some(vid), Some(pid)) => format!("{:04x}:{:04x}", vid, pid),
+                        _ => String::from("invalid ids"),
+                    };
+                    let how = if simulated { "simulated" } else { "by its USB ids" };
+                    println!("Matched: {} ({}, {})", name, ids, how);
+                    println!("Features: {}", features.join(", "));
+                },
+                None => println!("No device was matched"),
+            }
+            for (vid, pid) in unmatched {
+                println!("Not in the laptops file: {:04x}:{:04x}", vid, pid);
+            }
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
+    }
+}
+
+fn read_layout() {
+    match send_data(comms::DaemonCommand::GetLayout) {
+        Some(comms::DaemonResponse::GetLayout { layout, source }) => {
+            let source = match source.as_str() {
+                "config" => "set in the config",
+                "detected" => "detected from the model",
+                _ => "unknown model, default",
+            };
+            println!("Keyboard layout: {} ({})", layout, source);
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
+    }
+}
+
+fn fan_turbo(duration_secs: u32) {
+    match send_data(comms::DaemonCommand::FanTurbo { duration_secs }) {
+        Some(comms::DaemonResponse::FanTurbo { result: true, prior_rpm: 0 }) => {
+            println!("Fans at full speed for {} s, then back to auto", duration_secs);
+        },
+        Some(comms::DaemonResponse::FanTurbo { result: true, prior_rpm }) => {
+            println!("Fans at full speed for {} s, then back to {} RPM", duration_secs, prior_rpm);
+        },
+        Some(comms::DaemonResponse::FanTurbo { result: false, .. }) => {
+            exit_with_error("The daemon could not apply the setting!")
+        },
+        Some(comms::DaemonResponse::Error { message }) => exit_with_error(&message),
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn random_effect(seed: Option<u64>) {
+    match send_data(comms::DaemonCommand::RandomEffect { seed }) {
+        Some(comms::DaemonResponse::RandomEffect { result: true, name, params, seed }) => {
+            let params: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+            println!("Effect: {} {} (seed {})", name, params.join(" "), seed);
+        },
+        Some(comms::DaemonResponse::RandomEffect { result: false, .. }) => {
+            exit_with_error("The daemon could not apply the setting!")
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown error!"),
+    }
+}
+
+fn read_battery_health() {
+    match send_data(comms::DaemonCommand::GetBatteryHealth) {
+        Some(comms::DaemonResponse::GetBatteryHealth { available: false, .. }) => {
+            println!("Battery health: unavailable");
+        },
+        Some(comms::DaemonResponse::GetBatteryHealth {
+            cycle_count, design_capacity, full_capacity, unit, health_percent, ..
+        }) => {
+            println!("Battery health: {:.0}%", health_percent);
+            println!("Full charge capacity: {} of {} {}", full_capacity, design_capacity, unit);
+            match cycle_count {
+                Some(count) => println!("Charge cycles: {}", count),
+                None => println!("Charge cycles: unknown"),
+            }
+        },
+        Some(_) => exit_with_error("Daemon responded with invalid data!"),
+        None => exit_with_error("Unknown daemon error!"),
     }
 }
@@ -1,16 +1,28 @@
 use serde::{Deserialize, Serialize};
 use std::io::{Read, Write};
 use std::os::unix::net::{UnixListener, UnixStream};
+use std::time::Duration;
 
 /// Razer laptop control socket path
 pub const SOCKET_PATH: &str = "/tmp/razercontrol-socket";
 
+/// Environment variable moving the socket, read by the daemon and the clients
+pub const SOCKET_PATH_ENV: &str = "RAZER_LAPTOP_CONTROL_SOCKET";
+
+/// Longest command or response on the socket, far more than a frame or a
+/// key colour profile of every zone takes
+pub const MAX_MESSAGE_BYTES: usize = 1024 * 1024;
+
+/// Seconds a client waits for the daemon to answer, unless overridden by
+/// the RAZER_LAPTOP_CONTROL_TIMEOUT environment variable (0 waits forever)
+pub const DEFAULT_RESPONSE_TIMEOUT_S: u64 = 5;
+
 #[derive(Serialize, Deserialize, Debug)]
 /// Represents data sent TO the daemon
 pub enum DaemonCommand {
     SetFanSpeed { ac: usize, rpm: i32 },      // Fan speed
     GetFanSpeed { ac: usize },                 // Get (Fan speed)
-    SetPowerMode { ac: usize, pwr: u8, cpu: u8, gpu: u8}, // Power mode
+    SetPowerMode { ac: usize, pwr: u8, cpu: u8, gpu: u8}, // Power mode, boosts must be 0 unless pwr is 4
     GetPwrLevel { ac: usize },                 // Get (Power mode)
     GetCPUBoost { ac: usize },                 // Get (CPU boost)
     GetGPUBoost { ac: usize },                 // Get (GPU boost)
@@ -26,7 +38,93 @@ pub enum DaemonCommand {
     GetSync (),
     SetBatteryHealthOptimizer { is_on: bool, threshold: u8 },
     GetBatteryHealthOptimizer (),
-    GetDeviceName 
+    GetDeviceName,
+    GetEffect,
+    SetAnimationFps { fps: u8 },
+    SetFanCurve { ac: usize, points: Vec<(u8, i32)> }, // (temperature, rpm) points
+    GetFanCurve { ac: usize },
+    GetCpuTemperature,
+    GetGpuTemperature,
+    PushEffectLayer { name: String, params: Vec<u8>, key_mask: Vec<bool> }, // One entry per key of the keyboard zone, 90 on the Blade layout
+    PopEffectLayer,
+    GetEffectLayers,
+    GetBatteryState,
+    SetEffectHex { name: String, colors: Vec<String> }, // #rrggbb colours and decimal bytes (e.g. a duration), answered with SetEffect
+    GetSupportedFeatures,
+    PushEffectLayerRegion { name: String, params: Vec<u8>, region: String }, // answered with PushEffectLayer
+    GetRegions,
+    SetLogoBreathingSpeed { ac: usize, speed: u8 }, // 0 = hardware rate, 1-10 = software breathing
+    GetLogoBreathingSpeed { ac: usize },
+    ReloadConfig,
+    SetAutoSwitch { enabled: bool }, // Apply the settings of the power source when it changes
+    GetAutoSwitch,
+    GetActualFanRpm, // Measured by the tachometers, not the set point
+    PreviewEffect { name: String, params: Vec<u8>, frames: u8 }, // Rendered offscreen, the keyboard is left alone
+    SetCustomFrame { frame: u8 }, // Copies the shown keyboard to stored frame 0-15, 255 stores nothing
+    GetCustomFrame,
+    SetBrightnessSmooth { ac: usize, val: u8, duration_ms: u32 }, // Fades from the current brightness
+    SetIdleTimeout { seconds: u32 }, // Dims the keyboard after this long without a key event, 0 = off
+    GetIdleTimeout,
+    SetCPUBoost { ac: usize, cpu: u8 }, // Only the boost, the power mode is left alone
+    SetGPUBoost { ac: usize, gpu: u8 },
+    GetFullState, // Every setting and reading at once, for bug reports
+    SetLayerBrightness { layer: usize, brightness: u8 }, // Bottom layer is 0, brightness in percent
+    SetKeyColor { index: usize, r: u8, g: u8, b: u8 }, // Index on the keyboard zone, over the other layers
+    ClearKeyColor { index: usize },
+    SetGamma { value: f32 }, // Colour correction of the custom effects, 1.0 = off
+    GetGamma,
+    SetFanHysteresis { celsius: u8 }, // Margin around the last temperature the fan curve acted on
+    GetFanHysteresis,
+    SaveEffectPreset { name: String }, // Stores the current layers under a name
+    LoadEffectPreset { name: String }, // Replaces the current layers
+    ListEffectPresets,
+    ListEffects, // The custom effects of this daemon build
+    SetFullFrame { rgb: Vec<u8> }, // 3 bytes per key of every zone, shown instead of the effects until ResumeEffect
+    ResumeEffect,
+    SetEffectBackend { hardware: bool }, // Run static and breathing effects on the keyboard controller
+    GetEffectBackend,
+    SetBrightnessCurve { enabled: bool }, // Perceptual backlight levels, the percentages stay the same
+    GetBrightnessCurve,
+    ListDevices, // Every connected device, the commands go to the selected one
+    SelectDevice { index: usize },
+    GetPowerSource,
+    BlankKeyboard, // Black until RestoreKeyboard, the effects are kept
+    RestoreKeyboard,
+    SetIdleColor { r: u8, g: u8, b: u8 }, // Shown once the last effect layer is removed
+    GetIdleColor,
+    SetLogoSync { enabled: bool }, // A breathing logo follows the keyboard breathing effect
+    GetLogoSync,
+    AdjustBrightness { ac: usize, delta: i32 }, // Percent, for brightness up/down keys
+    AdjustFanSpeed { ac: usize, delta: i32 }, // RPM, from the slowest speed when automatic
+    GetRenderedFrame { layer_id: i32 }, // Negative for the frame on the keyboard, else a layer
+    Flash { r: u8, g: u8, b: u8, duration_ms: u32, count: u8, region: String }, // Pulses over the effects, then goes away
+    ImportKeyColors { keys: Vec<(String, [u8; 3])> }, // OpenRGB key names, shown like SetFullFrame
+    SetBatterySaverLighting { enabled: bool }, // The animations stand still on battery
+    GetBatterySaverLighting,
+    SetFanPercent { ac: usize, percent: u8 }, // Of the fan range of the device, 0 = Auto
+    SetAppProfile { process_name: String, profile_name: String }, // Applied while the process runs, an empty profile removes it
+    SetEffectTransition { duration_ms: u32 }, // Cross-fade when an effect replaces another, 0 cuts
+    GetEffectTransition,
+    GetAnimationStats,
+    ListPowerProfiles, // Power modes of the selected device
+    SetMinEffectBrightness { value: u8 }, // Percent the effects aren't dimmed below, brightness 0 still turns them off
+    GetMinEffectBrightness,
+    GetVersion,
+    SetLockIndicator { lock: String, r: u8, g: u8, b: u8 }, // capslock, numlock or scrolllock, black turns the indicator off
+    GetLayout,
+    FanTurbo { duration_secs: u32 }, // Full fan speed for a while, then back to the current setting
+    RandomEffect { seed: Option<u64> }, // Seeded from the time when None
+    GetBatteryHealth,
+    DuplicateLayer { layer_id: usize, new_mask: Vec<bool> }, // Copies the effect of a layer onto other keys, on top of the stack
+    PauseEffects, // The keyboard keeps its last frame until ResumeEffects
+    ResumeEffects,
+    SwitchProfile { name: String }, // Power, fan, brightness, logo and effects of a profile saved with SaveCurrentAsProfile
+    ListProfiles,
+    SaveCurrentAsProfile { name: String }, // Letters, digits, '-' and '_', stored apart from the app profiles
+    SetWhiteBalance { r_mul: u16, g_mul: u16, b_mul: u16 }, // Per mille of each channel (0-1000), 1000 = unchanged
+    GetWhiteBalance,
+    GetMatchInfo,
+    OnDevice { device: String, command: Box<DaemonCommand> }, // Runs command on the device with this index or serial number, the selected one stays selected
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -41,8 +139,8 @@ pub enum DaemonResponse {
     GetGPUBoost { gpu: u8 },                         // Get (GPU boost)
     SetLogoLedState {result: bool },
     GetLogoLedState { logo_state: u8 },
-    GetKeyboardRGB { layer: i32, rgbdata: Vec<u8> }, // Response (RGB) of 90 keys
-    SetEffect { result: bool },                       // Set keyboard colour
+    GetKeyboardRGB { layer: i32, rgbdata: Vec<u8> }, // Response (RGB) of every key, empty for a missing layer
+    SetEffect { result: bool, params: Vec<u8> },      // Set keyboard colour, params as run with the timing args clamped
     SetStandardEffect { result: bool },                       // Set keyboard colour
     SetBrightness { result: bool },
     SetIdle { result: bool },
@@ -51,12 +149,148 @@ pub enum DaemonResponse {
     GetSync { sync: bool },
     SetBatteryHealthOptimizer { result: bool },
     GetBatteryHealthOptimizer { is_on: bool, threshold: u8 },
-    GetDeviceName { name: String }
+    GetDeviceName { name: String },
+    GetEffect { name: String, params: Vec<u8> },
+    SetAnimationFps { fps: u8 },
+    SetFanCurve { result: bool },
+    GetFanCurve { points: Vec<(u8, i32)> },
+    GetTemperature { celsius: f32 },
+    PushEffectLayer { result: bool },
+    PopEffectLayer { result: bool },
+    GetEffectLayers { names: Vec<String> },                 // Bottom layer first
+    GetBatteryState { capacity: u8, charging: bool },        // Capacity in percent
+    GetSupportedFeatures { features: Vec<String>, fan_min: u16, fan_max: u16, fan_step: u16, has_logo: bool, can_boost: bool },
+    GetRegions { names: Vec<String> },
+    SetLogoBreathingSpeed { result: bool },
+    GetLogoBreathingSpeed { speed: u8 },
+    ReloadConfig { result: bool },
+    SetAutoSwitch { result: bool },
+    GetAutoSwitch { enabled: bool },
+    GetActualFanRpm { rpm1: i32, rpm2: i32 },        // -1 for a missing fan
+    PreviewEffect { rgbdata: Vec<u8> },              // RGB of every key, empty for an invalid effect
+    SetCustomFrame { result: bool },
+    GetCustomFrame { frame: u8 },
+    Error { message: String },                       // The command failed, with the reason
+    SetBrightnessSmooth { result: bool },
+    SetIdleTimeout { result: bool },
+    GetIdleTimeout { seconds: u32 },
+    SetCPUBoost { result: bool },
+    SetGPUBoost { result: bool },
+    GetFullState { json: String },                   // JSON object, see `razer-cli dump`
+    SetLayerBrightness { result: bool },
+    SetKeyColor { result: bool },
+    ClearKeyColor { result: bool },                  // false when the key had no colour
+    SetGamma { result: bool },
+    GetGamma { value: f32 },
+    SetFanHysteresis { result: bool },
+    GetFanHysteresis { celsius: u8 },
+    SaveEffectPreset { result: bool },
+    LoadEffectPreset { result: bool },
+    ListEffectPresets { names: Vec<String> },
+    ListEffects { effects: Vec<(String, String)> },  // (name shown and saved, name for SetEffect)
+    SetFullFrame { result: bool },
+    ResumeEffect { result: bool },                   // false when no frame was shown
+    SetEffectBackend { result: bool },
+    GetEffectBackend { hardware: bool },
+    SetBrightnessCurve { result: bool },
+    GetBrightnessCurve { enabled: bool },
+    ListDevices { devices: Vec<(String, String)>, selected: usize }, // Name and serial number
+    SelectDevice { result: bool },
+    GetPowerSource { source: String }, // "AC", "BAT" or "UNK" without a device
+    BlankKeyboard { result: bool },
+    RestoreKeyboard { result: bool },
+    SetIdleColor { result: bool },
+    GetIdleColor { r: u8, g: u8, b: u8, last_static: bool },
+    SetLogoSync { result: bool },
+    GetLogoSync { enabled: bool },
+    AdjustBrightness { result: bool, val: u8 }, // The new brightness
+    AdjustFanSpeed { result: bool, rpm: i32 }, // The new fan speed
+    GetRenderedFrame { layer_id: i32, rgbdata: Vec<u8> }, // RGB of every key, empty for a missing layer
+    Flash { result: bool, duration_ms: u32, count: u8 }, // Pulses as shown, clamped to the supported range
+    GetGpuTemperature { celsius: Option<f32>, source: String }, // "nvidia-smi" or "hwmon", "powered-down" or "none" without a reading
+    ImportKeyColors { result: bool, unmapped: Vec<String> }, // Names the keyboard has no key for
+    SetBatterySaverLighting { result: bool },
+    GetBatterySaverLighting { enabled: bool },
+    SetFanPercent { result: bool, rpm: i32 }, // The RPM the percentage maps to, 0 = Auto
+    SetAppProfile { result: bool },
+    SetEffectTransition { result: bool },
+    GetEffectTransition { duration_ms: u32 },
+    GetAnimationStats { target_fps: u8, measured_fps: f32, dropped_frames: u64 }, // Since the last effect was set
+    ListPowerProfiles { profiles: Vec<(u8, String)> }, // The value SetPowerMode takes and the name
+    SetMinEffectBrightness { result: bool },
+    GetMinEffectBrightness { value: u8 },
+    GetVersion { version: String, device_path: String, driver: String, attributes: Vec<String> }, // Kernel driver of the hidraw node and its sysfs attributes
+    SetLockIndicator { result: bool },
+    GetLayout { layout: String, source: String }, // ansi, iso, ansi_numpad or iso_numpad, from the config, detected or the default
+    FanTurbo { result: bool, prior_rpm: i32 }, // The speed the fans go back to, 0 = auto
+    RandomEffect { result: bool, name: String, params: Vec<u8>, seed: u64 },
+    // Capacities in mAh or mWh as told by unit, all 0 when not available
+    GetBatteryHealth {
+        available: bool,
+        cycle_count: Option<u32>,
+        design_capacity: u32,
+        full_capacity: u32,
+        unit: String,
+        health_percent: f32,
+    },
+    DuplicateLayer { result: bool },
+    PauseEffects { result: bool },  // false when already paused
+    ResumeEffects { result: bool }, // false when not paused
+    SwitchProfile { result: bool },
+    ListProfiles { names: Vec<String> }, // The profiles SwitchProfile takes, not the app profiles
+    SaveCurrentAsProfile { result: bool },
+    SetWhiteBalance { result: bool },
+    GetWhiteBalance { r_mul: u16, g_mul: u16, b_mul: u16 },
+    // Entry of the laptops file the selected device was matched with, name is
+    // None when nothing matched. simulated is true for the --simulate stand-in.
+    // unmatched holds the USB ids of the Razer devices that have no entry
+    GetMatchInfo {
+        name: Option<String>,
+        vendor_id: Option<u16>,
+        product_id: Option<u16>,
+        simulated: bool,
+        features: Vec<String>,
+        unmatched: Vec<(u16, u16)>,
+    },
+}
+
+/// Parses a `#rrggbb` or `rrggbb` colour
+#[allow(dead_code)]
+pub fn parse_hex_colour(hex: &str) -> Option<[u8; 3]> {
+    let hex = hex.trim().trim_start_matches('#');
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let mut rgb = [0u8; 3];
+    for (i, channel) in rgb.iter_mut().enumerate() {
+        *channel = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+
+    Some(rgb)
+}
+
+/// Parses an argument of `SetEffectHex`, either a `#rrggbb` or `rrggbb`
+/// colour or a single decimal byte such as a speed or duration
+#[allow(dead_code)]
+pub fn parse_hex_param(arg: &str) -> Option<Vec<u8>> {
+    match parse_hex_colour(arg) {
+        Some(rgb) => Some(rgb.to_vec()),
+        None => arg.trim().parse::<u8>().ok().map(|byte| vec![byte]),
+    }
+}
+
+/// The socket from RAZER_LAPTOP_CONTROL_SOCKET, `SOCKET_PATH` when it isn't set
+#[allow(dead_code)]
+pub fn socket_path() -> String {
+    match std::env::var(SOCKET_PATH_ENV) {
+        Ok(path) if !path.trim().is_empty() => path.trim().to_string(),
+        _ => String::from(SOCKET_PATH),
+    }
 }
 
 #[allow(dead_code)]
 pub fn bind() -> Option<UnixStream> {
-    if let Ok(socket) = UnixStream::connect(SOCKET_PATH) {
+    if let Ok(socket) = UnixStream::connect(socket_path()) {
         return Some(socket);
     } else {
         return None;
@@ -66,48 +300,147 @@ pub fn bind() -> Option<UnixStream> {
 #[allow(dead_code)]
 /// We use this from the app, but it should replace bind
 pub fn try_bind() -> std::io::Result<UnixStream> {
-    UnixStream::connect(SOCKET_PATH)
+    UnixStream::connect(socket_path())
+}
+
+/// Looks up a group by name through the system's group database, so LDAP
+/// or systemd groups are found too. Numeric ids are taken as they are
+#[allow(dead_code)]
+fn group_id(group: &str) -> Option<u32> {
+    if let Ok(gid) = group.parse() {
+        return Some(gid);
+    }
+    let name = std::ffi::CString::new(group).ok()?;
+    // SAFETY: all-zero is a valid value of this plain C struct
+    let mut entry: libc::group = unsafe { std::mem::zeroed() };
+    let mut buffer = vec![0 as libc::c_char; 16 * 1024];
+    let mut found = std::ptr::null_mut();
+    // SAFETY: every pointer outlives the call and the length of the buffer
+    // is passed along with it
+    let err = unsafe {
+        libc::getgrnam_r(name.as_ptr(), &mut entry, buffer.as_mut_ptr(), buffer.len(), &mut found)
+    };
+    if err != 0 || found.is_null() {
+        return None;
+    }
+
+    Some(entry.gr_gid)
 }
 
+/// Mode of the socket unless configured, only the daemon user and its group
+/// may connect
+pub const DEFAULT_SOCKET_MODE: u32 = 0o660;
+
+/// Creates the socket. `mode` (`DEFAULT_SOCKET_MODE` if None) and `group`
+/// set who may connect, an empty group keeps the group of the daemon user
 #[allow(dead_code)]
-pub fn create() -> Option<UnixListener> {
-    if let Ok(_) = std::fs::metadata(SOCKET_PATH) {
+pub fn create(mode: Option<u32>, group: &str) -> Option<UnixListener> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = socket_path();
+    if std::fs::metadata(&path).is_ok() {
         eprintln!("UNIX Socket already exists. Is another daemon running?");
         return None;
     }
-    if let Ok(listener) = UnixListener::bind(SOCKET_PATH) {
-        let mut perms = std::fs::metadata(SOCKET_PATH).unwrap().permissions();
-        perms.set_readonly(false);
-        if std::fs::set_permissions(SOCKET_PATH, perms).is_err() {
+    if let Ok(listener) = UnixListener::bind(&path) {
+        let perms = std::fs::Permissions::from_mode(mode.unwrap_or(DEFAULT_SOCKET_MODE));
+        if std::fs::set_permissions(&path, perms).is_err() {
             eprintln!("Could not set socket permissions");
             return None;
         }
+        if !group.is_empty() {
+            let Some(gid) = group_id(group) else {
+                eprintln!("Unknown socket group {}", group);
+                return None;
+            };
+            if let Err(e) = std::os::unix::fs::chown(&path, None, Some(gid)) {
+                eprintln!("Could not give the socket to group {}: {}", group, e);
+                return None;
+            }
+        }
         return Some(listener);
     }
     return None;
 }
 
+/// Why `send_to_daemon` has no response
+#[allow(dead_code)]
+#[derive(Debug)]
+pub enum SendError {
+    /// The daemon didn't answer within `response_timeout`, it may be stuck
+    /// on a write to the device
+    Timeout,
+    /// The command couldn't be sent or the response couldn't be read
+    Failed,
+}
+
+/// How long a client waits for the daemon, None to wait forever
+#[allow(dead_code)]
+pub fn response_timeout() -> Option<Duration> {
+    let seconds = std::env::var("RAZER_LAPTOP_CONTROL_TIMEOUT")
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(DEFAULT_RESPONSE_TIMEOUT_S);
+    if seconds == 0 {
+        return None;
+    }
+
+    Some(Duration::from_secs(seconds))
+}
+
+/// Writes a command or response with its length in front, as a little
+/// endian u32, so the other side knows when it has all of it
+pub fn write_message(stream: &mut impl Write, bytes: &[u8]) -> std::io::Result<()> {
+    if bytes.len() > MAX_MESSAGE_BYTES {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "The message is too long"));
+    }
+    stream.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    stream.write_all(bytes)
+}
+
+/// Reads a message written by `write_message`, however many reads it takes
+pub fn read_message(stream: &mut impl Read) -> std::io::Result<Vec<u8>> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_MESSAGE_BYTES {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "The message is too long"));
+    }
+    let mut bytes = vec![0u8; len];
+    stream.read_exact(&mut bytes)?;
+
+    Ok(bytes)
+}
+
 #[allow(dead_code)]
-pub fn send_to_daemon(command: DaemonCommand, mut sock: UnixStream) -> Option<DaemonResponse> {
+pub fn send_to_daemon(command: DaemonCommand, mut sock: UnixStream) -> Result<DaemonResponse, SendError> {
+    let timeout = response_timeout();
+    if sock.set_read_timeout(timeout).is_err() || sock.set_write_timeout(timeout).is_err() {
+        eprintln!("Could not set the socket timeout");
+    }
     if let Ok(encoded) = bincode::serialize(&command) {
-        if sock.write_all(&encoded).is_ok() {
-            let mut buf = [0u8; 4096];
-            return match sock.read(&mut buf) {
-                Ok(readed) if readed > 0 => read_from_socked_resp(&buf[0..readed]),
-                Ok(_) => {
+        if write_message(&mut sock, &encoded).is_ok() {
+            return match read_message(&mut sock) {
+                Ok(bytes) => read_from_socked_resp(&bytes).ok_or(SendError::Failed),
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
                     eprintln!("No response from daemon");
-                    None
+                    Err(SendError::Failed)
+                }
+                // Unix sockets report an expired timeout as WouldBlock
+                Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {
+                    eprintln!("The daemon did not answer in time");
+                    Err(SendError::Timeout)
                 }
                 Err(_) => {
                     eprintln!("Read failed!");
-                    None
+                    Err(SendError::Failed)
                 }
             };
         } else {
             eprintln!("Socket write failed!");
         }
     }
-    return None;
+    Err(SendError::Failed)
 }
 
 /// Deserializes incomming bytes in order to return
@@ -140,3 +473,394 @@ pub fn read_from_socket_req(bytes: &[u8]) -> Option<DaemonCommand> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::thread;
+
+    /// One command of every variant
+    fn sample_commands() -> Vec<DaemonCommand> {
+        vec![
+            DaemonCommand::SetFanSpeed { ac: 1, rpm: 3500 },
+            DaemonCommand::GetFanSpeed { ac: 0 },
+            DaemonCommand::SetPowerMode { ac: 1, pwr: 4, cpu: 2, gpu: 1 },
+            DaemonCommand::GetPwrLevel { ac: 1 },
+            DaemonCommand::GetCPUBoost { ac: 1 },
+            DaemonCommand::GetGPUBoost { ac: 0 },
+            DaemonCommand::SetLogoLedState { ac: 1, logo_state: 2 },
+            DaemonCommand::GetLogoLedState { ac: 1 },
+            DaemonCommand::GetKeyboardRGB { layer: -1 },
+            DaemonCommand::SetEffect { name: String::from("static"), params: vec![255, 0, 0] },
+            DaemonCommand::SetStandardEffect { name: String::from("wave"), params: vec![1] },
+            DaemonCommand::SetBrightness { ac: 1, val: 80 },
+            DaemonCommand::SetIdle { ac: 0, val: 300 },
+            DaemonCommand::GetBrightness { ac: 1 },
+            DaemonCommand::SetSync { sync: true },
+            DaemonCommand::GetSync(),
+            DaemonCommand::SetBatteryHealthOptimizer { is_on: true, threshold: 80 },
+            DaemonCommand::GetBatteryHealthOptimizer(),
+            DaemonCommand::GetDeviceName,
+            DaemonCommand::GetEffect,
+            DaemonCommand::SetAnimationFps { fps: 30 },
+            DaemonCommand::SetFanCurve { ac: 1, points: vec![(50, 3000), (80, 5000)] },
+            DaemonCommand::GetFanCurve { ac: 1 },
+            DaemonCommand::GetCpuTemperature,
+            DaemonCommand::GetGpuTemperature,
+            DaemonCommand::PushEffectLayer { name: String::from("static"), params: vec![0, 0, 255], key_mask: vec![true, false, true] },
+            DaemonCommand::PopEffectLayer,
+            DaemonCommand::GetEffectLayers,
+            DaemonCommand::GetBatteryState,
+            DaemonCommand::SetEffectHex { name: String::from("static"), colors: vec![String::from("#ff8000")] },
+            DaemonCommand::GetSupportedFeatures,
+            DaemonCommand::PushEffectLayerRegion { name: String::from("static"), params: vec![0, 255, 0], region: String::from("wasd") },
+            DaemonCommand::GetRegions,
+            DaemonCommand::SetLogoBreathingSpeed { ac: 1, speed: 5 },
+            DaemonCommand::GetLogoBreathingSpeed { ac: 1 },
+            DaemonCommand::ReloadConfig,
+            DaemonCommand::SetAutoSwitch { enabled: false },
+            DaemonCommand::GetAutoSwitch,
+            DaemonCommand::GetActualFanRpm,
+            DaemonCommand::PreviewEffect { name: String::from("static"), params: vec![255, 255, 255], frames: 2 },
+            DaemonCommand::SetCustomFrame { frame: 3 },
+            DaemonCommand::GetCustomFrame,
+            DaemonCommand::SetBrightnessSmooth { ac: 1, val: 40, duration_ms: 500 },
+            DaemonCommand::SetIdleTimeout { seconds: 60 },
+            DaemonCommand::GetIdleTimeout,
+            DaemonCommand::SetCPUBoost { ac: 1, cpu: 3 },
+            DaemonCommand::SetGPUBoost { ac: 1, gpu: 2 },
+            DaemonCommand::GetFullState,
+            DaemonCommand::SetLayerBrightness { layer: 1, brightness: 50 },
+            DaemonCommand::SetKeyColor { index: 17, r: 1, g: 2, b: 3 },
+            DaemonCommand::ClearKeyColor { index: 17 },
+            DaemonCommand::SetGamma { value: 2.2 },
+            DaemonCommand::GetGamma,
+            DaemonCommand::SetFanHysteresis { celsius: 3 },
+            DaemonCommand::GetFanHysteresis,
+            DaemonCommand::SaveEffectPreset { name: String::from("gaming") },
+            DaemonCommand::LoadEffectPreset { name: String::from("gaming") },
+            DaemonCommand::ListEffectPresets,
+            DaemonCommand::ListEffects,
+            DaemonCommand::SetFullFrame { rgb: vec![10; 270] },
+            DaemonCommand::ResumeEffect,
+            DaemonCommand::SetEffectBackend { hardware: true },
+            DaemonCommand::GetEffectBackend,
+            DaemonCommand::SetBrightnessCurve { enabled: true },
+            DaemonCommand::GetBrightnessCurve,
+            DaemonCommand::ListDevices,
+            DaemonCommand::SelectDevice { index: 1 },
+            DaemonCommand::OnDevice { device: String::from("1"), command: Box::new(DaemonCommand::GetPowerSource) },
+            DaemonCommand::GetPowerSource,
+            DaemonCommand::BlankKeyboard,
+            DaemonCommand::RestoreKeyboard,
+            DaemonCommand::SetIdleColor { r: 20, g: 0, b: 40 },
+            DaemonCommand::GetIdleColor,
+            DaemonCommand::SetLogoSync { enabled: true },
+            DaemonCommand::GetLogoSync,
+            DaemonCommand::AdjustBrightness { ac: 1, delta: -10 },
+            DaemonCommand::AdjustFanSpeed { ac: 0, delta: 500 },
+            DaemonCommand::GetRenderedFrame { layer_id: -1 },
+            DaemonCommand::Flash { r: 255, g: 0, b: 0, duration_ms: 300, count: 2, region: String::from("all") },
+            DaemonCommand::ImportKeyColors { keys: vec![(String::from("Key: A"), [255, 0, 0])] },
+            DaemonCommand::SetBatterySaverLighting { enabled: true },
+            DaemonCommand::GetBatterySaverLighting,
+            DaemonCommand::SetFanPercent { ac: 1, percent: 60 },
+            DaemonCommand::SetAppProfile { process_name: String::from("game.exe"), profile_name: String::from("gaming") },
+            DaemonCommand::SetEffectTransition { duration_ms: 500 },
+            DaemonCommand::GetEffectTransition,
+            DaemonCommand::GetAnimationStats,
+            DaemonCommand::ListPowerProfiles,
+            DaemonCommand::SetMinEffectBrightness { value: 20 },
+            DaemonCommand::GetMinEffectBrightness,
+            DaemonCommand::GetVersion,
+            DaemonCommand::SetLockIndicator { lock: String::from("capslock"), r: 255, g: 255, b: 255 },
+            DaemonCommand::GetLayout,
+            DaemonCommand::FanTurbo { duration_secs: 30 },
+            DaemonCommand::RandomEffect { seed: Some(42) },
+            DaemonCommand::GetBatteryHealth,
+            DaemonCommand::DuplicateLayer { layer_id: 0, new_mask: vec![false, true, true] },
+            DaemonCommand::PauseEffects,
+            DaemonCommand::ResumeEffects,
+            DaemonCommand::SwitchProfile { name: String::from("gaming") },
+            DaemonCommand::ListProfiles,
+            DaemonCommand::SaveCurrentAsProfile { name: String::from("work") },
+            DaemonCommand::SetWhiteBalance { r_mul: 1000, g_mul: 900, b_mul: 800 },
+            DaemonCommand::GetWhiteBalance,
+            DaemonCommand::GetMatchInfo,
+        ]
+    }
+
+    /// The answer of the daemon to a command, in the variant the clients
+    /// match on. No wildcard, a new command doesn't build until it is added.
+    fn expected_response(command: &DaemonCommand) -> DaemonResponse {
+        match command {
+            DaemonCommand::SetFanSpeed { .. } => DaemonResponse::SetFanSpeed { result: true },
+            DaemonCommand::GetFanSpeed { .. } => DaemonResponse::GetFanSpeed { rpm: 0 },
+            DaemonCommand::SetPowerMode { .. } => DaemonResponse::SetPowerMode { result: true },
+            DaemonCommand::GetPwrLevel { .. } => DaemonResponse::GetPwrLevel { pwr: 4 },
+            DaemonCommand::GetCPUBoost { .. } => DaemonResponse::GetCPUBoost { cpu: 2 },
+            DaemonCommand::GetGPUBoost { .. } => DaemonResponse::GetGPUBoost { gpu: 1 },
+            DaemonCommand::SetLogoLedState { .. } => DaemonResponse::SetLogoLedState { result: true },
+            DaemonCommand::GetLogoLedState { .. } => DaemonResponse::GetLogoLedState { logo_state: 1 },
+            DaemonCommand::GetKeyboardRGB { layer } => DaemonResponse::GetKeyboardRGB { layer: *layer, rgbdata: vec![255; 12] },
+            DaemonCommand::SetEffect { params, .. } => DaemonResponse::SetEffect { result: true, params: params.clone() },
+            DaemonCommand::SetStandardEffect { .. } => DaemonResponse::SetStandardEffect { result: true },
+            DaemonCommand::SetBrightness { .. } => DaemonResponse::SetBrightness { result: true },
+            DaemonCommand::SetIdle { .. } => DaemonResponse::SetIdle { result: true },
+            DaemonCommand::GetBrightness { .. } => DaemonResponse::GetBrightness { result: 80 },
+            DaemonCommand::SetSync { .. } => DaemonResponse::SetSync { result: true },
+            DaemonCommand::GetSync() => DaemonResponse::GetSync { sync: false },
+            DaemonCommand::SetBatteryHealthOptimizer { .. } => DaemonResponse::SetBatteryHealthOptimizer { result: true },
+            DaemonCommand::GetBatteryHealthOptimizer() => DaemonResponse::GetBatteryHealthOptimizer { is_on: true, threshold: 80 },
+            DaemonCommand::GetDeviceName => DaemonResponse::GetDeviceName { name: String::from("Blade 15") },
+            DaemonCommand::GetEffect => DaemonResponse::GetEffect { name: String::from("static"), params: vec![255, 0, 0] },
+            DaemonCommand::SetAnimationFps { fps } => DaemonResponse::SetAnimationFps { fps: *fps },
+            DaemonCommand::SetFanCurve { .. } => DaemonResponse::SetFanCurve { result: true },
+            DaemonCommand::GetFanCurve { .. } => DaemonResponse::GetFanCurve { points: vec![(60, 3500)] },
+            DaemonCommand::GetCpuTemperature => DaemonResponse::GetTemperature { celsius: 55.5 },
+            DaemonCommand::GetGpuTemperature => {
+                DaemonResponse::GetGpuTemperature { celsius: Some(48.0), source: String::from("nvidia-smi") }
+            }
+            DaemonCommand::PushEffectLayer { .. } => DaemonResponse::PushEffectLayer { result: true },
+            DaemonCommand::PopEffectLayer => DaemonResponse::PopEffectLayer { result: true },
+            DaemonCommand::GetEffectLayers => DaemonResponse::GetEffectLayers { names: vec![String::from("static")] },
+            DaemonCommand::GetBatteryState => DaemonResponse::GetBatteryState { capacity: 87, charging: true },
+            DaemonCommand::SetEffectHex { .. } => DaemonResponse::SetEffect { result: true, params: vec![255, 0, 0] },
+            DaemonCommand::GetSupportedFeatures => DaemonResponse::GetSupportedFeatures {
+                features: vec![String::from("fan"), String::from("logo")],
+                fan_min: 3500,
+                fan_max: 5000,
+                fan_step: 100,
+                has_logo: true,
+                can_boost: true,
+            },
+            DaemonCommand::PushEffectLayerRegion { .. } => DaemonResponse::PushEffectLayer { result: true },
+            DaemonCommand::GetRegions => DaemonResponse::GetRegions { names: vec![String::from("wasd")] },
+            DaemonCommand::SetLogoBreathingSpeed { .. } => DaemonResponse::SetLogoBreathingSpeed { result: true },
+            DaemonCommand::GetLogoBreathingSpeed { .. } => DaemonResponse::GetLogoBreathingSpeed { speed: 5 },
+            DaemonCommand::ReloadConfig => DaemonResponse::ReloadConfig { result: true },
+            DaemonCommand::SetAutoSwitch { .. } => DaemonResponse::SetAutoSwitch { result: true },
+            DaemonCommand::GetAutoSwitch => DaemonResponse::GetAutoSwitch { enabled: true },
+            DaemonCommand::GetActualFanRpm => DaemonResponse::GetActualFanRpm { rpm1: 3600, rpm2: -1 },
+            DaemonCommand::PreviewEffect { .. } => DaemonResponse::PreviewEffect { rgbdata: vec![0; 6] },
+            DaemonCommand::SetCustomFrame { .. } => DaemonResponse::SetCustomFrame { result: true },
+            DaemonCommand::GetCustomFrame => DaemonResponse::GetCustomFrame { frame: 255 },
+            DaemonCommand::SetBrightnessSmooth { .. } => DaemonResponse::SetBrightnessSmooth { result: true },
+            DaemonCommand::SetIdleTimeout { .. } => DaemonResponse::SetIdleTimeout { result: true },
+            DaemonCommand::GetIdleTimeout => DaemonResponse::GetIdleTimeout { seconds: 60 },
+            DaemonCommand::SetCPUBoost { .. } => DaemonResponse::SetCPUBoost { result: true },
+            DaemonCommand::SetGPUBoost { .. } => DaemonResponse::SetGPUBoost { result: true },
+            DaemonCommand::GetFullState => DaemonResponse::GetFullState { json: String::from("{}") },
+            DaemonCommand::SetLayerBrightness { .. } => DaemonResponse::SetLayerBrightness { result: true },
+            DaemonCommand::SetKeyColor { .. } => DaemonResponse::SetKeyColor { result: true },
+            DaemonCommand::ClearKeyColor { .. } => DaemonResponse::ClearKeyColor { result: false },
+            DaemonCommand::SetGamma { .. } => DaemonResponse::SetGamma { result: true },
+            DaemonCommand::GetGamma => DaemonResponse::GetGamma { value: 1.0 },
+            DaemonCommand::SetFanHysteresis { .. } => DaemonResponse::SetFanHysteresis { result: true },
+            DaemonCommand::GetFanHysteresis => DaemonResponse::GetFanHysteresis { celsius: 3 },
+            DaemonCommand::SaveEffectPreset { .. } => DaemonResponse::SaveEffectPreset { result: true },
+            DaemonCommand::LoadEffectPreset { .. } => DaemonResponse::LoadEffectPreset { result: true },
+            DaemonCommand::ListEffectPresets => DaemonResponse::ListEffectPresets { names: vec![String::from("gaming")] },
+            DaemonCommand::ListEffects => DaemonResponse::ListEffects {
+                effects: vec![(String::from("Static"), String::from("static"))],
+            },
+            DaemonCommand::SetFullFrame { .. } => DaemonResponse::SetFullFrame { result: true },
+            DaemonCommand::ResumeEffect => DaemonResponse::ResumeEffect { result: true },
+            DaemonCommand::SetEffectBackend { .. } => DaemonResponse::SetEffectBackend { result: true },
+            DaemonCommand::GetEffectBackend => DaemonResponse::GetEffectBackend { hardware: false },
+            DaemonCommand::SetBrightnessCurve { .. } => DaemonResponse::SetBrightnessCurve { result: true },
+            DaemonCommand::GetBrightnessCurve => DaemonResponse::GetBrightnessCurve { enabled: true },
+            DaemonCommand::ListDevices => DaemonResponse::ListDevices {
+                devices: vec![(String::from("Blade 15"), String::from("ABC123"))],
+                selected: 0,
+            },
+            DaemonCommand::SelectDevice { .. } => DaemonResponse::SelectDevice { result: true },
+            // Answered by the command it wraps
+            DaemonCommand::OnDevice { command, .. } => expected_response(command),
+            DaemonCommand::GetPowerSource => DaemonResponse::GetPowerSource { source: String::from("AC") },
+            DaemonCommand::BlankKeyboard => DaemonResponse::BlankKeyboard { result: true },
+            DaemonCommand::RestoreKeyboard => DaemonResponse::RestoreKeyboard { result: true },
+            DaemonCommand::SetIdleColor { .. } => DaemonResponse::SetIdleColor { result: true },
+            DaemonCommand::GetIdleColor => DaemonResponse::GetIdleColor { r: 20, g: 0, b: 40, last_static: false },
+            DaemonCommand::SetLogoSync { .. } => DaemonResponse::SetLogoSync { result: true },
+            DaemonCommand::GetLogoSync => DaemonResponse::GetLogoSync { enabled: true },
+            DaemonCommand::AdjustBrightness { .. } => DaemonResponse::AdjustBrightness { result: true, val: 70 },
+            DaemonCommand::AdjustFanSpeed { .. } => DaemonResponse::AdjustFanSpeed { result: true, rpm: 4000 },
+            DaemonCommand::GetRenderedFrame { layer_id } => {
+                DaemonResponse::GetRenderedFrame { layer_id: *layer_id, rgbdata: vec![0; 270] }
+            }
+            DaemonCommand::Flash { duration_ms, count, .. } => DaemonResponse::Flash { result: true, duration_ms: *duration_ms, count: *count },
+            DaemonCommand::ImportKeyColors { .. } => {
+                DaemonResponse::ImportKeyColors { result: true, unmapped: vec![String::from("Key: Numpad 1")] }
+            }
+            DaemonCommand::SetBatterySaverLighting { .. } => DaemonResponse::SetBatterySaverLighting { result: true },
+            DaemonCommand::GetBatterySaverLighting => DaemonResponse::GetBatterySaverLighting { enabled: true },
+            DaemonCommand::SetFanPercent { .. } => DaemonResponse::SetFanPercent { result: true, rpm: 4000 },
+            DaemonCommand::SetAppProfile { .. } => DaemonResponse::SetAppProfile { result: true },
+            DaemonCommand::SetEffectTransition { .. } => DaemonResponse::SetEffectTransition { result: true },
+            DaemonCommand::GetEffectTransition => DaemonResponse::GetEffectTransition { duration_ms: 500 },
+            DaemonCommand::SetMinEffectBrightness { .. } => DaemonResponse::SetMinEffectBrightness { result: true },
+            DaemonCommand::GetMinEffectBrightness => DaemonResponse::GetMinEffectBrightness { value: 20 },
+            DaemonCommand::GetVersion => DaemonResponse::GetVersion {
+                version: String::from("0.2.0"),
+                device_path: String::from("/dev/hidraw0"),
+                driver: String::from("hid-generic"),
+                attributes: vec![String::from("uevent")],
+            },
+            DaemonCommand::SetLockIndicator { .. } => DaemonResponse::SetLockIndicator { result: true },
+            DaemonCommand::GetLayout => {
+                DaemonResponse::GetLayout { layout: String::from("ansi"), source: String::from("detected") }
+            }
+            DaemonCommand::FanTurbo { .. } => DaemonResponse::FanTurbo { result: true, prior_rpm: 0 },
+            DaemonCommand::RandomEffect { .. } => DaemonResponse::RandomEffect {
+                result: true,
+                name: String::from("wave"),
+                params: vec![255, 0, 0, 0, 0, 255, 0, 5],
+                seed: 42,
+            },
+            DaemonCommand::GetBatteryHealth => DaemonResponse::GetBatteryHealth {
+                available: true,
+                cycle_count: Some(120),
+                design_capacity: 80000,
+                full_capacity: 72000,
+                unit: String::from("mWh"),
+                health_percent: 90.0,
+            },
+            DaemonCommand::DuplicateLayer { .. } => DaemonResponse::DuplicateLayer { result: true },
+            DaemonCommand::PauseEffects => DaemonResponse::PauseEffects { result: true },
+            DaemonCommand::ResumeEffects => DaemonResponse::ResumeEffects { result: true },
+            DaemonCommand::SwitchProfile { .. } => DaemonResponse::SwitchProfile { result: true },
+            DaemonCommand::ListProfiles => {
+                DaemonResponse::ListProfiles { names: vec![String::from("gaming"), String::from("work")] }
+            }
+            DaemonCommand::SaveCurrentAsProfile { .. } => DaemonResponse::SaveCurrentAsProfile { result: true },
+            DaemonCommand::SetWhiteBalance { .. } => DaemonResponse::SetWhiteBalance { result: true },
+            DaemonCommand::GetWhiteBalance => DaemonResponse::GetWhiteBalance { r_mul: 1000, g_mul: 900, b_mul: 800 },
+            DaemonCommand::GetMatchInfo => DaemonResponse::GetMatchInfo {
+                name: Some(String::from("Blade 15")),
+                vendor_id: Some(0x1532),
+                product_id: Some(0x02b6),
+                simulated: false,
+                features: vec![String::from("boost"), String::from("logo")],
+                unmatched: vec![(0x1532, 0x0084)],
+            },
+            DaemonCommand::ListPowerProfiles => {
+                DaemonResponse::ListPowerProfiles { profiles: vec![(0, String::from("Balanced")), (4, String::from("Custom"))] }
+            }
+            DaemonCommand::GetAnimationStats => {
+                DaemonResponse::GetAnimationStats { target_fps: 30, measured_fps: 29.5, dropped_frames: 2 }
+            }
+        }
+    }
+
+    /// Name of the variant, the Debug output up to the fields
+    fn variant<T: std::fmt::Debug>(value: &T) -> String {
+        let debug = format!("{:?}", value);
+        debug.split([' ', '(', '{']).next().unwrap_or_default().to_string()
+    }
+
+    /// Answers every connection like the daemon does, with the expected
+    /// response of the command
+    fn start_mock_daemon(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("razercontrol-test-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).expect("Could not bind the mock daemon");
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                let Ok(bytes) = read_message(&mut stream) else {
+                    continue;
+                };
+                if let Some(command) = read_from_socket_req(&bytes) {
+                    let encoded = bincode::serialize(&expected_response(&command)).unwrap();
+                    let _ = write_message(&mut stream, &encoded);
+                }
+            }
+        });
+        path
+    }
+
+    #[test]
+    fn commands_round_trip() {
+        for command in sample_commands() {
+            let encoded = bincode::serialize(&command).unwrap();
+            let decoded = read_from_socket_req(&encoded).expect("Command did not decode");
+            assert_eq!(format!("{:?}", decoded), format!("{:?}", command));
+        }
+    }
+
+    #[test]
+    fn responses_round_trip() {
+        let mut responses: Vec<DaemonResponse> = sample_commands().iter().map(expected_response).collect();
+        responses.push(DaemonResponse::Error { message: String::from("No device") });
+        for response in responses {
+            let encoded = bincode::serialize(&response).unwrap();
+            let decoded = read_from_socked_resp(&encoded).expect("Response did not decode");
+            assert_eq!(format!("{:?}", decoded), format!("{:?}", response));
+        }
+    }
+
+    #[test]
+    fn every_command_is_answered_with_its_variant() {
+        let path = start_mock_daemon("variants");
+        for command in sample_commands() {
+            let expected = variant(&expected_response(&command));
+            let sock = UnixStream::connect(&path).unwrap();
+            let response = send_to_daemon(command, sock).expect("Mock daemon did not answer");
+            assert_eq!(variant(&response), expected);
+        }
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn long_commands_are_read_whole() {
+        let path = start_mock_daemon("long");
+        let keys: Vec<(String, [u8; 3])> = (0..600).map(|i| (format!("Key: Number {}", i), [255, 0, 0])).collect();
+        let command = DaemonCommand::ImportKeyColors { keys };
+        assert!(bincode::serialize(&command).unwrap().len() > 4096);
+        let sock = UnixStream::connect(&path).unwrap();
+        let response = send_to_daemon(command, sock).expect("Mock daemon did not answer");
+        assert_eq!(variant(&response), "ImportKeyColors");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn differently_named_responses() {
+        assert_eq!(variant(&expected_response(&DaemonCommand::GetCpuTemperature)), "GetTemperature");
+        let hex = DaemonCommand::SetEffectHex { name: String::from("static"), colors: Vec::new() };
+        assert_eq!(variant(&expected_response(&hex)), "SetEffect");
+        let region = DaemonCommand::PushEffectLayerRegion { name: String::from("static"), params: Vec::new(), region: String::new() };
+        assert_eq!(variant(&expected_response(&region)), "PushEffectLayer");
+        for command in sample_commands() {
+            let name = variant(&command);
+            if !matches!(name.as_str(), "GetCpuTemperature" | "SetEffectHex" | "PushEffectLayerRegion" | "OnDevice") {
+                assert_eq!(variant(&expected_response(&command)), name);
+            }
+        }
+    }
+
+    #[test]
+    fn groups_are_looked_up_by_name_or_id() {
+        assert_eq!(group_id("root"), Some(0));
+        assert_eq!(group_id("1234"), Some(1234));
+        assert_eq!(group_id("no-such-group-here"), None);
+    }
+
+    #[test]
+    fn truncated_command_is_rejected() {
+        let encoded = bincode::serialize(&DaemonCommand::SetEffect { name: String::from("static"), params: vec![1, 2, 3] }).unwrap();
+        assert!(read_from_socket_req(&encoded[0..encoded.len() - 2]).is_none());
+    }
+
+    #[test]
+    fn hex_params_mix_colours_and_bytes() {
+        assert_eq!(parse_hex_param("#ff8000"), Some(vec![255, 128, 0]));
+        assert_eq!(parse_hex_param("ff8000"), Some(vec![255, 128, 0]));
+        assert_eq!(parse_hex_param("20"), Some(vec![20]));
+        assert_eq!(parse_hex_param("256"), None);
+        assert_eq!(parse_hex_param("red"), None);
+    }
+}
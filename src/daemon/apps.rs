@@ -0,0 +1,70 @@
+//! Finds the running programs for the app profiles. /proc is polled, the
+//! profile of the watched program that started last is the active one.
+
+use std::collections::HashSet;
+use std::fs;
+
+use crate::config::AppProfile;
+
+/// The kernel cuts the command names of the processes to this many bytes
+const COMM_LEN: usize = 15;
+
+/// Names of the running processes: the command name and the file name of
+/// the first argument, which holds the `.exe` of the Wine and Proton games
+pub fn running_processes() -> HashSet<String> {
+    let mut names = HashSet::new();
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return names;
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        // The other entries are the files of the kernel
+        if !entry.file_name().to_string_lossy().bytes().all(|c| c.is_ascii_digit()) {
+            continue;
+        }
+        let path = entry.path();
+        if let Ok(comm) = fs::read_to_string(path.join("comm")) {
+            names.insert(comm.trim_end().to_string());
+        }
+        if let Ok(cmdline) = fs::read(path.join("cmdline")) {
+            let first = cmdline.split(|&c| c == 0).next().unwrap_or_default();
+            let first = String::from_utf8_lossy(first);
+            if let Some(name) = first.rsplit(['/', '\\']).next().filter(|name| !name.is_empty()) {
+                names.insert(name.to_string());
+            }
+        }
+    }
+
+    names
+}
+
+fn is_running(process_name: &str, running: &HashSet<String>) -> bool {
+    if running.contains(process_name) {
+        return true;
+    }
+    // Kernel threads and programs started without a path only have the cut name
+    process_name.len() > COMM_LEN
+        && process_name.is_char_boundary(COMM_LEN)
+        && running.contains(&process_name[..COMM_LEN])
+}
+
+/// Watched programs that run, in the order they were seen starting
+#[derive(Default)]
+pub struct AppWatcher {
+    running: Vec<AppProfile>,
+}
+
+impl AppWatcher {
+    /// Follows the watched programs starting and exiting, returns the name of
+    /// the profile to apply, None while none of them runs. When several run,
+    /// the last one started wins and the others take over once it exits
+    pub fn update(&mut self, apps: &[AppProfile], running: &HashSet<String>) -> Option<String> {
+        self.running.retain(|app| apps.contains(app) && is_running(&app.process_name, running));
+        for app in apps {
+            if !self.running.contains(app) && is_running(&app.process_name, running) {
+                self.running.push(app.clone());
+            }
+        }
+
+        self.running.last().map(|app| app.profile_name.clone())
+    }
+}
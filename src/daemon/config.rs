@@ -1,9 +1,38 @@
+use log::*;
 use serde::{Deserialize, Serialize};
 use std::{fs, fs::File, io, env};
 use std::io::prelude::*;
+use std::sync::{Mutex, Once};
+use std::thread;
+use std::time::{Duration, Instant};
 
 const SETTINGS_FILE: &str = "/.local/share/razercontrol/daemon.json";
 const EFFECTS_FILE: &str = "/.local/share/razercontrol/effects.json";
+const PRESETS_DIR: &str = "/.local/share/razercontrol/presets/";
+const PROFILES_DIR: &str = "/.local/share/razercontrol/profiles/";
+const CONFIG_PROFILES_DIR: &str = "/.local/share/razercontrol/config-profiles/";
+
+/// How long the settings and the effects have to stay unchanged before they
+/// are written, so dragging a slider doesn't write the files for every step
+const WRITE_DEBOUNCE_MS: u64 = 500;
+/// How long a failed write waits before it is tried again
+const WRITE_RETRY_MS: u64 = 5000;
+
+/// File contents waiting for `WRITE_DEBOUNCE_MS` without a newer version
+static PENDING_WRITES: Mutex<Vec<PendingWrite>> = Mutex::new(Vec::new());
+/// Held while files are written, the writer thread and `flush_writes` would
+/// otherwise share the temporary file
+static WRITING: Mutex<()> = Mutex::new(());
+static WRITER: Once = Once::new();
+
+struct PendingWrite {
+    path: String,
+    data: String,
+    /// Written once `WRITE_DEBOUNCE_MS` passed since then, later for a retry
+    since: Instant,
+    /// Why the last write of this file failed, None if it didn't
+    error: Option<String>,
+}
 
 #[derive(Serialize, Deserialize, Copy, Clone)]
 pub struct PowerConfig {
@@ -13,6 +42,9 @@ pub struct PowerConfig {
     pub fan_rpm: i32,
     pub brightness: u8,
     pub logo_state: u8,
+    /// 0 keeps the hardware breathing rate, 1-10 breathes the logo in software
+    #[serde(default)]
+    pub logo_breathing_speed: u8,
     pub screensaver: bool, // turno of keyboard light if screen is blank
     pub idle: u32,
 }
@@ -26,12 +58,59 @@ impl PowerConfig {
             fan_rpm: 0,
             brightness: 128,
             logo_state: 0,
+            logo_breathing_speed: 0,
             screensaver: false,
             idle: 0,
         }
     }
 }
 
+/// Settings applied while a program runs, read from the profiles directory.
+/// Missing fields keep the current setting
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+pub struct Profile {
+    #[serde(default)]
+    pub power_mode: Option<u8>,
+    #[serde(default)]
+    pub cpu_boost: Option<u8>,
+    #[serde(default)]
+    pub gpu_boost: Option<u8>,
+    /// 0 lets the firmware decide, like `fan_rpm` of the power config
+    #[serde(default)]
+    pub fan_rpm: Option<i32>,
+    /// Effect preset shown instead of the current effects
+    #[serde(default)]
+    pub effect_preset: Option<String>,
+}
+
+/// Power, fan, brightness and logo settings of both power sources with the
+/// effect layers, saved by `SaveCurrentAsProfile` and put in place at once by
+/// `SwitchProfile`. The other settings, such as the app profiles, the socket
+/// and the remote access, stay as they are
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ConfigProfile {
+    pub power: [PowerConfig; 2],
+    #[serde(default)]
+    pub fan_curves: [Vec<(u8, i32)>; 2],
+    /// The effect layers, laid out like effects.json
+    pub effects: serde_json::Value,
+}
+
+/// Applies a profile while a process with this name runs
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct AppProfile {
+    pub process_name: String,
+    pub profile_name: String,
+}
+
+/// Lights the key of a lock while it is on
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct LockIndicator {
+    /// capslock, numlock or scrolllock
+    pub lock: String,
+    pub color: [u8; 3],
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Configuration {
     pub power: [PowerConfig; 2],
@@ -39,6 +118,138 @@ pub struct Configuration {
     pub no_light: f64, // no light bellow this percentage of battery
     pub standard_effect: u8,
     pub standard_effect_params: Vec<u8>,
+    #[serde(default = "default_animation_fps")]
+    pub animation_fps: u8,
+    /// (temperature in celsius, rpm) points for battery and ac, empty when the
+    /// fixed `fan_rpm` of the power config is used instead
+    #[serde(default)]
+    pub fan_curves: [Vec<(u8, i32)>; 2],
+    /// Accept JSON commands over TCP, see the README for the frame format
+    #[serde(default)]
+    pub tcp_enabled: bool,
+    #[serde(default = "default_tcp_address")]
+    pub tcp_address: String,
+    /// Shared secret every TCP request must carry
+    #[serde(default)]
+    pub tcp_token: String,
+    /// Accept the TCP request frames over WebSocket too and push events,
+    /// needs the websocket feature
+    #[serde(default)]
+    pub websocket_enabled: bool,
+    #[serde(default = "default_websocket_address")]
+    pub websocket_address: String,
+    /// Shared secret every WebSocket request must carry
+    #[serde(default)]
+    pub websocket_token: String,
+    /// Expose the main settings on the session D-Bus, needs the zbus feature
+    #[serde(default)]
+    pub dbus_enabled: bool,
+    /// Apply the settings of the new power source when plugging in or out
+    #[serde(default = "default_auto_switch")]
+    pub auto_switch: bool,
+    /// Seconds without a key event before the backlight is dimmed, 0 disables it
+    #[serde(default)]
+    pub idle_timeout: u32,
+    /// Brightness percentage the backlight is dimmed to, 0 turns it off
+    #[serde(default)]
+    pub idle_brightness: u8,
+    /// Gamma correction of the custom effect colours, 1.0 disables it
+    #[serde(default = "default_gamma")]
+    pub gamma: f32,
+    /// Per mille of red, green and blue sent to the keyboard, for a neutral white
+    #[serde(default = "default_white_balance")]
+    pub white_balance: [u16; 3],
+    /// Degrees the temperature has to move before the fan curve changes the speed
+    #[serde(default)]
+    pub fan_hysteresis: u8,
+    /// Most verbose messages logged (error, warn, info, debug or trace), info
+    /// when empty. `--log-level` and RAZER_LAPTOP_CONTROL_LOG take precedence
+    #[serde(default)]
+    pub log_level: String,
+    /// File the log is appended to, printed to stderr when empty
+    #[serde(default)]
+    pub log_file: String,
+    /// Program the effects the keyboard controller can run itself into it
+    /// instead of animating them in the daemon
+    #[serde(default)]
+    pub hardware_effects: bool,
+    /// Write the backlight through a perceptual curve, so low percentages
+    /// get finer steps. The percentages stored and reported don't change
+    #[serde(default)]
+    pub brightness_curve: bool,
+    /// A breathing logo follows the breathing keyboard effect instead of its
+    /// own rate
+    #[serde(default)]
+    pub logo_sync: bool,
+    /// Turn the keyboard black when the daemon stops, off leaves the last frame
+    #[serde(default = "default_blank_on_exit")]
+    pub blank_on_exit: bool,
+    /// Colour of the keyboard while no effect is active, black by default
+    #[serde(default)]
+    pub idle_color: [u8; 3],
+    /// Use the colour of the last static effect instead of `idle_color`
+    /// once there was one
+    #[serde(default)]
+    pub idle_color_last_static: bool,
+    /// Where the GPU temperature is read: "nvidia-smi", "hwmon", or auto
+    /// (nvidia-smi, then hwmon) when empty
+    #[serde(default)]
+    pub gpu_temperature_source: String,
+    /// Stop the animated effects on battery, the keyboard keeps their last
+    /// frame until the laptop is plugged in again
+    #[serde(default)]
+    pub battery_saver_lighting: bool,
+    /// Profiles applied while the programs run, the one of the last started
+    /// program wins
+    #[serde(default)]
+    pub app_profiles: Vec<AppProfile>,
+    /// Milliseconds a new effect fades in over the old one, 0 switches at once
+    #[serde(default)]
+    pub effect_transition_ms: u32,
+    /// Percentage the effects are never dimmed below, unless the brightness
+    /// is set to 0
+    #[serde(default)]
+    pub min_effect_brightness: u8,
+    /// Keys lit over the effects while their lock is on
+    #[serde(default)]
+    pub lock_indicators: Vec<LockIndicator>,
+    /// ansi, iso, ansi_numpad or iso_numpad, empty to infer it from the model
+    #[serde(default)]
+    pub keyboard_layout: String,
+    /// Octal mode of the socket such as "666", empty for 660
+    #[serde(default)]
+    pub socket_mode: String,
+    /// Group the socket is given to, so its members can connect without sudo
+    #[serde(default)]
+    pub socket_group: String,
+}
+
+fn default_gamma() -> f32 {
+    crate::kbd::board::DEFAULT_GAMMA
+}
+
+fn default_white_balance() -> [u16; 3] {
+    [crate::kbd::board::WHITE_BALANCE_ONE; 3]
+}
+
+fn default_auto_switch() -> bool {
+    true
+}
+
+fn default_blank_on_exit() -> bool {
+    true
+}
+
+fn default_tcp_address() -> String {
+    String::from("127.0.0.1:8790")
+}
+
+fn default_websocket_address() -> String {
+    String::from("127.0.0.1:8791")
+}
+
+fn default_animation_fps() -> u8 {
+    crate::kbd::DEFAULT_ANIMATION_FPS
 }
 
 impl Configuration {
@@ -48,14 +259,46 @@ impl Configuration {
             sync: false,
             no_light: 0.0,
             standard_effect: 0, // off
-            standard_effect_params: vec![]
+            standard_effect_params: vec![],
+            animation_fps: default_animation_fps(),
+            fan_curves: [vec![], vec![]],
+            tcp_enabled: false,
+            tcp_address: default_tcp_address(),
+            tcp_token: String::new(),
+            websocket_enabled: false,
+            websocket_address: default_websocket_address(),
+            websocket_token: String::new(),
+            dbus_enabled: false,
+            auto_switch: default_auto_switch(),
+            idle_timeout: 0,
+            idle_brightness: 0,
+            gamma: default_gamma(),
+            white_balance: default_white_balance(),
+            fan_hysteresis: 0,
+            log_level: String::new(),
+            log_file: String::new(),
+            hardware_effects: false,
+            brightness_curve: false,
+            logo_sync: false,
+            blank_on_exit: default_blank_on_exit(),
+            idle_color: [0, 0, 0],
+            idle_color_last_static: false,
+            gpu_temperature_source: String::new(),
+            battery_saver_lighting: false,
+            app_profiles: vec![],
+            effect_transition_ms: 0,
+            min_effect_brightness: 0,
+            lock_indicators: vec![],
+            keyboard_layout: String::new(),
+            socket_mode: String::new(),
+            socket_group: String::new(),
         };
     }
 
+    /// Queues the settings for writing, see `write_debounced`
     pub fn write_to_file(&mut self) -> io::Result<()> {
         let j: String = serde_json::to_string_pretty(&self)?;
-        File::create(get_home_directory() + SETTINGS_FILE)?.write_all(j.as_bytes())?;
-        Ok(())
+        write_debounced(get_home_directory() + SETTINGS_FILE, j)
     }
 
     pub fn read_from_config() -> io::Result<Configuration> {
@@ -64,10 +307,19 @@ impl Configuration {
         Ok(res)
     }
 
+    /// Drops a queued write of the settings, so the file edited by hand is
+    /// the one reloaded and kept
+    pub fn cancel_write() {
+        let path = get_home_directory() + SETTINGS_FILE;
+        if let Ok(mut pending) = PENDING_WRITES.lock() {
+            pending.retain(|write| write.path != path);
+        }
+    }
+
+    /// Queues the effects for writing like the settings
     pub fn write_effects_save(json: serde_json::Value) -> io::Result<()> {
         let j: String = serde_json::to_string_pretty(&json)?;
-        File::create(get_home_directory() + EFFECTS_FILE)?.write_all(j.as_bytes())?;
-        Ok(())
+        write_debounced(get_home_directory() + EFFECTS_FILE, j)
     }
 
     pub fn read_effects_file() -> io::Result<serde_json::Value> {
@@ -75,8 +327,204 @@ impl Configuration {
         let res: serde_json::Value = serde_json::from_str(str.as_str())?;
         Ok(res)
     }
+
+    /// Preset names are used as file names, so only plain ones are accepted
+    pub fn valid_preset_name(name: &str) -> bool {
+        !name.is_empty()
+            && name.len() <= 64
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    }
+
+    pub fn write_effect_preset(name: &str, json: serde_json::Value) -> io::Result<()> {
+        let dir = get_home_directory() + PRESETS_DIR;
+        fs::create_dir_all(&dir)?;
+        let j: String = serde_json::to_string_pretty(&json)?;
+        write_atomic(&(dir + name + ".json"), j.as_bytes())
+    }
+
+    pub fn read_effect_preset(name: &str) -> io::Result<serde_json::Value> {
+        let str = fs::read_to_string(get_home_directory() + PRESETS_DIR + name + ".json")?;
+        let res: serde_json::Value = serde_json::from_str(str.as_str())?;
+        Ok(res)
+    }
+
+    /// Names of the stored presets, sorted
+    pub fn list_effect_presets() -> io::Result<Vec<String>> {
+        list_json_files(&(get_home_directory() + PRESETS_DIR))
+    }
+
+    /// Profile names follow the rules of the preset names
+    pub fn read_profile(name: &str) -> io::Result<Profile> {
+        let str = fs::read_to_string(get_home_directory() + PROFILES_DIR + name + ".json")?;
+        let res: Profile = serde_json::from_str(str.as_str())?;
+        Ok(res)
+    }
+
+    /// Config profiles are stored apart from the app profiles, names follow
+    /// the rules of the preset names
+    pub fn read_config_profile(name: &str) -> io::Result<ConfigProfile> {
+        let str = fs::read_to_string(get_home_directory() + CONFIG_PROFILES_DIR + name + ".json")?;
+        let res: ConfigProfile = serde_json::from_str(str.as_str())?;
+        Ok(res)
+    }
+
+    pub fn write_config_profile(name: &str, profile: &ConfigProfile) -> io::Result<()> {
+        let dir = get_home_directory() + CONFIG_PROFILES_DIR;
+        fs::create_dir_all(&dir)?;
+        let j: String = serde_json::to_string_pretty(profile)?;
+        write_atomic(&(dir + name + ".json"), j.as_bytes())
+    }
+
+    /// Names of the stored config profiles, sorted
+    pub fn list_config_profiles() -> io::Result<Vec<String>> {
+        list_json_files(&(get_home_directory() + CONFIG_PROFILES_DIR))
+    }
 }
 
+/// Names of the JSON files of a directory without the extension, sorted
+fn list_json_files(dir: &str) -> io::Result<Vec<String>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        // Nothing was saved yet
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e),
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|path| path.file_stem()?.to_str().map(String::from))
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+/// Writes a file through a temporary file renamed over it, so a crash or a
+/// full disk leaves either the old or the new content and never a mix
+fn write_atomic(path: &str, data: &[u8]) -> io::Result<()> {
+    let tmp_path = format!("{}.tmp", path);
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)
+}
+
+/// Writes `data` to `path` once no newer content came for `WRITE_DEBOUNCE_MS`.
+/// The write itself happens later, an error means the last write of this
+/// file failed and is still being retried
+fn write_debounced(path: String, data: String) -> io::Result<()> {
+    let mut error = None;
+    if let Ok(mut pending) = PENDING_WRITES.lock() {
+        if let Some(index) = pending.iter().position(|write| write.path == path) {
+            error = pending.remove(index).error;
+        }
+        pending.push(PendingWrite { path: path.clone(), data, since: Instant::now(), error: error.clone() });
+    }
+    WRITER.call_once(|| {
+        thread::spawn(|| loop {
+            thread::sleep(Duration::from_millis(WRITE_DEBOUNCE_MS));
+            let _ = write_pending(false);
+        });
+    });
+    match error {
+        Some(e) => Err(io::Error::other(format!("The last write of {} failed: {}", path, e))),
+        None => Ok(()),
+    }
+}
+
+/// Writes the queued files that stayed unchanged long enough, or all of them.
+/// A failed write is queued again for a retry unless newer content came,
+/// the last error is returned
+fn write_pending(all: bool) -> io::Result<()> {
+    let Ok(_writing) = WRITING.lock() else {
+        return Ok(());
+    };
+    let due: Vec<PendingWrite> = match PENDING_WRITES.lock() {
+        Ok(mut pending) => {
+            let debounce = Duration::from_millis(WRITE_DEBOUNCE_MS);
+            let (due, waiting) = pending.drain(..).partition(|write| all || write.since.elapsed() >= debounce);
+            *pending = waiting;
+            due
+        }
+        Err(_) => return Ok(()),
+    };
+    let mut result = Ok(());
+    for mut write in due {
+        let Err(e) = write_atomic(&write.path, write.data.as_bytes()) else {
+            continue;
+        };
+        if write.error.is_none() {
+            error!("Error writing {}, retrying: {}", write.path, e);
+        }
+        write.error = Some(e.to_string());
+        result = Err(e);
+        // elapsed() stays 0 until then, so it isn't due before the retry delay
+        write.since = Instant::now() + Duration::from_millis(WRITE_RETRY_MS);
+        let Ok(mut pending) = PENDING_WRITES.lock() else {
+            continue;
+        };
+        // Newer content is written instead
+        if !pending.iter().any(|newer| newer.path == write.path) {
+            pending.push(write);
+        }
+    }
+
+    result
+}
+
+/// Writes the queued settings and effects right away, before the daemon
+/// exits. Returns the error of a write that failed
+pub fn flush_writes() -> io::Result<()> {
+    write_pending(true)
+}
+
+#[cfg(not(test))]
 fn get_home_directory() -> String {
     env::var("HOME").expect("The \"HOME\" environment variable must be set to a valid directory")
 }
+
+/// The tests write their settings to a directory of their own, never to the
+/// files of the user running them
+#[cfg(test)]
+fn get_home_directory() -> String {
+    let home = env::temp_dir().join(format!("razercontrol-home-{}", std::process::id()));
+    let _ = fs::create_dir_all(home.join(".local/share/razercontrol"));
+    home.to_string_lossy().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> String {
+        let dir = env::temp_dir().join(format!("razercontrol-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn atomic_write_replaces_the_file() {
+        let dir = test_dir("atomic");
+        let path = format!("{}/daemon.json", dir);
+        write_atomic(&path, b"old content that is longer").unwrap();
+        write_atomic(&path, b"new").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        // The temporary file was renamed over the file, not left behind
+        assert!(fs::metadata(format!("{}.tmp", path)).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn failed_write_is_reported_and_retried() {
+        let dir = test_dir("retry");
+        let path = format!("{}/missing/effects.json", dir);
+        assert!(write_debounced(path.clone(), String::from("first")).is_ok());
+        assert!(flush_writes().is_err());
+        // Still queued, the next change reports the failure
+        assert!(write_debounced(path.clone(), String::from("second")).is_err());
+        fs::create_dir_all(format!("{}/missing", dir)).unwrap();
+        assert!(flush_writes().is_ok());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "second");
+        assert!(write_debounced(path, String::from("third")).is_ok());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
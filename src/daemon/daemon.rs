@@ -1,9 +1,10 @@
-use std::io::{Read, Write};
+use std::fs::OpenOptions;
 use std::os::unix::net::UnixStream;
-use std::sync::Mutex;
+use std::sync::{Mutex, MutexGuard};
 use std::thread::{self, JoinHandle};
 use std::time;
 
+use clap::Parser;
 use log::*;
 use lazy_static::lazy_static;
 use signal_hook::iterator::Signals;
@@ -16,16 +17,34 @@ mod comms;
 mod config;
 mod kbd;
 mod device;
+mod apps;
 mod battery;
 mod dbus_mutter_displayconfig;
 mod dbus_mutter_idlemonitor;
+mod dbus_service;
 mod screensaver;
 mod login1;
+mod remote;
+mod sensors;
+mod websocket;
 
 use crate::kbd::Effect;
 
+/// How often the fan curve follows the CPU temperature
+const FAN_CURVE_INTERVAL_MS: u64 = 2000;
+const LOGO_BREATHING_INTERVAL_MS: u64 = 50;
+/// How often /proc is searched for the programs of the app profiles
+const APP_PROFILE_INTERVAL_MS: u64 = 2000;
+/// How often the lock LEDs are read for the lock indicators
+const LOCK_INDICATOR_INTERVAL_MS: u64 = 200;
+/// Longest fan turbo, in seconds
+const MAX_FAN_TURBO_SECS: u32 = 600;
+/// Attempts of the render thread at taking the device before dropping a frame
+const FRAME_LOCK_TRIES: u32 = 5;
+
 lazy_static! {
-    static ref EFFECT_MANAGER: Mutex<kbd::EffectManager> = Mutex::new(kbd::EffectManager::new());
+    /// Runs the effects, see `draw_frame` for how the frames reach the keyboard
+    static ref RENDERER: kbd::render::Renderer = kbd::render::Renderer::start(draw_frame, stop_effects);
     // static ref CONFIG: Mutex<config::Configuration> = {
         // match config::Configuration::read_from_config() {
             // Ok(c) => Mutex::new(c),
@@ -35,31 +54,64 @@ lazy_static! {
     static ref DEV_MANAGER: Mutex<device::DeviceManager> = {
         match device::DeviceManager::read_laptops_file() {
             Ok(c) => Mutex::new(c),
-            Err(_) => Mutex::new(device::DeviceManager::new()),
+            Err(e) => {
+                error!("Could not read the supported laptops from {}: {}", device::DEVICE_FILE, e);
+                Mutex::new(device::DeviceManager::new())
+            }
         }
     };
+    /// Effects shown before the effect preset of an app profile replaced
+    /// them, put back once no profile with a preset is active
+    static ref APP_PROFILE_EFFECTS: Mutex<Option<serde_json::Value>> = Mutex::new(None);
+}
+
+#[derive(Parser)]
+#[command(about = "razer laptop control daemon", name = "daemon")]
+struct DaemonArgs {
+    /// most verbose messages logged: off, error, warn, info, debug or trace
+    #[arg(long)]
+    log_level: Option<LevelFilter>,
+    /// append the log to this file instead of printing it
+    #[arg(long)]
+    log_file: Option<String>,
+    /// don't open the hardware, simulate the laptop of the device file with
+    /// this name (the first one without a name) and print its frames
+    #[arg(long, value_name = "NAME", num_args = 0..=1, default_missing_value = "")]
+    simulate: Option<String>,
 }
 
 // Main function for daemon
 fn main() {
+    let args = DaemonArgs::parse();
     setup_panic_hook();
-    init_logging();
+    init_logging(&args);
 
     if let Ok(mut d) = DEV_MANAGER.lock() {
-        d.discover_devices();
+        match &args.simulate {
+            Some(name) => {
+                if !d.simulate_device(name) {
+                    error!("No laptop called {:?} in {}", name, device::DEVICE_FILE);
+                    std::process::exit(1);
+                }
+            },
+            None => d.discover_devices(),
+        }
         if let Some(laptop) = d.get_device() {
-            println!("supported device: {:?}", laptop.get_name());
+            info!("supported device: {:?}", laptop.get_name());
         } else {
-            println!("no supported device found");
+            error!("No supported laptop found. Check that it is listed in {} and that the daemon can open /dev/hidraw*", device::DEVICE_FILE);
             std::process::exit(1);
         }
     } else {
-        println!("error loading supported devices");
+        error!("error loading supported devices");
         std::process::exit(1);
     }
 
 
     if let Ok(mut d) = DEV_MANAGER.lock() {
+        // Before the first brightness is written
+        device::set_brightness_curve(d.get_brightness_curve());
+        device::set_logo_sync(d.get_logo_sync());
         let dbus_system = Connection::new_system()
             .expect("failed to connect to D-Bus system bus");
         let proxy_ac = dbus_system.with_proxy("org.freedesktop.UPower", "/org/freedesktop/UPower/devices/line_power_AC0", time::Duration::from_millis(5000));
@@ -68,28 +120,70 @@ fn main() {
             info!("AC0 online: {:?}", online);
             d.set_ac_state(online);
             d.restore_standard_effect();
-            if let Ok(json) = config::Configuration::read_effects_file() {
-                EFFECT_MANAGER.lock().unwrap().load_from_save(json);
-            } else {
-                println!("No effects save, creating a new one");
-                // No effects found, start with a green static layer, just like synapse
-                EFFECT_MANAGER.lock().unwrap().push_effect(
-                    kbd::effects::Static::new(vec![0, 255, 0]), 
-                    [true; 90]
-                    );
+            kbd::set_animation_fps(d.get_animation_fps());
+            if !kbd::board::set_gamma(d.get_gamma()) {
+                warn!("Invalid gamma {} in the configuration, colours are not corrected", d.get_gamma());
+            }
+            if !kbd::board::set_white_balance(d.get_white_balance()) {
+                warn!("Invalid white balance {:?} in the configuration, colours are not balanced", d.get_white_balance());
+            }
+            let (idle_colour, last_static) = d.get_idle_color();
+            RENDERER.run(move |k| k.set_idle_colour(idle_colour, last_static));
+            let transition_ms = d.get_effect_transition();
+            RENDERER.run(move |k| k.set_transition_ms(transition_ms));
+            let min_brightness = d.get_min_effect_brightness();
+            RENDERER.run(move |k| k.set_min_brightness(min_brightness));
+            match config::Configuration::read_effects_file() {
+                Ok(json) => {
+                    RENDERER.run(|k| k.load_from_save(json));
+                }
+                Err(e) => {
+                    if e.kind() == std::io::ErrorKind::NotFound {
+                        info!("No effects save, creating a new one");
+                    } else {
+                        // Truncated by a crash, from before the writes were atomic
+                        warn!("Could not read the effects save, creating a new one: {}", e);
+                    }
+                    // No effects found, start with a green static layer, just like synapse
+                    RENDERER.run(|k| k.push_effect(
+                        kbd::effects::Static::new(vec![0, 255, 0]), 
+                        vec![true; kbd::board::zone_key_count()]
+                        ));
+                }
             }
         } else {
-            println!("error getting current power state");
+            error!("error getting current power state");
             std::process::exit(1);
         }
     }
 
-    start_keyboard_animator_task();
+    kbd::input::start_key_readers();
+    // Resolve the temperature and fan sensors before the first client asks for them
+    let _ = sensors::read_cpu_temperature();
+    let _ = sensors::read_gpu_temperature(sensors::GPU_SOURCE_HWMON);
+    let _ = sensors::read_fan_rpm(1);
+    start_fan_curve_task();
+    start_logo_breathing_task();
+    start_app_profile_task();
+    start_lock_indicator_task();
     start_screensaver_monitor_task();
     start_battery_monitor_task();
     let clean_thread = start_shutdown_task();
 
-    if let Some(listener) = comms::create() {
+    let tcp_config = DEV_MANAGER.lock().unwrap().get_tcp_config();
+    if let Some((address, token)) = tcp_config {
+        remote::start_tcp_listener(address, token, process_client_request);
+    }
+    let websocket_config = DEV_MANAGER.lock().unwrap().get_websocket_config();
+    if let Some((address, token)) = websocket_config {
+        websocket::start(address, token, process_client_request);
+    }
+    if DEV_MANAGER.lock().unwrap().get_dbus_enabled() {
+        dbus_service::start(process_client_request, current_ac);
+    }
+
+    let (socket_mode, socket_group) = DEV_MANAGER.lock().unwrap().get_socket_permissions();
+    if let Some(listener) = comms::create(socket_mode, &socket_group) {
         for stream in listener.incoming() {
             match stream {
                 Ok(stream) => handle_data(stream),
@@ -97,7 +191,7 @@ fn main() {
             }
         }
     } else {
-        eprintln!("Could not create Unix socket!");
+        error!("Could not create Unix socket!");
         std::process::exit(1);
     }
     clean_thread.join().unwrap();
@@ -108,31 +202,224 @@ fn setup_panic_hook() {
     let default_panic_hook = std::panic::take_hook();
     std::panic::set_hook(Box::new(move |info| {
         error!("Something went wrong! Removing the socket path");
-        if std::fs::metadata(comms::SOCKET_PATH).is_ok() {
-            std::fs::remove_file(comms::SOCKET_PATH).unwrap();
+        let socket_path = comms::socket_path();
+        if std::fs::metadata(&socket_path).is_ok() {
+            std::fs::remove_file(&socket_path).unwrap();
         }
         default_panic_hook(info);
     }));
 }
 
-fn init_logging() {
+/// The level comes from `--log-level`, RAZER_LAPTOP_CONTROL_LOG or the
+/// config, in that order, and the output goes to `--log-file` or the file of
+/// the config if any
+fn init_logging(args: &DaemonArgs) {
+    // Read on its own, the device manager logs while it loads the config
+    let config = config::Configuration::read_from_config().ok();
+    let config_level = config.as_ref().and_then(|c| c.log_level.parse::<LevelFilter>().ok());
     let mut builder = env_logger::Builder::from_default_env();
     builder.target(env_logger::Target::Stderr);
-    builder.filter_level(log::LevelFilter::Info);
+    builder.filter_level(config_level.unwrap_or(LevelFilter::Info));
     builder.format_timestamp_millis();
     builder.parse_env("RAZER_LAPTOP_CONTROL_LOG");
+    if let Some(level) = args.log_level {
+        builder.filter_level(level);
+    }
+
+    let log_file = args.log_file.clone()
+        .or_else(|| config.map(|c| c.log_file).filter(|file| !file.is_empty()));
+    let mut file_error = None;
+    if let Some(path) = &log_file {
+        match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => {
+                builder.target(env_logger::Target::Pipe(Box::new(file)));
+            }
+            Err(e) => file_error = Some(e),
+        }
+    }
     builder.init();
+
+    if let (Some(path), Some(e)) = (log_file, file_error) {
+        warn!("Could not open the log file {}, logging to stderr: {}", path, e);
+    }
+}
+
+/// Takes the device for the render thread, trying a few times 2 ms apart.
+/// None while a client keeps holding it: the client may be waiting for the
+/// render thread, so blocking could deadlock
+fn lock_for_frame() -> Option<MutexGuard<'static, device::DeviceManager>> {
+    for _ in 0..FRAME_LOCK_TRIES {
+        if let Ok(d) = DEV_MANAGER.try_lock() {
+            return Some(d);
+        }
+        thread::sleep(time::Duration::from_millis(2));
+    }
+    None
 }
 
-/// Handles keyboard animations
-pub fn start_keyboard_animator_task() -> JoinHandle<()> {
-    // Start the keyboard animator thread,
+/// Draws the next frame of the effects, called on the render thread. The
+/// frame is dropped when the device can't be taken, it shows up in the
+/// dropped frames of GetAnimationStats
+fn draw_frame(k: &mut kbd::EffectManager) {
+    let Some(mut d) = lock_for_frame() else {
+        return;
+    };
+    // Stepped here so a fade and the effects use the same brightness
+    d.update_brightness_fade();
+    // The effects are paused, not removed, while the keyboard is idle
+    if d.update_input_idle(kbd::input::last_activity_ms()) {
+        return;
+    }
+    k.set_brightness(d.get_effect_brightness());
+    k.set_frozen(d.get_battery_saver_active());
+    let Some(mut frame) = k.render() else {
+        return;
+    };
+    if let Some(laptop) = d.get_device() {
+        frame.update_kbd(laptop);
+        frame.update_custom_mode(laptop);
+        k.frame_written(frame);
+    }
+}
+
+/// Saves the effects and blanks the keyboard, called when the render thread stops
+fn stop_effects(k: &mut kbd::EffectManager) {
+    save_effects(k);
+    // Same as for the frames, don't wait forever for a client holding the device
+    for _ in 0..20 {
+        if let Ok(mut d) = DEV_MANAGER.try_lock() {
+            if !d.get_blank_on_exit() {
+                info!("Leaving the keyboard lit, blank_on_exit is off");
+                return;
+            }
+            if let Some(laptop) = d.get_device() {
+                let mut frame = kbd::board::KeyboardData::new();
+                frame.set_kbd_colour(0, 0, 0);
+                frame.update_kbd(laptop);
+                frame.update_custom_mode(laptop);
+            }
+            return;
+        }
+        thread::sleep(std::time::Duration::from_millis(50));
+    }
+}
+
+/// Applies the fan curve of the current power state
+fn start_fan_curve_task() -> JoinHandle<()> {
+    thread::spawn(|| {
+        loop {
+            if let Ok(mut d) = DEV_MANAGER.lock() {
+                d.update_fan_curve();
+            }
+            thread::sleep(std::time::Duration::from_millis(FAN_CURVE_INTERVAL_MS));
+        }
+    })
+}
+
+/// Applies the profiles of the watched programs while they run
+fn start_app_profile_task() -> JoinHandle<()> {
     thread::spawn(|| {
+        let mut watcher = apps::AppWatcher::default();
+        let mut active: Option<String> = None;
         loop {
-            if let Some(laptop) = DEV_MANAGER.lock().unwrap().get_device() {
-                EFFECT_MANAGER.lock().unwrap().update(laptop);
+            let watched = DEV_MANAGER.lock().map(|mut d| d.get_app_profiles()).unwrap_or_default();
+            let running = if watched.is_empty() { Default::default() } else { apps::running_processes() };
+            let profile = watcher.update(&watched, &running);
+            if profile != active {
+                switch_app_profile(profile.as_deref());
+                active = profile;
             }
-            thread::sleep(std::time::Duration::from_millis(kbd::ANIMATION_SLEEP_MS));
+            thread::sleep(std::time::Duration::from_millis(APP_PROFILE_INTERVAL_MS));
+        }
+    })
+}
+
+/// Lights the keys of the locks that are on, as far as they have an indicator
+fn start_lock_indicator_task() -> JoinHandle<()> {
+    thread::spawn(|| {
+        let mut shown: Vec<(usize, [u8; 3])> = vec![];
+        loop {
+            let indicators = DEV_MANAGER.lock().map(|mut d| d.get_lock_indicators()).unwrap_or_default();
+            let lit: Vec<(usize, [u8; 3])> = indicators
+                .iter()
+                .filter(|indicator| kbd::input::lock_state(&indicator.lock) == Some(true))
+                .filter_map(|indicator| Some((kbd::input::lock_key_index(&indicator.lock)?, indicator.color)))
+                .collect();
+            if lit != shown {
+                let keys = lit.clone();
+                RENDERER.run(move |k| k.set_indicators(keys));
+                shown = lit;
+            }
+            thread::sleep(std::time::Duration::from_millis(LOCK_INDICATOR_INTERVAL_MS));
+        }
+    })
+}
+
+/// Applies a profile of the profiles directory over the stored settings,
+/// None restores them
+fn switch_app_profile(name: Option<&str>) {
+    let profile = name.and_then(|name| match config::Configuration::read_profile(name) {
+        Ok(profile) => {
+            info!("Applying the app profile {}", name);
+            Some(profile)
+        }
+        Err(e) => {
+            warn!("Could not read the app profile {}: {}", name, e);
+            None
+        }
+    });
+    if profile.is_none() {
+        info!("No app profile active, restoring the settings");
+    }
+    let preset = profile.as_ref().and_then(|profile| profile.effect_preset.clone());
+    if let Ok(mut d) = DEV_MANAGER.lock() {
+        d.apply_app_profile(profile);
+    }
+
+    let json = preset.and_then(|name| match config::Configuration::read_effect_preset(&name) {
+        Ok(json) => Some(json),
+        Err(e) => {
+            warn!("Could not read preset {}: {}", name, e);
+            None
+        }
+    });
+    match json {
+        Some(json) => {
+            let Ok(mut saved) = APP_PROFILE_EFFECTS.lock() else {
+                return;
+            };
+            // Only the effects from before the first profile are kept
+            if saved.is_none() {
+                *saved = RENDERER.run(|k| k.save());
+            }
+            RENDERER.run(move |k| {
+                k.clear();
+                k.load_from_save(json);
+            });
+        }
+        None => restore_app_profile_effects(),
+    }
+}
+
+/// Shows the effects again that an app profile replaced, if any
+fn restore_app_profile_effects() {
+    let saved = APP_PROFILE_EFFECTS.lock().ok().and_then(|mut saved| saved.take());
+    if let Some(json) = saved {
+        RENDERER.run(move |k| {
+            k.clear();
+            k.load_from_save(json);
+        });
+    }
+}
+
+/// Fades the logo when breathing at a speed the firmware doesn't support
+fn start_logo_breathing_task() -> JoinHandle<()> {
+    thread::spawn(|| {
+        loop {
+            if let Ok(mut d) = DEV_MANAGER.lock() {
+                d.update_logo_breathing();
+            }
+            thread::sleep(std::time::Duration::from_millis(LOGO_BREATHING_INTERVAL_MS));
         }
     })
 }
@@ -163,10 +450,10 @@ fn start_screensaver_monitor_task() -> JoinHandle<()> {
         let _id = proxy_idle.match_signal(|h: dbus_mutter_idlemonitor::OrgGnomeMutterIdleMonitorWatchFired, _: &Connection, _: &Message| {
             if let Ok(mut d) = DEV_MANAGER.lock() {
                 if d.idle_id == h.id {
-                    println!("idle trigger {:?}", h.id);
+                    debug!("idle trigger {:?}", h.id);
                     d.light_off();
                 } else if d.active_id == h.id {
-                    println!("active trigger {:?}", h.id);
+                    debug!("active trigger {:?}", h.id);
                     d.restore_light();
                 }
             }
@@ -174,7 +461,7 @@ fn start_screensaver_monitor_task() -> JoinHandle<()> {
         });
         let proxy = dbus_session.with_proxy("org.freedesktop.ScreenSaver", "/org/freedesktop/ScreenSaver", time::Duration::from_millis(5000));
         let _id = proxy.match_signal(|h: screensaver::OrgFreedesktopScreenSaverActiveChanged, _: &Connection, _: &Message| {
-            println!("ActiveChanged {:?}", h.arg0);
+            debug!("ActiveChanged {:?}", h.arg0);
             if let Ok(mut d) = DEV_MANAGER.lock() {
                 if h.arg0 {
                     d.light_off();
@@ -230,7 +517,7 @@ fn start_battery_monitor_task() -> JoinHandle<()> {
             if let Some(online) = online {
                 info!("AC0 online: {:?}", online);
                 if let Ok(mut d) = DEV_MANAGER.lock() {
-                    d.set_ac_state(*online);
+                    d.queue_ac_state(*online);
                 }
             }
             true
@@ -240,6 +527,7 @@ fn start_battery_monitor_task() -> JoinHandle<()> {
             let perc: Option<&f64> = arg::prop_cast(&h.changed_properties, "Percentage");
             if let Some(perc) = perc {
                 info!("Battery percentage: {:.1}", perc);
+                websocket::broadcast(websocket::Event::Battery { capacity: perc.round() as u8 });
             }
             true
         });
@@ -257,10 +545,24 @@ fn start_battery_monitor_task() -> JoinHandle<()> {
             true
         });
 
-        loop { dbus_system.process(time::Duration::from_millis(1000)).unwrap(); }
+        loop {
+            dbus_system.process(time::Duration::from_millis(500)).unwrap();
+            let switched = DEV_MANAGER.lock().is_ok_and(|mut d| d.apply_pending_ac_state());
+            if switched {
+                dbus_service::power_source_changed();
+                websocket::broadcast(websocket::Event::PowerSource { ac: current_ac() == 1 });
+            }
+        }
     })
 }
 
+/// Power source whose settings are in use, 1 on AC
+fn current_ac() -> usize {
+    DEV_MANAGER.lock().ok()
+        .and_then(|mut d| d.get_device().map(|laptop| laptop.get_ac_state()))
+        .unwrap_or(0)
+}
+
 /// Monitors signals and stops the daemon when receiving one
 pub fn start_shutdown_task() -> JoinHandle<()> {
     thread::spawn(|| {
@@ -268,149 +570,930 @@ pub fn start_shutdown_task() -> JoinHandle<()> {
         let _ = signals.forever().next();
         
         // If we reach this point, we have a signal and it is time to exit
-        println!("Received signal, cleaning up");
-        let json = EFFECT_MANAGER.lock().unwrap().save();
-        if let Err(error) = config::Configuration::write_effects_save(json) {
-            error!("Error writing config {}", error);
+        info!("Received signal, cleaning up");
+        // The effects of a running game are not the ones to save
+        restore_app_profile_effects();
+        RENDERER.stop();
+        if let Err(e) = config::flush_writes() {
+            error!("The settings or effects could not be saved: {}", e);
         }
-        if std::fs::metadata(comms::SOCKET_PATH).is_ok() {
-            std::fs::remove_file(comms::SOCKET_PATH).unwrap();
+        let socket_path = comms::socket_path();
+        if std::fs::metadata(&socket_path).is_ok() {
+            std::fs::remove_file(&socket_path).unwrap();
         }
         std::process::exit(0);
     })
 }
 
-fn handle_data(mut stream: UnixStream) {
-    let mut buffer = [0u8; 4096];
-    if stream.read(&mut buffer).is_err() {
-        return;
+/// Creates a custom effect from its command name, None if the name is unknown
+/// or the params don't follow the layout of the effect
+fn new_effect(name: &str, params: Vec<u8>) -> Option<Box<dyn kbd::Effect>> {
+    kbd::EFFECTS
+        .iter()
+        .find(|info| info.command == name)
+        .and_then(|info| (info.create)(params))
+}
+
+/// Runs a new effect for a few frames offscreen and returns the RGB data of
+/// the last one, empty if the effect can't be created
+fn preview_effect(name: &str, params: Vec<u8>, frames: u8) -> Vec<u8> {
+    match new_effect(name, params) {
+        Some(mut effect) => {
+            for _ in 0..frames.max(1) {
+                effect.update();
+            }
+            effect.get_state()
+        }
+        None => vec![],
     }
+}
 
-    if let Some(cmd) = comms::read_from_socket_req(&buffer) {
-        if let Some(s) = process_client_request(cmd) {
-            if let Ok(x) = bincode::serialize(&s) {
-                let result = stream.write_all(&x);
+/// Standard effect id and params that show a custom effect on the keyboard
+/// controller, None when the controller can't run it. Reactive lighting is
+/// sent like `SetStandardEffect` sends it, to every device
+fn hardware_effect(name: &str, params: &[u8]) -> Option<(u8, Vec<u8>)> {
+    match (name, params) {
+        ("static", [r, g, b]) => Some((device::RazerLaptop::STATIC, vec![*r, *g, *b])),
+        // Kind 1 is a single colour, breathed at the rate of the firmware
+        ("breathing_single", [r, g, b, _]) => Some((device::RazerLaptop::BREATHING, vec![1, *r, *g, *b])),
+        ("reactive", [r, g, b, duration]) => {
+            Some((device::RazerLaptop::REACTIVE, vec![reactive_speed(*duration), *r, *g, *b]))
+        }
+        _ => None,
+    }
+}
 
-                if let Err(error) = result {
-                    println!("Client disconnected with error: {error}");
-                }
+/// Afterglow length of the firmware (1 short, 2 medium, 3 long) closest to
+/// the fade of the software effect, in tenths of a second
+fn reactive_speed(duration: u8) -> u8 {
+    match duration {
+        0..=5 => 1,
+        6..=15 => 2,
+        _ => 3,
+    }
+}
+
+/// Replaces the top effect layer with a new full keyboard effect. Returns
+/// the params the effect runs with, its timing args clamped, None when it
+/// could not be set
+fn set_effect(d: &mut device::DeviceManager, name: &str, params: Vec<u8>) -> Option<Vec<u8>> {
+    d.get_device()?;
+    let hardware = if d.get_effect_backend() { hardware_effect(name, &params) } else { None };
+    if let Some((effect_id, hw_params)) = hardware {
+        // Other layers can't be mixed in by the controller, those stay in software
+        let cleared = RENDERER.run(|k| {
+            if k.get_layer_names().len() > 1 {
+                return false;
             }
+            k.pop_effect();
+            k.skip_blank();
+            save_effects(k);
+            true
+        });
+        if cleared == Some(true) {
+            return d.set_standard_effect(effect_id, hw_params).then_some(params);
+        }
+    }
+    let mut e = new_effect(name, params)?;
+    let effective = e.get_varargs().to_vec();
+    RENDERER.run(|k| {
+        k.start_transition();
+        k.pop_effect(); // Remove old layer
+        k.push_effect(
+            e,
+            vec![true; kbd::board::zone_key_count()]
+            );
+        save_effects(k);
+    })?;
+    Some(effective)
+}
+
+/// Stacks a new effect on top of the current ones, for the keys of `mask`
+fn push_effect_layer(name: &str, params: Vec<u8>, mask: Vec<bool>) -> bool {
+    if let Some(effect) = new_effect(name, params) {
+        return RENDERER.run(move |k| {
+            k.push_effect(effect, mask);
+            save_effects(k);
+        }).is_some();
+    }
+
+    false
+}
+
+/// Passes the settings the render thread keeps a copy of on to it, after
+/// they were replaced as a whole
+fn apply_effect_settings(d: &mut device::DeviceManager) {
+    kbd::board::set_gamma(d.get_gamma());
+    kbd::board::set_white_balance(d.get_white_balance());
+    let (idle_colour, last_static) = d.get_idle_color();
+    RENDERER.run(move |k| k.set_idle_colour(idle_colour, last_static));
+    let transition_ms = d.get_effect_transition();
+    RENDERER.run(move |k| k.set_transition_ms(transition_ms));
+    let min_brightness = d.get_min_effect_brightness();
+    RENDERER.run(move |k| k.set_min_brightness(min_brightness));
+}
+
+/// Writes the effect layers to the effects file so they survive a restart
+fn save_effects(k: &mut kbd::EffectManager) {
+    if let Err(error) = config::Configuration::write_effects_save(k.save()) {
+        error!("Error writing effects {}", error);
+    }
+}
+
+fn handle_data(mut stream: UnixStream) {
+    let bytes = match comms::read_message(&mut stream) {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            warn!("Could not read the command of a client: {error}");
+            return;
         }
+    };
+
+    // Clients of another version send commands this one doesn't know
+    let response = match comms::read_from_socket_req(&bytes) {
+        Some(cmd) => process_client_request(cmd),
+        None => Some(error_response("The daemon could not read the command, is the client of the same version?")),
+    };
+    let Some(x) = response.and_then(|s| bincode::serialize(&s).ok()) else {
+        return;
+    };
+    if let Err(error) = comms::write_message(&mut stream, &x) {
+        error!("Client disconnected with error: {error}");
     }
 }
 
 pub fn process_client_request(cmd: comms::DaemonCommand) -> Option<comms::DaemonResponse> {
-    if let Ok(mut d) = DEV_MANAGER.lock() {
-        return match cmd {
-            comms::DaemonCommand::SetPowerMode { ac, pwr, cpu, gpu } => {
-                Some(comms::DaemonResponse::SetPowerMode { result: d.set_power_mode(ac, pwr, cpu, gpu) })
-            },
-            comms::DaemonCommand::SetFanSpeed { ac, rpm } => {
-                Some(comms::DaemonResponse::SetFanSpeed { result: d.set_fan_rpm(ac, rpm) })
-            },
-            comms::DaemonCommand::SetLogoLedState{ ac, logo_state } => {
-                Some(comms::DaemonResponse::SetLogoLedState { result: d.set_logo_led_state(ac, logo_state) })
-            },
-            comms::DaemonCommand::SetBrightness { ac, val } => {
-                Some(comms::DaemonResponse::SetBrightness {result: d.set_brightness(ac, val) })
+    // May run nvidia-smi, which can take a while, the device manager is only held
+    // to read the source so other clients and the fan curve aren't blocked.
+    // The GPU is the same whichever device a command is sent to
+    let gpu_temperature = match &cmd {
+        comms::DaemonCommand::OnDevice { command, .. } => matches!(**command, comms::DaemonCommand::GetGpuTemperature),
+        command => matches!(command, comms::DaemonCommand::GetGpuTemperature),
+    };
+    if gpu_temperature {
+        let source = DEV_MANAGER.lock().ok()?.get_gpu_temperature_source();
+        let (celsius, source) = sensors::read_gpu_temperature(&source);
+        return Some(comms::DaemonResponse::GetGpuTemperature { celsius, source: source.into() });
+    }
+    let Ok(mut d) = DEV_MANAGER.lock() else {
+        return Some(error_response("The device manager is unavailable"));
+    };
+    // Runs on another device than the selected one without selecting it, the
+    // lock is held so no other client sees the switch. The effects keep being
+    // drawn on the selected device
+    if let comms::DaemonCommand::OnDevice { device, command } = cmd {
+        // The selection would be undone when the command is done
+        if let comms::DaemonCommand::SelectDevice { .. } = *command {
+            return Some(error_response("select-device can't be sent to a device"));
+        }
+        let Some(index) = d.find_device(&device) else {
+            return Some(error_response("No connected device has this index or serial number"));
+        };
+        let selected = d.target_device(index);
+        let response = run_command(&mut d, *command);
+        d.target_device(selected);
+        return response;
+    }
+    run_command(&mut d, cmd)
+}
+
+fn run_command(d: &mut device::DeviceManager, cmd: comms::DaemonCommand) -> Option<comms::DaemonResponse> {
+    match cmd {
+        comms::DaemonCommand::SetPowerMode { ac, pwr, cpu, gpu } => {
+            Some(comms::DaemonResponse::SetPowerMode { result: d.set_power_mode(ac, pwr, cpu, gpu) })
+        },
+        comms::DaemonCommand::SetCPUBoost { ac, cpu } => {
+            Some(comms::DaemonResponse::SetCPUBoost { result: d.set_cpu_boost(ac, cpu) })
+        },
+        comms::DaemonCommand::SetGPUBoost { ac, gpu } => {
+            Some(comms::DaemonResponse::SetGPUBoost { result: d.set_gpu_boost(ac, gpu) })
+        },
+        comms::DaemonCommand::SetFanSpeed { ac, rpm } => {
+            Some(comms::DaemonResponse::SetFanSpeed { result: d.set_fan_rpm(ac, rpm) })
+        },
+        comms::DaemonCommand::SetLogoLedState{ ac, logo_state } => {
+            Some(comms::DaemonResponse::SetLogoLedState { result: d.set_logo_led_state(ac, logo_state) })
+        },
+        comms::DaemonCommand::SetBrightness { ac, val } => {
+            Some(comms::DaemonResponse::SetBrightness {result: d.set_brightness(ac, val) })
+        }
+        comms::DaemonCommand::SetIdle { ac, val } => {
+            Some(comms::DaemonResponse::SetIdle { result: d.change_idle(ac, val) })
+        }
+        comms::DaemonCommand::SetSync { sync } => {
+            Some(comms::DaemonResponse::SetSync { result: d.set_sync(sync) })
+        }
+        comms::DaemonCommand::GetBrightness{ac} =>  {
+            Some(comms::DaemonResponse::GetBrightness { result: d.get_brightness(ac)})
+        },
+        comms::DaemonCommand::SetBrightnessSmooth { ac, val, duration_ms } => {
+            Some(comms::DaemonResponse::SetBrightnessSmooth { result: d.set_brightness_smooth(ac, val, duration_ms) })
+        }
+        comms::DaemonCommand::SetIdleTimeout { seconds } => {
+            Some(comms::DaemonResponse::SetIdleTimeout { result: d.set_idle_timeout(seconds) })
+        }
+        comms::DaemonCommand::GetIdleTimeout => {
+            Some(comms::DaemonResponse::GetIdleTimeout { seconds: d.get_idle_timeout() })
+        }
+        comms::DaemonCommand::GetLogoLedState{ac} => Some(comms::DaemonResponse::GetLogoLedState {logo_state: d.get_logo_led_state(ac) }),
+        comms::DaemonCommand::GetKeyboardRGB { layer } => {
+            let map = RENDERER.run(move |k| k.get_map(layer)).unwrap_or_default();
+            Some(comms::DaemonResponse::GetKeyboardRGB {
+                layer,
+                rgbdata: map,
+            })
+        }
+        comms::DaemonCommand::GetSync() => Some(comms::DaemonResponse::GetSync { sync: d.get_sync() }),
+        comms::DaemonCommand::GetFanSpeed{ac} => Some(comms::DaemonResponse::GetFanSpeed { rpm: d.get_fan_rpm(ac)}),
+        comms::DaemonCommand::GetPwrLevel{ac} => Some(comms::DaemonResponse::GetPwrLevel { pwr: d.get_power_mode(ac) }),
+        comms::DaemonCommand::GetCPUBoost{ac} => Some(comms::DaemonResponse::GetCPUBoost { cpu: d.get_cpu_boost(ac) }),
+        comms::DaemonCommand::GetGPUBoost{ac} => Some(comms::DaemonResponse::GetGPUBoost { gpu: d.get_gpu_boost(ac) }),
+        comms::DaemonCommand::SetEffect{ name, params } => {
+            let effective = set_effect(d, &name, params);
+            Some(comms::DaemonResponse::SetEffect { result: effective.is_some(), params: effective.unwrap_or_default() })
+        }
+        comms::DaemonCommand::SetEffectHex { name, colors } => {
+            let mut params: Vec<u8> = vec![];
+            let mut res = true;
+            for colour in &colors {
+                match comms::parse_hex_param(colour) {
+                    Some(bytes) => params.extend_from_slice(&bytes),
+                    None => {
+                        warn!("Invalid hex colour or byte {:?} for effect {}", colour, name);
+                        res = false;
+                    }
+                }
             }
-            comms::DaemonCommand::SetIdle { ac, val } => {
-                Some(comms::DaemonResponse::SetIdle { result: d.change_idle(ac, val) })
+            let effective = if res { set_effect(d, &name, params) } else { None };
+            Some(comms::DaemonResponse::SetEffect { result: effective.is_some(), params: effective.unwrap_or_default() })
+        }
+
+        comms::DaemonCommand::PushEffectLayer { name, params, key_mask } => {
+            // Malformed masks are rejected rather than padded
+            let res = key_mask.len() == kbd::board::zone_key_count()
+                && push_effect_layer(&name, params, key_mask);
+            Some(comms::DaemonResponse::PushEffectLayer { result: res })
+        }
+        comms::DaemonCommand::PushEffectLayerRegion { name, params, region } => {
+            let device_name = d.get_device().map_or(String::new(), |laptop| laptop.get_name());
+            let res = match kbd::board::region_mask(&device_name, &region) {
+                Some(mask) => push_effect_layer(&name, params, mask),
+                None => {
+                    warn!("Unknown key region {}", region);
+                    false
+                }
+            };
+            Some(comms::DaemonResponse::PushEffectLayer { result: res })
+        }
+        comms::DaemonCommand::GetRegions => {
+            let device_name = d.get_device().map_or(String::new(), |laptop| laptop.get_name());
+            Some(comms::DaemonResponse::GetRegions { names: kbd::board::region_names(&device_name) })
+        }
+        comms::DaemonCommand::PopEffectLayer => {
+            let res = RENDERER.run(|k| {
+                if k.layer_count() == 0 {
+                    return false;
+                }
+                k.pop_effect();
+                save_effects(k);
+                true
+            });
+            Some(comms::DaemonResponse::PopEffectLayer { result: res.unwrap_or(false) })
+        }
+        comms::DaemonCommand::DuplicateLayer { layer_id, new_mask } => {
+            if new_mask.len() != kbd::board::zone_key_count() {
+                return Some(error_response(&format!("The mask needs {} keys", kbd::board::zone_key_count())));
             }
-            comms::DaemonCommand::SetSync { sync } => {
-                Some(comms::DaemonResponse::SetSync { result: d.set_sync(sync) })
+            let res = RENDERER.run(move |k| {
+                let res = k.duplicate_layer(layer_id, new_mask);
+                if res {
+                    save_effects(k);
+                }
+                res
+            });
+            match res {
+                Some(true) => Some(comms::DaemonResponse::DuplicateLayer { result: true }),
+                Some(false) => Some(error_response(&format!("There is no effect layer {}", layer_id))),
+                None => Some(comms::DaemonResponse::DuplicateLayer { result: false }),
             }
-            comms::DaemonCommand::GetBrightness{ac} =>  {
-                Some(comms::DaemonResponse::GetBrightness { result: d.get_brightness(ac)})
-            },
-            comms::DaemonCommand::GetLogoLedState{ac} => Some(comms::DaemonResponse::GetLogoLedState {logo_state: d.get_logo_led_state(ac) }),
-            comms::DaemonCommand::GetKeyboardRGB { layer } => {
-                let map = EFFECT_MANAGER.lock().unwrap().get_map(layer);
-                Some(comms::DaemonResponse::GetKeyboardRGB {
-                    layer,
-                    rgbdata: map,
-                })
-            }
-            comms::DaemonCommand::GetSync() => Some(comms::DaemonResponse::GetSync { sync: d.get_sync() }),
-            comms::DaemonCommand::GetFanSpeed{ac} => Some(comms::DaemonResponse::GetFanSpeed { rpm: d.get_fan_rpm(ac)}),
-            comms::DaemonCommand::GetPwrLevel{ac} => Some(comms::DaemonResponse::GetPwrLevel { pwr: d.get_power_mode(ac) }),
-            comms::DaemonCommand::GetCPUBoost{ac} => Some(comms::DaemonResponse::GetCPUBoost { cpu: d.get_cpu_boost(ac) }),
-            comms::DaemonCommand::GetGPUBoost{ac} => Some(comms::DaemonResponse::GetGPUBoost { gpu: d.get_gpu_boost(ac) }),
-            comms::DaemonCommand::SetEffect{ name, params } => {
-                let mut res = false;
-                if let Ok(mut k) = EFFECT_MANAGER.lock() {
-                    res = true;
-                    let effect = match name.as_str() {
-                        "static" => Some(kbd::effects::Static::new(params)),
-                        "static_gradient" => Some(kbd::effects::StaticGradient::new(params)),
-                        "wave_gradient" => Some(kbd::effects::WaveGradient::new(params)),
-                        "breathing_single" => Some(kbd::effects::BreathSingle::new(params)),
-                        _ => None
-                    };
+        }
+        comms::DaemonCommand::SetLayerBrightness { layer, brightness } => {
+            let res = RENDERER.run(move |k| {
+                let res = k.set_layer_brightness(layer, brightness);
+                if res {
+                    save_effects(k);
+                }
+                res
+            });
+            Some(comms::DaemonResponse::SetLayerBrightness { result: res.unwrap_or(false) })
+        }
+        comms::DaemonCommand::SetKeyColor { index, r, g, b } => {
+            let res = RENDERER.run(move |k| {
+                let res = k.set_key_colour(index, r, g, b);
+                if res {
+                    save_effects(k);
+                }
+                res
+            });
+            Some(comms::DaemonResponse::SetKeyColor { result: res.unwrap_or(false) })
+        }
+        comms::DaemonCommand::ClearKeyColor { index } => {
+            let res = RENDERER.run(move |k| {
+                let res = k.clear_key_colour(index);
+                if res {
+                    save_effects(k);
+                }
+                res
+            });
+            Some(comms::DaemonResponse::ClearKeyColor { result: res.unwrap_or(false) })
+        }
+        comms::DaemonCommand::GetEffectLayers => {
+            let names = RENDERER.run(|k| k.get_layer_names()).unwrap_or_default();
+            Some(comms::DaemonResponse::GetEffectLayers { names })
+        }
+        comms::DaemonCommand::SetLogoBreathingSpeed { ac, speed } => {
+            Some(comms::DaemonResponse::SetLogoBreathingSpeed { result: d.set_logo_breathing_speed(ac, speed) })
+        }
+        comms::DaemonCommand::GetLogoBreathingSpeed { ac } => {
+            Some(comms::DaemonResponse::GetLogoBreathingSpeed { speed: d.get_logo_breathing_speed(ac) })
+        }
+        comms::DaemonCommand::ReloadConfig => {
+            let res = d.reload_config();
+            apply_effect_settings(d);
+            Some(comms::DaemonResponse::ReloadConfig { result: res })
+        }
+        comms::DaemonCommand::SetGamma { value } => {
+            // Checked first so an invalid value is never saved
+            let res = kbd::board::set_gamma(value) && d.set_gamma(value);
+            Some(comms::DaemonResponse::SetGamma { result: res })
+        }
+        comms::DaemonCommand::GetGamma => {
+            Some(comms::DaemonResponse::GetGamma { value: d.get_gamma() })
+        }
+        comms::DaemonCommand::SetWhiteBalance { r_mul, g_mul, b_mul } => {
+            // Checked first so an invalid value is never saved
+            let res = kbd::board::set_white_balance([r_mul, g_mul, b_mul])
+                && d.set_white_balance([r_mul, g_mul, b_mul]);
+            Some(comms::DaemonResponse::SetWhiteBalance { result: res })
+        }
+        comms::DaemonCommand::GetWhiteBalance => {
+            let [r_mul, g_mul, b_mul] = d.get_white_balance();
+            Some(comms::DaemonResponse::GetWhiteBalance { r_mul, g_mul, b_mul })
+        }
+        comms::DaemonCommand::SetAutoSwitch { enabled } => {
+            Some(comms::DaemonResponse::SetAutoSwitch { result: d.set_auto_switch(enabled) })
+        }
+        comms::DaemonCommand::GetAutoSwitch => {
+            Some(comms::DaemonResponse::GetAutoSwitch { enabled: d.get_auto_switch() })
+        }
+        comms::DaemonCommand::PreviewEffect { name, params, frames } => {
+            Some(comms::DaemonResponse::PreviewEffect { rgbdata: preview_effect(&name, params, frames) })
+        }
+        comms::DaemonCommand::SetCustomFrame { frame } => {
+            // Only what the keyboard shows now is stored, the effects keep
+            // rendering to the live frame
+            let shown = RENDERER.run(|k| k.get_shown_frame());
+            let mut res = false;
+            if let (Some(laptop), Some(mut shown)) = (d.get_device(), shown) {
+                res = laptop.set_custom_frame_index(frame);
+                if res && frame != device::RazerLaptop::LIVE_CUSTOM_FRAME {
+                    shown.store_kbd(laptop, frame);
+                }
+            }
+            Some(comms::DaemonResponse::SetCustomFrame { result: res })
+        }
+        comms::DaemonCommand::GetCustomFrame => {
+            let frame = d.get_device().map_or(device::RazerLaptop::LIVE_CUSTOM_FRAME, |laptop| laptop.get_custom_frame_index());
+            Some(comms::DaemonResponse::GetCustomFrame { frame })
+        }
+        comms::DaemonCommand::GetFullState => {
+            Some(comms::DaemonResponse::GetFullState { json: full_state(d).to_string() })
+        }
+        comms::DaemonCommand::GetActualFanRpm => {
+            Some(comms::DaemonResponse::GetActualFanRpm { rpm1: sensors::read_fan_rpm(1), rpm2: sensors::read_fan_rpm(2) })
+        }
 
-                    if let Some(laptop) = d.get_device() {
-                        if let Some(e) = effect {
-                            k.pop_effect(laptop); // Remove old layer
-                            k.push_effect(
-                                e,
-                                [true; 90]
-                                );
-                        } else {
-                            res = false
-                        }
-                    } else {
-                        res = false;
-                    }
+        comms::DaemonCommand::SetStandardEffect{ name, params } => {
+            // TODO save standart effect may be struct ?
+            let mut res = false;
+            if d.get_device().is_some() {
+                // Remove old layer, the hardware effect takes over the keyboard
+                let popped = RENDERER.run(|k| {
+                    k.pop_effect();
+                    k.skip_blank();
+                    save_effects(k);
+                });
+                if popped.is_some() {
+                    let _res = match name.as_str() {
+                        "off" => d.set_standard_effect(device::RazerLaptop::OFF, params),
+                        "wave" => d.set_standard_effect(device::RazerLaptop::WAVE, params),
+                        "reactive" => d.set_standard_effect(device::RazerLaptop::REACTIVE, params),
+                        "breathing" => d.set_standard_effect(device::RazerLaptop::BREATHING, params),
+                        "spectrum" => d.set_standard_effect(device::RazerLaptop::SPECTRUM, params),
+                        "static" => d.set_standard_effect(device::RazerLaptop::STATIC, params),
+                        "starlight" => d.set_standard_effect(device::RazerLaptop::STARLIGHT, params), 
+                        _ => false,
+                    };
+                    res = _res;
                 }
-                Some(comms::DaemonResponse::SetEffect{result: res})
-            }
-
-            comms::DaemonCommand::SetStandardEffect{ name, params } => {
-                // TODO save standart effect may be struct ?
-                let mut res = false;
-                if let Some(laptop) = d.get_device() {
-                    if let Ok(mut k) = EFFECT_MANAGER.lock() {
-                        k.pop_effect(laptop); // Remove old layer
-                        let _res = match name.as_str() {
-                            "off" => d.set_standard_effect(device::RazerLaptop::OFF, params),
-                            "wave" => d.set_standard_effect(device::RazerLaptop::WAVE, params),
-                            "reactive" => d.set_standard_effect(device::RazerLaptop::REACTIVE, params),
-                            "breathing" => d.set_standard_effect(device::RazerLaptop::BREATHING, params),
-                            "spectrum" => d.set_standard_effect(device::RazerLaptop::SPECTRUM, params),
-                            "static" => d.set_standard_effect(device::RazerLaptop::STATIC, params),
-                            "starlight" => d.set_standard_effect(device::RazerLaptop::STARLIGHT, params), 
-                            _ => false,
-                        };
-                        res = _res;
+            } else {
+                res = false;
+            }
+            Some(comms::DaemonResponse::SetStandardEffect{result: res})
+        }
+        comms::DaemonCommand::SetBatteryHealthOptimizer { is_on, threshold } => { 
+            return Some(comms::DaemonResponse::SetBatteryHealthOptimizer { result: d.set_bho_handler(is_on, threshold)});
+        }
+        comms::DaemonCommand::GetBatteryHealthOptimizer() => {
+            return Some(d.get_bho_handler().map_or_else(
+                || error_response("Could not read the battery health optimizer"),
+                |result| comms::DaemonResponse::GetBatteryHealthOptimizer {
+                    is_on: (result.0),
+                    threshold: (result.1)
+                }
+            ));
+        }
+        comms::DaemonCommand::GetDeviceName => {
+            let name = match d.get_device() {
+                Some(device) => device.get_name(),
+                None => "Unknown Device".into()
+            };
+            Some(comms::DaemonResponse::GetDeviceName { name })
+        }
+        comms::DaemonCommand::GetMatchInfo => {
+            let unmatched = d.get_unmatched_devices();
+            let info = match d.get_matched_device() {
+                Some((device, simulated)) => comms::DaemonResponse::GetMatchInfo {
+                    name: Some(device.name.clone()),
+                    // Hex strings of the laptops file, as in find_supported_device
+                    vendor_id: u16::from_str_radix(&device.vid, 16).ok(),
+                    product_id: u16::from_str_radix(&device.pid, 16).ok(),
+                    simulated,
+                    features: device.features.clone(),
+                    unmatched,
+                },
+                None => comms::DaemonResponse::GetMatchInfo {
+                    name: None,
+                    vendor_id: None,
+                    product_id: None,
+                    simulated: false,
+                    features: vec![],
+                    unmatched,
+                },
+            };
+            Some(info)
+        }
+        comms::DaemonCommand::GetSupportedFeatures => {
+            Some(d.get_device().map_or_else(
+                || error_response("No supported laptop was found"),
+                |laptop| {
+                    let (fan_min, fan_max) = laptop.get_fan_limits();
+                    comms::DaemonResponse::GetSupportedFeatures {
+                        features: laptop.get_features(),
+                        fan_min,
+                        fan_max,
+                        fan_step: laptop.get_fan_step(),
+                        has_logo: laptop.have_feature(String::from("logo")),
+                        can_boost: laptop.have_feature(String::from("boost")),
                     }
-                } else {
-                    res = false;
                 }
-                Some(comms::DaemonResponse::SetStandardEffect{result: res})
+            ))
+        }
+        comms::DaemonCommand::SetAnimationFps { fps } => {
+            let fps = kbd::set_animation_fps(fps);
+            d.set_animation_fps(fps);
+            Some(comms::DaemonResponse::SetAnimationFps { fps })
+        }
+        comms::DaemonCommand::GetEffect => {
+            let (name, params) = RENDERER.run(|k| k.get_active_effect())
+                .flatten()
+                .unwrap_or((String::from("none"), vec![]));
+            Some(comms::DaemonResponse::GetEffect { name, params })
+        }
+        comms::DaemonCommand::SetFanCurve { ac, points } => {
+            Some(comms::DaemonResponse::SetFanCurve { result: d.set_fan_curve(ac, points) })
+        }
+        comms::DaemonCommand::SetFanHysteresis { celsius } => {
+            Some(comms::DaemonResponse::SetFanHysteresis { result: d.set_fan_hysteresis(celsius) })
+        }
+        comms::DaemonCommand::GetFanHysteresis => {
+            Some(comms::DaemonResponse::GetFanHysteresis { celsius: d.get_fan_hysteresis() })
+        }
+        comms::DaemonCommand::SaveEffectPreset { name } => {
+            if !config::Configuration::valid_preset_name(&name) {
+                return Some(error_response("Preset names may only contain letters, digits, '-' and '_'"));
             }
-            comms::DaemonCommand::SetBatteryHealthOptimizer { is_on, threshold } => { 
-                return Some(comms::DaemonResponse::SetBatteryHealthOptimizer { result: d.set_bho_handler(is_on, threshold)});
+            let Some(json) = RENDERER.run(|k| k.save()) else {
+                return Some(error_response("The effects are not running"));
+            };
+            let res = match config::Configuration::write_effect_preset(&name, json) {
+                Ok(()) => true,
+                Err(e) => {
+                    error!("Error writing preset {}: {}", name, e);
+                    false
+                }
+            };
+            Some(comms::DaemonResponse::SaveEffectPreset { result: res })
+        }
+        comms::DaemonCommand::LoadEffectPreset { name } => {
+            if !config::Configuration::valid_preset_name(&name) {
+                return Some(error_response("Preset names may only contain letters, digits, '-' and '_'"));
             }
-            comms::DaemonCommand::GetBatteryHealthOptimizer() => {
-                return d.get_bho_handler().map(|result| 
-                    comms::DaemonResponse::GetBatteryHealthOptimizer {
-                        is_on: (result.0), 
-                        threshold: (result.1) 
-                    }
-                );
+            let json = match config::Configuration::read_effect_preset(&name) {
+                Ok(json) => json,
+                Err(e) => return Some(error_response(&format!("Could not read preset {}: {}", name, e))),
+            };
+            let res = RENDERER.run(move |k| {
+                k.clear();
+                k.load_from_save(json);
+                save_effects(k);
+            });
+            Some(comms::DaemonResponse::LoadEffectPreset { result: res.is_some() })
+        }
+        comms::DaemonCommand::ListEffectPresets => {
+            match config::Configuration::list_effect_presets() {
+                Ok(names) => Some(comms::DaemonResponse::ListEffectPresets { names }),
+                Err(e) => Some(error_response(&format!("Could not list the presets: {}", e))),
             }
-            comms::DaemonCommand::GetDeviceName => {
-                let name = match &d.device {
-                    Some(device) => device.get_name(),
-                    None => "Unknown Device".into()
-                };
-                return Some(comms::DaemonResponse::GetDeviceName { name });
+        }
+        comms::DaemonCommand::SwitchProfile { name } => {
+            if !config::Configuration::valid_preset_name(&name) {
+                return Some(error_response("Profile names may only contain letters, digits, '-' and '_'"));
             }
-
-        };
-    } else {
-        return None;
+            let profile = match config::Configuration::read_config_profile(&name) {
+                Ok(profile) => profile,
+                Err(e) => return Some(error_response(&format!("Could not read profile {}: {}", name, e))),
+            };
+            let mut res = d.switch_config_profile(&profile);
+            if res {
+                let effects = profile.effects;
+                res = RENDERER.run(move |k| {
+                    k.start_transition();
+                    k.clear();
+                    k.load_from_save(effects);
+                    save_effects(k);
+                }).is_some();
+            }
+            Some(comms::DaemonResponse::SwitchProfile { result: res })
+        }
+        comms::DaemonCommand::ListProfiles => {
+            match config::Configuration::list_config_profiles() {
+                Ok(names) => Some(comms::DaemonResponse::ListProfiles { names }),
+                Err(e) => Some(error_response(&format!("Could not list the profiles: {}", e))),
+            }
+        }
+        comms::DaemonCommand::SaveCurrentAsProfile { name } => {
+            if !config::Configuration::valid_preset_name(&name) {
+                return Some(error_response("Profile names may only contain letters, digits, '-' and '_'"));
+            }
+            let Some(effects) = RENDERER.run(|k| k.save()) else {
+                return Some(error_response("The effects are not running"));
+            };
+            let Some(profile) = d.get_config_profile(effects) else {
+                return Some(error_response("There are no settings to save"));
+            };
+            let res = match config::Configuration::write_config_profile(&name, &profile) {
+                Ok(()) => true,
+                Err(e) => {
+                    error!("Error writing profile {}: {}", name, e);
+                    false
+                }
+            };
+            Some(comms::DaemonResponse::SaveCurrentAsProfile { result: res })
+        }
+        comms::DaemonCommand::SetBrightnessCurve { enabled } => {
+            Some(comms::DaemonResponse::SetBrightnessCurve { result: d.set_brightness_curve(enabled) })
+        }
+        comms::DaemonCommand::GetBrightnessCurve => {
+            Some(comms::DaemonResponse::GetBrightnessCurve { enabled: d.get_brightness_curve() })
+        }
+        comms::DaemonCommand::SetEffectBackend { hardware } => {
+            Some(comms::DaemonResponse::SetEffectBackend { result: d.set_effect_backend(hardware) })
+        }
+        comms::DaemonCommand::GetEffectBackend => {
+            Some(comms::DaemonResponse::GetEffectBackend { hardware: d.get_effect_backend() })
+        }
+        comms::DaemonCommand::SetIdleColor { r, g, b } => {
+            let res = d.set_idle_color([r, g, b]);
+            // A colour that wasn't stored isn't shown either
+            if res {
+                let (_, last_static) = d.get_idle_color();
+                RENDERER.run(move |k| k.set_idle_colour([r, g, b], last_static));
+            }
+            Some(comms::DaemonResponse::SetIdleColor { result: res })
+        }
+        comms::DaemonCommand::GetIdleColor => {
+            let ([r, g, b], last_static) = d.get_idle_color();
+            Some(comms::DaemonResponse::GetIdleColor { r, g, b, last_static })
+        }
+        comms::DaemonCommand::AdjustBrightness { ac, delta } => {
+            let val = d.adjust_brightness(ac, delta);
+            Some(comms::DaemonResponse::AdjustBrightness {
+                result: val.is_some(),
+                val: val.unwrap_or_else(|| d.get_brightness(ac)),
+            })
+        }
+        comms::DaemonCommand::AdjustFanSpeed { ac, delta } => {
+            let rpm = d.adjust_fan_rpm(ac, delta);
+            Some(comms::DaemonResponse::AdjustFanSpeed {
+                result: rpm.is_some(),
+                rpm: rpm.unwrap_or_else(|| d.get_fan_rpm(ac)),
+            })
+        }
+        comms::DaemonCommand::SetLogoSync { enabled } => {
+            Some(comms::DaemonResponse::SetLogoSync { result: d.set_logo_sync(enabled) })
+        }
+        comms::DaemonCommand::GetLogoSync => {
+            Some(comms::DaemonResponse::GetLogoSync { enabled: d.get_logo_sync() })
+        }
+        comms::DaemonCommand::GetRenderedFrame { layer_id } => {
+            let rgbdata = RENDERER.run(move |k| k.get_rendered_frame(layer_id)).unwrap_or_default();
+            Some(comms::DaemonResponse::GetRenderedFrame { layer_id, rgbdata })
+        }
+        comms::DaemonCommand::Flash { r, g, b, duration_ms, count, region } => {
+            let device_name = d.get_device().map_or(String::new(), |laptop| laptop.get_name());
+            let Some(mask) = kbd::board::region_mask(&device_name, &region) else {
+                return Some(error_response(&format!("Unknown key region {}", region)));
+            };
+            // The effect clamps the pulses to the supported range, short of u8 overflowing
+            let pulse = (duration_ms.min(kbd::effects::FLASH_MAX_MS) / 10) as u8;
+            let Some(mut effect) = kbd::create_effect::<kbd::effects::Flash>(vec![r, g, b, pulse, count]) else {
+                return Some(error_response("Invalid flash"));
+            };
+            let args = effect.get_varargs().to_vec();
+            let total_ms = kbd::effects::Flash::total_ms(&args);
+            let result = RENDERER.run(move |k| k.push_timed_effect(effect, mask, total_ms)).is_some();
+            Some(comms::DaemonResponse::Flash { result, duration_ms: args[3] as u32 * 10, count: args[4] })
+        }
+        comms::DaemonCommand::ImportKeyColors { keys } => {
+            let device_name = d.get_device().map_or(String::new(), |laptop| laptop.get_name());
+            // Keys missing from the profile and the other zones stay black
+            let mut rgb = vec![0u8; 3 * kbd::board::key_count()];
+            let mut unmapped = vec![];
+            for (name, colour) in keys {
+                match kbd::board::key_name_index(&device_name, &name) {
+                    Some(index) => rgb[index * 3..index * 3 + 3].copy_from_slice(&colour),
+                    None => unmapped.push(name),
+                }
+            }
+            if !unmapped.is_empty() {
+                warn!("No key for {:?} on this keyboard", unmapped);
+            }
+            let result = RENDERER.run(move |k| k.set_full_frame(rgb)).unwrap_or(false);
+            Some(comms::DaemonResponse::ImportKeyColors { result, unmapped })
+        }
+        comms::DaemonCommand::SetBatterySaverLighting { enabled } => {
+            Some(comms::DaemonResponse::SetBatterySaverLighting { result: d.set_battery_saver_lighting(enabled) })
+        }
+        comms::DaemonCommand::GetBatterySaverLighting => {
+            Some(comms::DaemonResponse::GetBatterySaverLighting { enabled: d.get_battery_saver_lighting() })
+        }
+        comms::DaemonCommand::SetFanPercent { ac, percent } => {
+            let rpm = d.set_fan_percent(ac, percent);
+            Some(comms::DaemonResponse::SetFanPercent {
+                result: rpm.is_some(),
+                rpm: rpm.unwrap_or_else(|| d.get_fan_rpm(ac)),
+            })
+        }
+        comms::DaemonCommand::SetAppProfile { process_name, profile_name } => {
+            if process_name.trim().is_empty() {
+                return Some(error_response("The process name is empty"));
+            }
+            if !profile_name.is_empty() && !config::Configuration::valid_preset_name(&profile_name) {
+                return Some(error_response("Profile names may only contain letters, digits, '-' and '_'"));
+            }
+            Some(comms::DaemonResponse::SetAppProfile { result: d.set_app_profile(&process_name, &profile_name) })
+        }
+        comms::DaemonCommand::RandomEffect { seed } => {
+            let seed = seed.unwrap_or_else(rand::random);
+            let (name, params) = kbd::random_effect(seed);
+            info!("Random effect {} {:?} (seed {})", name, params, seed);
+            let result = set_effect(d, name, params.clone()).is_some();
+            Some(comms::DaemonResponse::RandomEffect { result, name: String::from(name), params, seed })
+        }
+        comms::DaemonCommand::FanTurbo { duration_secs } => {
+            if duration_secs == 0 || duration_secs > MAX_FAN_TURBO_SECS {
+                return Some(error_response(&format!("The turbo lasts 1 to {} seconds", MAX_FAN_TURBO_SECS)));
+            }
+            let Some(prior_rpm) = d.start_fan_turbo(duration_secs) else {
+                // The custom power mode has no fan control
+                return Some(comms::DaemonResponse::FanTurbo { result: false, prior_rpm: 0 });
+            };
+            thread::spawn(move || {
+                thread::sleep(std::time::Duration::from_secs(duration_secs as u64));
+                // A turbo extended in the meantime is ended by its own timer
+                if let Ok(mut d) = DEV_MANAGER.lock() {
+                    d.update_fan_turbo();
+                }
+            });
+            Some(comms::DaemonResponse::FanTurbo { result: true, prior_rpm })
+        }
+        comms::DaemonCommand::GetLayout => {
+            let (layout, source) = d.get_layout();
+            Some(comms::DaemonResponse::GetLayout { layout, source })
+        }
+        comms::DaemonCommand::SetLockIndicator { lock, r, g, b } => {
+            let lock = lock.trim().to_lowercase();
+            if !kbd::input::LOCKS.iter().any(|(name, _)| *name == lock) {
+                return Some(error_response(&format!("Unknown lock {}, expected capslock, numlock or scrolllock", lock)));
+            }
+            // Turning an indicator off always works
+            if [r, g, b] != [0, 0, 0] {
+                if kbd::input::lock_state(&lock).is_none() {
+                    return Some(error_response(&format!("The {} LED is not exposed, the indicator is unsupported", lock)));
+                }
+                if kbd::input::lock_key_index(&lock).is_none() {
+                    return Some(error_response(&format!("The keyboard has no {} key", lock)));
+                }
+            }
+            Some(comms::DaemonResponse::SetLockIndicator { result: d.set_lock_indicator(&lock, [r, g, b]) })
+        }
+        comms::DaemonCommand::SetEffectTransition { duration_ms } => {
+            let duration_ms = duration_ms.min(kbd::MAX_TRANSITION_MS);
+            let res = d.set_effect_transition(duration_ms)
+                && RENDERER.run(move |k| k.set_transition_ms(duration_ms)).is_some();
+            Some(comms::DaemonResponse::SetEffectTransition { result: res })
+        }
+        comms::DaemonCommand::GetEffectTransition => {
+            Some(comms::DaemonResponse::GetEffectTransition { duration_ms: d.get_effect_transition() })
+        }
+        comms::DaemonCommand::GetAnimationStats => {
+            let Some(stats) = RENDERER.run(|k| k.get_stats()) else {
+                return Some(error_response("The effects are not running"));
+            };
+            Some(comms::DaemonResponse::GetAnimationStats {
+                target_fps: stats.target_fps,
+                measured_fps: stats.measured_fps,
+                dropped_frames: stats.dropped_frames,
+            })
+        }
+        comms::DaemonCommand::ListPowerProfiles => {
+            Some(comms::DaemonResponse::ListPowerProfiles { profiles: d.get_power_modes() })
+        }
+        comms::DaemonCommand::SetMinEffectBrightness { value } => {
+            let res = d.set_min_effect_brightness(value)
+                && RENDERER.run(move |k| k.set_min_brightness(value)).is_some();
+            Some(comms::DaemonResponse::SetMinEffectBrightness { result: res })
+        }
+        comms::DaemonCommand::GetMinEffectBrightness => {
+            Some(comms::DaemonResponse::GetMinEffectBrightness { value: d.get_min_effect_brightness() })
+        }
+        comms::DaemonCommand::GetVersion => {
+            let device_path = d.get_device_path();
+            let (driver, attributes) = device::hidraw_driver(&device_path);
+            Some(comms::DaemonResponse::GetVersion {
+                version: String::from(env!("CARGO_PKG_VERSION")),
+                device_path,
+                driver,
+                attributes,
+            })
+        }
+        comms::DaemonCommand::BlankKeyboard => {
+            let res = RENDERER.run(|k| k.blank_keyboard());
+            Some(comms::DaemonResponse::BlankKeyboard { result: res.is_some() })
+        }
+        comms::DaemonCommand::RestoreKeyboard => {
+            let res = RENDERER.run(|k| k.restore_keyboard());
+            Some(comms::DaemonResponse::RestoreKeyboard { result: res.unwrap_or(false) })
+        }
+        comms::DaemonCommand::GetPowerSource => {
+            // The state the daemon last saw, the debounce may still hold its settings back
+            let source = match d.get_device().map(|laptop| laptop.get_ac_state()) {
+                Some(1) => "AC",
+                Some(_) => "BAT",
+                None => "UNK",
+            };
+            Some(comms::DaemonResponse::GetPowerSource { source: source.into() })
+        }
+        comms::DaemonCommand::ListDevices => {
+            Some(comms::DaemonResponse::ListDevices {
+                devices: d.list_devices(),
+                selected: d.get_selected_device(),
+            })
+        }
+        // Handled by process_client_request, so this one was sent to a device
+        comms::DaemonCommand::OnDevice { .. } => Some(error_response("A command sent to a device can't be sent to another one")),
+        comms::DaemonCommand::SelectDevice { index } => {
+            let keys = kbd::board::zone_key_count();
+            let result = d.select_device(index);
+            // The masks of the layers are only valid for the layout they were made for
+            if result && kbd::board::zone_key_count() != keys {
+                RENDERER.run(|k| {
+                    k.clear();
+                    save_effects(k);
+                });
+            }
+            Some(comms::DaemonResponse::SelectDevice { result })
+        }
+        comms::DaemonCommand::SetFullFrame { rgb } => {
+            let res = RENDERER.run(move |k| k.set_full_frame(rgb));
+            Some(comms::DaemonResponse::SetFullFrame { result: res.unwrap_or(false) })
+        }
+        comms::DaemonCommand::ResumeEffect => {
+            let res = RENDERER.run(|k| k.resume_effects());
+            Some(comms::DaemonResponse::ResumeEffect { result: res.unwrap_or(false) })
+        }
+        comms::DaemonCommand::PauseEffects => {
+            let res = RENDERER.run(|k| k.pause_animation());
+            Some(comms::DaemonResponse::PauseEffects { result: res.unwrap_or(false) })
+        }
+        comms::DaemonCommand::ResumeEffects => {
+            let res = RENDERER.run(|k| k.resume_animation());
+            Some(comms::DaemonResponse::ResumeEffects { result: res.unwrap_or(false) })
+        }
+        comms::DaemonCommand::ListEffects => {
+            let effects = kbd::EFFECTS
+                .iter()
+                .map(|info| (info.name.to_string(), info.command.to_string()))
+                .collect();
+            Some(comms::DaemonResponse::ListEffects { effects })
+        }
+        comms::DaemonCommand::GetFanCurve { ac } => {
+            Some(comms::DaemonResponse::GetFanCurve { points: d.get_fan_curve(ac) })
+        }
+        comms::DaemonCommand::GetCpuTemperature => {
+            Some(sensors::read_cpu_temperature().map_or_else(
+                || error_response("No CPU temperature sensor was found"),
+                |celsius| comms::DaemonResponse::GetTemperature { celsius }
+            ))
+        }
+        // Answered above, without holding the device manager
+        comms::DaemonCommand::GetGpuTemperature => Some(error_response("The GPU temperature is read elsewhere")),
+        comms::DaemonCommand::GetBatteryState => {
+            Some(sensors::read_battery_state().map_or_else(
+                || error_response("No battery was found"),
+                |(capacity, charging)| comms::DaemonResponse::GetBatteryState { capacity, charging }
+            ))
+        }
+        comms::DaemonCommand::GetBatteryHealth => {
+            Some(match sensors::read_battery_health() {
+                Some(health) => comms::DaemonResponse::GetBatteryHealth {
+                    available: true,
+                    cycle_count: health.cycle_count,
+                    design_capacity: health.design_capacity,
+                    full_capacity: health.full_capacity,
+                    unit: String::from(health.unit),
+                    health_percent: health.health_percent(),
+                },
+                None => comms::DaemonResponse::GetBatteryHealth {
+                    available: false,
+                    cycle_count: None,
+                    design_capacity: 0,
+                    full_capacity: 0,
+                    unit: String::new(),
+                    health_percent: 0.0,
+                },
+            })
+        }
     }
 }
 
+/// Everything the clients can read, gathered with the same getters as the
+/// single commands. Settings are listed for both power sources
+fn full_state(d: &mut device::DeviceManager) -> serde_json::Value {
+    let power_state = |d: &mut device::DeviceManager, ac: usize| {
+        serde_json::json!({
+            "brightness": d.get_brightness(ac),
+            "logo": d.get_logo_led_state(ac),
+            "logo_breathing_speed": d.get_logo_breathing_speed(ac),
+            "power_mode": d.get_power_mode(ac),
+            "cpu_boost": d.get_cpu_boost(ac),
+            "gpu_boost": d.get_gpu_boost(ac),
+            "fan_rpm": d.get_fan_rpm(ac),
+            "fan_curve": d.get_fan_curve(ac),
+        })
+    };
+    let device_name = d.get_device().map(|laptop| laptop.get_name());
+    let ac_online = d.get_device().map(|laptop| laptop.get_ac_state() == 1);
+    // Read together, so they come from the same frame
+    let (effect, layers, paused) = RENDERER
+        .run(|k| (k.get_active_effect(), k.get_layer_names(), k.is_paused()))
+        .unwrap_or((None, vec![], false));
+
+    serde_json::json!({
+        "device": device_name,
+        "power_source": ac_online.map(|online| if online { "ac" } else { "battery" }),
+        "sync": d.get_sync(),
+        "auto_switch": d.get_auto_switch(),
+        "bho": d.get_bho_handler().map(|(is_on, threshold)| serde_json::json!({
+            "is_on": is_on,
+            "threshold": threshold,
+        })),
+        "effect": effect.map(|(name, params)| serde_json::json!({
+            "name": name,
+            "params": params,
+        })),
+        "effect_layers": layers,
+        "effects_paused": paused,
+        "measured_fan_rpm": [sensors::read_fan_rpm(1), sensors::read_fan_rpm(2)],
+        "ac": power_state(d, 1),
+        "battery": power_state(d, 0),
+    })
+}
+
+/// Response sent when a command fails, so the client can show why
+fn error_response(message: &str) -> comms::DaemonResponse {
+    comms::DaemonResponse::Error { message: message.into() }
+}
+
 
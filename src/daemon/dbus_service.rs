@@ -0,0 +1,230 @@
+//! Optional D-Bus service, so desktop widgets and scripts can use the daemon
+//! without speaking the socket protocol. Needs the `zbus` feature and
+//! `dbus_enabled` in the config.
+
+use crate::comms;
+
+/// Well-known name and object path on the session bus
+#[cfg(feature = "zbus")]
+const BUS_NAME: &str = "org.razer.LaptopControl";
+#[cfg(feature = "zbus")]
+const OBJECT_PATH: &str = "/org/razer/LaptopControl";
+
+#[cfg(feature = "zbus")]
+mod service {
+    use super::*;
+    use log::*;
+    use std::sync::OnceLock;
+    use zbus::{blocking, fdo, interface, SignalContext};
+
+    /// Kept alive for as long as the daemon runs, the service stops with it
+    static CONNECTION: OnceLock<blocking::Connection> = OnceLock::new();
+
+    /// The properties describe the current power source, the methods take it
+    /// as an argument like the socket commands do
+    struct LaptopControl {
+        process: fn(comms::DaemonCommand) -> Option<comms::DaemonResponse>,
+        current_ac: fn() -> usize,
+    }
+
+    impl LaptopControl {
+        fn send(&self, command: comms::DaemonCommand) -> fdo::Result<comms::DaemonResponse> {
+            match (self.process)(command) {
+                Some(comms::DaemonResponse::Error { message }) => Err(fdo::Error::Failed(message)),
+                Some(response) => Ok(response),
+                None => Err(fdo::Error::Failed(String::from("Command failed"))),
+            }
+        }
+    }
+
+    fn unexpected(response: comms::DaemonResponse) -> fdo::Error {
+        fdo::Error::Failed(format!("Unexpected daemon response {:?}", response))
+    }
+
+    #[interface(name = "org.razer.LaptopControl")]
+    impl LaptopControl {
+        /// Brightness in percent of the given power source
+        async fn set_brightness(
+            &self,
+            ac: bool,
+            value: u8,
+            #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        ) -> fdo::Result<bool> {
+            match self.send(comms::DaemonCommand::SetBrightness { ac: ac as usize, val: value })? {
+                comms::DaemonResponse::SetBrightness { result } => {
+                    let _ = self.brightness_changed(&ctxt).await;
+                    Ok(result)
+                }
+                response => Err(unexpected(response)),
+            }
+        }
+
+        /// Power mode (0 balanced, 1 gaming, 2 creator, 3 silent, 4 custom) with
+        /// the CPU and GPU boost of the custom mode, 0 for the other modes
+        async fn set_power_mode(
+            &self,
+            ac: bool,
+            mode: u8,
+            cpu_boost: u8,
+            gpu_boost: u8,
+            #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        ) -> fdo::Result<bool> {
+            let command = comms::DaemonCommand::SetPowerMode { ac: ac as usize, pwr: mode, cpu: cpu_boost, gpu: gpu_boost };
+            match self.send(command)? {
+                comms::DaemonResponse::SetPowerMode { result } => {
+                    let _ = self.power_mode_changed(&ctxt).await;
+                    Ok(result)
+                }
+                response => Err(unexpected(response)),
+            }
+        }
+
+        /// Fixed fan speed, 0 lets the firmware decide
+        async fn set_fan_speed(
+            &self,
+            ac: bool,
+            rpm: i32,
+            #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        ) -> fdo::Result<bool> {
+            match self.send(comms::DaemonCommand::SetFanSpeed { ac: ac as usize, rpm })? {
+                comms::DaemonResponse::SetFanSpeed { result } => {
+                    let _ = self.fan_speed_changed(&ctxt).await;
+                    Ok(result)
+                }
+                response => Err(unexpected(response)),
+            }
+        }
+
+        /// Same names and arguments as `razer-cli effect`
+        async fn set_effect(
+            &self,
+            name: String,
+            params: Vec<u8>,
+            #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        ) -> fdo::Result<bool> {
+            match self.send(comms::DaemonCommand::SetEffect { name, params })? {
+                comms::DaemonResponse::SetEffect { result, .. } => {
+                    let _ = self.effect_changed(&ctxt).await;
+                    Ok(result)
+                }
+                response => Err(unexpected(response)),
+            }
+        }
+
+        #[zbus(property)]
+        fn on_ac(&self) -> bool {
+            (self.current_ac)() == 1
+        }
+
+        #[zbus(property)]
+        fn brightness(&self) -> fdo::Result<u8> {
+            match self.send(comms::DaemonCommand::GetBrightness { ac: (self.current_ac)() })? {
+                comms::DaemonResponse::GetBrightness { result } => Ok(result),
+                response => Err(unexpected(response)),
+            }
+        }
+
+        #[zbus(property)]
+        fn power_mode(&self) -> fdo::Result<u8> {
+            match self.send(comms::DaemonCommand::GetPwrLevel { ac: (self.current_ac)() })? {
+                comms::DaemonResponse::GetPwrLevel { pwr } => Ok(pwr),
+                response => Err(unexpected(response)),
+            }
+        }
+
+        #[zbus(property)]
+        fn fan_speed(&self) -> fdo::Result<i32> {
+            match self.send(comms::DaemonCommand::GetFanSpeed { ac: (self.current_ac)() })? {
+                comms::DaemonResponse::GetFanSpeed { rpm } => Ok(rpm),
+                response => Err(unexpected(response)),
+            }
+        }
+
+        #[zbus(property)]
+        fn effect(&self) -> fdo::Result<String> {
+            match self.send(comms::DaemonCommand::GetEffect)? {
+                comms::DaemonResponse::GetEffect { name, .. } => Ok(name),
+                response => Err(unexpected(response)),
+            }
+        }
+
+        #[zbus(property)]
+        fn battery_capacity(&self) -> fdo::Result<u8> {
+            match self.send(comms::DaemonCommand::GetBatteryState)? {
+                comms::DaemonResponse::GetBatteryState { capacity, .. } => Ok(capacity),
+                response => Err(unexpected(response)),
+            }
+        }
+
+        #[zbus(property)]
+        fn charging(&self) -> fdo::Result<bool> {
+            match self.send(comms::DaemonCommand::GetBatteryState)? {
+                comms::DaemonResponse::GetBatteryState { charging, .. } => Ok(charging),
+                response => Err(unexpected(response)),
+            }
+        }
+    }
+
+    pub fn start(
+        process: fn(comms::DaemonCommand) -> Option<comms::DaemonResponse>,
+        current_ac: fn() -> usize,
+    ) -> bool {
+        let connection = blocking::connection::Builder::session()
+            .and_then(|builder| builder.name(BUS_NAME))
+            .and_then(|builder| builder.serve_at(OBJECT_PATH, LaptopControl { process, current_ac }))
+            .and_then(|builder| builder.build());
+        match connection {
+            Ok(connection) => {
+                info!("D-Bus service available as {}", BUS_NAME);
+                let _ = CONNECTION.set(connection);
+                true
+            }
+            Err(e) => {
+                error!("Could not start the D-Bus service: {}", e);
+                false
+            }
+        }
+    }
+
+    pub fn power_source_changed() {
+        let Some(connection) = CONNECTION.get() else {
+            return;
+        };
+        let iface = match connection.object_server().interface::<_, LaptopControl>(OBJECT_PATH) {
+            Ok(iface) => iface,
+            Err(_) => return,
+        };
+        let ctxt = iface.signal_context();
+        let control = iface.get();
+        let _ = zbus::block_on(control.on_ac_changed(ctxt));
+        let _ = zbus::block_on(control.brightness_changed(ctxt));
+        let _ = zbus::block_on(control.power_mode_changed(ctxt));
+        let _ = zbus::block_on(control.fan_speed_changed(ctxt));
+    }
+}
+
+/// Registers the service on the session bus, false when it could not
+#[cfg(feature = "zbus")]
+pub fn start(
+    process: fn(comms::DaemonCommand) -> Option<comms::DaemonResponse>,
+    current_ac: fn() -> usize,
+) -> bool {
+    service::start(process, current_ac)
+}
+
+#[cfg(not(feature = "zbus"))]
+pub fn start(
+    _process: fn(comms::DaemonCommand) -> Option<comms::DaemonResponse>,
+    _current_ac: fn() -> usize,
+) -> bool {
+    log::warn!("The D-Bus service is enabled but the daemon was built without the zbus feature");
+    false
+}
+
+/// Tells the D-Bus clients the settings of the other power source are in use
+/// now. Must not be called with the device manager locked, the new values are
+/// read through the daemon commands.
+pub fn power_source_changed() {
+    #[cfg(feature = "zbus")]
+    service::power_source_changed();
+}
@@ -2,14 +2,55 @@
 use serde::{Deserialize, Serialize};
 use serde_big_array::BigArray;
 use std::{thread, time, io, fs};
+use std::collections::HashMap;
+use std::path::Path;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
 use hidapi::HidApi;
+use log::*;
 use crate::dbus_mutter_idlemonitor;
 use crate::config;
+use crate::kbd;
 use crate::battery;
+use crate::sensors;
 use dbus::blocking::Connection;
 
 const RAZER_VENDOR_ID: u16 = 0x1532;
 
+/// Fastest software logo breathing speed
+pub const LOGO_BREATHING_MAX_SPEED: u8 = 10;
+
+/// Length of one logo breath at speed 1, speed n breathes n times faster
+const LOGO_BREATHING_SLOWEST_MS: u128 = 8000;
+
+/// Power modes of the firmware: the value written, its name and the feature
+/// a device needs to have it
+const POWER_MODES: [(u8, &str, Option<&str>); 5] = [
+    (0, "Balanced", None),
+    (1, "Gaming", None),
+    (2, "Creator", Some("creator_mode")),
+    (3, "Silent", None),
+    (4, "Custom", None),
+];
+
+/// The power source has to stay the same this long before its settings are
+/// applied, so a flaky charger doesn't thrash the fans
+const AC_SWITCH_DEBOUNCE: time::Duration = time::Duration::from_millis(3000);
+
+/// Backlight levels are written through `perceptual_brightness`
+static BRIGHTNESS_CURVE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_brightness_curve(enabled: bool) {
+    BRIGHTNESS_CURVE.store(enabled, Ordering::Relaxed);
+}
+
+/// A breathing logo follows the breathing keyboard effect
+static LOGO_SYNC: AtomicBool = AtomicBool::new(false);
+
+pub fn set_logo_sync(enabled: bool) {
+    LOGO_SYNC.store(enabled, Ordering::Relaxed);
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SupportedDevice {
     pub name: String,
@@ -17,6 +58,35 @@ pub struct SupportedDevice {
     pub pid: String,
     pub features: Vec<String>,
     pub fan: Vec<u16>,
+    /// Smallest fan speed change in RPM the device accepts
+    #[serde(default = "default_fan_step")]
+    pub fan_step: u16,
+    /// LED zones, the keyboard and for example a light bar. No entry of the
+    /// shipped laptops.json declares more than one: the extra zones are sent
+    /// as rows 6 and up of the custom frame, which is only known to work on
+    /// the models an entry opts in for
+    #[serde(default = "default_zones")]
+    pub zones: u8,
+    /// Columns of the key matrix, 15 on the Blade layout and more on models
+    /// with a numpad
+    #[serde(default = "default_keys_per_row")]
+    pub keys_per_row: u8,
+    /// Linux key code of every key index, row by row with 0 where there is no
+    /// key, for the reactive effects. The Blade table when empty
+    #[serde(default)]
+    pub key_codes: Vec<u16>,
+}
+
+fn default_keys_per_row() -> u8 {
+    kbd::board::DEFAULT_KEYS_PER_ROW as u8
+}
+
+fn default_fan_step() -> u16 {
+    100
+}
+
+fn default_zones() -> u8 {
+    1
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -72,27 +142,55 @@ impl RazerPacket {
     }
 }
 
-const DEVICE_FILE: &str = "/usr/share/razercontrol/laptops.json";
+pub const DEVICE_FILE: &str = "/usr/share/razercontrol/laptops.json";
+
+/// A laptop found at startup
+struct ConnectedDevice {
+    laptop: RazerLaptop,
+    serial: String,
+    supported: usize, // entry of the laptops file, for the key layout
+    path: String, // hidraw device node, empty when simulated
+    simulated: bool, // stand-in of --simulate rather than matched by its USB ids
+}
+
 pub struct DeviceManager {
-    pub device: Option <RazerLaptop>,
+    devices: Vec<ConnectedDevice>,
+    unmatched: Vec<(u16, u16)>, // USB ids of the Razer devices without an entry in the laptops file
+    selected: usize, // device the commands and effects go to
     supported_devices: Vec<SupportedDevice>,
     pub config: Option <config::Configuration>,
     pub idle_id: u32,
     pub active_id: u32,
     add_active: bool,
     pub change_idle: bool,
+    pending_ac: Option<(bool, time::Instant)>, // power source waiting for the debounce
+    brightness_fade: Option<(u8, u8, u128, u128)>, // from, to, start ms, duration ms
+    idle_saved_brightness: Option<u8>, // set while dimmed by the idle timeout
+    fan_curve_last: Option<f32>, // temperature the fan curve last acted on
+    app_profile: Option<config::Profile>, // applied over the power config while a watched program runs
+    layout_source: &'static str, // where the keyboard layout comes from: config, detected or default
+    fan_turbo: Option<(i32, u128)>, // rpm to go back to and the end in ms, while the fans run at full speed
 }
 
 impl DeviceManager {
     pub fn new () -> DeviceManager {
         return DeviceManager {
-            device: None,
+            devices: vec![],
+            unmatched: vec![],
+            selected: 0,
             supported_devices: vec![],
             config: None,
             idle_id: 0,
             active_id: 0,
             add_active: false,
             change_idle: false,
+            pending_ac: None,
+            brightness_fade: None,
+            idle_saved_brightness: None,
+            fan_curve_last: None,
+            app_profile: None,
+            layout_source: "default",
+            fan_turbo: None,
         };
     }
 
@@ -111,7 +209,7 @@ impl DeviceManager {
                     self.remove_watch(proxy_idle);
                 }
                 if let Ok(id) = proxy_idle.add_idle_watch(timeout) {
-                    println!("idle handler {:?}", id);
+                    debug!("idle handler {:?}", id);
                     self.idle_id = id;
                 }
             } else {
@@ -133,16 +231,409 @@ impl DeviceManager {
             config.sync = sync;
             config.power[other].brightness = config.power[ac].brightness;
             config.power[other].logo_state = config.power[ac].logo_state;
+            config.power[other].logo_breathing_speed = config.power[ac].logo_breathing_speed;
             config.power[other].screensaver = config.power[ac].screensaver;
             config.power[other].idle = config.power[ac].idle;
             if let Err(e) = config.write_to_file() {
-                eprintln!("Error write config {:?}", e);
+                error!("Error write config {:?}", e);
+            }
+        }
+
+        true
+    }
+
+    pub fn set_animation_fps(&mut self, fps: u8) -> bool {
+        if let Some(config) = self.get_config() {
+            config.animation_fps = fps;
+            if let Err(e) = config.write_to_file() {
+                error!("Error write config {:?}", e);
+            }
+        }
+
+        true
+    }
+
+    pub fn get_animation_fps(&mut self) -> u8 {
+        if let Some(config) = self.get_config() {
+            return config.animation_fps;
+        }
+
+        kbd::DEFAULT_ANIMATION_FPS
+    }
+
+    pub fn set_gamma(&mut self, gamma: f32) -> bool {
+        if let Some(config) = self.get_config() {
+            config.gamma = gamma;
+            if let Err(e) = config.write_to_file() {
+                error!("Error write config {:?}", e);
+            }
+        }
+
+        true
+    }
+
+    /// Switches the perceptual brightness curve, the backlight is written
+    /// again so the change shows right away
+    pub fn set_brightness_curve(&mut self, enabled: bool) -> bool {
+        if let Some(config) = self.get_config() {
+            config.brightness_curve = enabled;
+            if let Err(e) = config.write_to_file() {
+                error!("Error write config {:?}", e);
+            }
+        }
+        if let Some(laptop) = self.get_device() {
+            let level = laptop.get_brightness();
+            set_brightness_curve(enabled);
+            laptop.set_brightness(level);
+        } else {
+            set_brightness_curve(enabled);
+        }
+
+        true
+    }
+
+    /// Drives a breathing logo from the keyboard breathing effect, the logo
+    /// is written again so the change shows right away
+    pub fn set_logo_sync(&mut self, enabled: bool) -> bool {
+        if let Some(config) = self.get_config() {
+            config.logo_sync = enabled;
+            if let Err(e) = config.write_to_file() {
+                error!("Error write config {:?}", e);
+            }
+        }
+        set_logo_sync(enabled);
+        let ac = self.get_device().map_or(0, |laptop| laptop.get_ac_state());
+        let speed = self.get_logo_breathing_speed(ac);
+        if let Some(laptop) = self.get_device() {
+            laptop.set_logo_breathing_speed(speed);
+        }
+
+        true
+    }
+
+    pub fn get_logo_sync(&mut self) -> bool {
+        if let Some(config) = self.get_config() {
+            return config.logo_sync;
+        }
+
+        false
+    }
+
+    pub fn set_battery_saver_lighting(&mut self, enabled: bool) -> bool {
+        if let Some(config) = self.get_config() {
+            config.battery_saver_lighting = enabled;
+            if let Err(e) = config.write_to_file() {
+                error!("Error write config {:?}", e);
+            }
+        }
+
+        true
+    }
+
+    pub fn get_battery_saver_lighting(&mut self) -> bool {
+        if let Some(config) = self.get_config() {
+            return config.battery_saver_lighting;
+        }
+
+        false
+    }
+
+    /// True when the effects should stand still: on battery with the
+    /// battery saver lighting on
+    pub fn get_battery_saver_active(&mut self) -> bool {
+        let on_battery = self.get_device().is_some_and(|laptop| laptop.get_ac_state() == 0);
+        on_battery && self.get_battery_saver_lighting()
+    }
+
+    /// Maps a process name to a profile, an empty profile name removes the
+    /// mapping of the process
+    pub fn set_app_profile(&mut self, process_name: &str, profile_name: &str) -> bool {
+        if let Some(config) = self.get_config() {
+            config.app_profiles.retain(|app| app.process_name != process_name);
+            if !profile_name.is_empty() {
+                config.app_profiles.push(config::AppProfile {
+                    process_name: process_name.to_string(),
+                    profile_name: profile_name.to_string(),
+                });
+            }
+            if let Err(e) = config.write_to_file() {
+                error!("Error write config {:?}", e);
+            }
+        }
+
+        true
+    }
+
+    pub fn get_app_profiles(&mut self) -> Vec<config::AppProfile> {
+        if let Some(config) = self.get_config() {
+            return config.app_profiles.clone();
+        }
+
+        vec![]
+    }
+
+    /// Applies the power and fan settings of a profile over those of the
+    /// current power state, None goes back to the stored settings. The
+    /// config is left untouched. Only written when the old or the new profile
+    /// sets them, the backlight and the logo are never touched
+    pub fn apply_app_profile(&mut self, profile: Option<config::Profile>) -> bool {
+        // The power mode is sent along with the fan speed, so both are written
+        let sets_power = |profile: &Option<config::Profile>| {
+            profile.as_ref().is_some_and(|p| {
+                p.power_mode.is_some() || p.cpu_boost.is_some() || p.gpu_boost.is_some() || p.fan_rpm.is_some()
+            })
+        };
+        let changed = sets_power(&self.app_profile) || sets_power(&profile);
+        self.app_profile = profile;
+        if !changed {
+            return true;
+        }
+        let mut ac: usize = 0;
+        if let Some(laptop) = self.get_device() {
+            ac = laptop.get_ac_state();
+        }
+        let Some(config) = self.get_ac_config(ac).map(|config| self.with_app_profile(config)) else {
+            return false;
+        };
+        let mut res = false;
+        if let Some(laptop) = self.get_device() {
+            res = laptop.set_power_mode(config.power_mode, config.cpu_boost, config.gpu_boost);
+            res &= laptop.set_fan_rpm(config.fan_rpm as u16);
+        }
+        // The curve takes over again without a fixed speed of the profile
+        self.fan_turbo = None;
+        self.fan_curve_last = None;
+        self.update_fan_curve();
+
+        res
+    }
+
+    // the stored settings with those of the app profile on top
+    fn with_app_profile(&self, mut config: config::PowerConfig) -> config::PowerConfig {
+        if let Some(profile) = &self.app_profile {
+            config.power_mode = profile.power_mode.unwrap_or(config.power_mode);
+            config.cpu_boost = profile.cpu_boost.unwrap_or(config.cpu_boost);
+            config.gpu_boost = profile.gpu_boost.unwrap_or(config.gpu_boost);
+            config.fan_rpm = profile.fan_rpm.unwrap_or(config.fan_rpm);
+        }
+
+        config
+    }
+
+    pub fn get_brightness_curve(&mut self) -> bool {
+        if let Some(config) = self.get_config() {
+            return config.brightness_curve;
+        }
+
+        false
+    }
+
+    pub fn set_idle_color(&mut self, rgb: [u8; 3]) -> bool {
+        if let Some(config) = self.get_config() {
+            config.idle_color = rgb;
+            if let Err(e) = config.write_to_file() {
+                error!("Error write config {:?}", e);
             }
         }
 
         return true;
     }
 
+    /// Colour of the keyboard without effects, and whether the last static
+    /// effect replaces it
+    pub fn get_idle_color(&mut self) -> ([u8; 3], bool) {
+        if let Some(config) = self.get_config() {
+            return (config.idle_color, config.idle_color_last_static);
+        }
+
+        ([0, 0, 0], false)
+    }
+
+    pub fn get_gamma(&mut self) -> f32 {
+        if let Some(config) = self.get_config() {
+            return config.gamma;
+        }
+
+        kbd::board::DEFAULT_GAMMA
+    }
+
+    /// Stores the white balance, false if there is no configuration to keep
+    /// it in
+    pub fn set_white_balance(&mut self, multipliers: [u16; 3]) -> bool {
+        if let Some(config) = self.get_config() {
+            config.white_balance = multipliers;
+            if let Err(e) = config.write_to_file() {
+                error!("Error write config {:?}", e);
+            }
+            return true;
+        }
+
+        false
+    }
+
+    pub fn get_white_balance(&mut self) -> [u16; 3] {
+        if let Some(config) = self.get_config() {
+            return config.white_balance;
+        }
+
+        [kbd::board::WHITE_BALANCE_ONE; 3]
+    }
+
+    /// Address and token of the TCP listener, None when TCP mode is off
+    pub fn get_tcp_config(&mut self) -> Option<(String, String)> {
+        if let Some(config) = self.get_config().filter(|config| config.tcp_enabled) {
+            return Some((config.tcp_address.clone(), config.tcp_token.clone()));
+        }
+
+        None
+    }
+
+    pub fn get_websocket_config(&mut self) -> Option<(String, String)> {
+        if let Some(config) = self.get_config().filter(|c| c.websocket_enabled) {
+            return Some((config.websocket_address.clone(), config.websocket_token.clone()));
+        }
+
+        None
+    }
+
+    /// Mode and group of the socket, an invalid mode is ignored with a warning
+    pub fn get_socket_permissions(&mut self) -> (Option<u32>, String) {
+        if let Some(config) = self.get_config() {
+            let mode = config.socket_mode.trim();
+            if mode.is_empty() {
+                return (None, config.socket_group.clone());
+            }
+            match u32::from_str_radix(mode, 8) {
+                Ok(mode) if mode <= 0o777 => return (Some(mode), config.socket_group.clone()),
+                _ => {
+                    warn!("Invalid socket mode {:?}, expected an octal mode such as \"660\"", mode);
+                    return (None, config.socket_group.clone());
+                }
+            }
+        }
+
+        (None, String::new())
+    }
+
+    pub fn get_dbus_enabled(&mut self) -> bool {
+        if let Some(config) = self.get_config() {
+            return config.dbus_enabled;
+        }
+
+        false
+    }
+
+    pub fn set_auto_switch(&mut self, enabled: bool) -> bool {
+        if let Some(config) = self.get_config() {
+            config.auto_switch = enabled;
+            if let Err(e) = config.write_to_file() {
+                error!("Error write config {:?}", e);
+            }
+        }
+
+        true
+    }
+
+    pub fn get_auto_switch(&mut self) -> bool {
+        if let Some(config) = self.get_config() {
+            return config.auto_switch;
+        }
+
+        true
+    }
+
+    pub fn get_gpu_temperature_source(&mut self) -> String {
+        if let Some(config) = self.get_config() {
+            return config.gpu_temperature_source.clone();
+        }
+
+        String::new()
+    }
+
+    pub fn get_blank_on_exit(&mut self) -> bool {
+        if let Some(config) = self.get_config() {
+            return config.blank_on_exit;
+        }
+
+        true
+    }
+
+    pub fn set_effect_backend(&mut self, hardware: bool) -> bool {
+        if let Some(config) = self.get_config() {
+            config.hardware_effects = hardware;
+            if let Err(e) = config.write_to_file() {
+                error!("Error write config {:?}", e);
+            }
+        }
+
+        true
+    }
+
+    /// True when the effects the controller supports run on it
+    pub fn get_effect_backend(&mut self) -> bool {
+        if let Some(config) = self.get_config() {
+            return config.hardware_effects;
+        }
+
+        false
+    }
+
+    pub fn set_idle_timeout(&mut self, seconds: u32) -> bool {
+        if let Some(config) = self.get_config() {
+            config.idle_timeout = seconds;
+            if let Err(e) = config.write_to_file() {
+                error!("Error write config {:?}", e);
+            }
+        }
+
+        true
+    }
+
+    pub fn get_idle_timeout(&mut self) -> u32 {
+        if let Some(config) = self.get_config() {
+            return config.idle_timeout;
+        }
+
+        0
+    }
+
+    /// Dims the backlight once no key was touched for the idle timeout and
+    /// brings the saved brightness back on the next key event.
+    /// Returns true while the backlight is dimmed
+    pub fn update_input_idle(&mut self, last_activity_ms: Option<u128>) -> bool {
+        let mut timeout: u128 = 0;
+        let mut dim_to: u8 = 0;
+        if let Some(config) = self.get_config() {
+            timeout = config.idle_timeout as u128 * 1000;
+            dim_to = (config.idle_brightness.min(100) as u16 * 255 / 100) as u8;
+        }
+        let idle = match last_activity_ms {
+            Some(last) if timeout != 0 => kbd::get_millis().saturating_sub(last) >= timeout,
+            _ => false,
+        };
+
+        match (idle, self.idle_saved_brightness) {
+            (true, None) => {
+                // A fade that didn't finish is restored to its target
+                let fade_target = self.brightness_fade.take().map(|(_, to, _, _)| to);
+                if let Some(laptop) = self.get_device() {
+                    let saved = fade_target.unwrap_or_else(|| laptop.get_brightness());
+                    laptop.set_brightness(dim_to);
+                    self.idle_saved_brightness = Some(saved);
+                }
+            }
+            (false, Some(saved)) => {
+                self.idle_saved_brightness = None;
+                if let Some(laptop) = self.get_device() {
+                    laptop.set_brightness(saved);
+                }
+            }
+            _ => {}
+        }
+
+        self.idle_saved_brightness.is_some()
+    }
+
     pub fn get_sync(&mut self) -> bool {
         if let Some(config) = self.get_config() {
             return config.sync;
@@ -153,14 +644,14 @@ impl DeviceManager {
 
     fn remove_watch(&mut self, proxy_idle: &dyn dbus_mutter_idlemonitor::OrgGnomeMutterIdleMonitor) {
         if let Ok(_) = proxy_idle.remove_watch(self.idle_id) {
-            println!("remove idle handler");
+            debug!("remove idle handler");
         }
     }
 
     pub fn add_active_watch(&mut self, proxy_idle: &dyn dbus_mutter_idlemonitor::OrgGnomeMutterIdleMonitor) {
         if self.add_active {
             if let Ok(id) = proxy_idle.add_user_active_watch() {
-                println!("active handler {:?}", id);
+                debug!("active handler {:?}", id);
                 self.active_id = id;
             }
         }
@@ -170,10 +661,15 @@ impl DeviceManager {
         let str: Vec<u8> = fs::read(DEVICE_FILE)?;
         let mut res: DeviceManager = DeviceManager::new();
         res.supported_devices = serde_json::from_slice(str.as_slice())?;
-        println!("suported devices found: {:?}", res.supported_devices.len());
+        info!("suported devices found: {:?}", res.supported_devices.len());
         match config::Configuration::read_from_config() {
             Ok(c) => res.config = Some(c),
-            Err(_) => res.config = Some(config::Configuration::new()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => res.config = Some(config::Configuration::new()),
+            Err(e) => {
+                // Truncated or edited badly, it is replaced with the next change
+                warn!("Could not read the config, using the defaults: {}", e);
+                res.config = Some(config::Configuration::new());
+            }
         }
 
         Ok(res)
@@ -181,7 +677,7 @@ impl DeviceManager {
 
     fn get_ac_config(&mut self, ac: usize) -> Option<config::PowerConfig> {
         if let Some(c) = self.get_config() {
-            return Some(c.power[ac]);
+            return c.power.get(ac).copied();
         }
 
         return None;
@@ -239,7 +735,7 @@ impl DeviceManager {
                     config.power[other].idle = timeout;
                 }
                 if let Err(e) = config.write_to_file() {
-                    eprintln!("Error write config {:?}", e);
+                    error!("Error write config {:?}", e);
                 }
                 // arm = true;
                 self.change_idle = true;
@@ -249,14 +745,97 @@ impl DeviceManager {
         return true;
     }
 
+    /// Checks the boosts against the device, the CPU boost 3 needs the boost
+    /// feature
+    fn valid_boosts(&mut self, cpu: u8, gpu: u8) -> bool {
+        if cpu > 3 || gpu > 2 {
+            return false;
+        }
+        if cpu == 3 {
+            return self.get_device().is_some_and(|laptop| laptop.have_feature(String::from("boost")));
+        }
+
+        true
+    }
+
+    /// Power modes of the selected device, none without a device
+    pub fn get_power_modes(&mut self) -> Vec<(u8, String)> {
+        if let Some(laptop) = self.get_device() {
+            return laptop.get_power_modes();
+        }
+
+        vec![]
+    }
+
+    /// The boosts are only taken with the custom power mode (4), the other
+    /// modes need them left at 0 and keep the stored ones for later
     pub fn set_power_mode(&mut self, ac: usize, pwr: u8, cpu: u8, gpu: u8) -> bool {
+        if ac > 1 || !self.valid_boosts(cpu, gpu) {
+            return false;
+        }
+        if !self.get_power_modes().iter().any(|(mode, _)| *mode == pwr) {
+            return false;
+        }
+        if pwr != 4 && (cpu != 0 || gpu != 0) {
+            return false;
+        }
         let mut res: bool = false;
         if let Some(config) = self.get_config() {
             config.power[ac].power_mode = pwr;
+            if pwr == 4 {
+                config.power[ac].cpu_boost = cpu;
+                config.power[ac].gpu_boost = gpu;
+            }
+            if let Err(e) = config.write_to_file() {
+                error!("Error write config {:?}", e);
+            }
+        }
+        if let Some(laptop) = self.get_device() {
+            let state = laptop.get_ac_state();
+            if state != ac {
+                res = true;
+            } else {
+                res = laptop.set_power_mode(pwr, cpu, gpu);
+            }
+        }
+
+        res
+    }
+
+    /// Changes only the CPU boost, leaving the power mode and GPU boost alone
+    pub fn set_cpu_boost(&mut self, ac: usize, cpu: u8) -> bool {
+        if ac > 1 || !self.valid_boosts(cpu, 0) {
+            return false;
+        }
+        let mut res: bool = false;
+        if let Some(config) = self.get_config() {
             config.power[ac].cpu_boost = cpu;
+            if let Err(e) = config.write_to_file() {
+                error!("Error write config {:?}", e);
+            }
+        }
+        if let Some(laptop) = self.get_device() {
+            let state = laptop.get_ac_state();
+            if state != ac {
+                res = true;
+            } else {
+                res = laptop.set_custom_cpu_boost(cpu);
+            }
+        }
+
+        res
+    }
+
+    /// Changes only the GPU boost, leaving the power mode and CPU boost alone
+    pub fn set_gpu_boost(&mut self, ac: usize, gpu: u8) -> bool {
+        if ac > 1 || !self.valid_boosts(0, gpu) {
+            return false;
+        }
+        let mut res: bool = false;
+        if let Some(config) = self.get_config() {
             config.power[ac].gpu_boost = gpu;
             if let Err(e) = config.write_to_file() {
-                eprintln!("Error write config {:?}", e);
+                error!("Error write config {:?}", e);
             }
         }
         if let Some(laptop) = self.get_device() {
@@ -264,7 +843,7 @@ impl DeviceManager {
             if state != ac {
                 res = true;
             } else {
-                res = laptop.set_power_mode(pwr, cpu, gpu);
+                res = laptop.set_custom_gpu_boost(gpu);
             }
         }
 
@@ -276,7 +855,7 @@ impl DeviceManager {
             config.standard_effect = effect_id;
             config.standard_effect_params = params.clone();
             if let Err(e) = config.write_to_file() {
-                eprintln!("Error write config {:?}", e);
+                error!("Error write config {:?}", e);
             }
         }
         if let Some(laptop) = self.get_device() {
@@ -288,10 +867,13 @@ impl DeviceManager {
 
     pub fn set_fan_rpm(&mut self, ac:usize, rpm: i32) -> bool {
         let mut res: bool = false;
+        self.fan_turbo = None;
         if let Some(config) = self.get_config() {
             config.power[ac].fan_rpm = rpm;
+            // A fixed speed replaces the fan curve
+            config.fan_curves[ac].clear();
             if let Err(e) = config.write_to_file() {
-                eprintln!("Error write config {:?}", e);
+                error!("Error write config {:?}", e);
             }
         }
              
@@ -307,94 +889,417 @@ impl DeviceManager {
         return res;
     }
 
+    pub fn set_fan_curve(&mut self, ac: usize, mut points: Vec<(u8, i32)>) -> bool {
+        points.sort_by_key(|p| p.0);
+        points.dedup_by_key(|p| p.0);
+        self.fan_turbo = None;
+        let mut rpm: i32 = 0;
+        if let Some(config) = self.get_config() {
+            config.fan_curves[ac] = points.clone();
+            rpm = config.power[ac].fan_rpm;
+            if let Err(e) = config.write_to_file() {
+                error!("Error write config {:?}", e);
+            }
+        }
+
+        if points.is_empty() {
+            // Back to the fixed speed
+            if let Some(laptop) = self.get_device() {
+                if laptop.get_ac_state() == ac {
+                    return laptop.set_fan_rpm(rpm as u16);
+                }
+            }
+        } else {
+            self.fan_curve_last = None;
+            self.update_fan_curve();
+        }
+
+        return true;
+    }
+
+    pub fn set_fan_hysteresis(&mut self, celsius: u8) -> bool {
+        if let Some(config) = self.get_config() {
+            config.fan_hysteresis = celsius;
+            if let Err(e) = config.write_to_file() {
+                error!("Error write config {:?}", e);
+            }
+        }
+
+        return true;
+    }
+
+    pub fn set_min_effect_brightness(&mut self, percent: u8) -> bool {
+        if percent > 100 {
+            return false;
+        }
+        if let Some(config) = self.get_config() {
+            config.min_effect_brightness = percent;
+            if let Err(e) = config.write_to_file() {
+                error!("Error write config {:?}", e);
+            }
+        }
+
+        return true;
+    }
+
+    pub fn get_min_effect_brightness(&mut self) -> u8 {
+        if let Some(config) = self.get_config() {
+            return config.min_effect_brightness;
+        }
+
+        return 0;
+    }
+
+    /// Lights the key of `lock` with `color` while the lock is on, black
+    /// removes the indicator
+    pub fn set_lock_indicator(&mut self, lock: &str, color: [u8; 3]) -> bool {
+        if let Some(config) = self.get_config() {
+            config.lock_indicators.retain(|indicator| indicator.lock != lock);
+            if color != [0, 0, 0] {
+                config.lock_indicators.push(config::LockIndicator { lock: String::from(lock), color });
+            }
+            if let Err(e) = config.write_to_file() {
+                error!("Error write config {:?}", e);
+            }
+        }
+
+        return true;
+    }
+
+    pub fn get_lock_indicators(&mut self) -> Vec<config::LockIndicator> {
+        if let Some(config) = self.get_config() {
+            return config.lock_indicators.clone();
+        }
+
+        return vec![];
+    }
+
+    pub fn set_effect_transition(&mut self, duration_ms: u32) -> bool {
+        if let Some(config) = self.get_config() {
+            config.effect_transition_ms = duration_ms.min(kbd::MAX_TRANSITION_MS);
+            if let Err(e) = config.write_to_file() {
+                error!("Error write config {:?}", e);
+            }
+        }
+
+        return true;
+    }
+
+    pub fn get_effect_transition(&mut self) -> u32 {
+        if let Some(config) = self.get_config() {
+            return config.effect_transition_ms;
+        }
+
+        return 0;
+    }
+
+    pub fn get_fan_hysteresis(&mut self) -> u8 {
+        if let Some(config) = self.get_config() {
+            return config.fan_hysteresis;
+        }
+
+        return 0;
+    }
+
+    pub fn get_fan_curve(&mut self, ac: usize) -> Vec<(u8, i32)> {
+        if let Some(config) = self.get_config() {
+            return config.fan_curves[ac].clone();
+        }
+
+        return vec![];
+    }
+
+    /// Applies the fan curve of the current power state for the current CPU
+    /// temperature. Called periodically by the daemon.
+    pub fn update_fan_curve(&mut self) {
+        let mut ac: usize = 0;
+        if let Some(laptop) = self.get_device() {
+            ac = laptop.get_ac_state();
+        }
+        let points = self.get_fan_curve(ac);
+        // A fixed speed of the app profile replaces the curve, a turbo pauses it
+        let profile_rpm = self.app_profile.as_ref().and_then(|profile| profile.fan_rpm);
+        if points.is_empty() || profile_rpm.is_some() || self.fan_turbo.is_some() {
+            return;
+        }
+
+        if let Some(temp) = sensors::read_cpu_temperature() {
+            let hysteresis = self.get_fan_hysteresis() as f32;
+            if !fan_curve_follows(self.fan_curve_last, temp, hysteresis) {
+                return;
+            }
+            if let Some(laptop) = self.get_device() {
+                let rpm = laptop.clamp_fan_rpm(interpolate_fan_curve(&points, temp));
+                if laptop.get_fan_rpm() as i32 != rpm {
+                    laptop.set_fan_rpm(rpm as u16);
+                }
+                self.fan_curve_last = Some(temp);
+            }
+        }
+    }
+
+    /// Runs the fans at full speed for `duration_secs`, pausing the fan curve,
+    /// and returns the speed they go back to (0 = auto). A running turbo is
+    /// extended, a new fan setting or power source ends it
+    pub fn start_fan_turbo(&mut self, duration_secs: u32) -> Option<i32> {
+        let until = kbd::get_millis() + duration_secs as u128 * 1000;
+        let running = self.fan_turbo.map(|(prior, _)| prior);
+        let laptop = self.get_device()?;
+        let prior = running.unwrap_or(laptop.get_fan_rpm() as i32);
+        let (_, max) = laptop.get_fan_limits();
+        if !laptop.set_fan_rpm(max) {
+            return None;
+        }
+        self.fan_turbo = Some((prior, until));
+
+        return Some(prior);
+    }
+
+    /// Puts the fans back once the turbo is over, the fan curve takes over
+    /// again if there is one
+    pub fn update_fan_turbo(&mut self) {
+        let Some((prior, until)) = self.fan_turbo else {
+            return;
+        };
+        if kbd::get_millis() < until {
+            return;
+        }
+        self.fan_turbo = None;
+        info!("Fan turbo over, back to {} RPM", prior);
+        if let Some(laptop) = self.get_device() {
+            laptop.set_fan_rpm(prior as u16);
+        }
+        self.fan_curve_last = None;
+        self.update_fan_curve();
+    }
+
     pub fn set_logo_led_state(&mut self, ac:usize, logo_state: u8) -> bool {
+        if ac > 1 || logo_state > 2 {
+            return false;
+        }
         let mut res: bool = false;
+        let mut sync = false;
         if let Some(config) = self.get_config() {
             config.power[ac].logo_state = logo_state;
-            if config.sync {
+            sync = config.sync;
+            if sync {
                 let other = (ac + 1) & 0x01;
                 config.power[other].logo_state = logo_state;
             }
             if let Err(e) = config.write_to_file() {
-                eprintln!("Error write config {:?}", e);
+                error!("Error write config {:?}", e);
             }
         }
              
         if let Some(laptop) = self.get_device() {
             let state = laptop.get_ac_state();
            
+            // When synced the state of the current power source changed too
+            if state != ac && !sync {
+                res = true;
+            } else {
+                res = laptop.set_logo_led_state(logo_state);
+            }
+        }
+
+        res
+    }
+
+    pub fn get_logo_led_state(&mut self, ac: usize) -> u8 {
+        // if let Some(laptop) = self.get_device() {
+            // if laptop.ac_state as usize == ac {
+                // return laptop.get_logo_led_state();
+            // }
+        // }
+    
+        if let Some(config) = self.get_ac_config(ac) {
+            return config.logo_state;
+        }
+
+        0
+    }
+
+    pub fn set_logo_breathing_speed(&mut self, ac: usize, speed: u8) -> bool {
+        let speed = speed.min(LOGO_BREATHING_MAX_SPEED);
+        if let Some(config) = self.get_config() {
+            config.power[ac].logo_breathing_speed = speed;
+            if config.sync {
+                let other = (ac + 1) & 0x01;
+                config.power[other].logo_breathing_speed = speed;
+            }
+            if let Err(e) = config.write_to_file() {
+                error!("Error write config {:?}", e);
+            }
+        }
+
+        // The other power source only gets it stored
+        match self.get_device() {
+            Some(laptop) => laptop.get_ac_state() != ac || laptop.set_logo_breathing_speed(speed),
+            None => true,
+        }
+    }
+
+    pub fn get_logo_breathing_speed(&mut self, ac: usize) -> u8 {
+        if let Some(config) = self.get_ac_config(ac) {
+            return config.logo_breathing_speed;
+        }
+
+        0
+    }
+
+    /// Steps the software logo breathing, does nothing while the hardware breathes
+    pub fn update_logo_breathing(&mut self) {
+        if let Some(laptop) = self.get_device() {
+            laptop.update_logo_breathing();
+        }
+    }
+
+    pub fn set_brightness(&mut self, ac:usize, brightness: u8) -> bool {
+        let mut res: bool = false;
+        let _val = brightness as u16  * 255 / 100;
+        self.store_brightness(ac, _val as u8);
+        self.brightness_fade = None;
+ 
+        if let Some(laptop) = self.get_device() {
+            let state = laptop.get_ac_state();
             if state != ac {
                 res = true;
             } else {
-                res = laptop.set_logo_led_state(logo_state);
+                res = laptop.set_brightness(_val as u8);
+            }
+        }
+
+        res
+    }
+
+    /// Moves the brightness by `delta` percent, clamped to 0..=100, and
+    /// returns the new percentage, None if it couldn't be written
+    pub fn adjust_brightness(&mut self, ac: usize, delta: i32) -> Option<u8> {
+        let brightness = step_brightness(self.get_brightness(ac), delta);
+        if !self.set_brightness(ac, brightness) {
+            return None;
+        }
+
+        Some(brightness)
+    }
+
+    /// Moves the fixed fan speed by `delta` RPM within the range of the
+    /// device and returns the new speed, None if it couldn't be written
+    pub fn adjust_fan_rpm(&mut self, ac: usize, delta: i32) -> Option<i32> {
+        let (min, max) = self.get_device()?.get_fan_limits();
+        let rpm = step_fan_rpm(self.get_fan_rpm(ac), delta, min, max);
+        if !self.set_fan_rpm(ac, rpm) {
+            return None;
+        }
+
+        Some(rpm)
+    }
+
+    /// Sets the fan speed in percent of the fan range of the device, 0 for
+    /// automatic. Returns the RPM it maps to, `None` above 100 percent.
+    pub fn set_fan_percent(&mut self, ac: usize, percent: u8) -> Option<i32> {
+        if percent > 100 {
+            return None;
+        }
+        let laptop = self.get_device()?;
+        let (min, max) = laptop.get_fan_limits();
+        let rpm = fan_percent_rpm(percent, min, max, laptop.get_fan_step());
+        if !self.set_fan_rpm(ac, rpm) {
+            return None;
+        }
+
+        Some(rpm)
+    }
+
+    /// Like `set_brightness` but ramps from the current brightness over
+    /// `duration_ms`, the steps are written by `update_brightness_fade`
+    pub fn set_brightness_smooth(&mut self, ac: usize, brightness: u8, duration_ms: u32) -> bool {
+        let mut res: bool = false;
+        let val = (brightness.min(100) as u16 * 255 / 100) as u8;
+        self.store_brightness(ac, val);
+
+        let mut fade = None;
+        if let Some(laptop) = self.get_device() {
+            if laptop.get_ac_state() != ac {
+                res = true;
+            } else if duration_ms == 0 {
+                res = laptop.set_brightness(val);
+            } else {
+                fade = Some((laptop.get_brightness(), val, kbd::get_millis(), duration_ms as u128));
+                res = true;
             }
         }
+        self.brightness_fade = fade;
 
-        return res;
+        res
     }
 
-    pub fn get_logo_led_state(&mut self, ac: usize) -> u8 {
-        // if let Some(laptop) = self.get_device() {
-            // if laptop.ac_state as usize == ac {
-                // return laptop.get_logo_led_state();
-            // }
-        // }
-    
-        if let Some(config) = self.get_ac_config(ac) {
-            return config.logo_state;
+    /// Writes the next step of a brightness fade, called every frame
+    pub fn update_brightness_fade(&mut self) {
+        let Some(level) = self.brightness_fade_level() else {
+            return;
+        };
+        if self.brightness_fade.is_some_and(|(_, _, start, duration)| kbd::get_millis().saturating_sub(start) >= duration) {
+            self.brightness_fade = None;
+        }
+        if let Some(laptop) = self.get_device() {
+            laptop.set_brightness(level);
         }
+    }
 
-        return 0;
+    /// Raw brightness a running fade is at, None when there is no fade
+    fn brightness_fade_level(&self) -> Option<u8> {
+        let (from, to, start, duration) = self.brightness_fade?;
+        let progress = (kbd::get_millis().saturating_sub(start) as f32 / duration as f32).min(1.0);
+
+        Some((from as f32 + (to as f32 - from as f32) * progress).round() as u8)
     }
 
-    pub fn set_brightness(&mut self, ac:usize, brightness: u8) -> bool {
-        let mut res: bool = false;
-        let _val = brightness as u16  * 255 / 100;
+    fn store_brightness(&mut self, ac: usize, val: u8) {
         if let Some(config) = self.get_config() {
-            config.power[ac].brightness = _val as u8;
+            config.power[ac].brightness = val;
             if config.sync {
                 let other = (ac + 1) & 0x01;
-                config.power[other].brightness = _val as u8;
+                config.power[other].brightness = val;
             }
             if let Err(e) = config.write_to_file() {
-                eprintln!("Error write config {:?}", e);
-            }
-        }
- 
-        if let Some(laptop) = self.get_device() {
-            let state = laptop.get_ac_state();
-            if state != ac {
-                res = true;
-            } else {
-                res = laptop.set_brightness(_val as u8);
+                error!("Error write config {:?}", e);
             }
         }
-
-        return res;
     }
 
     pub fn get_brightness(&mut self, ac: usize) -> u8 {
         if let Some(laptop) = self.get_device() {
             if laptop.ac_state as usize == ac {
-                let val = laptop.get_brightness() as u32;
-                let mut perc = val * 100 * 100/ 255;
-                perc += 50;
-                perc /= 100;
-                return perc as u8;
+                return brightness_to_percent(laptop.get_brightness());
             }
         }
 
         if let Some(config) = self.get_ac_config(ac) {
-            let val = config.brightness as u32;
-            let mut perc = val * 100 * 100/ 255;
-            perc += 50;
-            perc /= 100;
-            return perc as u8;
+            return brightness_to_percent(config.brightness);
         }
 
         return 0
     }
 
+    /// Brightness percentage of the active power profile, read from the
+    /// config so it is cheap enough to be called every frame
+    pub fn get_effect_brightness(&mut self) -> u8 {
+        // Follow a fade so the effects don't jump to the target
+        if let Some(level) = self.brightness_fade_level() {
+            return brightness_to_percent(level);
+        }
+        let mut ac: usize = 0;
+        if let Some(laptop) = self.get_device() {
+            ac = laptop.get_ac_state();
+        }
+        if let Some(config) = self.get_ac_config(ac) {
+            return brightness_to_percent(config.brightness);
+        }
+
+        100
+    }
+
     pub fn get_fan_rpm(&mut self, ac: usize) -> i32 {
         if let Some(laptop) = self.get_device() {
             if laptop.ac_state as usize == ac {
@@ -451,17 +1356,112 @@ impl DeviceManager {
         return 0;
     }
 
+    /// Called when the power source changes, the switch happens once it
+    /// stayed the same for `AC_SWITCH_DEBOUNCE`
+    pub fn queue_ac_state(&mut self, ac: bool) {
+        self.pending_ac = Some((ac, time::Instant::now()));
+    }
+
+    /// Switches to a queued power source once it settled, true when it did
+    pub fn apply_pending_ac_state(&mut self) -> bool {
+        if let Some((ac, _)) = self.pending_ac.filter(|(_, since)| since.elapsed() >= AC_SWITCH_DEBOUNCE) {
+            self.pending_ac = None;
+            let mut current: Option<usize> = None;
+            if let Some(laptop) = self.get_device() {
+                current = Some(laptop.get_ac_state());
+            }
+            if current != Some(ac as usize) {
+                self.set_ac_state(ac);
+                return true;
+            }
+        }
+
+        false
+    }
+
     pub fn set_ac_state(&mut self, ac: bool) {
         if let Some(laptop) = self.get_device() {
             laptop.set_ac_state(ac);
         }
+        if !self.get_auto_switch() {
+            info!("Automatic switching is off, keeping the current settings");
+            return;
+        }
         self.change_idle = true;
-        let config: Option<config::PowerConfig> = self.get_ac_config(ac as usize);
+        self.apply_ac_config(ac as usize);
+    }
+
+    /// Applies the stored settings of a power state to the laptop
+    fn apply_ac_config(&mut self, ac: usize) -> bool {
+        let mut res = false;
+        self.brightness_fade = None;
+        // The new brightness is the one to restore, it is dimmed again if still idle
+        self.idle_saved_brightness = None;
+        let config: Option<config::PowerConfig> = self.get_ac_config(ac)
+            .map(|config| self.with_app_profile(config));
         if let Some(config) = config {
             if let Some(laptop) = self.get_device() {
-                laptop.set_config(config);
+                res = laptop.set_config(config);
+            }
+        }
+        // The fixed rpm was just applied, don't wait for the next tick of the curve
+        self.fan_turbo = None;
+        self.fan_curve_last = None;
+        self.update_fan_curve();
+
+        res
+    }
+
+    /// Reads the config file again and applies it to the current power state,
+    /// for edits made while the daemon is running
+    pub fn reload_config(&mut self) -> bool {
+        config::Configuration::cancel_write();
+        match config::Configuration::read_from_config() {
+            Ok(c) => self.config = Some(c),
+            Err(e) => {
+                error!("Error read config {:?}", e);
+                return false;
             }
         }
+        self.apply_config()
+    }
+
+    /// The settings a config profile holds, with these effect layers
+    pub fn get_config_profile(&mut self, effects: serde_json::Value) -> Option<config::ConfigProfile> {
+        let config = self.get_config()?;
+        Some(config::ConfigProfile { power: config.power, fan_curves: config.fan_curves.clone(), effects })
+    }
+
+    /// Puts the settings of a config profile in place, stores and applies them
+    pub fn switch_config_profile(&mut self, profile: &config::ConfigProfile) -> bool {
+        let Some(config) = self.get_config() else {
+            return false;
+        };
+        config.power = profile.power;
+        config.fan_curves = profile.fan_curves.clone();
+        if let Err(e) = config.write_to_file() {
+            error!("Error write config {:?}", e);
+        }
+        let mut ac: usize = 0;
+        if let Some(laptop) = self.get_device() {
+            ac = laptop.get_ac_state();
+        }
+
+        self.apply_ac_config(ac)
+    }
+
+    /// Applies the settings that were read again as a whole
+    fn apply_config(&mut self) -> bool {
+        set_brightness_curve(self.get_brightness_curve());
+        set_logo_sync(self.get_logo_sync());
+        self.apply_layout();
+        self.change_idle = true;
+        let mut ac: usize = 0;
+        if let Some(laptop) = self.get_device() {
+            ac = laptop.get_ac_state();
+        }
+
+        self.apply_ac_config(ac)
     }
 
     pub fn set_ac_state_get(&mut self) {
@@ -474,18 +1474,114 @@ impl DeviceManager {
                 laptop.set_ac_state(online);
             }
             self.change_idle = true;
-            let config: Option<config::PowerConfig> = self.get_ac_config(online as usize);
-            if let Some(config) = config {
-                if let Some(laptop) = self.get_device() {
-                    laptop.set_config(config);
-                }
-            }
+            self.apply_ac_config(online as usize);
         }
 
     }
 
     pub fn get_device(&mut self) -> Option<&mut RazerLaptop> {
-        return self.device.as_mut();
+        self.devices.get_mut(self.selected).map(|d| &mut d.laptop)
+    }
+
+    /// Name and serial number of every connected device, in the order of the
+    /// indexes `select_device` takes
+    pub fn list_devices(&self) -> Vec<(String, String)> {
+        self.devices.iter()
+            .map(|d| (d.laptop.get_name(), d.serial.clone()))
+            .collect()
+    }
+
+    pub fn get_selected_device(&self) -> usize {
+        self.selected
+    }
+
+    /// Index of the device with this index or serial number
+    pub fn find_device(&self, device: &str) -> Option<usize> {
+        match device.parse::<usize>() {
+            Ok(index) if index < self.devices.len() => Some(index),
+            _ => self.devices.iter().position(|d| d.serial == device),
+        }
+    }
+
+    /// Points the commands at another device without applying the settings
+    /// and the layout to it as `select_device` does, gives back the device
+    /// they went to
+    pub fn target_device(&mut self, index: usize) -> usize {
+        std::mem::replace(&mut self.selected, index)
+    }
+
+    /// hidraw device node of the selected device, empty when simulated
+    pub fn get_device_path(&self) -> String {
+        self.devices.get(self.selected).map(|d| d.path.clone()).unwrap_or_default()
+    }
+
+    /// Sends the following commands and effects to another connected device.
+    /// The settings of the current power source are applied to it, they are
+    /// shared by all the devices
+    pub fn select_device(&mut self, index: usize) -> bool {
+        if index >= self.devices.len() {
+            return false;
+        }
+        let ac = self.get_device().map_or(0, |laptop| laptop.get_ac_state());
+        self.selected = index;
+        self.apply_layout();
+        if let Some(laptop) = self.get_device() {
+            laptop.set_ac_state(ac == 1);
+        }
+        self.apply_ac_config(ac);
+
+        true
+    }
+
+    /// Sets the key layout of the effects to the one of the selected device
+    fn apply_layout(&mut self) {
+        let Some(device) = self.devices.get(self.selected) else {
+            return;
+        };
+        let supported = &self.supported_devices[device.supported];
+        let zones = kbd::board::set_zone_count(supported.zones);
+        if zones > 1 {
+            info!("{} declares {} LED zones, writing rows past the keyboard", supported.name, zones);
+        }
+        kbd::board::set_keys_per_row(supported.keys_per_row);
+        if !kbd::input::set_key_codes(supported.key_codes.clone()) {
+            warn!("The key codes of {} don't match its layout, using the Blade table", supported.name);
+        }
+        let (layout, source) = self.resolve_layout();
+        if source == "default" {
+            warn!("Unknown layout for {}, using the default 90-key ANSI layout", supported.name);
+        } else {
+            info!("Keyboard layout: {} ({})", layout.name(), source);
+        }
+        kbd::board::set_layout(layout);
+        self.layout_source = source;
+    }
+
+    /// Layout of the selected device: the one of the config, else the one of
+    /// the model, else ANSI
+    fn resolve_layout(&self) -> (kbd::board::Layout, &'static str) {
+        let configured = self.config.as_ref().map_or("", |config| config.keyboard_layout.as_str());
+        if !configured.is_empty() {
+            match kbd::board::Layout::from_name(configured) {
+                Some(layout) => return (layout, "config"),
+                None => warn!("Invalid keyboard_layout {} in the configuration, detecting it", configured),
+            }
+        }
+        let detected = self
+            .devices
+            .get(self.selected)
+            .map(|device| &self.supported_devices[device.supported])
+            .and_then(|supported| kbd::board::detect_layout(&supported.name, supported.keys_per_row));
+        match detected {
+            Some(layout) => (layout, "detected"),
+            None => (kbd::board::Layout::Ansi, "default"),
+        }
+    }
+
+    /// Name of the layout in use and where it comes from: config, detected
+    /// or default
+    pub fn get_layout(&self) -> (String, String) {
+        (String::from(kbd::board::layout().name()), String::from(self.layout_source))
     }
 
     pub fn set_bho_handler(&mut self, is_on: bool, threshold: u8) -> bool {
@@ -507,14 +1603,53 @@ impl DeviceManager {
         // self.device = Some(device);
     // }
 
-    pub fn find_supported_device(&mut self, vid: u16, pid: u16) -> Option<&SupportedDevice> {
-        for device in &self.supported_devices {
-            // Unwrap: we control the strings and know they are are valid
-            let svid = u16::from_str_radix(&device.vid, 16).unwrap();
-            let spid = u16::from_str_radix(&device.pid, 16).unwrap();
+    /// Uses a simulated laptop instead of the connected devices, the entry of
+    /// the laptops file called `name` or the first one when empty
+    pub fn simulate_device(&mut self, name: &str) -> bool {
+        let found = self.supported_devices.iter()
+            .position(|d| name.is_empty() || d.name == name);
+        let Some(supported) = found else {
+            return false;
+        };
+        let supported_device = &self.supported_devices[supported];
+        let laptop = RazerLaptop::new_simulated(
+            supported_device.name.clone(),
+            supported_device.features.clone(),
+            supported_device.fan.clone(),
+            supported_device.fan_step,
+        );
+        info!("Simulating {}", laptop.get_name());
+        self.devices = vec![ConnectedDevice { laptop, serial: String::from("simulated"), supported, path: String::new(), simulated: true }];
+        self.selected = 0;
+        self.apply_layout();
+
+        true
+    }
+
+    /// Entry of the laptops file the selected device was matched with, and
+    /// whether it is simulated rather than matched by its USB ids
+    pub fn get_matched_device(&self) -> Option<(&SupportedDevice, bool)> {
+        let device = self.devices.get(self.selected)?;
+        let supported = self.supported_devices.get(device.supported)?;
+        Some((supported, device.simulated))
+    }
+
+    /// USB ids of the Razer devices found without an entry in the laptops file
+    pub fn get_unmatched_devices(&self) -> Vec<(u16, u16)> {
+        self.unmatched.clone()
+    }
+
+    /// Index of the entry of the laptops file matching the ids
+    pub fn find_supported_device(&self, vid: u16, pid: u16) -> Option<usize> {
+        for (index, device) in self.supported_devices.iter().enumerate() {
+            // The file can be edited by hand, an entry with bad ids is skipped
+            let (Ok(svid), Ok(spid)) = (u16::from_str_radix(&device.vid, 16), u16::from_str_radix(&device.pid, 16)) else {
+                warn!("{} in {} has invalid USB ids {:?}:{:?}", device.name, DEVICE_FILE, device.vid, device.pid);
+                continue;
+            };
 
             if svid == vid && spid == pid {
-                return Some(device);
+                return Some(index);
             }
         }
 
@@ -532,41 +1667,130 @@ impl DeviceManager {
                 for device in devices {
 
                     let result = self.find_supported_device(device.vendor_id(), device.product_id());
-                    if let Some(supported_device) = result {
+                    if result.is_none() {
+                        info!("Razer device {:04x}:{:04x} is not listed in {}", device.vendor_id(), device.product_id(), DEVICE_FILE);
+                        self.unmatched.push((device.vendor_id(), device.product_id()));
+                    }
+                    if let Some(supported) = result {
+                        let supported_device = &self.supported_devices[supported];
 
                         match api.open_path(device.path()) {
                             Ok(dev) => {
-                                self.device = Some(RazerLaptop::new(
+                                let laptop = RazerLaptop::new(
                                     supported_device.name.clone(),
                                     supported_device.features.clone(),
                                     supported_device.fan.clone(),
+                                    supported_device.fan_step,
                                     dev
-                                ));
-                                break;
+                                );
+                                let serial = device.serial_number().unwrap_or_default().to_string();
+                                let path = device.path().to_string_lossy().to_string();
+                                info!("Found {} (serial {:?}) at {}", laptop.get_name(), serial, path);
+                                self.devices.push(ConnectedDevice { laptop, serial, supported, path, simulated: false });
                             },
                             Err(e) => {
-                                eprintln!("Error: {}", e);
+                                error!("Could not open {} at {}, check the permissions of /dev/hidraw*: {}",
+                                    supported_device.name, device.path().to_string_lossy(), e);
                             }
                         };
                     }
                 }
             },
             Err(e) => {
-                eprintln!("Error: {}", e);
+                error!("Could not list the hidraw devices: {}", e);
+            },
+        }
+        // The first device found is used until a client selects another one
+        self.selected = 0;
+        self.apply_layout();
+    }
+}
+
+/// Stands in for the hidraw device in `--simulate` mode, so effects and the
+/// protocol can be worked on without the hardware
+struct SimulatedDevice {
+    /// Arguments of the last write of every command, what the matching read
+    /// (the same id with the 0x80 bit set) answers
+    values: HashMap<(u8, u8), [u8; 80]>,
+    /// Custom frame rows written since the frame was last shown
+    rows: HashMap<u8, Vec<u8>>,
+    /// Last frame printed, the same frame is not printed again
+    shown: Vec<Vec<u8>>,
+}
+
+impl SimulatedDevice {
+    fn new() -> SimulatedDevice {
+        SimulatedDevice {
+            values: HashMap::new(),
+            rows: HashMap::new(),
+            shown: vec![],
+        }
+    }
+
+    fn send_report(&mut self, report: &RazerPacket) -> Option<RazerPacket> {
+        let mut response = RazerPacket::new(report.command_class, report.command_id, report.data_size);
+        response.status = RazerPacket::RAZER_CMD_SUCCESSFUL;
+        if report.command_id & 0x80 != 0 {
+            // Never written, the read gives the request back, zeros past the ids
+            let write = (report.command_class, report.command_id & 0x7f);
+            response.args = self.values.get(&write).copied().unwrap_or(report.args);
+            return Some(response);
+        }
+        match (report.command_class, report.command_id) {
+            (0x03, 0x0b) => {
+                let keys = report.args[3] as usize;
+                self.rows.insert(report.args[1], report.args[7..7 + 3 * keys].to_vec());
             },
+            (0x03, 0x0a) if report.args[0] == RazerLaptop::CUSTOMFRAME => self.show_frame(),
+            _ => {},
         }
+        self.values.insert((report.command_class, report.command_id), report.args);
+
+        Some(response)
+    }
+
+    /// Prints the custom frame to stdout, one line per row and two coloured
+    /// cells per key
+    fn show_frame(&mut self) {
+        let mut rows: Vec<(u8, Vec<u8>)> = self.rows.drain().collect();
+        rows.sort_by_key(|(row, _)| *row);
+        let frame: Vec<Vec<u8>> = rows.into_iter().map(|(_, data)| data).collect();
+        if frame == self.shown {
+            return;
+        }
+        let mut out = String::new();
+        for row in &frame {
+            for key in row.chunks(3) {
+                out += &format!("\x1b[48;2;{};{};{}m  ", key[0], key[1], key[2]);
+            }
+            out += "\x1b[0m\n";
+        }
+        let _ = writeln!(io::stdout().lock(), "{}", out);
+        self.shown = frame;
     }
 }
 
+/// Where the reports of a laptop go
+enum DeviceHandle {
+    Hid(hidapi::HidDevice),
+    Simulated(SimulatedDevice),
+}
+
 pub struct RazerLaptop {
     name: String,
     features: Vec<String>,
     fan: Vec<u16>,
-    device: hidapi::HidDevice,
+    fan_step: u16,
+    device: DeviceHandle,
     power: u8, // need for fan
     fan_rpm: u8, // need for power
     ac_state: u8, // index config array
     screensaver: bool,
+    logo_state: u8, // need for software breathing
+    logo_breathing_speed: u8,
+    logo_saved_brightness: Option<u8>, // logo brightness to go back to, while the daemon breathes it
+    custom_frame: u8, // stored frame written last, the effects always render to the live one
+    brightness: u8, // last level written, before the brightness curve
 }
 //
 impl RazerLaptop {
@@ -584,20 +1808,40 @@ impl RazerLaptop {
     pub const BREATHING:u8 = 0x03;
     pub const SPECTRUM:u8 = 0x04;
     pub const CUSTOMFRAME:u8 = 0x05;
+// custom frames
+    /// Frame shown right away, the one the effects render to
+    pub const LIVE_CUSTOM_FRAME:u8 = 0xff;
+    /// Highest stored frame index
+    pub const MAX_CUSTOM_FRAME:u8 = 0x0f;
     pub const STATIC:u8 = 0x06;
     #[allow(dead_code)]
     pub const STARLIGHT:u8 = 0x19;
 
-    pub fn new(name: String, features: Vec<String>, fan: Vec<u16>, device: hidapi::HidDevice) -> RazerLaptop {
+    pub fn new(name: String, features: Vec<String>, fan: Vec<u16>, fan_step: u16, device: hidapi::HidDevice) -> RazerLaptop {
+        RazerLaptop::with_handle(name, features, fan, fan_step, DeviceHandle::Hid(device))
+    }
+
+    /// A laptop that only exists in memory, its frames are printed to stdout
+    pub fn new_simulated(name: String, features: Vec<String>, fan: Vec<u16>, fan_step: u16) -> RazerLaptop {
+        RazerLaptop::with_handle(name, features, fan, fan_step, DeviceHandle::Simulated(SimulatedDevice::new()))
+    }
+
+    fn with_handle(name: String, features: Vec<String>, fan: Vec<u16>, fan_step: u16, device: DeviceHandle) -> RazerLaptop {
         return RazerLaptop{
             name,
             features,
             fan,
+            fan_step,
             device,
             power: 0,
             fan_rpm: 0,
             ac_state: 0,
-            screensaver: false
+            screensaver: false,
+            logo_state: 0,
+            logo_breathing_speed: 0,
+            logo_saved_brightness: None,
+            custom_frame: RazerLaptop::LIVE_CUSTOM_FRAME,
+            brightness: 0,
         };
     }
 
@@ -608,6 +1852,7 @@ impl RazerLaptop {
     pub fn set_config(&mut self, config: config::PowerConfig) -> bool {
         let mut ret: bool = false;
 
+        self.logo_breathing_speed = config.logo_breathing_speed;
         if !self.screensaver {
             ret |= self.set_brightness(config.brightness);
             ret |= self.set_logo_led_state(config.logo_state);
@@ -643,6 +1888,29 @@ impl RazerLaptop {
         return self.features.contains(&fch);
     }
 
+    pub fn get_features(&self) -> Vec<String> {
+        self.features.clone()
+    }
+
+    /// Values and names of the power modes the device has
+    pub fn get_power_modes(&self) -> Vec<(u8, String)> {
+        POWER_MODES
+            .iter()
+            .filter(|(_, _, feature)| feature.is_none_or(|feature| self.features.iter().any(|f| f == feature)))
+            .map(|(mode, name, _)| (*mode, name.to_string()))
+            .collect()
+    }
+
+    /// Minimum and maximum fan speed in RPM
+    pub fn get_fan_limits(&self) -> (u16, u16) {
+        (self.fan[0], self.fan[1])
+    }
+
+    /// Smallest fan speed change in RPM the device accepts
+    pub fn get_fan_step(&self) -> u16 {
+        self.fan_step
+    }
+
     fn clamp_fan(&mut self, rpm: u16) -> u8 {
         if rpm > self.fan[1] {
             return (self.fan[1] / 100) as u8;
@@ -654,6 +1922,12 @@ impl RazerLaptop {
         return (rpm / 100) as u8;
     }
 
+    /// Clamps a fan speed to the range supported by the device, with the
+    /// same 100 RPM resolution as `get_fan_rpm`
+    pub fn clamp_fan_rpm(&mut self, rpm: i32) -> i32 {
+        self.clamp_fan(rpm.clamp(0, u16::MAX as i32) as u16) as i32 * 100
+    }
+
     fn clamp_u8(&mut self, value: u8, min: u8, max: u8) ->u8 {
         if value > max {
             return max;
@@ -680,14 +1954,14 @@ impl RazerLaptop {
         return false;
     }
 
-    pub fn set_custom_frame_data(&mut self, row: u8, data: Vec<u8>) {
-        // if data.len() == kbd::board::KEYS_PER_ROW {
-        if data.len() == 45 {
-            let mut report: RazerPacket = RazerPacket::new(0x03, 0x0b, 0x34);
-            report.args[0] = 0xff;
+    pub fn set_custom_frame_data(&mut self, frame: u8, row: u8, data: Vec<u8>) {
+        let keys = kbd::board::keys_per_row();
+        if data.len() == 3 * keys {
+            let mut report: RazerPacket = RazerPacket::new(0x03, 0x0b, (7 + data.len()) as u8);
+            report.args[0] = frame;
             report.args[1] = row;
             report.args[2] = 0x00; // start col
-            report.args[3] = 0x0f; // end col
+            report.args[3] = keys as u8; // end col
             for idx in 0..data.len() {
                 report.args[idx + 7] = data[idx];
             }
@@ -695,6 +1969,21 @@ impl RazerLaptop {
         }
     }
 
+    /// Records the stored frame a keyboard frame was written to, up to
+    /// `MAX_CUSTOM_FRAME`, or `LIVE_CUSTOM_FRAME` for none
+    pub fn set_custom_frame_index(&mut self, frame: u8) -> bool {
+        if frame > RazerLaptop::MAX_CUSTOM_FRAME && frame != RazerLaptop::LIVE_CUSTOM_FRAME {
+            return false;
+        }
+        self.custom_frame = frame;
+
+        true
+    }
+
+    pub fn get_custom_frame_index(&self) -> u8 {
+        self.custom_frame
+    }
+
     pub fn set_custom_frame(&mut self) -> bool {
         let mut report: RazerPacket = RazerPacket::new(0x03, 0x0a, 0x02);
         report.args[0] = RazerLaptop::CUSTOMFRAME; // effect id
@@ -782,6 +2071,24 @@ impl RazerLaptop {
         return false;
     }
 
+    /// The boosts are only used by the custom power mode, in the other modes
+    /// the value is just stored for when custom gets selected
+    pub fn set_custom_cpu_boost(&mut self, boost: u8) -> bool {
+        if self.power != 4 {
+            return true;
+        }
+        self.get_cpu_boost();
+        self.set_cpu_boost(boost)
+    }
+
+    pub fn set_custom_gpu_boost(&mut self, boost: u8) -> bool {
+        if self.power != 4 {
+            return true;
+        }
+        self.get_gpu_boost();
+        self.set_gpu_boost(boost)
+    }
+
     pub fn set_power_mode(&mut self, mode: u8, cpu_boost: u8, gpu_boost: u8) -> bool {
         if mode <= 3 {
             self.power = mode;
@@ -843,11 +2150,14 @@ impl RazerLaptop {
     }
 
     pub fn set_logo_led_state(&mut self, mode: u8) -> bool {
+        self.logo_state = mode;
+        self.follow_logo_breathing();
         if mode > 0 {
             let mut report: RazerPacket = RazerPacket::new(0x03, 0x02, 0x03);
             report.args[0] = RazerLaptop::VARSTORE;
             report.args[1] = RazerLaptop::LOGO_LED;
-            if mode == 1 {
+            if mode == 1 || self.software_logo_breathing() {
+                // the software breathing fades a static logo
                 report.args[2] = 0x00;
             } else if mode == 2 {
                 report.args[2] = 0x02;
@@ -866,6 +2176,70 @@ impl RazerLaptop {
         return false;
     }
 
+    /// The firmware only knows one breathing rate, so any other speed, or
+    /// following the keyboard, is driven by the daemon through the logo brightness
+    fn software_logo_breathing(&self) -> bool {
+        self.logo_state == 2 && (self.logo_breathing_speed > 0 || LOGO_SYNC.load(Ordering::Relaxed))
+    }
+
+    pub fn set_logo_breathing_speed(&mut self, speed: u8) -> bool {
+        self.logo_breathing_speed = speed;
+        if self.logo_state == 2 && !self.screensaver {
+            return self.set_logo_led_state(2);
+        }
+
+        true
+    }
+
+    /// Saves the logo brightness when the software breathing starts and puts
+    /// it back when it stops, the breathing overwrites it on every step
+    fn follow_logo_breathing(&mut self) {
+        let breathing = self.software_logo_breathing() && !self.screensaver;
+        match (breathing, self.logo_saved_brightness) {
+            (true, None) => self.logo_saved_brightness = Some(self.get_logo_brightness()),
+            (false, Some(brightness)) => {
+                self.logo_saved_brightness = None;
+                self.set_logo_brightness(brightness);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn update_logo_breathing(&mut self) {
+        // Following the keyboard can be turned on and off at any time
+        self.follow_logo_breathing();
+        if !self.software_logo_breathing() || self.screensaver {
+            return;
+        }
+        let synced = kbd::breath_level().filter(|_| LOGO_SYNC.load(Ordering::Relaxed));
+        if let Some(level) = synced {
+            self.set_logo_brightness(level);
+            return;
+        }
+        // Without a breathing keyboard to follow, speed 0 breathes at the slowest rate
+        let period = LOGO_BREATHING_SLOWEST_MS / self.logo_breathing_speed.max(1) as u128;
+        let phase = (kbd::get_millis() % period) as f64 / period as f64;
+        let level = (1.0 - (phase * 2.0 * std::f64::consts::PI).cos()) / 2.0;
+        self.set_logo_brightness((level * 255.0) as u8);
+    }
+
+    fn set_logo_brightness(&mut self, brightness: u8) -> bool {
+        let mut report: RazerPacket = RazerPacket::new(0x03, 0x03, 0x03);
+        report.args[0] = RazerLaptop::VARSTORE;
+        report.args[1] = RazerLaptop::LOGO_LED;
+        report.args[2] = brightness;
+
+        self.send_report(report).is_some()
+    }
+
+    /// Logo brightness, full when it can't be read
+    fn get_logo_brightness(&mut self) -> u8 {
+        let mut report: RazerPacket = RazerPacket::new(0x03, 0x83, 0x03);
+        report.args[0] = RazerLaptop::VARSTORE;
+        report.args[1] = RazerLaptop::LOGO_LED;
+        self.send_report(report).map_or(255, |response| response.args[2])
+    }
+
     #[allow(dead_code)]
     pub fn get_logo_led_state(&mut self) -> u8 {
         let mut report: RazerPacket = RazerPacket::new(0x03, 0x82, 0x03);
@@ -877,11 +2251,17 @@ impl RazerLaptop {
         return 0;
     }
 
+    /// Sets the backlight (0-255), through the brightness curve when it is on
     pub fn set_brightness(&mut self, brightness: u8) -> bool {
+        self.brightness = brightness;
         let mut report: RazerPacket = RazerPacket::new(0x03, 0x03, 0x03);
         report.args[0] = RazerLaptop::VARSTORE;
         report.args[1] = RazerLaptop::BACKLIGHT_LED;
-        report.args[2] = brightness;
+        report.args[2] = if BRIGHTNESS_CURVE.load(Ordering::Relaxed) {
+            perceptual_brightness(brightness)
+        } else {
+            brightness
+        };
         if let Some(_) = self.send_report(report) {
             return true;
         }
@@ -889,13 +2269,23 @@ impl RazerLaptop {
         return false;
     }
 
+    /// Reads the backlight (0-255), undoing the brightness curve when it is on
     pub fn get_brightness(&mut self) -> u8 {
         let mut report: RazerPacket = RazerPacket::new(0x03, 0x83, 0x03);
         report.args[0] = RazerLaptop::VARSTORE;
         report.args[1] = RazerLaptop::BACKLIGHT_LED;
         report.args[2] = 0x00;
         if let Some(response) = self.send_report(report){
-            return response.args[2];
+            let level = response.args[2];
+            if !BRIGHTNESS_CURVE.load(Ordering::Relaxed) {
+                return level;
+            }
+            // The curve maps several levels to the same low value, the one
+            // written last is the one to report
+            if perceptual_brightness(self.brightness) == level {
+                return self.brightness;
+            }
+            return linear_brightness(level);
         }
         return 0;
     }
@@ -922,19 +2312,23 @@ impl RazerLaptop {
 
         return self.send_report(report)
             .map_or(false, |r| { 
-                println!("Response Packet:\n{:#?}", r); 
+                debug!("Response Packet:\n{:#?}", r); 
                 true
             } 
         );
     }
 
     fn send_report(&mut self, mut report: RazerPacket) -> Option<RazerPacket>{
+        let device = match &mut self.device {
+            DeviceHandle::Hid(device) => device,
+            DeviceHandle::Simulated(device) => return device.send_report(&report),
+        };
         let mut temp_buf: [u8; 91] = [0x00; 91];
         for _ in 0..3 {
-            match self.device.send_feature_report(report.calc_crc().as_slice()) {
+            match device.send_feature_report(report.calc_crc().as_slice()) {
                 Ok(_) => {
                     thread::sleep(time::Duration::from_micros(1000));
-                    match self.device.get_feature_report(&mut temp_buf) {
+                    match device.get_feature_report(&mut temp_buf) {
                         Ok(size) => {
                             if size == 91 {
                                 match bincode::deserialize::<RazerPacket>(&temp_buf){
@@ -947,30 +2341,30 @@ impl RazerLaptop {
                                         if response.remaining_packets != report.remaining_packets || 
                                             response.command_class != report.command_class ||
                                                 response.command_id != report.command_id {
-                                                    eprintln!("Response doesn't match request");
+                                                    warn!("Response doesn't match request");
                                                 }
                                         else if response.status == RazerPacket::RAZER_CMD_SUCCESSFUL {
                                             return Some(response);
                                         }
                                         if response.status == RazerPacket::RAZER_CMD_NOT_SUPPORTED {
-                                            eprintln!("Command not supported");
+                                            warn!("Command not supported");
                                         }
                                     },
                                     Err(e) => {
-                                        eprintln!("Error: {}", e);
+                                        error!("Error: {}", e);
                                     }
                                 }
                             } else {
-                                eprintln!("Invalid report length: {:?}", size);
+                                warn!("Invalid report length: {:?}", size);
                             }
                         },
                         Err(e) => {
-                            eprintln!("Error: {}", e);
+                            error!("Error: {}", e);
                         }
                     }
                 },
                 Err(e) => {
-                    eprintln!("Error: {}", e);
+                    error!("Error: {}", e);
                 }
             };
 
@@ -982,6 +2376,117 @@ impl RazerLaptop {
 
 }
 
+/// Brightness percentage `delta` away from `current`, stopping at 0 and 100
+fn step_brightness(current: u8, delta: i32) -> u8 {
+    (current as i32).saturating_add(delta).clamp(0, 100) as u8
+}
+
+/// Fan speed `delta` RPM away from `current`, within `min..=max`. Automatic
+/// (0) counts as the slowest speed and stepping down stops there, it never
+/// switches back to automatic
+fn step_fan_rpm(current: i32, delta: i32, min: u16, max: u16) -> i32 {
+    let from = if current <= 0 { min as i32 } else { current };
+    from.saturating_add(delta).clamp(min as i32, max as i32)
+}
+
+/// Kernel driver bound to the HID device behind a hidraw node and the
+/// attributes of its sysfs directory, for bug reports. hid-generic is the
+/// usual driver, with OpenRazer's the directory also holds its attributes
+pub fn hidraw_driver(path: &str) -> (String, Vec<String>) {
+    let Some(node) = path.strip_prefix("/dev/") else {
+        return (String::new(), vec![]);
+    };
+    let dir = Path::new("/sys/class/hidraw").join(node).join("device");
+    let driver = fs::read_link(dir.join("driver")).ok()
+        .and_then(|link| link.file_name().map(|name| name.to_string_lossy().to_string()))
+        .unwrap_or_default();
+    let mut attributes: Vec<String> = fs::read_dir(&dir).into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_file()))
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect();
+    attributes.sort();
+
+    (driver, attributes)
+}
+
+// 1-100 percent of the fan range rounded to the fan step, 0 stays automatic
+fn fan_percent_rpm(percent: u8, min: u16, max: u16, step: u16) -> i32 {
+    if percent == 0 {
+        return 0;
+    }
+    let (min, max) = (min as i32, max as i32);
+    let step = (step as i32).max(1);
+    let rpm = min + (max - min) * percent.min(100) as i32 / 100;
+    ((rpm + step / 2) / step * step).clamp(min, max)
+}
+
+// linear interpolation between the points (sorted by temperature) of a fan
+// curve, holding the first and last rpm outside of the curve
+fn interpolate_fan_curve(points: &[(u8, i32)], temp: f32) -> i32 {
+    let first = points[0];
+    let last = points[points.len() - 1];
+    if temp <= first.0 as f32 {
+        return first.1;
+    }
+    if temp >= last.0 as f32 {
+        return last.1;
+    }
+    for pair in points.windows(2) {
+        let (t0, rpm0) = (pair[0].0 as f32, pair[0].1 as f32);
+        let (t1, rpm1) = (pair[1].0 as f32, pair[1].1 as f32);
+        if temp <= t1 {
+            return (rpm0 + (rpm1 - rpm0) * (temp - t0) / (t1 - t0)) as i32;
+        }
+    }
+
+    last.1
+}
+
+/// Around a point of the curve the temperature goes up and down by a degree
+/// all the time, the fan curve only follows it once it moved `hysteresis`
+/// degrees away from the one it last acted on
+fn fan_curve_follows(last_temp: Option<f32>, temp: f32, hysteresis: f32) -> bool {
+    last_temp.is_none_or(|last| (temp - last).abs() >= hysteresis)
+}
+
+// brightness is stored as 0-255, but exposed as a rounded 0-100 percentage
+fn brightness_to_percent(val: u8) -> u8 {
+    let mut perc = val as u32 * 100 * 100 / 255;
+    perc += 50;
+    perc /= 100;
+    perc as u8
+}
+
+/// Maps a backlight level (0-255) to the one written with the brightness
+/// curve. The level is read as the CIE lightness L* (0-100%) and the
+/// luminance matching it is written, so 10% gives 3 instead of 25 and 50%
+/// gives 47 instead of 127. A level above 0 never turns the backlight off
+fn perceptual_brightness(val: u8) -> u8 {
+    let lightness = val as f32 / 255.0 * 100.0;
+    let luminance = if lightness > 8.0 {
+        ((lightness + 16.0) / 116.0).powi(3)
+    } else {
+        lightness / 903.3
+    };
+    let level = (luminance * 255.0).round() as u8;
+
+    if val > 0 { level.max(1) } else { 0 }
+}
+
+/// Inverse of `perceptual_brightness`
+fn linear_brightness(val: u8) -> u8 {
+    let luminance = val as f32 / 255.0;
+    let lightness = if luminance > 0.008856 {
+        116.0 * luminance.cbrt() - 16.0
+    } else {
+        luminance * 903.3
+    };
+
+    (lightness / 100.0 * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
 // top bit flags whether battery health optimization is on or off
 // bottom bits are the actual threshold that it is set to
 fn byte_to_bho(u: u8) -> (bool, u8) {
@@ -994,3 +2499,148 @@ fn bho_to_byte(is_on: bool, threshold: u8) -> u8 {
     }
     return threshold;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Device manager of a simulated Blade, with the default settings
+    fn simulated_manager() -> DeviceManager {
+        let mut manager = DeviceManager::new();
+        manager.supported_devices = vec![SupportedDevice {
+            name: String::from("Blade 15"),
+            vid: String::from("1532"),
+            pid: String::from("02b6"),
+            features: vec![String::from("logo")],
+            fan: vec![2000, 5000],
+            fan_step: 100,
+            zones: 1,
+            keys_per_row: 15,
+            key_codes: vec![],
+        }];
+        manager.config = Some(config::Configuration::new());
+        assert!(manager.simulate_device(""));
+        manager
+    }
+
+    fn power_config(power_mode: u8, brightness: u8, logo_state: u8) -> config::PowerConfig {
+        config::PowerConfig { power_mode, brightness, logo_state, ..config::PowerConfig::new() }
+    }
+
+    #[test]
+    fn unplugging_applies_the_battery_settings() {
+        // Selecting the simulated device sets the layout
+        let _layout = kbd::board::test_layout(15, 1);
+        let mut manager = simulated_manager();
+        let config = manager.config.as_mut().unwrap();
+        config.power[1] = power_config(1, 255, 1);
+        config.power[0] = power_config(3, 51, 0);
+        manager.set_ac_state(true);
+        assert_eq!((manager.get_power_mode(1), manager.get_brightness(1)), (1, 100));
+        manager.set_ac_state(false);
+        let laptop = manager.get_device().unwrap();
+        assert_eq!(laptop.get_ac_state(), 0);
+        assert_eq!(laptop.logo_state, 0);
+        // Read back from the device, not from the config
+        assert_eq!((manager.get_power_mode(0), manager.get_brightness(0)), (3, 20));
+    }
+
+    #[test]
+    fn logo_is_set_per_power_source() {
+        let _layout = kbd::board::test_layout(15, 1);
+        let mut manager = simulated_manager();
+        manager.config.as_mut().unwrap().sync = false;
+        manager.set_ac_state(true);
+        assert!(manager.set_logo_led_state(1, 2));
+        assert_eq!((manager.get_logo_led_state(1), manager.get_logo_led_state(0)), (2, 0));
+        // The battery setting is stored without touching the logo on AC
+        assert!(manager.set_logo_led_state(0, 1));
+        assert_eq!((manager.get_logo_led_state(1), manager.get_logo_led_state(0)), (2, 1));
+        assert_eq!(manager.get_device().unwrap().logo_state, 2);
+        // Synced, both change
+        manager.config.as_mut().unwrap().sync = true;
+        assert!(manager.set_logo_led_state(1, 0));
+        assert_eq!((manager.get_logo_led_state(1), manager.get_logo_led_state(0)), (0, 0));
+        assert_eq!(manager.get_device().unwrap().logo_state, 0);
+    }
+
+    #[test]
+    fn brightness_steps_stop_at_0_and_100() {
+        assert_eq!(step_brightness(40, 10), 50);
+        assert_eq!(step_brightness(40, -10), 30);
+        assert_eq!(step_brightness(0, -10), 0);
+        assert_eq!(step_brightness(20, -80), 0);
+        assert_eq!(step_brightness(100, 10), 100);
+        assert_eq!(step_brightness(95, i32::MAX), 100);
+        assert_eq!(step_brightness(5, i32::MIN), 0);
+    }
+
+    #[test]
+    fn fan_steps_stay_in_the_fan_range() {
+        assert_eq!(step_fan_rpm(3000, 500, 2000, 5000), 3500);
+        assert_eq!(step_fan_rpm(3000, -500, 2000, 5000), 2500);
+        assert_eq!(step_fan_rpm(4800, 500, 2000, 5000), 5000);
+        assert_eq!(step_fan_rpm(2200, -500, 2000, 5000), 2000);
+        // Automatic counts as the slowest speed and is never stepped back to
+        assert_eq!(step_fan_rpm(0, 500, 2000, 5000), 2500);
+        assert_eq!(step_fan_rpm(0, -500, 2000, 5000), 2000);
+        assert_eq!(step_fan_rpm(-1, 0, 2000, 5000), 2000);
+    }
+
+    #[test]
+    fn fan_percentages_are_rounded_to_the_step() {
+        assert_eq!(fan_percent_rpm(0, 2000, 5000, 100), 0);
+        assert_eq!(fan_percent_rpm(1, 2000, 5000, 100), 2000);
+        assert_eq!(fan_percent_rpm(50, 2000, 5000, 100), 3500);
+        assert_eq!(fan_percent_rpm(55, 2000, 5000, 100), 3700);
+        assert_eq!(fan_percent_rpm(100, 2000, 5000, 100), 5000);
+        assert_eq!(fan_percent_rpm(255, 2000, 5000, 100), 5000);
+        // A step of 0 is taken as 1
+        assert_eq!(fan_percent_rpm(50, 2000, 5000, 0), 3500);
+    }
+
+    #[test]
+    fn fan_curve_is_interpolated_between_its_points() {
+        let points = [(50, 2000), (70, 3000), (90, 5000)];
+        assert_eq!(interpolate_fan_curve(&points, 60.0), 2500);
+        assert_eq!(interpolate_fan_curve(&points, 70.0), 3000);
+        assert_eq!(interpolate_fan_curve(&points, 85.0), 4500);
+        // The ends hold outside of the curve
+        assert_eq!(interpolate_fan_curve(&points, 20.0), 2000);
+        assert_eq!(interpolate_fan_curve(&points, 100.0), 5000);
+        assert_eq!(interpolate_fan_curve(&[(60, 3000)], 40.0), 3000);
+        assert_eq!(interpolate_fan_curve(&[(60, 3000)], 80.0), 3000);
+    }
+
+    #[test]
+    fn fan_curve_waits_for_the_hysteresis() {
+        assert!(fan_curve_follows(None, 60.0, 3.0));
+        assert!(!fan_curve_follows(Some(60.0), 62.0, 3.0));
+        assert!(!fan_curve_follows(Some(60.0), 58.0, 3.0));
+        assert!(fan_curve_follows(Some(60.0), 63.0, 3.0));
+        assert!(fan_curve_follows(Some(60.0), 57.0, 3.0));
+        // No hysteresis follows every reading
+        assert!(fan_curve_follows(Some(60.0), 60.0, 0.0));
+    }
+
+    #[test]
+    fn brightness_curve_round_trips() {
+        assert_eq!((perceptual_brightness(0), perceptual_brightness(255)), (0, 255));
+        assert_eq!((linear_brightness(0), linear_brightness(255)), (0, 255));
+        for level in 0..=255u8 {
+            // The dark end of the curve has fewer levels, so only this way round is close
+            let back = perceptual_brightness(linear_brightness(level));
+            assert!(back.abs_diff(level) <= 1, "{level} came back as {back}");
+        }
+    }
+
+    #[test]
+    fn brightness_curve_is_monotonic() {
+        for level in 0..255u8 {
+            assert!(perceptual_brightness(level) <= perceptual_brightness(level + 1));
+            assert!(linear_brightness(level) <= linear_brightness(level + 1));
+        }
+        // Only 0 turns the backlight off
+        assert!((1..=255u8).all(|level| perceptual_brightness(level) > 0));
+    }
+}
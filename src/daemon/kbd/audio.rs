@@ -0,0 +1,113 @@
+use super::get_millis;
+use lazy_static::lazy_static;
+use log::*;
+use std::collections::VecDeque;
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// Sample rate the monitor source is recorded at
+pub const SAMPLE_RATE: u32 = 16000;
+
+/// Number of samples kept, enough for one analysis window
+const MAX_SAMPLES: usize = 1024;
+
+/// The recorder is stopped when no effect asked for samples for this long
+const IDLE_TIMEOUT_MS: u128 = 5000;
+
+lazy_static! {
+    static ref SAMPLES: Mutex<VecDeque<f32>> = Mutex::new(VecDeque::with_capacity(MAX_SAMPLES));
+    static ref LAST_READ_MS: Mutex<u128> = Mutex::new(0);
+}
+
+static CAPTURE_RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Returns the most recent samples (between -1 and 1), oldest first.
+///
+/// The first call starts recording the default monitor source with `parec`,
+/// which works with both PulseAudio and PipeWire. If it can't be started
+/// the buffer simply stays empty.
+pub fn latest_samples() -> Vec<f32> {
+    if let Ok(mut last_read) = LAST_READ_MS.lock() {
+        *last_read = get_millis();
+    }
+    if !CAPTURE_RUNNING.swap(true, Ordering::SeqCst) {
+        thread::spawn(capture);
+    }
+    match SAMPLES.lock() {
+        Ok(samples) => samples.iter().copied().collect(),
+        Err(_) => vec![],
+    }
+}
+
+fn capture() {
+    let child = Command::new("parec")
+        .args([
+            "--device=@DEFAULT_MONITOR@",
+            "--format=s16le",
+            "--channels=1",
+            "--raw",
+        ])
+        .arg(format!("--rate={}", SAMPLE_RATE))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            warn!("Could not record the audio monitor source with parec: {}", e);
+            // Let the next request retry once the idle timeout expired
+            thread::sleep(std::time::Duration::from_millis(IDLE_TIMEOUT_MS as u64));
+            CAPTURE_RUNNING.store(false, Ordering::SeqCst);
+            return;
+        }
+    };
+    info!("Recording the audio monitor source");
+
+    if let Some(mut stdout) = child.stdout.take() {
+        // 1/50 of a second per read
+        let mut chunk = vec![0u8; (SAMPLE_RATE / 50 * 2) as usize];
+        while stdout.read_exact(&mut chunk).is_ok() {
+            if let Ok(mut samples) = SAMPLES.lock() {
+                for pair in chunk.chunks_exact(2) {
+                    if samples.len() == MAX_SAMPLES {
+                        samples.pop_front();
+                    }
+                    samples.push_back(i16::from_le_bytes([pair[0], pair[1]]) as f32 / 32768.0);
+                }
+            }
+            let last_read = LAST_READ_MS.lock().map_or(0, |t| *t);
+            if get_millis().saturating_sub(last_read) > IDLE_TIMEOUT_MS {
+                break;
+            }
+        }
+    }
+
+    let _ = child.kill();
+    let _ = child.wait();
+    if let Ok(mut samples) = SAMPLES.lock() {
+        samples.clear();
+    }
+    info!("Stopped recording the audio monitor source");
+    CAPTURE_RUNNING.store(false, Ordering::SeqCst);
+}
+
+/// Magnitude of `samples` at `frequency` (Hz), using the Goertzel algorithm.
+/// Cheaper than a full FFT when only a handful of bands are needed.
+pub fn band_magnitude(samples: &[f32], frequency: f32) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let coeff = 2.0 * (2.0 * std::f32::consts::PI * frequency / SAMPLE_RATE as f32).cos();
+    let (mut s1, mut s2) = (0.0f32, 0.0f32);
+    for sample in samples {
+        let s0 = sample + coeff * s1 - s2;
+        s2 = s1;
+        s1 = s0;
+    }
+    let power = s1 * s1 + s2 * s2 - coeff * s1 * s2;
+
+    power.max(0.0).sqrt() / samples.len() as f32
+}
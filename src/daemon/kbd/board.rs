@@ -1,14 +1,164 @@
-/*
-use crate::driver_sysfs;
-*/
 use crate::device;
 use std::cmp::Ordering;
 use std::ops;
+use std::sync::atomic::{AtomicU8, Ordering as AtomicOrdering};
+use std::sync::RwLock;
 
 // -- RGB Key channel --
 
-pub const KEYS_PER_ROW: usize = 15;
+/// Columns of the Blade layout, used unless the device declares another one
+pub const DEFAULT_KEYS_PER_ROW: usize = 15;
+/// Widest row a layout can declare, as many keys as one custom frame report
+/// holds (numpad models)
+pub const MAX_KEYS_PER_ROW: usize = 24;
+/// Rows of one zone, the main keyboard is always zone 0
 pub const ROWS: usize = 6;
+/// Keys of the keyboard zone of the widest layout, the size of the per key
+/// buffers of the effects
+pub const MAX_ZONE_KEYS: usize = MAX_KEYS_PER_ROW * ROWS;
+/// Most LED zones a device can declare, extra zones follow the keyboard rows
+/// (6-11 and 12-17) of the custom frame
+pub const MAX_ZONES: usize = 3;
+
+/// Columns of the current device, set once it has been found
+static KEYS_PER_ROW: AtomicU8 = AtomicU8::new(DEFAULT_KEYS_PER_ROW as u8);
+
+/// Sets the columns of the key matrix of the device, returning the value
+/// actually applied after clamping it to `1..=MAX_KEYS_PER_ROW`
+pub fn set_keys_per_row(keys: u8) -> u8 {
+    let keys = keys.clamp(1, MAX_KEYS_PER_ROW as u8);
+    KEYS_PER_ROW.store(keys, AtomicOrdering::Relaxed);
+    keys
+}
+
+pub fn keys_per_row() -> usize {
+    KEYS_PER_ROW.load(AtomicOrdering::Relaxed) as usize
+}
+
+/// Keys of the keyboard zone, 90 for the Blade layout
+pub fn zone_key_count() -> usize {
+    keys_per_row() * ROWS
+}
+
+/// (row, column) of a key index of the keyboard zone, row 0 being the top
+/// row and column 0 the left most one
+pub fn key_coordinates(index: usize) -> (usize, usize) {
+    (index / keys_per_row(), index % keys_per_row())
+}
+
+/// (x, y) of a key index of the keyboard zone in key widths from the centre
+/// of the keyboard, x growing to the right and y downwards
+pub fn key_position(index: usize) -> (f32, f32) {
+    let (row, col) = key_coordinates(index);
+    (
+        col as f32 - (keys_per_row() - 1) as f32 / 2.0,
+        row as f32 - (ROWS - 1) as f32 / 2.0,
+    )
+}
+
+/// Zones of the current device, set once it has been found
+static ZONE_COUNT: AtomicU8 = AtomicU8::new(1);
+
+/// Sets the number of zones of the device, returning the value actually
+/// applied after clamping it to `1..=MAX_ZONES`
+pub fn set_zone_count(zones: u8) -> u8 {
+    let zones = zones.clamp(1, MAX_ZONES as u8);
+    ZONE_COUNT.store(zones, AtomicOrdering::Relaxed);
+    zones
+}
+
+pub fn zone_count() -> usize {
+    ZONE_COUNT.load(AtomicOrdering::Relaxed) as usize
+}
+
+/// Serialises the tests that depend on the layout, which is global. Sets
+/// the columns and zones for as long as the guard is held
+#[cfg(test)]
+pub fn test_layout(keys_per_row: u8, zones: u8) -> std::sync::MutexGuard<'static, ()> {
+    static LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    let guard = LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    set_keys_per_row(keys_per_row);
+    set_zone_count(zones);
+    guard
+}
+
+/// Keys of every zone together, 90 for a Blade with a single zone
+pub fn key_count() -> usize {
+    zone_count() * zone_key_count()
+}
+
+/// Gamma the colours are corrected with unless configured otherwise
+pub const DEFAULT_GAMMA: f32 = 2.2;
+/// Accepted gamma values, 1.0 leaves the colours as they are
+pub const MIN_GAMMA: f32 = 0.1;
+pub const MAX_GAMMA: f32 = 5.0;
+
+/// Corrected value of every channel value, applied when a frame is sent
+static GAMMA_TABLE: RwLock<[u8; 256]> = RwLock::new(identity_table());
+
+const fn identity_table() -> [u8; 256] {
+    let mut table = [0u8; 256];
+    let mut value = 0;
+    while value < 256 {
+        table[value] = value as u8;
+        value += 1;
+    }
+    table
+}
+
+/// Lookup table raising every channel value to the power of `gamma`
+pub fn gamma_table(gamma: f32) -> [u8; 256] {
+    let mut table = [0u8; 256];
+    for (value, corrected) in table.iter_mut().enumerate() {
+        *corrected = ((value as f32 / 255.0).powf(gamma) * 255.0).round() as u8;
+    }
+    table
+}
+
+/// Sets the gamma correction of the frames sent to the keyboard, returning
+/// false if it is outside `MIN_GAMMA..=MAX_GAMMA`
+pub fn set_gamma(gamma: f32) -> bool {
+    if !(MIN_GAMMA..=MAX_GAMMA).contains(&gamma) {
+        return false;
+    }
+    if let Ok(mut table) = GAMMA_TABLE.write() {
+        *table = gamma_table(gamma);
+    }
+    true
+}
+
+/// Fixed point scale of the white balance multipliers, per mille of a channel,
+/// so this value leaves the channel as it is
+pub const WHITE_BALANCE_ONE: u16 = 1000;
+
+/// Per mille red, green and blue are scaled by when a frame is sent
+static WHITE_BALANCE: RwLock<[u16; 3]> = RwLock::new([WHITE_BALANCE_ONE; 3]);
+
+/// Sets the white balance of the frames sent to the keyboard, returning false
+/// if a multiplier is above `WHITE_BALANCE_ONE`. The channels can only be
+/// dimmed, a tinted white is corrected by dimming the channels it leans to
+pub fn set_white_balance(multipliers: [u16; 3]) -> bool {
+    if multipliers.iter().any(|multiplier| *multiplier > WHITE_BALANCE_ONE) {
+        return false;
+    }
+    if let Ok(mut balance) = WHITE_BALANCE.write() {
+        *balance = multipliers;
+    }
+    true
+}
+
+/// Scales a channel value by a white balance multiplier
+pub fn balance_channel(value: u8, multiplier: u16) -> u8 {
+    (value as u32 * multiplier.min(WHITE_BALANCE_ONE) as u32 / WHITE_BALANCE_ONE as u32) as u8
+}
+
+/// Applies the white balance then the gamma table to the channels of a row,
+/// balanced first as the gamma curve is meant for the final value
+pub fn correct_channels(data: &mut [u8], table: &[u8; 256], balance: [u16; 3]) {
+    for (channel, value) in data.iter_mut().enumerate() {
+        *value = table[balance_channel(*value, balance[channel % 3]) as usize];
+    }
+}
 
 #[derive(Copy, Clone, Debug)]
 /// Represents the colour channels for a key
@@ -55,11 +205,11 @@ impl AnimatorKeyColour {
         return input as u8;
     }
 
-    pub fn divide(&mut self, divisor: f32) -> AnimatorKeyColour {
+    pub fn multiply(&mut self, factor: f32) -> AnimatorKeyColour {
         AnimatorKeyColour {
-            red: self.red / divisor,
-            green: self.green / divisor,
-            blue: self.blue / divisor,
+            red: self.red * factor,
+            green: self.green * factor,
+            blue: self.blue * factor,
         }
     }
 
@@ -129,10 +279,39 @@ impl PartialOrd for AnimatorKeyColour {
     }
 }
 
+/// Converts a hue (0-360 degrees), saturation and value (0-1) to a colour
+pub fn hsv_to_rgb(hue: f32, saturation: f32, value: f32) -> KeyColour {
+    let hue = hue.rem_euclid(360.0) / 60.0;
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+    let (r, g, b) = match hue as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = value - chroma;
+    AnimatorKeyColour::new_f((r + m) * 255.0, (g + m) * 255.0, (b + m) * 255.0).get_clamped_colour()
+}
+
+/// Scales a colour channel by a brightness percentage, clamped to 100%
+pub fn scale_channel(value: u8, percent: u8) -> u8 {
+    (value as u16 * percent.min(100) as u16 / 100) as u8
+}
+
+/// Channel `t` of the way from `from` to `to`, `t` in 0.0..=1.0
+pub fn blend_channel(from: u8, to: u8, t: f32) -> u8 {
+    let t = t.clamp(0.0, 1.0);
+    (from as f32 + (to as f32 - from as f32) * t).round() as u8
+}
+
 #[derive(Copy, Clone, Debug)]
-/// Represents a horizontal row of 15 keys on the keyboard
+/// Represents a horizontal row of keys on the keyboard, only the columns of
+/// the layout are used
 pub struct RowData {
-    keys: [KeyColour; KEYS_PER_ROW],
+    keys: [KeyColour; MAX_KEYS_PER_ROW],
 }
 
 impl RowData {
@@ -143,7 +322,7 @@ impl RowData {
                 red: 255,
                 green: 255,
                 blue: 255,
-            }; KEYS_PER_ROW],
+            }; MAX_KEYS_PER_ROW],
         };
     }
 
@@ -169,13 +348,13 @@ impl RowData {
     /// * g - Green channel value
     /// * b - Blue channel value
     pub fn set_row_color(&mut self, r: u8, g: u8, b: u8) {
-        (0..KEYS_PER_ROW).for_each(|x| self.set_key_color(x, r, g, b)) // Sets the entire row
+        (0..keys_per_row()).for_each(|x| self.set_key_color(x, r, g, b)) // Sets the entire row
     }
 
     pub fn get_row_data(&mut self) -> Vec<u8> {
         // *3 as itll be the RGB values
-        let mut v = Vec::<u8>::with_capacity(3 * KEYS_PER_ROW);
-        self.keys.iter().for_each(|k| {
+        let mut v = Vec::<u8>::with_capacity(3 * keys_per_row());
+        self.keys.iter().take(keys_per_row()).for_each(|k| {
             v.push(k.red);
             v.push(k.green);
             v.push(k.blue);
@@ -185,15 +364,16 @@ impl RowData {
 }
 
 #[derive(Copy, Clone, Debug)]
+/// Colours of every zone, only the rows of the zones the device has are used
 pub struct KeyboardData {
-    rows: [RowData; ROWS],
+    rows: [RowData; ROWS * MAX_ZONES],
     // brightness: u8,
 }
 
 impl KeyboardData {
     pub fn new() -> KeyboardData {
         return KeyboardData {
-            rows: [RowData::new(); ROWS],
+            rows: [RowData::new(); ROWS * MAX_ZONES],
             // brightness: 0,
         };
     }
@@ -213,26 +393,40 @@ impl KeyboardData {
     // }
 
     pub fn update_kbd(&mut self, laptop: &mut device::RazerLaptop) -> bool {
-        // driver_sysfs::write_rgb_map(self.get_curr_state())
-        for idx in 0..ROWS {
-            laptop.set_custom_frame_data(idx as u8, self.rows[idx].get_row_data());
+        self.write_frame(laptop, device::RazerLaptop::LIVE_CUSTOM_FRAME)
+    }
+
+    /// Writes the rows to one of the frames stored on the keyboard
+    /// controller instead of the live one
+    pub fn store_kbd(&mut self, laptop: &mut device::RazerLaptop, frame: u8) -> bool {
+        self.write_frame(laptop, frame)
+    }
+
+    fn write_frame(&mut self, laptop: &mut device::RazerLaptop, frame: u8) -> bool {
+        let table = GAMMA_TABLE.read().map_or(identity_table(), |table| *table);
+        let balance = WHITE_BALANCE.read().map_or([WHITE_BALANCE_ONE; 3], |balance| *balance);
+        // The rows of the extra zones come right after the keyboard rows, in
+        // the row byte of the same custom frame report. Only devices with
+        // `zones` in their laptops.json entry get them, see `SupportedDevice`
+        for idx in 0..zone_count() * ROWS {
+            let mut data = self.rows[idx].get_row_data();
+            correct_channels(&mut data, &table, balance);
+            laptop.set_custom_frame_data(frame, idx as u8, data);
         }
         return true;
     }
 
     pub fn update_custom_mode(&mut self, laptop: &mut device::RazerLaptop) -> bool {
-        // driver_sysfs::write_custom_mode_frame(1)
-        // driver_sysfs::write_custom_mode_frame(1)
         return laptop.set_custom_frame();
     }
 
     /// Sets a specific key in the keyboard matrix to a colour
     #[allow(dead_code)]
     pub fn set_key_colour(&mut self, row: usize, col: usize, r: u8, g: u8, b: u8) {
-        if row >= ROWS {
+        if row >= zone_count() * ROWS {
             return;
         }
-        if col >= KEYS_PER_ROW {
+        if col >= keys_per_row() {
             return;
         }
         self.rows[row].set_key_color(col, r, g, b)
@@ -241,7 +435,7 @@ impl KeyboardData {
     /// Sets a horizontal row on the keyboard to a colour
     #[allow(dead_code)]
     pub fn set_row_colour(&mut self, row: usize, r: u8, g: u8, b: u8) {
-        if row >= ROWS {
+        if row >= zone_count() * ROWS {
             return;
         }
         self.rows[row].set_row_color(r, g, b)
@@ -249,36 +443,416 @@ impl KeyboardData {
 
     /// Sets a vertical column on the keyboard to a colour
     pub fn set_col_colour(&mut self, col: usize, r: u8, g: u8, b: u8) {
-        if col >= KEYS_PER_ROW {
+        if col >= keys_per_row() {
             return;
         }
-        for row_id in 0..ROWS {
+        for row_id in 0..zone_count() * ROWS {
             self.rows[row_id].set_key_color(col, r, g, b)
         }
     }
 
+    /// Scales every key by a brightness percentage (0 = black, 100 = unchanged)
+    pub fn scale_brightness(&mut self, percent: u8) {
+        for row in self.rows.iter_mut().take(zone_count() * ROWS) {
+            for key in row.keys.iter_mut().take(keys_per_row()) {
+                key.red = scale_channel(key.red, percent);
+                key.green = scale_channel(key.green, percent);
+                key.blue = scale_channel(key.blue, percent);
+            }
+        }
+    }
+
+    /// Mixes in `from`, at `t` = 0.0 the keys are those of `from` and at 1.0
+    /// they are unchanged
+    pub fn blend_from(&mut self, from: &KeyboardData, t: f32) {
+        for (row, from_row) in self.rows.iter_mut().zip(from.rows.iter()).take(zone_count() * ROWS) {
+            for (key, from_key) in row.keys.iter_mut().zip(from_row.keys.iter()).take(keys_per_row()) {
+                key.red = blend_channel(from_key.red, key.red, t);
+                key.green = blend_channel(from_key.green, key.green, t);
+                key.blue = blend_channel(from_key.blue, key.blue, t);
+            }
+        }
+    }
+
     /// Sets the entire keyboard to a colour
     pub fn set_kbd_colour(&mut self, r: u8, g: u8, b: u8) {
-        for row_id in 0..ROWS {
+        for row_id in 0..zone_count() * ROWS {
             self.rows[row_id].set_row_color(r, g, b)
         }
     }
 
-    /// Returns a specific key
+    /// Returns a specific key, black for an index past the keys of the
+    /// device
     pub fn get_key_at(self, index: usize) -> KeyColour {
-        self.rows[index / KEYS_PER_ROW].keys[index % KEYS_PER_ROW]
+        if index >= key_count() {
+            return KeyColour { red: 0, green: 0, blue: 0 };
+        }
+        self.rows[index / keys_per_row()].keys[index % keys_per_row()]
     }
 
-    /// Internal function used only for the combining of effect layers
+    /// Internal function used only for the combining of effect layers. An
+    /// index past the keys of the device is ignored
     pub fn set_key_at(&mut self, index: usize, col: KeyColour) {
-        self.rows[index / KEYS_PER_ROW].keys[index % KEYS_PER_ROW] = col
+        if index >= key_count() {
+            return;
+        }
+        self.rows[index / keys_per_row()].keys[index % keys_per_row()] = col
     }
 
+    /// RGB data of every key, zone after zone
     pub fn get_curr_state(&mut self) -> Vec<u8> {
-        let mut all_vals = Vec::<u8>::with_capacity(3 * KEYS_PER_ROW * ROWS);
-        for row in self.rows.iter_mut() {
+        let mut all_vals = Vec::<u8>::with_capacity(3 * key_count());
+        for row in self.rows.iter_mut().take(zone_count() * ROWS) {
             all_vals.extend(&row.get_row_data());
         }
         return all_vals;
     }
 }
+
+// -- Layouts --
+
+/// Physical layout of a keyboard, numpad models have more than
+/// `DEFAULT_KEYS_PER_ROW` columns
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Layout {
+    Ansi,
+    Iso,
+    AnsiNumpad,
+    IsoNumpad,
+}
+
+impl Layout {
+    const ALL: [Layout; 4] = [Layout::Ansi, Layout::Iso, Layout::AnsiNumpad, Layout::IsoNumpad];
+
+    /// Name used by the config and the clients
+    pub fn name(&self) -> &'static str {
+        match self {
+            Layout::Ansi => "ansi",
+            Layout::Iso => "iso",
+            Layout::AnsiNumpad => "ansi_numpad",
+            Layout::IsoNumpad => "iso_numpad",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Layout> {
+        let name = name.trim().to_lowercase();
+        Layout::ALL.iter().copied().find(|layout| layout.name() == name)
+    }
+
+    pub fn has_numpad(&self) -> bool {
+        matches!(self, Layout::AnsiNumpad | Layout::IsoNumpad)
+    }
+
+    fn with_numpad(self) -> Layout {
+        match self {
+            Layout::Ansi => Layout::AnsiNumpad,
+            Layout::Iso => Layout::IsoNumpad,
+            layout => layout,
+        }
+    }
+}
+
+/// Layouts of the known models by the start of their `SupportedDevice`
+/// name, the first match wins. The names are those of the US SKUs, an ISO
+/// keyboard can't be told apart by name and needs `keyboard_layout` in the
+/// config
+const KNOWN_LAYOUTS: [(&str, Layout); 3] = [
+    ("Razer Blade", Layout::Ansi),
+    ("Blade", Layout::Ansi),
+    ("Book", Layout::Ansi),
+];
+
+/// Layout of the current device, set once it has been found
+static LAYOUT: RwLock<Layout> = RwLock::new(Layout::Ansi);
+
+/// Infers the layout from the name of a supported device, with a numpad when
+/// its matrix is wider than the Blade one. None for unknown models
+pub fn detect_layout(device_name: &str, keys_per_row: u8) -> Option<Layout> {
+    let (_, layout) = KNOWN_LAYOUTS.iter().find(|(prefix, _)| device_name.starts_with(prefix))?;
+    if keys_per_row as usize > DEFAULT_KEYS_PER_ROW {
+        return Some(layout.with_numpad());
+    }
+
+    Some(*layout)
+}
+
+pub fn set_layout(layout: Layout) {
+    if let Ok(mut current) = LAYOUT.write() {
+        *current = layout;
+    }
+}
+
+pub fn layout() -> Layout {
+    LAYOUT.read().map_or(Layout::Ansi, |layout| *layout)
+}
+
+// -- Key regions --
+
+/// Named regions of the ANSI Blade layout, as key indices (row * 15 + column,
+/// see `input::KEY_CODE_ROWS` for where every key sits in the matrix). Wider
+/// layouts keep the main block in the left most columns, the regions stay
+/// on the same rows and columns there
+const BLADE_REGIONS: [(&str, &[usize]); 4] = [
+    ("wasd", &[32, 46, 47, 48]),
+    ("arrows", &[72, 86, 87, 88]),
+    ("function_row", &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]),
+    ("number_row", &[16, 17, 18, 19, 20, 21, 22, 23, 24, 25]),
+];
+
+/// OpenRGB names of the keys of the ANSI Blade layout, at the same places
+/// as `input::KEY_CODE_ROWS`. Lower case without the "Key: " prefix, '|'
+/// separates the other spellings of a key
+const BLADE_KEY_NAMES: [[&str; DEFAULT_KEYS_PER_ROW]; ROWS] = [
+    [
+        "escape|esc", "f1", "f2", "f3", "f4", "f5", "f6", "f7", "f8", "f9", "f10", "f11", "f12", "insert",
+        "delete",
+    ],
+    ["`", "1", "2", "3", "4", "5", "6", "7", "8", "9", "0", "-", "=", "backspace", ""],
+    ["tab", "q", "w", "e", "r", "t", "y", "u", "i", "o", "p", "[", "]", "\\ (ansi)|\\", ""],
+    ["caps lock", "a", "s", "d", "f", "g", "h", "j", "k", "l", ";", "'", "enter", "", ""],
+    [
+        "left shift", "z", "x", "c", "v", "b", "n", "m", ",", ".", "/", "right shift", "up arrow|up", "", "",
+    ],
+    [
+        "left control|left ctrl", "left fn|right fn|fn", "left windows|left super", "left alt", "space", "", "",
+        "", "", "right alt", "right control|right ctrl", "left arrow|left", "down arrow|down",
+        "right arrow|right", "",
+    ],
+];
+
+type KeyNames = [[&'static str; DEFAULT_KEYS_PER_ROW]; ROWS];
+type Regions = [(&'static str, &'static [usize])];
+
+/// Key name and region tables of the known models by the start of their
+/// `SupportedDevice` name, like `KNOWN_LAYOUTS`. Every known model has the
+/// Blade main block, a model with another one gets its own tables here
+const MODEL_TABLES: [(&str, &KeyNames, &Regions); 3] = [
+    ("Razer Blade", &BLADE_KEY_NAMES, &BLADE_REGIONS),
+    ("Blade", &BLADE_KEY_NAMES, &BLADE_REGIONS),
+    ("Book", &BLADE_KEY_NAMES, &BLADE_REGIONS),
+];
+
+/// Returns the key name table for the keyboard of a laptop, by its
+/// `SupportedDevice` name. None for unknown models
+fn layout_key_names(device_name: &str) -> Option<&'static KeyNames> {
+    let (_, names, _) = MODEL_TABLES.iter().find(|(prefix, _, _)| device_name.starts_with(prefix))?;
+    Some(*names)
+}
+
+/// Index on the keyboard zone of a key named like OpenRGB does ("Key: A",
+/// "Key: Left Shift"), None when the keyboard of `device_name` has no such key
+pub fn key_name_index(device_name: &str, name: &str) -> Option<usize> {
+    let name = name.trim().to_lowercase();
+    let name = name.strip_prefix("key:").map_or(name.as_str(), str::trim);
+    if name.is_empty() {
+        return None;
+    }
+    let index = layout_key_names(device_name)?
+        .iter()
+        .flatten()
+        .position(|names| names.split('|').any(|n| n == name))?;
+    let (row, col) = (index / DEFAULT_KEYS_PER_ROW, index % DEFAULT_KEYS_PER_ROW);
+    if col >= keys_per_row() {
+        return None;
+    }
+
+    Some(row * keys_per_row() + col)
+}
+
+/// Returns the region table for the keyboard of a laptop, by its
+/// `SupportedDevice` name. Empty for unknown models, which only have `all`
+/// and the numpad
+fn layout_regions(device_name: &str) -> &'static Regions {
+    MODEL_TABLES
+        .iter()
+        .find(|(prefix, _, _)| device_name.starts_with(prefix))
+        .map_or(&[], |(_, _, regions)| *regions)
+}
+
+/// Names of the regions available on the keyboard of `device_name`
+pub fn region_names(device_name: &str) -> Vec<String> {
+    let mut names = vec![String::from("all")];
+    names.extend(layout_regions(device_name).iter().map(|(name, _)| name.to_string()));
+    if layout().has_numpad() {
+        names.push(String::from("numpad"));
+    }
+    names
+}
+
+/// Builds the key mask of a named region, None if the layout has no such region
+pub fn region_mask(device_name: &str, region: &str) -> Option<Vec<bool>> {
+    if region == "all" {
+        return Some(vec![true; zone_key_count()]);
+    }
+    // Every column right of the Blade block
+    if region == "numpad" && layout().has_numpad() {
+        return Some((0..zone_key_count()).map(|index| key_coordinates(index).1 >= DEFAULT_KEYS_PER_ROW).collect());
+    }
+    let (_, keys) = layout_regions(device_name).iter().find(|(name, _)| *name == region)?;
+    let mut mask = vec![false; zone_key_count()];
+    for key in keys.iter() {
+        let (row, col) = (key / DEFAULT_KEYS_PER_ROW, key % DEFAULT_KEYS_PER_ROW);
+        if col < keys_per_row() {
+            mask[row * keys_per_row() + col] = true;
+        }
+    }
+
+    Some(mask)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regions_follow_the_model_and_layout() {
+        let _layout = test_layout(15, 1);
+        assert_eq!(region_names("Blade 15 2016"), ["all", "wasd", "arrows", "function_row", "number_row"]);
+        assert_eq!(region_names("Some Other Laptop"), ["all"]);
+        assert!(region_mask("Some Other Laptop", "wasd").is_none());
+        assert!(region_mask("Blade 15 2016", "numpad").is_none());
+        assert_eq!(key_name_index("Blade 15 2016", "Key: W"), Some(32));
+        assert_eq!(key_name_index("Some Other Laptop", "Key: W"), None);
+
+        set_keys_per_row(22);
+        set_layout(Layout::AnsiNumpad);
+        assert!(region_names("Blade 15 2016").contains(&String::from("numpad")));
+        let numpad = region_mask("Blade 15 2016", "numpad").unwrap();
+        assert_eq!(numpad.iter().filter(|&&key| key).count(), 7 * ROWS);
+        // The main block keeps its rows and columns
+        assert!(region_mask("Blade 15 2016", "wasd").unwrap()[2 * 22 + 2]);
+        set_layout(Layout::Ansi);
+    }
+
+    #[test]
+    fn full_channel_is_scaled_by_the_multiplier() {
+        assert_eq!(balance_channel(255, WHITE_BALANCE_ONE), 255);
+        assert_eq!(balance_channel(255, 500), 127);
+        assert_eq!(balance_channel(255, 0), 0);
+        // Above one is treated as one, channels are never boosted
+        assert_eq!(balance_channel(255, u16::MAX), 255);
+    }
+
+    #[test]
+    fn balance_is_applied_before_gamma() {
+        let table = gamma_table(2.0);
+        let mut data = [255, 255, 255, 128, 128, 128];
+        correct_channels(&mut data, &table, [500, WHITE_BALANCE_ONE, 0]);
+        // 255 halved to 127 then gamma corrected, not 255 corrected then halved
+        assert_eq!(data[0], table[127]);
+        assert_ne!(data[0], balance_channel(table[255], 500));
+        assert_eq!(&data[1..], &[table[255], table[0], table[64], table[128], table[0]]);
+    }
+
+    #[test]
+    fn channels_are_scaled_by_the_brightness() {
+        for value in [0, 1, 128, 255] {
+            assert_eq!(scale_channel(value, 0), 0);
+            assert_eq!(scale_channel(value, 100), value);
+            // Above 100% is treated as 100%
+            assert_eq!(scale_channel(value, u8::MAX), value);
+        }
+        assert_eq!(scale_channel(255, 50), 127);
+        assert_eq!(scale_channel(200, 25), 50);
+        assert_eq!(scale_channel(1, 99), 0);
+    }
+
+    #[test]
+    fn brightness_scales_every_key() {
+        let _layout = test_layout(15, 2);
+        let mut data = KeyboardData::new();
+        data.set_kbd_colour(200, 100, 0);
+        let mut off = data;
+        off.scale_brightness(0);
+        assert!(off.get_curr_state().iter().all(|channel| *channel == 0));
+        let mut full = data;
+        full.scale_brightness(100);
+        assert_eq!(full.get_curr_state(), data.get_curr_state());
+        data.scale_brightness(50);
+        assert!(data.get_curr_state().chunks(3).all(|rgb| rgb == [100, 50, 0]));
+    }
+
+    #[test]
+    fn channels_blend_from_one_end_to_the_other() {
+        for (from, to) in [(0, 255), (255, 0), (10, 20), (90, 90)] {
+            assert_eq!(blend_channel(from, to, 0.0), from);
+            assert_eq!(blend_channel(from, to, 1.0), to);
+        }
+        assert_eq!(blend_channel(0, 255, 0.5), 128);
+        assert_eq!(blend_channel(255, 0, 0.5), 128);
+        assert_eq!(blend_channel(10, 20, 0.5), 15);
+        // t is clamped
+        assert_eq!(blend_channel(10, 20, -1.0), 10);
+        assert_eq!(blend_channel(10, 20, 2.0), 20);
+    }
+
+    #[test]
+    fn blade_layout_has_90_keys() {
+        let _layout = test_layout(DEFAULT_KEYS_PER_ROW as u8, 1);
+        assert_eq!((keys_per_row(), zone_key_count(), key_count()), (15, 90, 90));
+        assert_eq!(key_coordinates(89), (5, 14));
+        assert_eq!(key_position(0), (-7.0, -2.5));
+    }
+
+    #[test]
+    fn numpad_layout_has_more_columns() {
+        let _layout = test_layout(22, 2);
+        assert_eq!((keys_per_row(), zone_key_count(), key_count()), (22, 132, 264));
+        assert_eq!(key_coordinates(21), (0, 21));
+        assert_eq!(key_coordinates(22), (1, 0));
+        assert_eq!(key_coordinates(131), (5, 21));
+        assert_eq!(detect_layout("Blade 18", 22), Some(Layout::AnsiNumpad));
+        assert_eq!(detect_layout("Blade 15", 15), Some(Layout::Ansi));
+        assert_eq!(detect_layout("Unknown", 22), None);
+    }
+
+    #[test]
+    fn layout_sizes_are_clamped() {
+        let _layout = test_layout(15, 1);
+        assert_eq!(set_keys_per_row(0), 1);
+        assert_eq!(set_keys_per_row(u8::MAX), MAX_KEYS_PER_ROW as u8);
+        assert_eq!(zone_key_count(), MAX_ZONE_KEYS);
+        assert_eq!(set_zone_count(0), 1);
+        assert_eq!(set_zone_count(u8::MAX), MAX_ZONES as u8);
+    }
+
+    fn rgb(key: KeyColour) -> [u8; 3] {
+        [key.red, key.green, key.blue]
+    }
+
+    #[test]
+    fn keys_past_the_layout_are_ignored() {
+        let _layout = test_layout(15, 1);
+        let mut data = KeyboardData::new();
+        let before = data.get_curr_state();
+        let red = KeyColour { red: 255, green: 0, blue: 0 };
+        data.set_key_at(key_count(), red);
+        data.set_key_at(usize::MAX, red);
+        data.set_key_colour(ROWS, 0, 255, 0, 0);
+        data.set_key_colour(0, keys_per_row(), 255, 0, 0);
+        assert_eq!(data.get_curr_state(), before);
+        assert_eq!(rgb(data.get_key_at(key_count())), [0, 0, 0]);
+        assert_eq!(rgb(data.get_key_at(usize::MAX)), [0, 0, 0]);
+        // The last key is still in
+        data.set_key_at(key_count() - 1, red);
+        assert_eq!(rgb(data.get_key_at(key_count() - 1)), [255, 0, 0]);
+    }
+
+    #[test]
+    fn gamma_1_leaves_the_channels_alone() {
+        let table = gamma_table(1.0);
+        assert!((0..=255u8).all(|value| table[value as usize] == value));
+    }
+
+    #[test]
+    fn gamma_tables_keep_the_ends_and_order() {
+        for gamma in [MIN_GAMMA, 0.8, 1.0, 2.2, MAX_GAMMA] {
+            let table = gamma_table(gamma);
+            assert_eq!((table[0], table[255]), (0, 255), "gamma {gamma}");
+            assert!(table.windows(2).all(|pair| pair[0] <= pair[1]), "gamma {gamma}");
+        }
+        // Above 1 darkens the middle, below 1 brightens it
+        assert!(gamma_table(2.2)[128] < 128);
+        assert!(gamma_table(0.8)[128] > 128);
+    }
+}
@@ -1,8 +1,19 @@
 use super::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Timing args out of range are clamped by `new` rather than rejected, and
+/// the effects report the clamped values. Durations in tenths of a second
+/// divide the elapsed time, so they can't be 0
+const MIN_DURATION_TENTHS: u8 = 1;
+/// Fastest waves and drops in keys or rows per second, beyond that they
+/// jump several keys from one frame to the next and only flicker
+const MAX_SPEED: u8 = 60;
 
 ///
 /// STATIC KEYBOARD EFFECT
 /// 1 colour, simple
+/// Args: red, green, blue
 ///
 
 #[derive(Copy, Clone)]
@@ -29,6 +40,13 @@ impl Effect for Static {
         return self.kbd;
     }
 
+    fn valid_args(args: &[u8]) -> bool
+    where
+        Self: Sized,
+    {
+        args.len() == 3
+    }
+
     fn get_name() -> &'static str
     where
         Self: Sized,
@@ -45,26 +63,73 @@ impl Effect for Static {
     }
 
     fn save(&mut self) -> EffectSave {
-        EffectSave {
-            args: self.args.to_vec(),
-            name: String::from("Static"),
-        }
+        EffectSave::new("Static", self.args.to_vec())
     }
 
     fn get_state(&mut self) -> Vec<u8> {
         self.kbd.get_curr_state()
     }
+
+    fn is_static(&self) -> bool {
+        true
+    }
+}
+
+/// Blend curves of the static gradient
+pub const EASING_LINEAR: u8 = 0;
+pub const EASING_SMOOTHSTEP: u8 = 1;
+pub const EASING_IN: u8 = 2;
+pub const EASING_OUT: u8 = 3;
+
+/// Blend factor of an `EASING_*` curve at `t`, both from 0 to 1
+pub fn ease(mode: u8, t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    match mode {
+        EASING_SMOOTHSTEP => t * t * (3.0 - 2.0 * t),
+        EASING_IN => t * t,
+        EASING_OUT => t * (2.0 - t),
+        _ => t,
+    }
+}
+
+/// Colour stops of the gradient effects, exactly `r1, g1, b1, r2, g2, b2`
+/// for 2 colours, otherwise a stop count followed by that many colours and
+/// maybe more arguments. A count form is never 6 bytes long, so the two
+/// can't be mistaken for each other. Returns the stops and the arguments
+/// after them, None when some colours are missing.
+fn gradient_stops(args: &[u8]) -> Option<(Vec<board::AnimatorKeyColour>, &[u8])> {
+    let (count, start) = if args.len() == 6 { (2, 0) } else { (*args.first()? as usize, 1) };
+    let end = start + count * 3;
+    if count < 2 || args.len() < end {
+        return None;
+    }
+    let stops = args[start..end]
+        .chunks(3)
+        .map(|c| board::AnimatorKeyColour::new_u(c[0], c[1], c[2]))
+        .collect();
+    Some((stops, &args[end..]))
+}
+
+/// Colour at `t` (0 to 1) of evenly spaced stops, eased between each pair
+fn blend_stops(stops: &[board::AnimatorKeyColour], easing: u8, t: f32) -> board::AnimatorKeyColour {
+    let position = t.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+    let segment = (position as usize).min(stops.len() - 2);
+    let (from, to) = (stops[segment], stops[segment + 1]);
+    from + (to - from).multiply(ease(easing, position - segment as f32))
 }
 
 ///
 /// STATIC_BLEND KEYBOARD EFFECT
-/// 2 colours forming a gradient
+/// 2 or more colours forming a gradient
+/// Args: red1, green1, blue1, red2, green2, blue2. Or the number of colours,
+/// red, green, blue of each, optionally the blend curve (linear when absent,
+/// see `ease`), which is also the form for 2 colours with a curve
 ///
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct StaticGradient {
     kbd: board::KeyboardData,
-    args: [u8; 6],
+    args: Vec<u8>,
 }
 
 impl Effect for StaticGradient {
@@ -73,16 +138,12 @@ impl Effect for StaticGradient {
         Self: Sized,
     {
         let mut kbd = board::KeyboardData::new();
-        let args: [u8; 6] = [
-            args[0], args[1], args[2], args[3], args[4], args[5]
-        ];
-        let mut c1 = board::AnimatorKeyColour::new_u(args[0], args[1], args[2]);
-        let c2 = board::AnimatorKeyColour::new_u(args[3], args[4], args[5]);
-        let delta = (c2 - c1).divide(14.0);
-        for i in 0..15 {
-            let clamped = c1.get_clamped_colour();
+        let (stops, rest) = gradient_stops(&args).unwrap_or_default();
+        let easing = rest.first().copied().unwrap_or(EASING_LINEAR);
+        let last = (board::keys_per_row() - 1).max(1) as f32;
+        for i in 0..board::keys_per_row() {
+            let clamped = blend_stops(&stops, easing, i as f32 / last).get_clamped_colour();
             kbd.set_col_colour(i, clamped.red, clamped.green, clamped.blue);
-            c1 += delta;
         }
 
         Box::new(StaticGradient { kbd, args })
@@ -92,6 +153,16 @@ impl Effect for StaticGradient {
         self.kbd // Nothing to update
     }
 
+    fn valid_args(args: &[u8]) -> bool
+    where
+        Self: Sized,
+    {
+        match gradient_stops(args) {
+            Some((_, rest)) => rest.len() <= 1 && rest.iter().all(|easing| *easing <= EASING_OUT),
+            None => false,
+        }
+    }
+
     fn get_name() -> &'static str
     where
         Self: Sized,
@@ -104,14 +175,11 @@ impl Effect for StaticGradient {
     }
 
     fn clone_box(&self) -> Box<dyn Effect> {
-        return Box::new(*self);
+        Box::new(self.clone())
     }
 
     fn save(&mut self) -> EffectSave {
-        EffectSave {
-            args: self.args.to_vec(),
-            name: String::from("Static Gradient"),
-        }
+        EffectSave::new("Static Gradient", self.args.clone())
     }
 
     fn get_state(&mut self) -> Vec<u8> {
@@ -121,12 +189,15 @@ impl Effect for StaticGradient {
 
 ///
 /// STATIC_BLEND KEYBOARD EFFECT
-/// 2 colours forming a gradient, animated across the keyboard
+/// 2 or more colours forming a gradient, animated across the keyboard
+/// Args: red1, green1, blue1, red2, green2, blue2, or the number of colours
+/// followed by red, green, blue of each
 ///
 
+#[derive(Clone)]
 pub struct WaveGradient {
     kbd: board::KeyboardData,
-    args: [u8; 6],
+    args: Vec<u8>,
     colour_band: Vec<board::AnimatorKeyColour>,
 }
 
@@ -135,30 +206,25 @@ impl Effect for WaveGradient {
     where
         Self: Sized,
     {
-        let args: [u8; 6] = [
-            args[0], args[1], args[2], args[3], args[4], args[5],
-        ];
+        let (stops, _) = gradient_stops(&args).unwrap_or_default();
         let mut wave = WaveGradient {
             kbd: board::KeyboardData::new(),
             args,
             colour_band: vec![],
         };
-        let mut c1 = board::AnimatorKeyColour::new_u(args[0], args[1], args[2]);
-        let mut c2 = board::AnimatorKeyColour::new_u(args[3], args[4], args[5]);
-        let c_delta = (c2 - c1).divide(15.0);
-        for _ in 0..15 {
-            wave.colour_band.push(c1);
-            c1 += c_delta;
+        // Through the colours and back, over the width of the keyboard
+        let cols = board::keys_per_row();
+        for i in 0..cols {
+            wave.colour_band.push(blend_stops(&stops, EASING_LINEAR, i as f32 / cols as f32));
         }
-        for _ in 0..15 {
-            wave.colour_band.push(c2);
-            c2 -= c_delta;
+        for i in 0..cols {
+            wave.colour_band.push(blend_stops(&stops, EASING_LINEAR, 1.0 - i as f32 / cols as f32));
         }
         Box::new(wave)
     }
 
     fn update(&mut self) -> board::KeyboardData {
-        for i in 0..15 {
+        for i in 0..board::keys_per_row() {
             let c = self.colour_band[i].get_clamped_colour();
             self.kbd.set_col_colour(i, c.red, c.green, c.blue);
         }
@@ -166,6 +232,13 @@ impl Effect for WaveGradient {
         self.kbd
     }
 
+    fn valid_args(args: &[u8]) -> bool
+    where
+        Self: Sized,
+    {
+        matches!(gradient_stops(args), Some((_, rest)) if rest.is_empty())
+    }
+
     fn get_name() -> &'static str
     where
         Self: Sized,
@@ -182,10 +255,7 @@ impl Effect for WaveGradient {
     }
 
     fn save(&mut self) -> EffectSave {
-        EffectSave {
-            args: self.args.to_vec(),
-            name: String::from("Wave Gradient"),
-        }
+        EffectSave::new("Wave Gradient", self.args.clone())
     }
 
     fn get_state(&mut self) -> Vec<u8> {
@@ -193,91 +263,117 @@ impl Effect for WaveGradient {
     }
 }
 
-impl Clone for WaveGradient {
-    fn clone(&self) -> Self {
-        WaveGradient {
-            kbd: self.kbd,
-            args: self.args,
-            colour_band: self.colour_band.to_vec(),
-        }
-    }
-}
-
 ///
 /// BREATHING (1 Colour) KEYBOARD EFFECT
 /// 1 colour, fading in and out
+/// Args: red, green, blue, duration of a breath in tenths of a second
+/// (at least 1), optionally the lowest and highest brightness in percent (0
+/// and 100 when absent)
 ///
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct BreathSingle {
-    args: [u8; 4],
+    args: Vec<u8>,
     kbd: board::KeyboardData,
     step_duration_ms: u128,
     static_start_ms: u128,
     curr_step: u8, // Step 0 = Off, 1 = increasing, 2 = On, 3 = decreasing
     target_colour: board::AnimatorKeyColour,
     current_colour: board::AnimatorKeyColour,
-    animator_step_colour: board::AnimatorKeyColour,
+    /// Brightness bounds the fade is mapped into, from 0 to 1
+    min: f32,
+    max: f32,
 }
 
-impl Effect for BreathSingle {
-    fn new(args: Vec<u8>) -> Box<dyn Effect> {
+impl BreathSingle {
+    fn from_args(mut args: Vec<u8>) -> BreathSingle {
+        args[3] = args[3].max(MIN_DURATION_TENTHS);
         let mut k = board::KeyboardData::new();
         let cycle_duration_ms = args[3] as f32 * 100.0;
         k.set_kbd_colour(0, 0, 0); // Sets all keyboard lights off initially
-        Box::new(BreathSingle {
-            args: [args[0], args[1], args[2], args[3]],
+        let (min, max) = match args[..] {
+            [_, _, _, _, min, max] => (min as f32 / 100.0, max as f32 / 100.0),
+            _ => (0.0, 1.0),
+        };
+        BreathSingle {
             kbd: k,
             step_duration_ms: cycle_duration_ms as u128,
-            static_start_ms: get_millis(),
+            static_start_ms: animation_millis(),
             curr_step: 0,
             target_colour: board::AnimatorKeyColour::new_u(args[0], args[1], args[2]),
             current_colour: board::AnimatorKeyColour::new_u(0, 0, 0),
-            animator_step_colour: board::AnimatorKeyColour::new_f(
-                args[0] as f32 / (cycle_duration_ms as f32 / ANIMATION_SLEEP_MS as f32) as f32,
-                args[1] as f32 / (cycle_duration_ms as f32 / ANIMATION_SLEEP_MS as f32) as f32,
-                args[2] as f32 / (cycle_duration_ms as f32 / ANIMATION_SLEEP_MS as f32) as f32,
-            ),
-        })
+            args,
+            min,
+            max,
+        }
+    }
+}
+
+impl Effect for BreathSingle {
+    fn new(args: Vec<u8>) -> Box<dyn Effect> {
+        Box::new(BreathSingle::from_args(args))
     }
 
     fn update(&mut self) -> board::KeyboardData {
+        // Worked out every frame so the fade keeps its duration if the frame rate changes
+        let animator_step_colour = self
+            .target_colour
+            .multiply(get_animation_sleep_ms() as f32 / self.step_duration_ms as f32);
         match self.curr_step {
             0 => {
                 self.current_colour = board::AnimatorKeyColour::new_u(0, 0, 0);
-                if get_millis() - self.static_start_ms >= self.step_duration_ms {
+                if animation_millis().saturating_sub(self.static_start_ms) >= self.step_duration_ms {
                     self.curr_step += 1;
                 }
             }
             1 => {
                 // Increasing
-                self.current_colour += self.animator_step_colour;
+                self.current_colour += animator_step_colour;
                 if self.current_colour >= self.target_colour {
                     self.curr_step += 1;
-                    self.static_start_ms = get_millis();
+                    self.static_start_ms = animation_millis();
                 }
             }
             2 => {
                 self.current_colour = self.target_colour;
-                if get_millis() - self.static_start_ms >= self.step_duration_ms {
+                if animation_millis().saturating_sub(self.static_start_ms) >= self.step_duration_ms {
                     self.curr_step += 1;
                 }
             }
             3 => {
                 // Decreasing
-                self.current_colour -= self.animator_step_colour;
+                self.current_colour -= animator_step_colour;
                 let target = board::AnimatorKeyColour::new_u(0, 0, 0);
                 if self.current_colour <= target {
                     self.curr_step = 0;
-                    self.static_start_ms = get_millis();
+                    self.static_start_ms = animation_millis();
                 }
             }
             _ => {} // Unknown state? Ignore
         }
-        let col = self.current_colour.get_clamped_colour();
+        // The fade runs from off to the colour, shown between the bounds
+        let fade = self.current_colour.get_clamped_colour();
+        let peak = self.args[0].max(self.args[1]).max(self.args[2]) as f32;
+        let fraction = (fade.red.max(fade.green).max(fade.blue) as f32 / peak.max(1.0)).min(1.0);
+        let shown = self.target_colour.multiply(self.min + (self.max - self.min) * fraction);
+        let col = shown.get_clamped_colour();
         self.kbd.set_kbd_colour(col.red, col.green, col.blue); // Cast back to u8
+        // How far along the fade is, from the brightest channel
+        let level = col.red.max(col.green).max(col.blue) as u32 * 255 / (peak as u32).max(1);
+        publish_breath_level(level.min(255) as u8);
         return self.kbd;
     }
 
+    fn valid_args(args: &[u8]) -> bool
+    where
+        Self: Sized,
+    {
+        match args {
+            [_, _, _, _] => true,
+            [_, _, _, _, min, max] => min <= max && *max <= 100,
+            _ => false,
+        }
+    }
+
     fn get_name() -> &'static str
     where
         Self: Sized,
@@ -290,17 +386,1746 @@ impl Effect for BreathSingle {
     }
 
     fn clone_box(&self) -> Box<dyn Effect> {
-        return Box::new(*self);
+        Box::new(self.clone())
+    }
+
+    fn save(&mut self) -> EffectSave {
+        EffectSave::new("Breathing Single", self.args.clone())
+    }
+
+    fn get_state(&mut self) -> Vec<u8> {
+        self.kbd.get_curr_state()
+    }
+}
+
+/// Speed at which a ripple ring travels, in keys per second
+const RIPPLE_SPEED: f32 = 15.0;
+
+///
+/// RIPPLE KEYBOARD EFFECT
+/// 1 colour, rings expanding from every pressed key
+/// Args: red, green, blue, radius in keys
+///
+
+#[derive(Copy, Clone)]
+pub struct Ripple {
+    kbd: board::KeyboardData,
+    args: [u8; 4],
+    colour: board::AnimatorKeyColour,
+    radius: f32,
+}
+
+impl Effect for Ripple {
+    fn new(args: Vec<u8>) -> Box<dyn Effect>
+    where
+        Self: Sized,
+    {
+        let mut kbd = board::KeyboardData::new();
+        kbd.set_kbd_colour(0, 0, 0);
+        Box::new(Ripple {
+            kbd,
+            args: [args[0], args[1], args[2], args[3]],
+            colour: board::AnimatorKeyColour::new_u(args[0], args[1], args[2]),
+            radius: args[3].max(1) as f32,
+        })
+    }
+
+    fn update(&mut self) -> board::KeyboardData {
+        let now = animation_millis();
+        let lifetime_ms = (self.radius / RIPPLE_SPEED * 1000.0) as u128;
+        let presses = input::key_presses_since(now.saturating_sub(lifetime_ms));
+
+        let mut intensity = vec![0f32; board::zone_key_count()];
+        for press in presses {
+            let ring = now.saturating_sub(press.time_ms) as f32 / 1000.0 * RIPPLE_SPEED;
+            let fade = 1.0 - ring / self.radius;
+            let (press_row, press_col) = (
+                (press.index / board::keys_per_row()) as f32,
+                (press.index % board::keys_per_row()) as f32,
+            );
+            for (pos, value) in intensity.iter_mut().enumerate() {
+                let row = (pos / board::keys_per_row()) as f32 - press_row;
+                let col = (pos % board::keys_per_row()) as f32 - press_col;
+                let distance = (row * row + col * col).sqrt();
+                let on_ring = (1.0 - (distance - ring).abs()).max(0.0);
+                *value = value.max(on_ring * fade);
+            }
+        }
+
+        for (pos, value) in intensity.iter().enumerate() {
+            let c = self.colour.multiply(*value).get_clamped_colour();
+            self.kbd.set_key_at(pos, c);
+        }
+        self.kbd
+    }
+
+    fn valid_args(args: &[u8]) -> bool
+    where
+        Self: Sized,
+    {
+        args.len() == 4
+    }
+
+    fn get_name() -> &'static str
+    where
+        Self: Sized,
+    {
+        "Ripple"
+    }
+
+    fn get_varargs(&mut self) -> &[u8] {
+        &self.args
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(*self)
+    }
+
+    fn save(&mut self) -> EffectSave {
+        EffectSave::new("Ripple", self.args.to_vec())
+    }
+
+    fn get_state(&mut self) -> Vec<u8> {
+        self.kbd.get_curr_state()
+    }
+}
+
+/// Keyboard with every key off, for the effects that only draw the keyboard
+/// zone and leave the extra zones dark
+fn dark_keyboard() -> board::KeyboardData {
+    let mut kbd = board::KeyboardData::new();
+    kbd.set_kbd_colour(0, 0, 0);
+    kbd
+}
+
+/// Number of bytes needed to describe every key of a zone, 270 for the Blade layout
+pub fn custom_frame_len() -> usize {
+    3 * board::zone_key_count()
+}
+
+///
+/// CUSTOM FRAME KEYBOARD EFFECT
+/// Per key colours, uploaded as a full RGB buffer of the layout
+/// Args: red, green, blue of each key, row by row, optionally followed by
+/// the keys of the extra zones
+///
+
+#[derive(Clone)]
+pub struct CustomFrame {
+    kbd: board::KeyboardData,
+    args: Vec<u8>,
+}
+
+impl Effect for CustomFrame {
+    fn new(args: Vec<u8>) -> Box<dyn Effect>
+    where
+        Self: Sized,
+    {
+        let mut kbd = board::KeyboardData::new();
+        kbd.set_kbd_colour(0, 0, 0); // Zones left out of the frame stay dark
+        for (pos, rgb) in args.chunks_exact(3).take(board::key_count()).enumerate() {
+            kbd.set_key_at(pos, board::KeyColour { red: rgb[0], green: rgb[1], blue: rgb[2] });
+        }
+        Box::new(CustomFrame { kbd, args })
+    }
+
+    fn update(&mut self) -> board::KeyboardData {
+        self.kbd // Nothing to update
+    }
+
+    fn valid_args(args: &[u8]) -> bool
+    where
+        Self: Sized,
+    {
+        args.len().is_multiple_of(custom_frame_len())
+            && (1..=board::zone_count()).contains(&(args.len() / custom_frame_len()))
+    }
+
+    fn get_name() -> &'static str
+    where
+        Self: Sized,
+    {
+        "Custom Frame"
+    }
+
+    fn get_varargs(&mut self) -> &[u8] {
+        &self.args
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(self.clone())
     }
 
     fn save(&mut self) -> EffectSave {
-        EffectSave {
-            args: self.args.to_vec(),
-            name: String::from("Breathing Single"),
+        EffectSave::new("Custom Frame", self.args.clone())
+    }
+
+    fn get_state(&mut self) -> Vec<u8> {
+        self.kbd.get_curr_state()
+    }
+}
+
+///
+/// REACTIVE KEYBOARD EFFECT
+/// 1 colour, pressed keys light up and fade back to black
+/// Args: red, green, blue, fade duration in tenths of a second (at least 1)
+///
+
+#[derive(Copy, Clone)]
+pub struct Reactive {
+    kbd: board::KeyboardData,
+    args: [u8; 4],
+    colour: board::AnimatorKeyColour,
+    fade_duration_ms: u128,
+    last_check_ms: u128,
+    last_press_ms: [Option<u128>; board::MAX_ZONE_KEYS],
+}
+
+impl Effect for Reactive {
+    fn new(args: Vec<u8>) -> Box<dyn Effect>
+    where
+        Self: Sized,
+    {
+        let fade_duration = args[3].max(MIN_DURATION_TENTHS);
+        let mut kbd = board::KeyboardData::new();
+        kbd.set_kbd_colour(0, 0, 0);
+        Box::new(Reactive {
+            kbd,
+            args: [args[0], args[1], args[2], fade_duration],
+            colour: board::AnimatorKeyColour::new_u(args[0], args[1], args[2]),
+            fade_duration_ms: fade_duration as u128 * 100,
+            last_check_ms: animation_millis(),
+            last_press_ms: [None; board::MAX_ZONE_KEYS],
+        })
+    }
+
+    fn update(&mut self) -> board::KeyboardData {
+        let now = animation_millis();
+        for press in input::key_presses_since(self.last_check_ms) {
+            self.last_press_ms[press.index] = Some(press.time_ms);
+        }
+        self.last_check_ms = now;
+
+        for (pos, last_press) in self.last_press_ms.iter_mut().take(board::zone_key_count()).enumerate() {
+            let remaining = match last_press {
+                Some(time) if now.saturating_sub(*time) < self.fade_duration_ms => {
+                    1.0 - now.saturating_sub(*time) as f32 / self.fade_duration_ms as f32
+                }
+                _ => {
+                    *last_press = None;
+                    0.0
+                }
+            };
+            self.kbd.set_key_at(pos, self.colour.multiply(remaining).get_clamped_colour());
         }
+        self.kbd
+    }
+
+    fn valid_args(args: &[u8]) -> bool
+    where
+        Self: Sized,
+    {
+        args.len() == 4
+    }
+
+    fn get_name() -> &'static str
+    where
+        Self: Sized,
+    {
+        "Reactive"
+    }
+
+    fn get_varargs(&mut self) -> &[u8] {
+        &self.args
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(*self)
+    }
+
+    fn save(&mut self) -> EffectSave {
+        EffectSave::new("Reactive", self.args.to_vec())
     }
 
     fn get_state(&mut self) -> Vec<u8> {
         self.kbd.get_curr_state()
     }
 }
+
+///
+/// AUDIO VISUALIZER KEYBOARD EFFECT
+/// 1 hue, every column is a spectrum band of the system audio, lighting up
+/// from the bottom row as it gets louder
+/// Args: hue (0-255 around the colour wheel), sensitivity
+///
+
+/// Lowest and highest analysed frequencies in Hz, spread logarithmically
+/// across the columns
+const AUDIO_MIN_FREQUENCY: f32 = 60.0;
+const AUDIO_MAX_FREQUENCY: f32 = 6000.0;
+/// How much of a bar is kept from one frame to the next, so it falls smoothly
+const AUDIO_DECAY: f32 = 0.8;
+
+#[derive(Copy, Clone)]
+pub struct AudioVisualizer {
+    kbd: board::KeyboardData,
+    args: [u8; 2],
+    colour: board::AnimatorKeyColour,
+    sensitivity: f32,
+    levels: [f32; board::MAX_KEYS_PER_ROW],
+}
+
+impl Effect for AudioVisualizer {
+    fn new(args: Vec<u8>) -> Box<dyn Effect>
+    where
+        Self: Sized,
+    {
+        let colour = board::hsv_to_rgb(args[0] as f32 * 360.0 / 256.0, 1.0, 1.0);
+        let mut kbd = board::KeyboardData::new();
+        kbd.set_kbd_colour(0, 0, 0);
+        Box::new(AudioVisualizer {
+            kbd,
+            args: [args[0], args[1]],
+            colour: board::AnimatorKeyColour::new_u(colour.red, colour.green, colour.blue),
+            sensitivity: args[1].max(1) as f32,
+            levels: [0.0; board::MAX_KEYS_PER_ROW],
+        })
+    }
+
+    fn update(&mut self) -> board::KeyboardData {
+        // Without audio there are no samples, every band is silent and the board black
+        let samples = audio::latest_samples();
+        let ratio = AUDIO_MAX_FREQUENCY / AUDIO_MIN_FREQUENCY;
+        for col in 0..board::keys_per_row() {
+            let frequency = AUDIO_MIN_FREQUENCY
+                * ratio.powf(col as f32 / (board::keys_per_row() - 1) as f32);
+            let level = (audio::band_magnitude(&samples, frequency) * self.sensitivity).min(1.0);
+            self.levels[col] = level.max(self.levels[col] * AUDIO_DECAY);
+
+            let height = self.levels[col] * board::ROWS as f32;
+            for row in 0..board::ROWS {
+                let fill = (height - (board::ROWS - 1 - row) as f32).clamp(0.0, 1.0);
+                let colour = self.colour.multiply(fill).get_clamped_colour();
+                self.kbd.set_key_colour(row, col, colour.red, colour.green, colour.blue);
+            }
+        }
+        self.kbd
+    }
+
+    fn valid_args(args: &[u8]) -> bool
+    where
+        Self: Sized,
+    {
+        args.len() == 2
+    }
+
+    fn get_name() -> &'static str
+    where
+        Self: Sized,
+    {
+        "Audio Visualizer"
+    }
+
+    fn get_varargs(&mut self) -> &[u8] {
+        &self.args
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(*self)
+    }
+
+    fn save(&mut self) -> EffectSave {
+        EffectSave::new("Audio Visualizer", self.args.to_vec())
+    }
+
+    fn get_state(&mut self) -> Vec<u8> {
+        self.kbd.get_curr_state()
+    }
+}
+
+///
+/// SPECTRUM CYCLE KEYBOARD EFFECT
+/// Whole keyboard in one colour, going around the colour wheel
+/// Args: duration of a cycle in tenths of a second (at least 1)
+///
+
+#[derive(Copy, Clone)]
+pub struct SpectrumCycle {
+    kbd: board::KeyboardData,
+    args: [u8; 1],
+    period_ms: u128,
+    start_ms: u128,
+}
+
+impl Effect for SpectrumCycle {
+    fn new(args: Vec<u8>) -> Box<dyn Effect>
+    where
+        Self: Sized,
+    {
+        let period = args[0].max(MIN_DURATION_TENTHS);
+        Box::new(SpectrumCycle {
+            kbd: board::KeyboardData::new(),
+            args: [period],
+            period_ms: period as u128 * 100,
+            start_ms: animation_millis(),
+        })
+    }
+
+    fn update(&mut self) -> board::KeyboardData {
+        // Based on the elapsed time, so the cycle speed doesn't depend on the frame rate
+        let elapsed = animation_millis().saturating_sub(self.start_ms) % self.period_ms;
+        let colour = board::hsv_to_rgb(elapsed as f32 * 360.0 / self.period_ms as f32, 1.0, 1.0);
+        self.kbd.set_kbd_colour(colour.red, colour.green, colour.blue);
+        self.kbd
+    }
+
+    fn valid_args(args: &[u8]) -> bool
+    where
+        Self: Sized,
+    {
+        args.len() == 1
+    }
+
+    fn get_name() -> &'static str
+    where
+        Self: Sized,
+    {
+        "Spectrum Cycle"
+    }
+
+    fn get_varargs(&mut self) -> &[u8] {
+        &self.args
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(*self)
+    }
+
+    fn save(&mut self) -> EffectSave {
+        EffectSave::new("Spectrum Cycle", self.args.to_vec())
+    }
+
+    fn get_state(&mut self) -> Vec<u8> {
+        self.kbd.get_curr_state()
+    }
+}
+
+///
+/// BREATHING (Multiple colours) KEYBOARD EFFECT
+/// Each colour of a list fades in and out, one after the other
+/// Args: colour count, that many red, green, blue triples, duration of
+/// each colour in tenths of a second (at least 1)
+///
+
+#[derive(Clone)]
+pub struct BreathMulti {
+    kbd: board::KeyboardData,
+    args: Vec<u8>,
+    colours: Vec<board::AnimatorKeyColour>,
+    step_duration_ms: u128,
+    start_ms: u128,
+}
+
+impl Effect for BreathMulti {
+    fn new(mut args: Vec<u8>) -> Box<dyn Effect>
+    where
+        Self: Sized,
+    {
+        // Checked by valid_args, but a short list must not panic either
+        if args.is_empty() {
+            args.push(MIN_DURATION_TENTHS);
+        }
+        let last = args.len() - 1;
+        args[last] = args[last].max(MIN_DURATION_TENTHS);
+        let colours = args
+            .get(1..last)
+            .unwrap_or_default()
+            .chunks_exact(3)
+            .map(|rgb| board::AnimatorKeyColour::new_u(rgb[0], rgb[1], rgb[2]))
+            .collect();
+        Box::new(BreathMulti {
+            kbd: board::KeyboardData::new(),
+            step_duration_ms: args[last] as u128 * 100,
+            args,
+            colours,
+            start_ms: animation_millis(),
+        })
+    }
+
+    fn update(&mut self) -> board::KeyboardData {
+        if self.colours.is_empty() {
+            return self.kbd;
+        }
+        let elapsed = animation_millis().saturating_sub(self.start_ms);
+        let step = (elapsed / self.step_duration_ms) as usize % self.colours.len();
+        let progress = (elapsed % self.step_duration_ms) as f32 / self.step_duration_ms as f32;
+        // Half a sine period per colour: fades in, peaks halfway, fades out
+        let brightness = (progress * std::f32::consts::PI).sin();
+        let colour = self.colours[step].multiply(brightness).get_clamped_colour();
+        self.kbd.set_kbd_colour(colour.red, colour.green, colour.blue);
+        self.kbd
+    }
+
+    fn valid_args(args: &[u8]) -> bool
+    where
+        Self: Sized,
+    {
+        !args.is_empty() && args[0] > 0 && args.len() == 2 + 3 * args[0] as usize
+    }
+
+    fn get_name() -> &'static str
+    where
+        Self: Sized,
+    {
+        "Breathing Multi"
+    }
+
+    fn get_varargs(&mut self) -> &[u8] {
+        &self.args
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(self.clone())
+    }
+
+    fn save(&mut self) -> EffectSave {
+        EffectSave::new("Breathing Multi", self.args.clone())
+    }
+
+    fn get_state(&mut self) -> Vec<u8> {
+        self.kbd.get_curr_state()
+    }
+}
+
+///
+/// STARLIGHT KEYBOARD EFFECT
+/// Random keys light up and fade out like twinkling stars over a background
+/// Args: red, green, blue, density, random hue (0 or 1), background red,
+/// green, blue
+///
+
+/// How long one star twinkles
+const STARLIGHT_TWINKLE_MS: u128 = 1500;
+
+#[derive(Clone)]
+pub struct Starlight {
+    kbd: board::KeyboardData,
+    args: [u8; 8],
+    colour: board::AnimatorKeyColour,
+    background: board::AnimatorKeyColour,
+    random_hue: bool,
+    density: f32,
+    /// When each key started twinkling, 0 while it is dark
+    started_ms: [u128; board::MAX_ZONE_KEYS],
+    colours: [board::AnimatorKeyColour; board::MAX_ZONE_KEYS],
+    last_update_ms: u128,
+    rng: StdRng,
+}
+
+impl Starlight {
+    /// Starlight drawing its stars with `rng`, a seeded one for the tests
+    fn with_rng(args: &[u8], rng: StdRng) -> Starlight {
+        let colour = board::AnimatorKeyColour::new_u(args[0], args[1], args[2]);
+        Starlight {
+            kbd: dark_keyboard(),
+            args: [args[0], args[1], args[2], args[3], args[4], args[5], args[6], args[7]],
+            colour,
+            background: board::AnimatorKeyColour::new_u(args[5], args[6], args[7]),
+            random_hue: args[4] != 0,
+            density: args[3] as f32 / 255.0,
+            started_ms: [0; board::MAX_ZONE_KEYS],
+            colours: [colour; board::MAX_ZONE_KEYS],
+            last_update_ms: animation_millis(),
+            rng,
+        }
+    }
+
+    /// Frame at `now` in ms of the animation clock
+    fn draw(&mut self, now: u128) -> board::KeyboardData {
+        // Chance for a dark key to start twinkling during this frame, at full
+        // density about half the keys are lit at any time
+        let elapsed = now.saturating_sub(self.last_update_ms) as f32;
+        let chance = self.density * elapsed / STARLIGHT_TWINKLE_MS as f32 / 2.0;
+        self.last_update_ms = now;
+
+        for pos in 0..board::zone_key_count() {
+            if self.started_ms[pos] != 0 && now.saturating_sub(self.started_ms[pos]) >= STARLIGHT_TWINKLE_MS {
+                self.started_ms[pos] = 0;
+            }
+            if self.started_ms[pos] == 0 && self.rng.r#gen::<f32>() < chance {
+                self.started_ms[pos] = now;
+                self.colours[pos] = if self.random_hue {
+                    let c = board::hsv_to_rgb(self.rng.gen_range(0.0..360.0), 1.0, 1.0);
+                    board::AnimatorKeyColour::new_u(c.red, c.green, c.blue)
+                } else {
+                    self.colour
+                };
+            }
+
+            let intensity = if self.started_ms[pos] == 0 {
+                0.0
+            } else {
+                // Rises and fades along half a sine period
+                let progress = now.saturating_sub(self.started_ms[pos]) as f32 / STARLIGHT_TWINKLE_MS as f32;
+                (progress * std::f32::consts::PI).sin()
+            };
+            let star = self.colours[pos];
+            let c = board::AnimatorKeyColour::new_f(
+                self.background.red + (star.red - self.background.red) * intensity,
+                self.background.green + (star.green - self.background.green) * intensity,
+                self.background.blue + (star.blue - self.background.blue) * intensity,
+            );
+            self.kbd.set_key_at(pos, c.get_clamped_colour());
+        }
+        self.kbd
+    }
+}
+
+impl Effect for Starlight {
+    fn new(args: Vec<u8>) -> Box<dyn Effect>
+    where
+        Self: Sized,
+    {
+        Box::new(Starlight::with_rng(&args, StdRng::seed_from_u64(animation_millis() as u64)))
+    }
+
+    fn update(&mut self) -> board::KeyboardData {
+        self.draw(animation_millis())
+    }
+
+    fn valid_args(args: &[u8]) -> bool
+    where
+        Self: Sized,
+    {
+        args.len() == 8
+    }
+
+    fn get_name() -> &'static str
+    where
+        Self: Sized,
+    {
+        "Starlight"
+    }
+
+    fn get_varargs(&mut self) -> &[u8] {
+        &self.args
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(self.clone())
+    }
+
+    fn save(&mut self) -> EffectSave {
+        EffectSave::new("Starlight", self.args.to_vec())
+    }
+
+    fn get_state(&mut self) -> Vec<u8> {
+        self.kbd.get_curr_state()
+    }
+}
+
+///
+/// WAVE KEYBOARD EFFECT
+/// 2 colours, a wave going from one to the other sweeping the keyboard in a
+/// chosen direction
+/// Args: red1, green1, blue1, red2, green2, blue2, direction (0 = left to
+/// right, 1 = right to left, 2 = top to bottom, 3 = bottom to top), speed
+/// in keys per second (1-60)
+///
+
+#[derive(Copy, Clone)]
+pub struct Wave {
+    kbd: board::KeyboardData,
+    args: [u8; 8],
+    colour1: board::AnimatorKeyColour,
+    colour2: board::AnimatorKeyColour,
+    start_ms: u128,
+}
+
+impl Effect for Wave {
+    fn new(args: Vec<u8>) -> Box<dyn Effect>
+    where
+        Self: Sized,
+    {
+        Box::new(Wave {
+            kbd: dark_keyboard(),
+            args: [args[0], args[1], args[2], args[3], args[4], args[5], args[6], args[7].clamp(1, MAX_SPEED)],
+            colour1: board::AnimatorKeyColour::new_u(args[0], args[1], args[2]),
+            colour2: board::AnimatorKeyColour::new_u(args[3], args[4], args[5]),
+            start_ms: animation_millis(),
+        })
+    }
+
+    fn update(&mut self) -> board::KeyboardData {
+        let travelled = animation_millis().saturating_sub(self.start_ms) as f32 / 1000.0 * self.args[7] as f32;
+        for pos in 0..board::zone_key_count() {
+            let (row, col) = board::key_coordinates(pos);
+            // Position of the key along the axis, in the direction of the wave
+            let (position, length) = match self.args[6] {
+                1 => ((board::keys_per_row() - 1 - col) as f32, board::keys_per_row() as f32),
+                2 => (row as f32, board::ROWS as f32),
+                3 => ((board::ROWS - 1 - row) as f32, board::ROWS as f32),
+                _ => (col as f32, board::keys_per_row() as f32),
+            };
+            // One full colour1 -> colour2 -> colour1 period across the axis
+            let phase = (position - travelled) / length * 2.0 * std::f32::consts::PI;
+            let mix = (1.0 - phase.cos()) / 2.0;
+            let c = board::AnimatorKeyColour::new_f(
+                self.colour1.red + (self.colour2.red - self.colour1.red) * mix,
+                self.colour1.green + (self.colour2.green - self.colour1.green) * mix,
+                self.colour1.blue + (self.colour2.blue - self.colour1.blue) * mix,
+            );
+            self.kbd.set_key_at(pos, c.get_clamped_colour());
+        }
+        self.kbd
+    }
+
+    fn valid_args(args: &[u8]) -> bool
+    where
+        Self: Sized,
+    {
+        args.len() == 8 && args[6] <= 3
+    }
+
+    fn get_name() -> &'static str
+    where
+        Self: Sized,
+    {
+        "Wave"
+    }
+
+    fn get_varargs(&mut self) -> &[u8] {
+        &self.args
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(*self)
+    }
+
+    fn save(&mut self) -> EffectSave {
+        EffectSave::new("Wave", self.args.to_vec())
+    }
+
+    fn get_state(&mut self) -> Vec<u8> {
+        self.kbd.get_curr_state()
+    }
+}
+
+///
+/// RAIN KEYBOARD EFFECT
+/// 1 colour, drops with a fading trail falling down the columns, Matrix style
+/// Args: red, green, blue, fall speed in rows per second (1-60)
+///
+
+/// Shortest and longest trail behind a drop, in keys
+const RAIN_MIN_TAIL: f32 = 1.0;
+const RAIN_MAX_TAIL: f32 = 4.0;
+
+#[derive(Clone)]
+pub struct Rain {
+    kbd: board::KeyboardData,
+    args: [u8; 4],
+    colour: board::AnimatorKeyColour,
+    /// Row the drop of each column is at, negative while waiting above the keyboard
+    heads: [f32; board::MAX_KEYS_PER_ROW],
+    tails: [f32; board::MAX_KEYS_PER_ROW],
+    last_update_ms: u128,
+    rng: StdRng,
+}
+
+impl Rain {
+    /// Puts a column's drop back above the keyboard with a new trail, the
+    /// random height spreads the drops out over time
+    fn respawn(&mut self, col: usize) {
+        self.heads[col] = -self.rng.gen_range(0.0..board::ROWS as f32 * 2.0);
+        self.tails[col] = self.rng.gen_range(RAIN_MIN_TAIL..=RAIN_MAX_TAIL);
+    }
+}
+
+impl Effect for Rain {
+    fn new(args: Vec<u8>) -> Box<dyn Effect>
+    where
+        Self: Sized,
+    {
+        let mut rain = Rain {
+            kbd: dark_keyboard(),
+            args: [args[0], args[1], args[2], args[3].clamp(1, MAX_SPEED)],
+            colour: board::AnimatorKeyColour::new_u(args[0], args[1], args[2]),
+            heads: [0.0; board::MAX_KEYS_PER_ROW],
+            tails: [0.0; board::MAX_KEYS_PER_ROW],
+            last_update_ms: animation_millis(),
+            rng: StdRng::seed_from_u64(animation_millis() as u64),
+        };
+        for col in 0..board::keys_per_row() {
+            rain.respawn(col);
+        }
+        Box::new(rain)
+    }
+
+    fn update(&mut self) -> board::KeyboardData {
+        let now = animation_millis();
+        let fallen = now.saturating_sub(self.last_update_ms) as f32 / 1000.0 * self.args[3] as f32;
+        self.last_update_ms = now;
+
+        for col in 0..board::keys_per_row() {
+            self.heads[col] += fallen;
+            // Gone once the end of the trail left the bottom row
+            if self.heads[col] - self.tails[col] >= board::ROWS as f32 {
+                self.respawn(col);
+            }
+        }
+
+        for pos in 0..board::zone_key_count() {
+            let (row, col) = board::key_coordinates(pos);
+            // How many keys behind the drop this key is, the drop itself is 0
+            let behind = self.heads[col] - row as f32;
+            let intensity = if (0.0..self.tails[col] + 1.0).contains(&behind) {
+                1.0 - behind / (self.tails[col] + 1.0)
+            } else {
+                0.0
+            };
+            let c = board::AnimatorKeyColour::new_f(
+                self.colour.red * intensity,
+                self.colour.green * intensity,
+                self.colour.blue * intensity,
+            );
+            self.kbd.set_key_at(pos, c.get_clamped_colour());
+        }
+        self.kbd
+    }
+
+    fn valid_args(args: &[u8]) -> bool
+    where
+        Self: Sized,
+    {
+        args.len() == 4
+    }
+
+    fn get_name() -> &'static str
+    where
+        Self: Sized,
+    {
+        "Rain"
+    }
+
+    fn get_varargs(&mut self) -> &[u8] {
+        &self.args
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(self.clone())
+    }
+
+    fn save(&mut self) -> EffectSave {
+        EffectSave::new("Rain", self.args.to_vec())
+    }
+
+    fn get_state(&mut self) -> Vec<u8> {
+        self.kbd.get_curr_state()
+    }
+}
+
+///
+/// AMBILIGHT KEYBOARD EFFECT
+/// Keyboard following the colours of the screen, either its average colour or
+/// every key taking the colour of the part of the screen above it. Shows a
+/// static colour while the screen can't be captured
+/// Args: mode (0 = average, 1 = per key), red, green, blue of the fallback
+///
+
+/// Share of the way to the screen colour covered each frame, smooths out
+/// the jumps between two screenshots
+const AMBILIGHT_SMOOTHING: f32 = 0.3;
+
+#[derive(Copy, Clone)]
+pub struct Ambilight {
+    kbd: board::KeyboardData,
+    args: [u8; 4],
+    colours: [board::AnimatorKeyColour; board::MAX_ZONE_KEYS],
+}
+
+impl Effect for Ambilight {
+    fn new(args: Vec<u8>) -> Box<dyn Effect>
+    where
+        Self: Sized,
+    {
+        Box::new(Ambilight {
+            kbd: dark_keyboard(),
+            args: [args[0], args[1], args[2], args[3]],
+            colours: [board::AnimatorKeyColour::new_u(args[1], args[2], args[3]); board::MAX_ZONE_KEYS],
+        })
+    }
+
+    fn update(&mut self) -> board::KeyboardData {
+        let frame = screen::latest_frame();
+        let average = frame.as_ref().map(|frame| frame.average((0.0, 1.0), (0.0, 1.0)));
+        for pos in 0..board::zone_key_count() {
+            let (row, col) = board::key_coordinates(pos);
+            let target = match (&frame, average) {
+                (Some(frame), _) if self.args[0] == 1 => {
+                    let x = (col as f32 / board::keys_per_row() as f32, (col + 1) as f32 / board::keys_per_row() as f32);
+                    let y = (row as f32 / board::ROWS as f32, (row + 1) as f32 / board::ROWS as f32);
+                    frame.average(x, y)
+                }
+                (_, Some(average)) => average,
+                _ => [self.args[1], self.args[2], self.args[3]],
+            };
+            let c = &mut self.colours[pos];
+            c.red += (target[0] as f32 - c.red) * AMBILIGHT_SMOOTHING;
+            c.green += (target[1] as f32 - c.green) * AMBILIGHT_SMOOTHING;
+            c.blue += (target[2] as f32 - c.blue) * AMBILIGHT_SMOOTHING;
+            self.kbd.set_key_at(pos, c.get_clamped_colour());
+        }
+        self.kbd
+    }
+
+    fn valid_args(args: &[u8]) -> bool
+    where
+        Self: Sized,
+    {
+        args.len() == 4 && args[0] <= 1
+    }
+
+    fn get_name() -> &'static str
+    where
+        Self: Sized,
+    {
+        "Ambilight"
+    }
+
+    fn get_varargs(&mut self) -> &[u8] {
+        &self.args
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(*self)
+    }
+
+    fn save(&mut self) -> EffectSave {
+        EffectSave::new("Ambilight", self.args.to_vec())
+    }
+
+    fn get_state(&mut self) -> Vec<u8> {
+        self.kbd.get_curr_state()
+    }
+}
+
+///
+/// COLOR WHEEL KEYBOARD EFFECT
+/// Every key gets the hue of its angle around the centre of the keyboard,
+/// and the whole wheel turns over time
+/// Args: speed in degrees per second (0 = still, up to 255, which is less
+/// than a turn per second), saturation in percent
+///
+
+#[derive(Copy, Clone)]
+pub struct ColorWheel {
+    kbd: board::KeyboardData,
+    args: [u8; 2],
+    start_ms: u128,
+}
+
+impl Effect for ColorWheel {
+    fn new(args: Vec<u8>) -> Box<dyn Effect>
+    where
+        Self: Sized,
+    {
+        Box::new(ColorWheel {
+            kbd: dark_keyboard(),
+            args: [args[0], args[1]],
+            start_ms: animation_millis(),
+        })
+    }
+
+    fn update(&mut self) -> board::KeyboardData {
+        // Based on the elapsed time, so the rotation speed doesn't depend on the frame rate
+        let rotation = animation_millis().saturating_sub(self.start_ms) as f32 * self.args[0] as f32 / 1000.0;
+        let saturation = self.args[1] as f32 / 100.0;
+        for pos in 0..board::zone_key_count() {
+            let (x, y) = board::key_position(pos);
+            let angle = y.atan2(x).to_degrees();
+            self.kbd.set_key_at(pos, board::hsv_to_rgb(angle + rotation, saturation, 1.0));
+        }
+        self.kbd
+    }
+
+    fn valid_args(args: &[u8]) -> bool
+    where
+        Self: Sized,
+    {
+        args.len() == 2 && args[1] <= 100
+    }
+
+    fn get_name() -> &'static str
+    where
+        Self: Sized,
+    {
+        "Color Wheel"
+    }
+
+    fn get_varargs(&mut self) -> &[u8] {
+        &self.args
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(*self)
+    }
+
+    fn save(&mut self) -> EffectSave {
+        EffectSave::new("Color Wheel", self.args.to_vec())
+    }
+
+    fn get_state(&mut self) -> Vec<u8> {
+        self.kbd.get_curr_state()
+    }
+}
+
+///
+/// LOAD METER KEYBOARD EFFECT
+/// One row of keys as a bar graph of the CPU utilisation, from /proc/stat.
+/// Each key of the bar has the hue of its place between the low and high
+/// hue, green to yellow to red by default
+/// Args: row (0 = top), low hue, high hue (0-255)
+///
+
+/// Time between two CPU samples, shorter deltas make the bar jitter
+const LOAD_METER_SAMPLE_MS: u128 = 500;
+
+#[derive(Copy, Clone)]
+pub struct LoadMeter {
+    kbd: board::KeyboardData,
+    args: [u8; 3],
+    /// (busy, total) jiffies of the last sample, None before the first one
+    last_times: Option<(u64, u64)>,
+    last_sample_ms: u128,
+    load: f32,
+}
+
+/// Busy and total jiffies of all CPUs since boot, from the first line of
+/// /proc/stat
+fn read_cpu_times() -> Option<(u64, u64)> {
+    let stat = std::fs::read_to_string("/proc/stat").ok()?;
+    let times: Vec<u64> = stat
+        .lines()
+        .next()?
+        .split_whitespace()
+        .skip(1)
+        .take(8) // guest time is already counted in user time
+        .filter_map(|value| value.parse().ok())
+        .collect();
+    if times.len() < 5 {
+        return None;
+    }
+    let total: u64 = times.iter().sum();
+    let idle = times[3] + times[4]; // idle and iowait
+
+    Some((total - idle, total))
+}
+
+impl Effect for LoadMeter {
+    fn new(args: Vec<u8>) -> Box<dyn Effect>
+    where
+        Self: Sized,
+    {
+        let mut kbd = board::KeyboardData::new();
+        kbd.set_kbd_colour(0, 0, 0);
+        Box::new(LoadMeter {
+            kbd,
+            args: [args[0], args[1], args[2]],
+            last_times: read_cpu_times(),
+            last_sample_ms: animation_millis(),
+            load: 0.0,
+        })
+    }
+
+    fn update(&mut self) -> board::KeyboardData {
+        let now = animation_millis();
+        if now.saturating_sub(self.last_sample_ms) >= LOAD_METER_SAMPLE_MS {
+            self.last_sample_ms = now;
+            let times = read_cpu_times();
+            // The load stays at 0 until there are two samples to compare
+            match (times, self.last_times) {
+                (Some((busy, total)), Some((last_busy, last_total))) if total > last_total => {
+                    self.load = busy.saturating_sub(last_busy) as f32 / (total - last_total) as f32;
+                }
+                _ => {}
+            }
+            self.last_times = times;
+        }
+
+        let keys = board::keys_per_row();
+        let lit = self.load.clamp(0.0, 1.0) * keys as f32;
+        let (low, high) = (self.args[1] as f32 * 360.0 / 256.0, self.args[2] as f32 * 360.0 / 256.0);
+        for col in 0..keys {
+            // The last key of the bar is partly lit
+            let fill = (lit - col as f32).clamp(0.0, 1.0);
+            let place = if keys > 1 { col as f32 / (keys - 1) as f32 } else { 0.0 };
+            let colour = board::hsv_to_rgb(low + (high - low) * place, 1.0, fill);
+            self.kbd.set_key_colour(self.args[0] as usize, col, colour.red, colour.green, colour.blue);
+        }
+        self.kbd
+    }
+
+    fn valid_args(args: &[u8]) -> bool
+    where
+        Self: Sized,
+    {
+        args.len() == 3 && (args[0] as usize) < board::ROWS
+    }
+
+    fn get_name() -> &'static str
+    where
+        Self: Sized,
+    {
+        "Load Meter"
+    }
+
+    fn get_varargs(&mut self) -> &[u8] {
+        &self.args
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(*self)
+    }
+
+    fn save(&mut self) -> EffectSave {
+        EffectSave::new("Load Meter", self.args.to_vec())
+    }
+
+    fn get_state(&mut self) -> Vec<u8> {
+        self.kbd.get_curr_state()
+    }
+}
+
+/// Bounds of a single pulse and of the number of pulses
+pub const FLASH_MIN_MS: u32 = 50;
+pub const FLASH_MAX_MS: u32 = 2000;
+pub const FLASH_MAX_COUNT: u8 = 20;
+
+///
+/// FLASH KEYBOARD EFFECT
+/// 1 colour pulsing a few times, for notifications. Stays black after the
+/// last pulse, the effect manager removes it then
+/// Args: red, green, blue, duration of a pulse in hundredths of a second
+/// (5-200), number of pulses (1-20)
+///
+
+#[derive(Copy, Clone)]
+pub struct Flash {
+    kbd: board::KeyboardData,
+    args: [u8; 5],
+    start_ms: u128,
+}
+
+impl Flash {
+    /// How long all the pulses of `args` take
+    pub fn total_ms(args: &[u8]) -> u128 {
+        args[3] as u128 * 10 * args[4] as u128
+    }
+}
+
+impl Effect for Flash {
+    fn new(args: Vec<u8>) -> Box<dyn Effect>
+    where
+        Self: Sized,
+    {
+        let pulse = args[3].clamp((FLASH_MIN_MS / 10) as u8, (FLASH_MAX_MS / 10) as u8);
+        Box::new(Flash {
+            kbd: board::KeyboardData::new(),
+            args: [args[0], args[1], args[2], pulse, args[4].clamp(1, FLASH_MAX_COUNT)],
+            start_ms: animation_millis(),
+        })
+    }
+
+    fn update(&mut self) -> board::KeyboardData {
+        let elapsed = animation_millis().saturating_sub(self.start_ms);
+        let pulse_ms = self.args[3] as u128 * 10;
+        let level = if elapsed < Flash::total_ms(&self.args) {
+            // Fades in and out once per pulse
+            (std::f32::consts::PI * (elapsed % pulse_ms) as f32 / pulse_ms as f32).sin()
+        } else {
+            0.0
+        };
+        let [r, g, b] = [self.args[0], self.args[1], self.args[2]].map(|c| (c as f32 * level) as u8);
+        self.kbd.set_kbd_colour(r, g, b);
+        self.kbd
+    }
+
+    fn valid_args(args: &[u8]) -> bool
+    where
+        Self: Sized,
+    {
+        args.len() == 5
+    }
+
+    fn get_name() -> &'static str
+    where
+        Self: Sized,
+    {
+        "Flash"
+    }
+
+    fn get_varargs(&mut self) -> &[u8] {
+        &self.args
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(*self)
+    }
+
+    fn save(&mut self) -> EffectSave {
+        EffectSave::new("Flash", self.args.to_vec())
+    }
+
+    fn get_state(&mut self) -> Vec<u8> {
+        self.kbd.get_curr_state()
+    }
+}
+
+///
+/// SPOTLIGHT KEYBOARD EFFECT
+/// A bright circle following the mouse pointer, the screen mapped onto the
+/// keyboard, with the other keys dimmed. Stays in the centre while the
+/// pointer position can't be read
+/// Args: red, green, blue, radius in keys (at least 1), brightness of the
+/// other keys in percent
+///
+
+#[derive(Copy, Clone)]
+pub struct Spotlight {
+    kbd: board::KeyboardData,
+    args: [u8; 5],
+}
+
+impl Effect for Spotlight {
+    fn new(args: Vec<u8>) -> Box<dyn Effect>
+    where
+        Self: Sized,
+    {
+        Box::new(Spotlight {
+            kbd: dark_keyboard(),
+            args: [args[0], args[1], args[2], args[3].max(1), args[4]],
+        })
+    }
+
+    fn update(&mut self) -> board::KeyboardData {
+        let (x, y) = pointer::latest_position().unwrap_or((0.5, 0.5));
+        // Same origin as key_position, the centre of the keyboard
+        let centre = ((x - 0.5) * board::keys_per_row() as f32, (y - 0.5) * board::ROWS as f32);
+        let radius = self.args[3] as f32;
+        let background = self.args[4] as f32 / 100.0;
+        for pos in 0..board::zone_key_count() {
+            let (key_x, key_y) = board::key_position(pos);
+            let distance = ((key_x - centre.0).powi(2) + (key_y - centre.1).powi(2)).sqrt();
+            // Full brightness in the middle, fading out to the edge of the circle
+            let light = (1.0 - distance / radius).clamp(0.0, 1.0);
+            let level = background + (1.0 - background) * light;
+            self.kbd.set_key_at(
+                pos,
+                board::KeyColour {
+                    red: (self.args[0] as f32 * level) as u8,
+                    green: (self.args[1] as f32 * level) as u8,
+                    blue: (self.args[2] as f32 * level) as u8,
+                },
+            );
+        }
+        self.kbd
+    }
+
+    fn valid_args(args: &[u8]) -> bool
+    where
+        Self: Sized,
+    {
+        args.len() == 5 && args[4] <= 100
+    }
+
+    fn get_name() -> &'static str
+    where
+        Self: Sized,
+    {
+        "Spotlight"
+    }
+
+    fn get_varargs(&mut self) -> &[u8] {
+        &self.args
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(*self)
+    }
+
+    fn save(&mut self) -> EffectSave {
+        EffectSave::new("Spotlight", self.args.to_vec())
+    }
+
+    fn get_state(&mut self) -> Vec<u8> {
+        self.kbd.get_curr_state()
+    }
+}
+
+///
+/// HEATMAP KEYBOARD EFFECT
+/// Every key coloured by how often it was pressed lately, the counts halve
+/// over the half-life so the map follows what is being typed now
+/// Args: colour ramp (0 = blue to red, 1 = black over red and yellow to
+/// white), half-life in minutes (at least 1)
+///
+
+/// Heat the hottest key has to reach before it is shown at the top of the
+/// ramp, so a handful of presses doesn't already glow red
+const HEATMAP_MIN_SCALE: f32 = 20.0;
+
+#[derive(Copy, Clone)]
+pub struct Heatmap {
+    kbd: board::KeyboardData,
+    args: [u8; 2],
+    half_life_ms: f32,
+    heat: [f32; board::MAX_ZONE_KEYS],
+    last_check_ms: u128,
+}
+
+impl Heatmap {
+    /// Colour of a heat level between 0 and 1 on the selected ramp
+    fn ramp_colour(&self, level: f32) -> board::KeyColour {
+        match self.args[0] {
+            0 => board::hsv_to_rgb(240.0 * (1.0 - level), 1.0, 0.25 + 0.75 * level),
+            _ => {
+                // Red comes in first, then green turns it yellow and blue white
+                let channel = |start: f32| ((level - start) * 3.0).clamp(0.0, 1.0) * 255.0;
+                board::AnimatorKeyColour::new_f(channel(0.0), channel(1.0 / 3.0), channel(2.0 / 3.0))
+                    .get_clamped_colour()
+            }
+        }
+    }
+}
+
+impl Effect for Heatmap {
+    fn new(args: Vec<u8>) -> Box<dyn Effect>
+    where
+        Self: Sized,
+    {
+        let half_life = args[1].max(1);
+        Box::new(Heatmap {
+            kbd: dark_keyboard(),
+            args: [args[0], half_life],
+            half_life_ms: half_life as f32 * 60000.0,
+            heat: [0.0; board::MAX_ZONE_KEYS],
+            last_check_ms: animation_millis(),
+        })
+    }
+
+    fn update(&mut self) -> board::KeyboardData {
+        let now = animation_millis();
+        let decay = 0.5f32.powf(now.saturating_sub(self.last_check_ms) as f32 / self.half_life_ms);
+        for heat in self.heat.iter_mut() {
+            *heat *= decay;
+        }
+        for press in input::key_presses_since(self.last_check_ms) {
+            self.heat[press.index] += 1.0;
+        }
+        self.last_check_ms = now;
+
+        let count = board::zone_key_count();
+        let hottest = self.heat[..count].iter().fold(HEATMAP_MIN_SCALE, |max, heat| max.max(*heat));
+        for pos in 0..count {
+            let colour = self.ramp_colour(self.heat[pos] / hottest);
+            self.kbd.set_key_at(pos, colour);
+        }
+        self.kbd
+    }
+
+    fn valid_args(args: &[u8]) -> bool
+    where
+        Self: Sized,
+    {
+        args.len() == 2 && args[0] <= 1
+    }
+
+    fn get_name() -> &'static str
+    where
+        Self: Sized,
+    {
+        "Heatmap"
+    }
+
+    fn get_varargs(&mut self) -> &[u8] {
+        &self.args
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(*self)
+    }
+
+    fn save(&mut self) -> EffectSave {
+        EffectSave::new("Heatmap", self.args.to_vec())
+    }
+
+    fn get_state(&mut self) -> Vec<u8> {
+        self.kbd.get_curr_state()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb(colour: board::AnimatorKeyColour) -> [u8; 3] {
+        let c = colour.get_clamped_colour();
+        [c.red, c.green, c.blue]
+    }
+
+    fn stop_colours(args: &[u8]) -> Vec<[u8; 3]> {
+        let (stops, _) = gradient_stops(args).expect("Stops did not parse");
+        stops.into_iter().map(rgb).collect()
+    }
+
+    #[test]
+    fn two_colour_gradient() {
+        assert_eq!(stop_colours(&[255, 0, 0, 0, 0, 255]), vec![[255, 0, 0], [0, 0, 255]]);
+        let (stops, rest) = gradient_stops(&[255, 0, 0, 0, 0, 255]).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(rgb(blend_stops(&stops, EASING_LINEAR, 0.0)), [255, 0, 0]);
+        assert_eq!(rgb(blend_stops(&stops, EASING_LINEAR, 1.0)), [0, 0, 255]);
+    }
+
+    #[test]
+    fn seven_bytes_are_two_counted_colours() {
+        assert_eq!(stop_colours(&[2, 10, 20, 30, 40, 50, 60]), vec![[10, 20, 30], [40, 50, 60]]);
+        let (_, rest) = gradient_stops(&[2, 10, 20, 30, 40, 50, 60, EASING_SMOOTHSTEP]).unwrap();
+        assert_eq!(rest, &[EASING_SMOOTHSTEP]);
+    }
+
+    #[test]
+    fn three_stop_gradient() {
+        let (stops, rest) = gradient_stops(&[3, 255, 0, 0, 0, 255, 0, 0, 0, 255]).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(rgb(blend_stops(&stops, EASING_LINEAR, 0.0)), [255, 0, 0]);
+        assert_eq!(rgb(blend_stops(&stops, EASING_LINEAR, 0.25)), [127, 127, 0]);
+        assert_eq!(rgb(blend_stops(&stops, EASING_LINEAR, 0.5)), [0, 255, 0]);
+        assert_eq!(rgb(blend_stops(&stops, EASING_LINEAR, 1.0)), [0, 0, 255]);
+    }
+
+    #[test]
+    fn five_stop_gradient() {
+        let args = [5, 0, 0, 0, 100, 0, 0, 200, 0, 0, 100, 0, 0, 0, 0, 0];
+        assert_eq!(stop_colours(&args).len(), 5);
+        let (stops, _) = gradient_stops(&args).unwrap();
+        let reds: Vec<u8> = [0.0, 0.125, 0.25, 0.5, 0.75, 1.0]
+            .iter()
+            .map(|t| rgb(blend_stops(&stops, EASING_LINEAR, *t))[0])
+            .collect();
+        assert_eq!(reds, vec![0, 50, 100, 200, 100, 0]);
+    }
+
+    #[test]
+    fn every_easing_starts_at_0_and_ends_at_1() {
+        // An unknown mode is linear
+        for mode in [EASING_LINEAR, EASING_SMOOTHSTEP, EASING_IN, EASING_OUT, 200] {
+            assert_eq!(ease(mode, 0.0), 0.0, "mode {mode}");
+            assert_eq!(ease(mode, 1.0), 1.0, "mode {mode}");
+            // t is clamped
+            assert_eq!(ease(mode, -1.0), 0.0, "mode {mode}");
+            assert_eq!(ease(mode, 2.0), 1.0, "mode {mode}");
+        }
+        assert_eq!(ease(EASING_LINEAR, 0.5), 0.5);
+        assert_eq!(ease(EASING_SMOOTHSTEP, 0.5), 0.5);
+        assert_eq!(ease(EASING_IN, 0.5), 0.25);
+        assert_eq!(ease(EASING_OUT, 0.5), 0.75);
+    }
+
+    /// Creates an effect through the registry, like SetEffect does
+    fn effect(command: &str, args: Vec<u8>) -> Box<dyn Effect> {
+        let info = EFFECTS.iter().find(|info| info.command == command).expect("Unknown effect");
+        (info.create)(args).unwrap_or_else(|| panic!("{} rejected its args", command))
+    }
+
+    /// Renders a few frames of an effect with the timing arg at `index` set
+    /// to `value` and returns the value the effect runs with. The colour of
+    /// the effects is pure red, the other channels have to stay dark
+    fn run_with_timing(command: &str, mut args: Vec<u8>, index: usize, value: u8) -> u8 {
+        args[index] = value;
+        let mut effect = effect(command, args);
+        for _ in 0..3 {
+            let state = effect.update().get_curr_state();
+            assert_eq!(state.len(), 3 * board::key_count());
+            if !matches!(command, "spectrum_cycle" | "heatmap" | "color_wheel") {
+                assert!(state.chunks(3).all(|rgb| rgb[1] == 0 && rgb[2] == 0), "{} with {}", command, value);
+            }
+        }
+        effect.get_varargs()[index]
+    }
+
+    #[test]
+    fn timing_args_are_clamped() {
+        let _layout = board::test_layout(15, 1);
+        // Effect, args, index of the timing arg, value run with for 0 and for 255
+        let cases: [(&str, Vec<u8>, usize, u8, u8); 9] = [
+            ("breathing_single", vec![255, 0, 0, 10], 3, 1, 255),
+            ("breathing_single", vec![255, 0, 0, 10, 20, 80], 3, 1, 255),
+            ("breathing_multi", vec![2, 255, 0, 0, 128, 0, 0, 10], 7, 1, 255),
+            ("reactive", vec![255, 0, 0, 10], 3, 1, 255),
+            ("spectrum_cycle", vec![10], 0, 1, 255),
+            ("wave", vec![255, 0, 0, 128, 0, 0, 0, 10], 7, 1, MAX_SPEED),
+            ("rain", vec![255, 0, 0, 10], 3, 1, MAX_SPEED),
+            ("heatmap", vec![0, 5], 1, 1, 255),
+            // Still or less than a turn per second, nothing to clamp
+            ("color_wheel", vec![30, 100], 0, 0, 255),
+        ];
+        for (command, args, index, low, high) in cases {
+            assert_eq!(run_with_timing(command, args.clone(), index, 0), low, "{} with 0", command);
+            assert_eq!(run_with_timing(command, args, index, u8::MAX), high, "{} with 255", command);
+        }
+    }
+
+    #[test]
+    fn wave_gradient_covers_the_numpad() {
+        let _layout = board::test_layout(22, 1);
+        let mut wave = effect("wave_gradient", vec![255, 0, 0, 0, 0, 255]);
+        for _ in 0..3 {
+            let state = wave.update().get_curr_state();
+            assert_eq!(state.len(), 3 * 22 * board::ROWS);
+            assert!(state.chunks(3).all(|rgb| rgb != [255, 255, 255]));
+        }
+    }
+
+    #[test]
+    fn extra_zones_stay_dark() {
+        let _layout = board::test_layout(15, 2);
+        let cases: [(&str, Vec<u8>); 7] = [
+            ("starlight", vec![1, 255, 0, 0, 0, 0, 255, 10]),
+            ("wave", vec![255, 0, 0, 128, 0, 0, 0, 10]),
+            ("rain", vec![255, 0, 0, 10]),
+            ("ambilight", vec![0, 255, 0, 0]),
+            ("color_wheel", vec![30, 100]),
+            ("spotlight", vec![255, 0, 0, 4, 100]),
+            ("heatmap", vec![0, 5]),
+        ];
+        for (command, args) in cases {
+            let mut effect = effect(command, args);
+            let state = effect.update().get_curr_state();
+            let extra = &state[3 * board::zone_key_count()..];
+            assert_eq!(extra.len(), 3 * board::zone_key_count());
+            assert!(extra.chunks(3).all(|rgb| rgb != [255, 255, 255]), "{} lights the extra zone", command);
+        }
+    }
+
+    #[test]
+    fn flash_pulses_are_clamped() {
+        let _layout = board::test_layout(15, 1);
+        let mut short = create_effect::<Flash>(vec![255, 0, 0, 0, 0]).expect("Flash rejected 0");
+        assert_eq!(short.get_varargs(), &[255, 0, 0, (FLASH_MIN_MS / 10) as u8, 1]);
+        short.update();
+        let mut long = create_effect::<Flash>(vec![255, 0, 0, u8::MAX, u8::MAX]).expect("Flash rejected 255");
+        assert_eq!(long.get_varargs(), &[255, 0, 0, (FLASH_MAX_MS / 10) as u8, FLASH_MAX_COUNT]);
+        long.update();
+        assert_eq!(Flash::total_ms(long.get_varargs()), FLASH_MAX_MS as u128 * FLASH_MAX_COUNT as u128);
+    }
+
+    #[test]
+    fn spotlight_radius_is_at_least_one_key() {
+        let mut spotlight = create_effect::<Spotlight>(vec![255, 255, 255, 0, 20]).expect("Spotlight rejected 0");
+        assert_eq!(spotlight.get_varargs()[3], 1);
+    }
+
+    #[test]
+    fn breathing_multi_without_args_does_not_panic() {
+        let _layout = board::test_layout(15, 1);
+        let mut breath = BreathMulti::new(vec![]);
+        breath.update();
+        let mut breath = BreathMulti::new(vec![0]);
+        breath.update();
+    }
+
+    #[test]
+    fn missing_colours_are_rejected() {
+        assert!(gradient_stops(&[]).is_none());
+        assert!(gradient_stops(&[1, 1, 2, 3]).is_none());
+        assert!(gradient_stops(&[3, 1, 2, 3, 4, 5, 6]).is_none());
+        assert!(gradient_stops(&[255, 0, 0, 0, 0]).is_none());
+    }
+
+    /// Frame with a different colour on every key of `zones` zones
+    fn numbered_frame(zones: usize) -> Vec<u8> {
+        (0..zones * board::zone_key_count())
+            .flat_map(|key| [key as u8, (key * 2) as u8, 255 - key as u8])
+            .collect()
+    }
+
+    #[test]
+    fn custom_frames_cover_whole_zones() {
+        let _layout = board::test_layout(15, 2);
+        let frame = custom_frame_len();
+        assert_eq!(frame, 3 * 15 * board::ROWS);
+        assert!(CustomFrame::valid_args(&vec![0; frame]));
+        assert!(CustomFrame::valid_args(&vec![0; frame * 2]));
+        assert!(!CustomFrame::valid_args(&vec![0; frame * 3]));
+        assert!(!CustomFrame::valid_args(&vec![0; frame + 3]));
+        assert!(!CustomFrame::valid_args(&vec![0; frame - 1]));
+    }
+
+    #[test]
+    fn custom_frame_colours_every_key() {
+        let _layout = board::test_layout(15, 2);
+        let args = numbered_frame(1);
+        let mut frame = CustomFrame::new(args.clone()).update();
+        for key in 0..board::zone_key_count() {
+            let colour = frame.get_key_at(key);
+            assert_eq!([colour.red, colour.green, colour.blue], [args[3 * key], args[3 * key + 1], args[3 * key + 2]]);
+        }
+        // The zone left out stays dark
+        let state = frame.get_curr_state();
+        assert!(state[args.len()..].iter().all(|channel| *channel == 0));
+    }
+
+    #[test]
+    fn custom_frame_survives_a_save() {
+        let _layout = board::test_layout(15, 2);
+        let args = numbered_frame(2);
+        let mut layer = EffectLayer::new(CustomFrame::new(args.clone()), vec![true; board::zone_key_count()]);
+        let mut loaded = EffectLayer::from_save(layer.get_save().unwrap()).expect("Save did not load");
+        assert_eq!(loaded.effect.get_varargs(), &args[..]);
+        assert_eq!(loaded.get_state(), layer.get_state());
+    }
+
+    #[test]
+    fn breathing_stays_within_its_bounds() {
+        let _layout = board::test_layout(15, 1);
+        // 20% to 80% of a red of 200, and the whole range without bounds
+        for (args, low, high) in [(vec![200, 100, 50, 10, 20, 80], 40, 160), (vec![200, 100, 50, 10], 0, 200)] {
+            let mut breath = BreathSingle::from_args(args);
+            let mut target = breath.target_colour;
+            let mut seen = vec![];
+            // Every step of the cycle, with the fade anywhere from off to full
+            for step in 0..4 {
+                for level in 0..=20 {
+                    breath.curr_step = step;
+                    breath.current_colour = target.multiply(level as f32 / 20.0);
+                    let red = breath.update().get_key_at(0).red;
+                    assert!((low..=high).contains(&red), "step {step} level {level}: {red} outside {low}..={high}");
+                    seen.push(red);
+                }
+            }
+            assert!(seen.contains(&low) && seen.contains(&high), "{seen:?}");
+        }
+    }
+
+    /// Starlight on a seeded RNG whose clock starts at 1 s
+    fn starlight(args: [u8; 8], seed: u64) -> Starlight {
+        let mut starlight = Starlight::with_rng(&args, StdRng::seed_from_u64(seed));
+        starlight.last_update_ms = 1000;
+        starlight
+    }
+
+    fn key_colours(mut frame: board::KeyboardData) -> Vec<[u8; 3]> {
+        frame.get_curr_state()[..3 * board::zone_key_count()].chunks(3).map(|c| [c[0], c[1], c[2]]).collect()
+    }
+
+    #[test]
+    fn full_density_lights_every_star() {
+        let _layout = board::test_layout(15, 1);
+        let mut stars = starlight([255, 0, 0, 255, 0, 0, 0, 40], 7);
+        // Long enough for every key to start twinkling, at its dark start
+        let started = key_colours(stars.draw(1000 + STARLIGHT_TWINKLE_MS * 2));
+        assert!(started.iter().all(|rgb| *rgb == [0, 0, 40]));
+        // Half way through the twinkle every star is at its brightest
+        let lit = key_colours(stars.draw(1000 + STARLIGHT_TWINKLE_MS * 2 + STARLIGHT_TWINKLE_MS / 2));
+        assert!(lit.iter().all(|rgb| *rgb == [255, 0, 0]), "{lit:?}");
+    }
+
+    #[test]
+    fn no_density_shows_the_background() {
+        let _layout = board::test_layout(15, 1);
+        let mut stars = starlight([255, 0, 0, 0, 0, 0, 40, 0], 7);
+        for now in [2000, 5000, 60000] {
+            assert!(key_colours(stars.draw(now)).iter().all(|rgb| *rgb == [0, 40, 0]));
+        }
+    }
+
+    #[test]
+    fn random_hue_stars_follow_the_seed() {
+        let _layout = board::test_layout(15, 1);
+        let args = [255, 255, 255, 128, 1, 0, 0, 0];
+        let (mut first, mut second, mut other) = (starlight(args, 3), starlight(args, 3), starlight(args, 4));
+        let mut differs = false;
+        for now in (1100..6000).step_by(100) {
+            let frame = key_colours(first.draw(now));
+            assert_eq!(frame, key_colours(second.draw(now)));
+            differs |= frame != key_colours(other.draw(now));
+        }
+        assert!(differs);
+        // The hues of the twinkling stars are fully saturated, never the white of the args
+        let twinkling: Vec<board::KeyColour> = (0..board::zone_key_count())
+            .filter(|pos| first.started_ms[*pos] != 0)
+            .map(|pos| first.colours[pos].get_clamped_colour())
+            .collect();
+        assert!(!twinkling.is_empty());
+        assert!(twinkling.iter().all(|c| c.red.min(c.green).min(c.blue) == 0 && c.red.max(c.green).max(c.blue) == 255));
+    }
+
+    /// Args layout check of an effect
+    type ValidArgs = fn(&[u8]) -> bool;
+
+    #[test]
+    fn args_are_checked_against_the_layout_of_each_effect() {
+        let _layout = board::test_layout(15, 1);
+        let frame = custom_frame_len();
+        let last_row = board::ROWS as u8 - 1;
+        let cases: Vec<(&str, ValidArgs, Vec<u8>, bool)> = vec![
+            ("static", Static::valid_args, vec![1, 2, 3], true),
+            ("static", Static::valid_args, vec![1, 2], false),
+            ("static", Static::valid_args, vec![1, 2, 3, 4], false),
+            ("static_gradient", StaticGradient::valid_args, vec![1, 2, 3, 4, 5, 6], true),
+            ("static_gradient", StaticGradient::valid_args, vec![2, 1, 2, 3, 4, 5, 6, EASING_OUT], true),
+            ("static_gradient", StaticGradient::valid_args, vec![2, 1, 2, 3, 4, 5, 6, EASING_OUT + 1], false),
+            ("static_gradient", StaticGradient::valid_args, vec![2, 1, 2, 3, 4, 5, 6, 0, 0], false),
+            ("static_gradient", StaticGradient::valid_args, vec![1, 1, 2, 3], false),
+            ("wave_gradient", WaveGradient::valid_args, vec![1, 2, 3, 4, 5, 6], true),
+            ("wave_gradient", WaveGradient::valid_args, vec![2, 1, 2, 3, 4, 5, 6], true),
+            ("wave_gradient", WaveGradient::valid_args, vec![2, 1, 2, 3, 4, 5, 6, 0], false),
+            ("breathing_single", BreathSingle::valid_args, vec![1, 2, 3, 20], true),
+            ("breathing_single", BreathSingle::valid_args, vec![1, 2, 3, 20, 10, 100], true),
+            ("breathing_single", BreathSingle::valid_args, vec![1, 2, 3, 20, 60, 50], false),
+            ("breathing_single", BreathSingle::valid_args, vec![1, 2, 3, 20, 10, 101], false),
+            ("breathing_single", BreathSingle::valid_args, vec![1, 2, 3, 20, 10], false),
+            ("breathing_multi", BreathMulti::valid_args, vec![2, 1, 2, 3, 4, 5, 6, 10], true),
+            ("breathing_multi", BreathMulti::valid_args, vec![2, 1, 2, 3, 4, 5, 6], false),
+            ("breathing_multi", BreathMulti::valid_args, vec![0, 10], false),
+            ("breathing_multi", BreathMulti::valid_args, vec![], false),
+            ("ripple", Ripple::valid_args, vec![1, 2, 3, 4], true),
+            ("ripple", Ripple::valid_args, vec![1, 2, 3], false),
+            ("custom_frame", CustomFrame::valid_args, vec![0; frame], true),
+            ("custom_frame", CustomFrame::valid_args, vec![0; frame - 1], false),
+            // More frames than the layout has zones
+            ("custom_frame", CustomFrame::valid_args, vec![0; frame * 2], false),
+            ("custom_frame", CustomFrame::valid_args, vec![], false),
+            ("reactive", Reactive::valid_args, vec![1, 2, 3, 4], true),
+            ("reactive", Reactive::valid_args, vec![1, 2, 3, 4, 5], false),
+            ("audio_visualizer", AudioVisualizer::valid_args, vec![1, 2], true),
+            ("audio_visualizer", AudioVisualizer::valid_args, vec![1], false),
+            ("spectrum_cycle", SpectrumCycle::valid_args, vec![60], true),
+            ("spectrum_cycle", SpectrumCycle::valid_args, vec![], false),
+            ("starlight", Starlight::valid_args, vec![1, 2, 3, 4, 5, 6, 7, 8], true),
+            ("starlight", Starlight::valid_args, vec![1, 2, 3, 4, 5, 6, 7], false),
+            ("wave", Wave::valid_args, vec![1, 2, 3, 4, 5, 6, 3, 8], true),
+            ("wave", Wave::valid_args, vec![1, 2, 3, 4, 5, 6, 4, 8], false),
+            ("wave", Wave::valid_args, vec![1, 2, 3, 4, 5, 6, 3], false),
+            ("rain", Rain::valid_args, vec![1, 2, 3, 4], true),
+            ("rain", Rain::valid_args, vec![1, 2, 3], false),
+            ("ambilight", Ambilight::valid_args, vec![1, 2, 3, 4], true),
+            ("ambilight", Ambilight::valid_args, vec![2, 2, 3, 4], false),
+            ("color_wheel", ColorWheel::valid_args, vec![60, 100], true),
+            ("color_wheel", ColorWheel::valid_args, vec![60, 101], false),
+            ("load_meter", LoadMeter::valid_args, vec![last_row, 2, 3], true),
+            ("load_meter", LoadMeter::valid_args, vec![last_row + 1, 2, 3], false),
+            ("flash", Flash::valid_args, vec![1, 2, 3, 4, 5], true),
+            ("flash", Flash::valid_args, vec![1, 2, 3, 4], false),
+            ("spotlight", Spotlight::valid_args, vec![1, 2, 3, 4, 100], true),
+            ("spotlight", Spotlight::valid_args, vec![1, 2, 3, 4, 101], false),
+            ("heatmap", Heatmap::valid_args, vec![1, 10], true),
+            ("heatmap", Heatmap::valid_args, vec![2, 10], false),
+        ];
+        for (name, valid_args, args, expected) in cases {
+            assert_eq!(valid_args(&args), expected, "{name} {args:?}");
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use super::{animation_millis, get_millis};
+use super::board::{keys_per_row, zone_key_count, DEFAULT_KEYS_PER_ROW, ROWS};
+use lazy_static::lazy_static;
+use log::*;
+use std::fs::{self, File};
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::thread::{self, JoinHandle};
+
+/// How long a key press is kept in the buffer before being discarded
+const KEY_PRESS_LIFETIME_MS: u128 = 5000;
+
+/// Directory where udev exposes the keyboard event nodes
+const INPUT_BY_PATH_DIR: &str = "/dev/input/by-path";
+
+/// Size of a `struct input_event` on 64 bit platforms
+const INPUT_EVENT_SIZE: usize = 24;
+const EV_KEY: u16 = 0x01;
+const KEY_PRESSED: i32 = 1;
+
+/// Directory of the LED class devices, each keyboard exposes its lock LEDs
+/// there as `input<n>::capslock` and so on
+const LEDS_DIR: &str = "/sys/class/leds";
+
+/// Lock LEDs by the name of their LED class device, with the key code of
+/// the key the indicator lights
+pub const LOCKS: [(&str, u16); 3] = [("capslock", 58), ("numlock", 69), ("scrolllock", 70)];
+
+/// Linux key codes (see `linux/input-event-codes.h`) for every row of the
+/// board, from left to right.
+///
+/// The board is the 6x15 matrix used by the custom frame packets, so the
+/// table follows the physical ANSI layout of the Blade keyboards: each key
+/// takes the column it starts in, keys wider than one column (backspace,
+/// enter, shifts, space...) only light their left most cell, and the `Fn`
+/// key, which never reaches the OS, keeps its slot as a 0 placeholder.
+/// Other layouts still work, they just map a few keys to a neighbouring cell.
+/// Devices with another matrix declare their own table, see `set_key_codes`.
+const KEY_CODE_ROWS: [[u16; DEFAULT_KEYS_PER_ROW]; ROWS] = [
+    // Esc, F1-F12, Insert, Delete
+    [1, 59, 60, 61, 62, 63, 64, 65, 66, 67, 68, 87, 88, 110, 111],
+    // `, 1-0, -, =, Backspace
+    [41, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 0],
+    // Tab, Q-P, [, ], \
+    [15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 43, 0],
+    // Caps lock, A-L, ;, ', Enter
+    [58, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40, 28, 0, 0],
+    // Left shift, Z-M, ",", ".", /, Right shift, Up
+    [42, 44, 45, 46, 47, 48, 49, 50, 51, 52, 53, 54, 103, 0, 0],
+    // Left ctrl, Fn, Super, Left alt, Space, Right alt, Right ctrl, Left, Down, Right
+    [29, 0, 125, 56, 57, 0, 0, 0, 0, 100, 97, 105, 108, 106, 0],
+];
+
+/// A single key press on the keyboard
+#[derive(Copy, Clone, Debug)]
+pub struct KeyPress {
+    /// Index of the key within the keyboard zone
+    pub index: usize,
+    /// Time of the press, as returned by `animation_millis()`
+    pub time_ms: u128,
+}
+
+lazy_static! {
+    static ref KEY_PRESSES: Mutex<Vec<KeyPress>> = Mutex::new(vec![]);
+    /// Last key event of any kind, starts at the first call so the daemon
+    /// doesn't think the keyboard has been idle since boot
+    static ref LAST_ACTIVITY_MS: Mutex<u128> = Mutex::new(get_millis());
+    /// Key code of every key index declared by the device, empty for the Blade table
+    static ref KEY_CODES: RwLock<Vec<u16>> = RwLock::new(vec![]);
+}
+
+/// Set once a reader thread is running, without one there is no activity to track
+static READING_KEYS: AtomicBool = AtomicBool::new(false);
+
+/// Registers a key press so reactive effects can pick it up on their next update
+pub fn push_key_press(index: usize) {
+    let now = animation_millis();
+    if let Ok(mut presses) = KEY_PRESSES.lock() {
+        presses.retain(|p| now.saturating_sub(p.time_ms) < KEY_PRESS_LIFETIME_MS);
+        presses.push(KeyPress { index, time_ms: now });
+    }
+}
+
+/// Returns every buffered key press that happened at or after `since_ms`
+pub fn key_presses_since(since_ms: u128) -> Vec<KeyPress> {
+    match KEY_PRESSES.lock() {
+        Ok(presses) => presses.iter().filter(|p| p.time_ms >= since_ms).copied().collect(),
+        Err(_) => vec![],
+    }
+}
+
+/// Time of the last key press or release, None when no keyboard can be read
+pub fn last_activity_ms() -> Option<u128> {
+    if !READING_KEYS.load(Ordering::SeqCst) {
+        return None;
+    }
+    LAST_ACTIVITY_MS.lock().ok().map(|t| *t)
+}
+
+/// Sets the Linux key code of every key index of the layout, row by row with
+/// 0 where the matrix has no key. An empty table keeps `KEY_CODE_ROWS`, false
+/// when the table doesn't cover the keyboard zone
+pub fn set_key_codes(codes: Vec<u16>) -> bool {
+    if !codes.is_empty() && codes.len() != zone_key_count() {
+        return false;
+    }
+    if let Ok(mut table) = KEY_CODES.write() {
+        *table = codes;
+    }
+    true
+}
+
+/// Maps a Linux key code to its index on the keyboard zone
+pub fn key_code_to_index(code: u16) -> Option<usize> {
+    if code == 0 {
+        return None;
+    }
+    let codes = KEY_CODES.read().map_or(vec![], |codes| codes.clone());
+    if !codes.is_empty() {
+        return codes.iter().position(|c| *c == code);
+    }
+    // The Blade block sits on the left of a wider matrix
+    let index = KEY_CODE_ROWS.iter().flatten().position(|c| *c == code)?;
+    let (row, col) = (index / DEFAULT_KEYS_PER_ROW, index % DEFAULT_KEYS_PER_ROW);
+    if col >= keys_per_row() {
+        return None;
+    }
+
+    Some(row * keys_per_row() + col)
+}
+
+/// Whether a lock is on for any keyboard, None when no keyboard exposes its LED
+pub fn lock_state(lock: &str) -> Option<bool> {
+    let suffix = format!("::{}", lock);
+    let mut state = None;
+    for entry in fs::read_dir(LEDS_DIR).ok()?.flatten() {
+        if !entry.file_name().to_string_lossy().ends_with(&suffix) {
+            continue;
+        }
+        let on = fs::read_to_string(entry.path().join("brightness"))
+            .ok()
+            .and_then(|brightness| brightness.trim().parse::<u32>().ok())
+            .is_some_and(|brightness| brightness > 0);
+        state = Some(state.unwrap_or(false) || on);
+    }
+    state
+}
+
+/// Index on the keyboard zone of the key of a lock, None when the keyboard
+/// has no such key
+pub fn lock_key_index(lock: &str) -> Option<usize> {
+    let (_, code) = LOCKS.iter().find(|(name, _)| *name == lock)?;
+    key_code_to_index(*code)
+}
+
+/// Spawns one reader thread per keyboard event node, feeding the key press
+/// buffer. The daemon user needs read access to the nodes (usually by being
+/// part of the `input` group).
+pub fn start_key_readers() -> Vec<JoinHandle<()>> {
+    let nodes: Vec<String> = match fs::read_dir(INPUT_BY_PATH_DIR) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path().to_string_lossy().to_string())
+            .filter(|p| p.ends_with("-event-kbd"))
+            .collect(),
+        Err(e) => {
+            warn!("Could not list {}: {}", INPUT_BY_PATH_DIR, e);
+            vec![]
+        }
+    };
+    if nodes.is_empty() {
+        warn!("No keyboard found, reactive effects won't see key presses");
+    }
+
+    nodes.into_iter().filter_map(|node| {
+        match File::open(&node) {
+            Ok(file) => {
+                info!("Reading key presses from {}", node);
+                READING_KEYS.store(true, Ordering::SeqCst);
+                Some(thread::spawn(move || read_key_events(file)))
+            }
+            Err(e) => {
+                warn!("Could not open {}: {}", node, e);
+                None
+            }
+        }
+    }).collect()
+}
+
+fn read_key_events(mut file: File) {
+    let mut event = [0u8; INPUT_EVENT_SIZE];
+    while file.read_exact(&mut event).is_ok() {
+        let kind = u16::from_ne_bytes([event[16], event[17]]);
+        let code = u16::from_ne_bytes([event[18], event[19]]);
+        let value = i32::from_ne_bytes([event[20], event[21], event[22], event[23]]);
+        if kind != EV_KEY {
+            continue;
+        }
+        if let Ok(mut last) = LAST_ACTIVITY_MS.lock() {
+            *last = get_millis();
+        }
+        if let Some(index) = key_code_to_index(code).filter(|_| value == KEY_PRESSED) {
+            push_key_press(index);
+        }
+    }
+    warn!("Keyboard event node closed");
+}
@@ -1,29 +1,146 @@
 pub mod board;
 pub mod effects;
-use crate::device;
+pub mod input;
+pub mod audio;
+pub mod screen;
+pub mod pointer;
+pub mod render;
 use serde::{Deserialize, Serialize};
+use lazy_static::lazy_static;
+use log::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use serde_json::json;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::time::Instant;
 
-const ANIMATION_FPS: u64 = 10; // 33 ms ~= 30fps
+pub const DEFAULT_ANIMATION_FPS: u8 = 10;
+pub const MIN_ANIMATION_FPS: u8 = 1;
+pub const MAX_ANIMATION_FPS: u8 = 60;
 
-pub const ANIMATION_SLEEP_MS: u64 = (1000.0 / ANIMATION_FPS as f32) as u64;
+/// A frame later than this many intervals counts as dropped
+const DROPPED_FRAME_INTERVALS: f32 = 1.5;
+/// Longer gaps between two frames are pauses (idle, blanked keyboard, a full
+/// frame shown), they restart the measurement instead of counting as dropped
+const STATS_PAUSE_MS: u128 = 1000;
 
+/// Longest cross-fade between two effects
+pub const MAX_TRANSITION_MS: u32 = 10000;
+
+/// Frame rate the effects are currently rendered at
+static ANIMATION_FPS: AtomicU8 = AtomicU8::new(DEFAULT_ANIMATION_FPS);
+
+/// Sets the animation frame rate, returning the value actually applied
+/// after clamping it to `MIN_ANIMATION_FPS..=MAX_ANIMATION_FPS`
+pub fn set_animation_fps(fps: u8) -> u8 {
+    let fps = fps.clamp(MIN_ANIMATION_FPS, MAX_ANIMATION_FPS);
+    ANIMATION_FPS.store(fps, Ordering::Relaxed);
+    fps
+}
+
+pub fn get_animation_fps() -> u8 {
+    ANIMATION_FPS.load(Ordering::Relaxed)
+}
+
+/// Time between two frames at the current frame rate
+pub fn get_animation_sleep_ms() -> u64 {
+    1000 / get_animation_fps() as u64
+}
+
+/// A breathing effect that wasn't drawn for this long no longer leads the logo
+const BREATH_SYNC_TIMEOUT_MS: u128 = 500;
+
+/// Level (0-255) of the last frame of a single colour breathing effect, and
+/// when it was drawn
+static BREATH_LEVEL: AtomicU8 = AtomicU8::new(0);
+static BREATH_LEVEL_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Called by the breathing effect every frame, for the logo to breathe along
+pub fn publish_breath_level(level: u8) {
+    BREATH_LEVEL.store(level, Ordering::Relaxed);
+    BREATH_LEVEL_MS.store(get_millis() as u64, Ordering::Relaxed);
+}
+
+/// Level of the breathing keyboard, None when no breathing effect is drawn
+pub fn breath_level() -> Option<u8> {
+    let drawn = BREATH_LEVEL_MS.load(Ordering::Relaxed) as u128;
+    if get_millis().saturating_sub(drawn) > BREATH_SYNC_TIMEOUT_MS {
+        return None;
+    }
+    Some(BREATH_LEVEL.load(Ordering::Relaxed))
+}
+
+lazy_static! {
+    static ref CLOCK_START: Instant = Instant::now();
+}
+
+/// Milliseconds since the daemon started on a monotonic clock, so time spans
+/// stay right when the wall clock is set back or jumps forward
 pub fn get_millis() -> u128 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_millis()
+    CLOCK_START.elapsed().as_millis()
 }
 
+/// Animation time the effects were paused at, 0 while they run
+static PAUSED_AT_MS: AtomicU64 = AtomicU64::new(0);
+/// Time the effects spent paused, left out of `animation_millis`
+static PAUSED_TOTAL_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Clock of the effects and of the key presses they react to. It is
+/// `get_millis()` without the time the effects were paused for and stands
+/// still during a pause, so resumed effects go on where they stopped
+pub fn animation_millis() -> u128 {
+    let paused_at = PAUSED_AT_MS.load(Ordering::Relaxed);
+    if paused_at != 0 {
+        return paused_at as u128;
+    }
+    get_millis().saturating_sub(PAUSED_TOTAL_MS.load(Ordering::Relaxed) as u128)
+}
+
+/// Layout of the saved effects and of their args. Saves from before the
+/// version was written are version 0, `migrate_args` brings their args up
+/// to date
+pub const SAVE_VERSION: u64 = 2;
+
 #[derive(Serialize, Deserialize)]
 pub struct EffectSave {
     args: Vec<u8>,
     name: String,
+    #[serde(default)]
+    version: u64,
+}
+
+impl EffectSave {
+    /// Save of the effect called `name` in the current `SAVE_VERSION`
+    pub fn new(name: &str, args: Vec<u8>) -> EffectSave {
+        EffectSave { args, name: String::from(name), version: SAVE_VERSION }
+    }
+}
+
+/// Args of an effect saved with an older `SAVE_VERSION` in the current
+/// layout, None when they can't be read. Version 1 only added the version
+/// field. Version 2 dropped the 7 byte form of the static gradient, 2
+/// colours and the blend curve, which could be mistaken for 2 counted colours
+fn migrate_args(version: u64, name: &str, mut args: Vec<u8>) -> Option<Vec<u8>> {
+    match version {
+        0 | 1 => {
+            if name == "Static Gradient" && args.len() == 7 {
+                args.insert(0, 2);
+            }
+            Some(args)
+        }
+        SAVE_VERSION => Some(args),
+        _ => {
+            warn!(
+                "{} was saved by a newer version of the daemon (save version {}, this one reads up to {})",
+                name, version, SAVE_VERSION
+            );
+            None
+        }
+    }
 }
 
 /// Base effect trait.
-/// An effect is a lighting function that is updated 30 times per second
+/// An effect is a lighting function that is updated once per frame
 /// in order to create an animation of some description on the laptop's
 /// keyboard
 pub trait Effect: Send + Sync {
@@ -32,8 +149,13 @@ pub trait Effect: Send + Sync {
     where
         Self: Sized;
     /// Updates the keyboard, returning the current state of the keyboard
-    /// Called 30 times per second by the Effect Manager
+    /// Called once per frame by the Effect Manager
     fn update(&mut self) -> board::KeyboardData;
+    /// Returns true when `args` follows the layout described for the effect,
+    /// `new` may panic otherwise
+    fn valid_args(args: &[u8]) -> bool
+    where
+        Self: Sized;
     /// Returns the arguments used to spawn the effect
     fn get_varargs(&mut self) -> &[u8];
     /// Returns the name of the effect (Unique identifier)
@@ -43,6 +165,94 @@ pub trait Effect: Send + Sync {
     fn clone_box(&self) -> Box<dyn Effect>;
     fn save(&mut self) -> EffectSave;
     fn get_state(&mut self) -> Vec<u8>;
+    /// Returns true for a single static colour, which the idle colour and the
+    /// per key layers are made of
+    fn is_static(&self) -> bool {
+        false
+    }
+}
+
+/// Creates an effect, None when `args` don't follow its layout
+pub fn create_effect<T: Effect>(args: Vec<u8>) -> Option<Box<dyn Effect>> {
+    if !T::valid_args(&args) {
+        warn!("Invalid arguments for effect {}: {:?}", T::get_name(), args);
+        return None;
+    }
+
+    Some(T::new(args))
+}
+
+/// An effect the daemon can run
+pub struct EffectInfo {
+    /// Name of the saves, the one `Effect::get_name` returns
+    pub name: &'static str,
+    /// Name the clients pass to SetEffect and PushEffectLayer
+    pub command: &'static str,
+    pub create: fn(Vec<u8>) -> Option<Box<dyn Effect>>,
+}
+
+/// Every custom effect. The saves, the commands and ListEffects all look
+/// effects up here, so a new one only has to be added to this list
+pub const EFFECTS: &[EffectInfo] = &[
+    EffectInfo { name: "Static", command: "static", create: create_effect::<effects::Static> },
+    EffectInfo { name: "Static Gradient", command: "static_gradient", create: create_effect::<effects::StaticGradient> },
+    EffectInfo { name: "Wave Gradient", command: "wave_gradient", create: create_effect::<effects::WaveGradient> },
+    EffectInfo { name: "Breathing Single", command: "breathing_single", create: create_effect::<effects::BreathSingle> },
+    EffectInfo { name: "Breathing Multi", command: "breathing_multi", create: create_effect::<effects::BreathMulti> },
+    EffectInfo { name: "Ripple", command: "ripple", create: create_effect::<effects::Ripple> },
+    EffectInfo { name: "Reactive", command: "reactive", create: create_effect::<effects::Reactive> },
+    EffectInfo { name: "Audio Visualizer", command: "audio_visualizer", create: create_effect::<effects::AudioVisualizer> },
+    EffectInfo { name: "Spectrum Cycle", command: "spectrum_cycle", create: create_effect::<effects::SpectrumCycle> },
+    EffectInfo { name: "Starlight", command: "starlight", create: create_effect::<effects::Starlight> },
+    EffectInfo { name: "Wave", command: "wave", create: create_effect::<effects::Wave> },
+    EffectInfo { name: "Rain", command: "rain", create: create_effect::<effects::Rain> },
+    EffectInfo { name: "Ambilight", command: "ambilight", create: create_effect::<effects::Ambilight> },
+    EffectInfo { name: "Color Wheel", command: "color_wheel", create: create_effect::<effects::ColorWheel> },
+    EffectInfo { name: "Load Meter", command: "load_meter", create: create_effect::<effects::LoadMeter> },
+    EffectInfo { name: "Spotlight", command: "spotlight", create: create_effect::<effects::Spotlight> },
+    EffectInfo { name: "Heatmap", command: "heatmap", create: create_effect::<effects::Heatmap> },
+    EffectInfo { name: "Custom Frame", command: "custom_frame", create: create_effect::<effects::CustomFrame> },
+];
+
+/// Makes random but valid args for an effect
+type RandomArgs = fn(&mut StdRng) -> Vec<u8>;
+
+/// Effects `random_effect` picks from, with the args generator of each. The
+/// ones that need key presses, sound, the screen or a frame are left out
+const RANDOM_EFFECTS: [(&str, RandomArgs); 10] = [
+    ("static", |rng| random_colour(rng)),
+    ("static_gradient", |rng| [random_colour(rng), random_colour(rng)].concat()),
+    ("wave_gradient", |rng| [random_colour(rng), random_colour(rng)].concat()),
+    ("breathing_single", |rng| [random_colour(rng), vec![rng.gen_range(10..=40)]].concat()),
+    ("breathing_multi", |rng| {
+        let count = rng.gen_range(2..=4);
+        let mut args = vec![count];
+        for _ in 0..count {
+            args.extend(random_colour(rng));
+        }
+        args.push(rng.gen_range(5..=20));
+        args
+    }),
+    ("spectrum_cycle", |rng| vec![rng.gen_range(30..=150)]),
+    // Stars on black, of the colour or of random hues
+    ("starlight", |rng| [random_colour(rng), vec![rng.gen_range(40..=160), rng.gen_range(0..=1), 0, 0, 0]].concat()),
+    ("wave", |rng| [random_colour(rng), random_colour(rng), vec![rng.gen_range(0..=3), rng.gen_range(2..=12)]].concat()),
+    ("rain", |rng| [random_colour(rng), vec![rng.gen_range(5..=20)]].concat()),
+    ("color_wheel", |rng| vec![rng.gen_range(10..=120), rng.gen_range(60..=100)]),
+];
+
+/// A fully saturated colour of a random hue, grey or dark ones don't show off much
+fn random_colour(rng: &mut StdRng) -> Vec<u8> {
+    let colour = board::hsv_to_rgb(rng.gen_range(0.0..360.0), 1.0, 1.0);
+    vec![colour.red, colour.green, colour.blue]
+}
+
+/// Command name and args of a random effect, with vivid colours and speeds
+/// that are neither sluggish nor hectic. The same seed gives the same effect
+pub fn random_effect(seed: u64) -> (&'static str, Vec<u8>) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let (command, args) = RANDOM_EFFECTS[rng.gen_range(0..RANDOM_EFFECTS.len())];
+    (command, args(&mut rng))
 }
 
 /// An effect combined with a mask layer.
@@ -52,30 +262,48 @@ struct EffectLayer {
     /// Mask for keys
     key_mask: Vec<bool>,
     effect: Box<dyn Effect>,
+    /// Percentage the layer is scaled by before it is composited
+    brightness: u8,
+    /// Time a notification removes itself at, None for the layers
+    expires_ms: Option<u128>,
+    /// Last frame of the effect, before the layer brightness
+    last_frame: Option<board::KeyboardData>,
 }
 
 unsafe impl Send for EffectLayer {}
 unsafe impl Sync for EffectLayer {}
 
 impl EffectLayer {
-    fn new(effect: Box<dyn Effect>, mask: [bool; 90]) -> EffectLayer {
+    fn new(effect: Box<dyn Effect>, mask: Vec<bool>) -> EffectLayer {
         return EffectLayer {
-            key_mask: mask.to_vec(),
+            key_mask: mask,
             effect,
+            brightness: 100,
+            expires_ms: None,
+            last_frame: None,
         };
     }
 
-    fn update(&mut self) -> board::KeyboardData {
-        return self.effect.update();
+    /// Next frame of the layer. A frozen layer repeats its last frame
+    fn update(&mut self, frozen: bool) -> board::KeyboardData {
+        let mut board = match self.last_frame {
+            Some(frame) if frozen => frame,
+            _ => self.effect.update(),
+        };
+        self.last_frame = Some(board);
+        if self.brightness < 100 {
+            board.scale_brightness(self.brightness);
+        }
+        board
     }
 
     fn get_save(&mut self) -> Option<serde_json::Value> {
         match serde_json::to_value(self.effect.save()) {
             Ok(mut x) => {
                 let keys = serde_json::to_value(&self.key_mask).unwrap();
-                x.as_object_mut()
-                    .unwrap()
-                    .insert(String::from("key_mask"), keys);
+                let object = x.as_object_mut().unwrap();
+                object.insert(String::from("key_mask"), keys);
+                object.insert(String::from("brightness"), json!(self.brightness));
                 Some(x)
             }
             Err(_) => None,
@@ -84,34 +312,53 @@ impl EffectLayer {
 
     fn from_save(json: serde_json::Value) -> Option<EffectLayer> {
         if json["key_mask"].is_null() || json["name"].is_null() || json["args"].is_null() {
-            eprintln!("Missing data for effect!");
+            warn!("Missing data for effect!");
             return None;
         }
-        let key_mask: Vec<bool> = serde_json::from_value(json["key_mask"].clone()).unwrap();
-        if key_mask.len() != 90 {
-            eprintln!(
-                "Invalid key count effect. Expected 90, found {}",
+        let key_mask: Vec<bool> = match serde_json::from_value(json["key_mask"].clone()) {
+            Ok(key_mask) => key_mask,
+            Err(e) => {
+                warn!("Invalid key mask of a saved effect: {}", e);
+                return None;
+            }
+        };
+        if key_mask.len() != board::zone_key_count() {
+            warn!(
+                "Invalid key count effect. Expected {}, found {}",
+                board::zone_key_count(),
                 key_mask.len()
             );
             return None;
         }
-        let name: String = serde_json::from_value(json["name"].clone()).unwrap();
-        let args: Vec<u8> = serde_json::from_value(json["args"].clone()).unwrap();
-
-        let effect: Option<Box<dyn Effect>> = match name.as_str() {
-            "Static" => Some(effects::Static::new(args)),
-            "Wave Gradient" => Some(effects::WaveGradient::new(args)),
-            "Breathing Single" => Some(effects::BreathSingle::new(args)),
-            "Static Gradient" => Some(effects::StaticGradient::new(args)),
-            _ => None,
+        let Some(name) = json["name"].as_str().map(String::from) else {
+            warn!("Invalid name of a saved effect: {}", json["name"]);
+            return None;
+        };
+        let args: Vec<u8> = match serde_json::from_value(json["args"].clone()) {
+            Ok(args) => args,
+            Err(e) => {
+                warn!("Invalid args of the saved effect {}: {}", name, e);
+                return None;
+            }
         };
+        let args = migrate_args(json["version"].as_u64().unwrap_or(0), &name, args)?;
+        // Saves from before layers had a brightness are at full brightness
+        let brightness = json["brightness"].as_u64().map_or(100, |b| b.min(100) as u8);
+
+        let effect = EFFECTS
+            .iter()
+            .find(|info| info.name == name)
+            .and_then(|info| (info.create)(args));
         if effect.is_none() {
-            eprintln!("Effect failed to load. Invalid name: {}", name);
+            warn!("Effect failed to load: {}", name);
             return None;
         }
         return Some(EffectLayer {
             key_mask,
             effect: effect.unwrap(),
+            brightness,
+            expires_ms: None,
+            last_frame: None,
         });
     }
 
@@ -127,6 +374,55 @@ pub struct EffectManager {
     layers: Vec<EffectLayer>,
     last_update_ms: u128,
     render_board: board::KeyboardData,
+    /// Global brightness percentage applied on top of every effect
+    brightness: u8,
+    /// Lowest percentage the frames are dimmed to unless the brightness is 0
+    min_brightness: u8,
+    /// The last layer was removed, the keyboard still has to be set to black
+    blank_pending: bool,
+    /// Frame sent by a client, shown instead of the layers until resumed
+    full_frame: Option<board::KeyboardData>,
+    /// The keyboard is kept black until restored, the layers stay
+    blanked: bool,
+    /// Colour shown once the last layer is removed
+    idle_colour: [u8; 3],
+    /// Show the colour of the last static effect instead of `idle_colour`
+    idle_last_static: bool,
+    last_static: Option<[u8; 3]>,
+    /// Last frame that reached the keyboard, brightness included
+    shown: board::KeyboardData,
+    /// The effects stand still and only changes reach the keyboard, for the
+    /// battery saver
+    frozen: bool,
+    /// Length of the cross-fade when an effect replaces another, 0 cuts
+    transition_ms: u128,
+    /// Frame the outgoing effect left, brightness excluded, and when the
+    /// cross-fade from it started
+    transition: Option<(board::KeyboardData, u128)>,
+    /// Frames composed since `stats_since_ms` and how many came late
+    stats_frames: u64,
+    stats_dropped: u64,
+    stats_since_ms: u128,
+    /// Static layers over single keys for the lock indicators, drawn above
+    /// every other layer and never saved or popped
+    indicators: Vec<EffectLayer>,
+    /// Timed layers such as flashes, drawn over the layers under the
+    /// indicators. Kept out of the layer indices, so they are never listed,
+    /// saved or popped
+    notifications: Vec<EffectLayer>,
+    /// The indicators or notifications changed and have to be drawn even
+    /// without layers
+    overlay_changed: bool,
+    /// Nothing is rendered, the keyboard keeps the last frame until resumed
+    paused: bool,
+}
+
+/// Frame rate the effects run at, for spotting stutter
+pub struct AnimationStats {
+    pub target_fps: u8,
+    /// 0 until two frames were composed
+    pub measured_fps: f32,
+    pub dropped_frames: u64,
 }
 
 unsafe impl Send for EffectManager {}
@@ -138,44 +434,345 @@ impl EffectManager {
             layers: vec![],
             last_update_ms: get_millis(),
             render_board: board::KeyboardData::new(),
+            brightness: 100,
+            min_brightness: 0,
+            blank_pending: false,
+            full_frame: None,
+            blanked: false,
+            idle_colour: [0, 0, 0],
+            idle_last_static: false,
+            last_static: None,
+            shown: board::KeyboardData::new(),
+            frozen: false,
+            transition_ms: 0,
+            transition: None,
+            stats_frames: 0,
+            stats_dropped: 0,
+            stats_since_ms: 0,
+            indicators: vec![],
+            notifications: vec![],
+            overlay_changed: false,
+            paused: false,
         }
     }
 
-    pub fn push_effect(&mut self, effect: Box<dyn Effect>, mask: [bool; 90]) {
+    /// Sets the global brightness percentage the rendered frames are scaled
+    /// by. Anything but 0 is raised to the minimum brightness, 0 stays off
+    pub fn set_brightness(&mut self, percent: u8) {
+        self.brightness = match percent {
+            0 => 0,
+            _ => percent.min(100).max(self.min_brightness),
+        };
+    }
+
+    /// Sets the floor of `set_brightness`, applied from the next frame
+    pub fn set_min_brightness(&mut self, percent: u8) {
+        self.min_brightness = percent.min(100);
+    }
+
+    pub fn push_effect(&mut self, mut effect: Box<dyn Effect>, mask: Vec<bool>) {
+        if let (true, &[r, g, b]) = (effect.is_static(), effect.get_varargs()) {
+            self.last_static = Some([r, g, b]);
+        }
+        self.reset_stats();
         self.layers.push(EffectLayer::new(effect, mask))
     }
 
-    pub fn pop_effect(&mut self, laptop: &mut device::RazerLaptop) {
+    /// Shows an effect over the layers for `duration_ms`, as a notification
+    /// that doesn't shift the layer indices and isn't saved
+    pub fn push_timed_effect(&mut self, effect: Box<dyn Effect>, mask: Vec<bool>, duration_ms: u128) {
+        let mut layer = EffectLayer::new(effect, mask);
+        layer.expires_ms = Some(get_millis() + duration_ms);
+        self.notifications.push(layer);
+        self.overlay_changed = true;
+    }
+
+    pub fn pop_effect(&mut self) {
         self.layers.pop();
-        // If no more layers, erase keyboard rendering and set it to black
+        // If no more layers, erase keyboard rendering and set it to the idle colour with the next frame
         if self.layers.is_empty() {
-            self.render_board.set_kbd_colour(0, 0, 0); 
-            self.render_board.update_kbd(laptop);
-            self.render_board.update_custom_mode(laptop);
+            self.show_idle_colour();
         }
     }
 
-    pub fn update(&mut self, laptop: &mut device::RazerLaptop) {
-        // Do nothing if we have no effects!
-        if self.layers.is_empty() {
+    /// Removes every layer, the keyboard turns to the idle colour with the
+    /// next frame unless new layers are added first
+    pub fn clear(&mut self) {
+        self.layers.clear();
+        self.show_idle_colour();
+    }
+
+    /// Sets what the keyboard shows without layers, right away when there
+    /// are none
+    pub fn set_idle_colour(&mut self, rgb: [u8; 3], last_static: bool) {
+        self.idle_colour = rgb;
+        self.idle_last_static = last_static;
+        if self.layers.is_empty() && self.full_frame.is_none() {
+            self.show_idle_colour();
+        }
+    }
+
+    fn show_idle_colour(&mut self) {
+        let [r, g, b] = match self.last_static {
+            Some(rgb) if self.idle_last_static => rgb,
+            _ => self.idle_colour,
+        };
+        self.render_board.set_kbd_colour(r, g, b);
+        self.blank_pending = true;
+    }
+
+    /// Keeps the keyboard as it is after the last layer was removed, for
+    /// when a hardware effect replaces the custom frames
+    pub fn skip_blank(&mut self) {
+        self.blank_pending = false;
+    }
+
+    /// Stops or restarts the animations. Frozen layers keep showing their
+    /// last frame, new layers and other changes still show up
+    pub fn set_frozen(&mut self, frozen: bool) {
+        self.frozen = frozen;
+    }
+
+    /// Sets the length of the cross-fade of `start_transition`, 0 turns it off
+    pub fn set_transition_ms(&mut self, ms: u32) {
+        self.transition_ms = ms.min(MAX_TRANSITION_MS) as u128;
+        if self.transition_ms == 0 {
+            self.transition = None;
+        }
+    }
+
+    /// Fades from the current frame to what the layers show next. Called
+    /// before the layers are replaced, a fade that didn't end yet goes on
+    /// from where it is
+    pub fn start_transition(&mut self) {
+        if self.transition_ms == 0 {
             return;
         }
+        let now = get_millis();
+        let from = self.transition_frame(now).unwrap_or(self.render_board);
+        self.transition = Some((from, now));
+    }
+
+    /// The composed layers mixed with the frame of the outgoing effect, None
+    /// once the cross-fade is over. Recomputed every frame, so the mix moves
+    /// on even when the new effect is a static one that never changes
+    fn transition_frame(&self, now: u128) -> Option<board::KeyboardData> {
+        let (from, start) = self.transition?;
+        let elapsed = now.saturating_sub(start);
+        if elapsed >= self.transition_ms {
+            return None;
+        }
+        let mut frame = self.render_board;
+        frame.blend_from(&from, elapsed as f32 / self.transition_ms as f32);
+        Some(frame)
+    }
+
+    /// Combines the layers into the next frame, None when there is nothing to
+    /// draw. Frozen, a frame the keyboard already shows isn't drawn again
+    pub fn render(&mut self) -> Option<board::KeyboardData> {
+        if self.paused {
+            return None;
+        }
+        let mut frame = self.compose()?;
+        if self.frozen && frame.get_curr_state() == self.shown.get_curr_state() {
+            return None;
+        }
+        Some(frame)
+    }
+
+    fn compose(&mut self) -> Option<board::KeyboardData> {
+        let now = get_millis();
+        let count = self.notifications.len();
+        self.notifications.retain(|layer| layer.expires_ms.is_none_or(|expires| now < expires));
+        if self.notifications.len() < count {
+            self.overlay_changed = true;
+        }
+        if self.blanked {
+            return if self.blank_pending { Some(board::KeyboardData::new()) } else { None };
+        }
+        if let Some(mut frame) = self.full_frame {
+            frame.scale_brightness(self.brightness);
+            return Some(frame);
+        }
+        if self.layers.is_empty() {
+            if !self.blank_pending && !self.overlay_changed && self.notifications.is_empty() {
+                return None;
+            }
+            let mut frame = self.render_board;
+            self.draw_notifications(&mut frame);
+            self.draw_indicators(&mut frame);
+            frame.scale_brightness(self.brightness);
+            return Some(frame);
+        }
         for layer in self.layers.iter_mut() {
-            let tmp_board = layer.update();
-            for (pos, state) in layer.key_mask.iter().enumerate() {
-                if *state {
+            let tmp_board = layer.update(self.frozen);
+            // Masks only cover the keyboard zone, a layer over every key of it
+            // also covers the extra zones
+            let covers_all = layer.key_mask.iter().all(|state| *state);
+            for pos in 0..board::key_count() {
+                if layer.key_mask.get(pos).copied().unwrap_or(covers_all) {
                     self.render_board.set_key_at(pos, tmp_board.get_key_at(pos))
                 }
             }
         }
-        // Don't forget to actually render the board
+        self.count_frame(now);
         self.last_update_ms = get_millis();
-        self.render_board.update_kbd(laptop);
-        self.render_board.update_custom_mode(laptop);
+        let mut frame = match self.transition_frame(now) {
+            Some(frame) => frame,
+            None => {
+                self.transition = None;
+                self.render_board
+            }
+        };
+        self.draw_notifications(&mut frame);
+        self.draw_indicators(&mut frame);
+        frame.scale_brightness(self.brightness);
+
+        Some(frame)
+    }
+
+    /// Draws the notifications over the cross-fade. They keep going while the
+    /// effects are frozen, they end soon anyway
+    fn draw_notifications(&mut self, frame: &mut board::KeyboardData) {
+        for layer in self.notifications.iter_mut() {
+            let tmp_board = layer.update(false);
+            let covers_all = layer.key_mask.iter().all(|state| *state);
+            for pos in 0..board::key_count() {
+                if layer.key_mask.get(pos).copied().unwrap_or(covers_all) {
+                    frame.set_key_at(pos, tmp_board.get_key_at(pos))
+                }
+            }
+        }
+    }
+
+    /// Lights the keys of the lock indicators, over the cross-fade so they
+    /// don't fade with the effects
+    fn draw_indicators(&mut self, frame: &mut board::KeyboardData) {
+        self.overlay_changed = false;
+        for layer in self.indicators.iter_mut() {
+            let tmp_board = layer.update(self.frozen);
+            for (pos, on) in layer.key_mask.iter().enumerate() {
+                if *on {
+                    frame.set_key_at(pos, tmp_board.get_key_at(pos))
+                }
+            }
+        }
+    }
+
+    /// Counts a composed frame, late when it came more than
+    /// `DROPPED_FRAME_INTERVALS` frame intervals after the last one
+    fn count_frame(&mut self, now: u128) {
+        let gap = now.saturating_sub(self.last_update_ms);
+        if self.stats_frames == 0 || gap > STATS_PAUSE_MS {
+            self.stats_frames = 1;
+            self.stats_since_ms = now;
+            return;
+        }
+        self.stats_frames += 1;
+        if gap as f32 > get_animation_sleep_ms() as f32 * DROPPED_FRAME_INTERVALS {
+            self.stats_dropped += 1;
+        }
+    }
+
+    fn reset_stats(&mut self) {
+        self.stats_frames = 0;
+        self.stats_dropped = 0;
+    }
+
+    /// Frame rate measured since the last effect was set or the last pause
+    pub fn get_stats(&self) -> AnimationStats {
+        let elapsed = self.last_update_ms.saturating_sub(self.stats_since_ms);
+        let measured_fps = if self.stats_frames < 2 || elapsed == 0 {
+            0.0
+        } else {
+            (self.stats_frames - 1) as f32 * 1000.0 / elapsed as f32
+        };
+        AnimationStats { target_fps: get_animation_fps(), measured_fps, dropped_frames: self.stats_dropped }
+    }
+
+    /// Shows a frame of RGB triples, laid out like the args of the custom
+    /// frame effect, instead of the layers. They are paused, not removed
+    pub fn set_full_frame(&mut self, rgb: Vec<u8>) -> bool {
+        match create_effect::<effects::CustomFrame>(rgb) {
+            Some(mut frame) => {
+                self.full_frame = Some(frame.update());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Shows the layers again after `set_full_frame`, false when no frame
+    /// was shown
+    pub fn resume_effects(&mut self) -> bool {
+        if self.full_frame.take().is_none() {
+            return false;
+        }
+        if self.layers.is_empty() {
+            self.show_idle_colour();
+        }
+        true
+    }
+
+    /// Stops rendering and stops the animation clock, the keyboard keeps the
+    /// last frame. Changes to the layers show up once resumed. False when
+    /// already paused
+    pub fn pause_animation(&mut self) -> bool {
+        if self.paused {
+            return false;
+        }
+        // Never 0, that is the value for running effects
+        PAUSED_AT_MS.store((animation_millis() as u64).max(1), Ordering::Relaxed);
+        self.paused = true;
+        true
+    }
+
+    /// Restarts the effects where `pause_animation` stopped them, false when
+    /// they weren't paused
+    pub fn resume_animation(&mut self) -> bool {
+        if !self.paused {
+            return false;
+        }
+        let paused_at = PAUSED_AT_MS.load(Ordering::Relaxed);
+        PAUSED_TOTAL_MS.store((get_millis() as u64).saturating_sub(paused_at), Ordering::Relaxed);
+        PAUSED_AT_MS.store(0, Ordering::Relaxed);
+        self.paused = false;
+        // The gap isn't a dropped frame
+        self.reset_stats();
+        true
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Turns the keyboard black with the next frame and keeps it black, the
+    /// layers and a full frame are kept for `restore_keyboard`
+    pub fn blank_keyboard(&mut self) {
+        self.blanked = true;
+        self.blank_pending = true;
+    }
+
+    /// Shows what was there before `blank_keyboard`, false when the
+    /// keyboard wasn't blanked
+    pub fn restore_keyboard(&mut self) -> bool {
+        if !self.blanked {
+            return false;
+        }
+        self.blanked = false;
+        // Nothing else to draw, back to the idle colour
+        self.blank_pending = self.layers.is_empty() && self.full_frame.is_none();
+        true
+    }
+
+    /// Called once a frame of `render` reached the keyboard
+    pub fn frame_written(&mut self, frame: board::KeyboardData) {
+        self.blank_pending = false;
+        self.shown = frame;
     }
 
     pub fn save(&mut self) -> serde_json::value::Value {
-        let mut save_json = json!({"effects" : []});
+        let mut save_json = json!({"version": SAVE_VERSION, "effects" : []});
 
         let tmp_saves: Vec<Option<serde_json::Value>> =
             self.layers.iter_mut().map(|l| l.get_save()).collect();
@@ -184,32 +781,366 @@ impl EffectManager {
             if let Some(x) = save {
                 save_json["effects"].as_array_mut().unwrap().push(x);
             } else {
-                eprintln!("Warning, discarding effect!");
+                warn!("Warning, discarding effect!");
             }
         }
         return save_json;
     }
 
-    pub fn load_from_save(&mut self, mut json: serde_json::Value) {
-        if json["effects"].is_null() {
-            eprintln!("Invalid json. No effects field!");
+    pub fn load_from_save(&mut self, json: serde_json::Value) {
+        let Some(effects) = json["effects"].as_array() else {
+            error!("Invalid json. No effects list!");
+            return;
+        };
+        // The layers carry their own version, a newer file is refused as a
+        // whole rather than shown half
+        let version = json["version"].as_u64().unwrap_or(0);
+        if version > SAVE_VERSION {
+            error!(
+                "The effects were saved by a newer version of the daemon (save version {}, this one reads up to {}), not loading them",
+                version, SAVE_VERSION
+            );
             return;
         }
-        for e in json["effects"].as_array_mut().unwrap() {
+        self.reset_stats();
+        for e in effects {
             if let Some(x) = EffectLayer::from_save(e.clone()) {
                 self.layers.push(x);
             } else {
-                eprintln!("Error adding effect");
+                // Effects that are no longer known are dropped with the next save
+                warn!("Error adding effect, skipping it");
+            }
+        }
+    }
+
+    /// Returns the effect names of the layers, bottom layer first
+    pub fn get_layer_names(&mut self) -> Vec<String> {
+        self.layers.iter_mut().map(|layer| layer.effect.save().name).collect()
+    }
+
+    /// Pushes a copy of a layer, with its effect in the state it is in now and
+    /// its brightness, masked by `mask` instead
+    pub fn duplicate_layer(&mut self, layer: usize, mask: Vec<bool>) -> bool {
+        let Some(source) = self.layers.get(layer) else {
+            return false;
+        };
+        let brightness = source.brightness;
+        self.push_effect(source.effect.clone_box(), mask);
+        if let Some(copy) = self.layers.last_mut() {
+            copy.brightness = brightness;
+        }
+        true
+    }
+
+    /// Sets the brightness percentage of a layer (bottom layer is 0), false
+    /// when there is no such layer
+    pub fn set_layer_brightness(&mut self, layer: usize, percent: u8) -> bool {
+        match self.layers.get_mut(layer) {
+            Some(layer) => {
+                layer.brightness = percent.min(100);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Lights a single key on top of every other layer, with a static layer
+    /// masking only that key. A colour the key already had is replaced
+    pub fn set_key_colour(&mut self, index: usize, red: u8, green: u8, blue: u8) -> bool {
+        if index >= board::zone_key_count() {
+            return false;
+        }
+        // Moved to the top so it stays over layers pushed in the meantime
+        if let Some(pos) = self.key_layer_position(index) {
+            self.layers.remove(pos);
+        }
+        let mut mask = vec![false; board::zone_key_count()];
+        mask[index] = true;
+        self.push_effect(effects::Static::new(vec![red, green, blue]), mask);
+        true
+    }
+
+    /// Removes the colour set with `set_key_colour`, false if the key had none
+    pub fn clear_key_colour(&mut self, index: usize) -> bool {
+        match self.key_layer_position(index) {
+            Some(pos) if pos + 1 == self.layers.len() => {
+                // Same as removing the top layer, blanks the keyboard if it was the last
+                self.pop_effect();
+                true
             }
+            Some(pos) => {
+                self.layers.remove(pos);
+                true
+            }
+            None => false,
         }
     }
 
+    /// Replaces the lock indicators with these keys and colours, an empty
+    /// list turns them all off
+    pub fn set_indicators(&mut self, keys: Vec<(usize, [u8; 3])>) {
+        self.indicators = keys
+            .into_iter()
+            .filter(|(index, _)| *index < board::zone_key_count())
+            .map(|(index, rgb)| {
+                let mut mask = vec![false; board::zone_key_count()];
+                mask[index] = true;
+                EffectLayer::new(effects::Static::new(rgb.to_vec()), mask)
+            })
+            .collect();
+        self.overlay_changed = true;
+    }
+
+    /// Position of the static layer covering only `index`
+    fn key_layer_position(&mut self, index: usize) -> Option<usize> {
+        self.layers.iter_mut().position(|layer| {
+            layer.key_mask.iter().enumerate().all(|(pos, on)| *on == (pos == index))
+                && layer.effect.is_static()
+        })
+    }
+
+    pub fn layer_count(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// Returns the name and arguments of the top most effect layer
+    pub fn get_active_effect(&mut self) -> Option<(String, Vec<u8>)> {
+        self.layers.last_mut().map(|layer| {
+            (layer.effect.save().name, layer.effect.get_varargs().to_vec())
+        })
+    }
+
+    /// Frame the keyboard shows, as last written
+    pub fn get_shown_frame(&self) -> board::KeyboardData {
+        self.shown
+    }
+
+    /// RGB of every key as the keyboard shows it for a negative `layer_id`,
+    /// after the brightness, a full frame or blanking. Otherwise the state
+    /// of that layer, empty when there is no such layer
+    pub fn get_rendered_frame(&mut self, layer_id: i32) -> Vec<u8> {
+        if layer_id < 0 {
+            return self.shown.get_curr_state();
+        }
+        match self.layers.get_mut(layer_id as usize) {
+            Some(layer) => layer.get_state(),
+            None => vec![],
+        }
+    }
+
+    /// Composed layers without the brightness for a negative `layer_id`,
+    /// else the state of that layer, empty when there is no such layer
     pub fn get_map(&mut self, layer_id: i32) -> Vec<u8> {
         if layer_id < 0 {
             // Requesting global layer
             return self.render_board.get_curr_state();
-        } else {
-            return self.layers[layer_id as usize].get_state();
         }
+        match self.layers.get_mut(layer_id as usize) {
+            Some(layer) => layer.get_state(),
+            None => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn old_two_colour_gradients_get_a_count() {
+        let args = vec![1, 2, 3, 4, 5, 6, effects::EASING_OUT];
+        assert_eq!(migrate_args(1, "Static Gradient", args.clone()), Some([vec![2], args.clone()].concat()));
+        assert_eq!(migrate_args(0, "Static Gradient", args.clone()), Some([vec![2], args.clone()].concat()));
+        // Already counted, or not a static gradient
+        let counted = vec![2, 1, 2, 3, 4, 5, 6, effects::EASING_OUT];
+        assert_eq!(migrate_args(1, "Static Gradient", counted.clone()), Some(counted));
+        assert_eq!(migrate_args(1, "Wave Gradient", args.clone()), Some(args.clone()));
+        assert_eq!(migrate_args(SAVE_VERSION, "Static Gradient", args.clone()), Some(args));
+    }
+
+    #[test]
+    fn random_effects_have_valid_args() {
+        let _layout = board::test_layout(15, 1);
+        let mut picked = std::collections::HashSet::new();
+        for seed in 0..1000 {
+            let (command, args) = random_effect(seed);
+            let info = EFFECTS.iter().find(|info| info.command == command).expect("Unknown effect");
+            assert!((info.create)(args.clone()).is_some(), "seed {seed}: {command} {args:?}");
+            picked.insert(command);
+        }
+        assert_eq!(picked.len(), RANDOM_EFFECTS.len());
+    }
+
+    #[test]
+    fn maps_of_missing_layers_are_empty() {
+        let _layout = board::test_layout(15, 1);
+        let mut manager = EffectManager::new();
+        manager.push_effect(effects::Static::new(vec![0, 0, 255]), vec![true; board::zone_key_count()]);
+        assert_eq!(manager.get_map(0).len(), 3 * board::zone_key_count());
+        assert!(manager.get_map(1).is_empty());
+        assert!(manager.get_map(i32::MAX).is_empty());
+        // Negative is the composed frame
+        assert_eq!(manager.get_map(-1).len(), 3 * board::key_count());
+    }
+
+    #[test]
+    fn newer_saves_are_not_migrated() {
+        assert_eq!(migrate_args(SAVE_VERSION + 1, "Static", vec![1, 2, 3]), None);
+    }
+
+    fn saved_layer(name: &str, args: serde_json::Value, version: Option<u64>) -> serde_json::Value {
+        let mut save = json!({
+            "key_mask": vec![true; board::zone_key_count()],
+            "name": name,
+            "args": args,
+        });
+        if let Some(version) = version {
+            save["version"] = json!(version);
+        }
+        save
+    }
+
+    #[test]
+    fn layers_are_loaded_from_old_saves() {
+        let _layout = board::test_layout(15, 1);
+        let gradient = json!([1, 2, 3, 4, 5, 6, effects::EASING_OUT]);
+        // Saves without a version are from before it was written
+        for version in [None, Some(1)] {
+            let mut layer = EffectLayer::from_save(saved_layer("Static Gradient", gradient.clone(), version)).unwrap();
+            assert_eq!(layer.get_save().unwrap()["args"], json!([2, 1, 2, 3, 4, 5, 6, effects::EASING_OUT]));
+            assert_eq!(layer.get_save().unwrap()["version"], json!(SAVE_VERSION));
+        }
+    }
+
+    #[test]
+    fn masks_follow_the_numpad_layout() {
+        let _layout = board::test_layout(22, 1);
+        let save = saved_layer("Static", json!([1, 2, 3]), Some(SAVE_VERSION));
+        assert_eq!(save["key_mask"].as_array().unwrap().len(), 132);
+        assert!(EffectLayer::from_save(save.clone()).is_some());
+        // A mask of the 90-key layout doesn't fit
+        let mut blade = save;
+        blade["key_mask"] = json!(vec![true; 90]);
+        assert!(EffectLayer::from_save(blade).is_none());
+    }
+
+    #[test]
+    fn layers_of_newer_saves_are_skipped() {
+        let _layout = board::test_layout(15, 1);
+        let save = saved_layer("Static", json!([1, 2, 3]), Some(SAVE_VERSION + 1));
+        assert!(EffectLayer::from_save(save).is_none());
+    }
+
+    #[test]
+    fn layers_with_bad_args_are_skipped() {
+        let _layout = board::test_layout(15, 1);
+        // Not bytes
+        assert!(EffectLayer::from_save(saved_layer("Static", json!([1, 2, 300]), None)).is_none());
+        assert!(EffectLayer::from_save(saved_layer("Static", json!("red"), None)).is_none());
+        // Bytes the effect doesn't take
+        assert!(EffectLayer::from_save(saved_layer("Static", json!([1, 2]), Some(SAVE_VERSION))).is_none());
+    }
+
+    #[test]
+    fn stacked_layers_survive_a_restart() {
+        let _layout = board::test_layout(15, 1);
+        let keys = board::zone_key_count();
+        let left: Vec<bool> = (0..keys).map(|pos| board::key_coordinates(pos).1 < 7).collect();
+        let mut manager = EffectManager::new();
+        manager.push_effect(effects::Static::new(vec![0, 0, 255]), vec![true; keys]);
+        manager.push_effect(create_effect::<effects::StaticGradient>(vec![255, 0, 0, 0, 255, 0]).unwrap(), left.clone());
+        manager.set_layer_brightness(1, 40);
+        // Written to the effects file as text and read back by a new daemon
+        let text = manager.save().to_string();
+        let mut restarted = EffectManager::new();
+        restarted.load_from_save(serde_json::from_str(&text).unwrap());
+        assert_eq!(restarted.get_layer_names(), vec![String::from("Static"), String::from("Static Gradient")]);
+        assert_eq!(restarted.layers[1].key_mask, left);
+        assert_eq!(restarted.layers[1].brightness, 40);
+        for layer in 0..2 {
+            assert_eq!(restarted.get_map(layer), manager.get_map(layer));
+        }
+        assert_eq!(restarted.save(), manager.save());
+    }
+
+    #[test]
+    fn malformed_layers_are_skipped_on_load() {
+        let _layout = board::test_layout(15, 1);
+        let good = saved_layer("Static", json!([1, 2, 3]), Some(SAVE_VERSION));
+        let mut short_mask = good.clone();
+        short_mask["key_mask"] = json!(vec![true; board::zone_key_count() - 1]);
+        let mut no_mask = good.clone();
+        no_mask.as_object_mut().unwrap().remove("key_mask");
+        let mut no_name = good.clone();
+        no_name.as_object_mut().unwrap().remove("name");
+        let save = json!({
+            "version": SAVE_VERSION,
+            "effects": [
+                short_mask,
+                no_mask,
+                no_name,
+                saved_layer("Static", json!([1, 2]), Some(SAVE_VERSION)),
+                saved_layer("Fireworks", json!([1, 2, 3]), Some(SAVE_VERSION)),
+                good,
+                "not a layer",
+            ],
+        });
+        let mut manager = EffectManager::new();
+        manager.load_from_save(save);
+        assert_eq!(manager.get_layer_names(), vec![String::from("Static")]);
+    }
+
+    #[test]
+    fn malformed_saves_load_nothing() {
+        let _layout = board::test_layout(15, 1);
+        let layer = saved_layer("Static", json!([1, 2, 3]), Some(SAVE_VERSION));
+        for save in [
+            json!({}),
+            json!({"effects": "Static"}),
+            json!([layer.clone()]),
+            json!({"version": SAVE_VERSION + 1, "effects": [layer.clone()]}),
+        ] {
+            let mut manager = EffectManager::new();
+            manager.load_from_save(save.clone());
+            assert_eq!(manager.layer_count(), 0, "{save}");
+        }
+    }
+
+    fn flash(duration_ms: u128) -> (Box<dyn Effect>, Vec<bool>, u128) {
+        let effect = create_effect::<effects::Flash>(vec![255, 0, 0, 30, 2]).unwrap();
+        (effect, vec![true; board::zone_key_count()], duration_ms)
+    }
+
+    #[test]
+    fn flash_is_not_a_layer() {
+        let _layout = board::test_layout(15, 1);
+        let mut manager = EffectManager::new();
+        let all = vec![true; board::zone_key_count()];
+        manager.push_effect(effects::Static::new(vec![0, 0, 255]), all.clone());
+        manager.push_effect(effects::Static::new(vec![0, 255, 0]), all);
+        let (effect, mask, duration_ms) = flash(600);
+        manager.push_timed_effect(effect, mask, duration_ms);
+        assert_eq!(manager.layer_count(), 2);
+        // Pops the top layer, not the flash over it
+        manager.pop_effect();
+        assert_eq!(manager.get_active_effect(), Some((String::from("Static"), vec![0, 0, 255])));
+        assert_eq!(manager.save()["effects"].as_array().map(|effects| effects.len()), Some(1));
+    }
+
+    #[test]
+    fn ended_flash_is_drawn_away() {
+        let _layout = board::test_layout(15, 1);
+        let mut manager = EffectManager::new();
+        manager.set_idle_colour([0, 0, 255], false);
+        let idle = manager.render().expect("No idle colour");
+        manager.frame_written(idle);
+        assert!(manager.render().is_none());
+        let (effect, mask, _) = flash(0);
+        manager.push_timed_effect(effect, mask, 0);
+        // Already over, the keyboard still has to show what is below
+        let mut frame = manager.render().expect("No frame after the flash");
+        assert!(frame.get_curr_state().chunks(3).all(|rgb| rgb == [0, 0, 255]));
+        manager.frame_written(frame);
+        assert!(manager.render().is_none());
     }
 }
@@ -0,0 +1,110 @@
+use super::get_millis;
+use lazy_static::lazy_static;
+use log::*;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+/// Time between two readings of the pointer position
+const POLL_INTERVAL_MS: u64 = 50;
+
+/// The polling is stopped when no effect asked for a position for this long
+const IDLE_TIMEOUT_MS: u128 = 5000;
+
+lazy_static! {
+    static ref LATEST_POSITION: Mutex<Option<(f32, f32)>> = Mutex::new(None);
+    static ref LAST_READ_MS: Mutex<u128> = Mutex::new(0);
+}
+
+static POLL_RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Returns the position of the mouse pointer as fractions (0 to 1) of the
+/// screen width and height, None while it is unknown.
+///
+/// The first call starts reading the position in the background with
+/// xdotool, like `screen::latest_frame` does for the screenshots. Wayland
+/// compositors only tell the position to X11 programs while the pointer is
+/// over one, without xdotool or a display this keeps returning None.
+pub fn latest_position() -> Option<(f32, f32)> {
+    if let Ok(mut last_read) = LAST_READ_MS.lock() {
+        *last_read = get_millis();
+    }
+    if !POLL_RUNNING.swap(true, Ordering::SeqCst) {
+        thread::spawn(poll);
+    }
+    LATEST_POSITION.lock().ok().and_then(|position| *position)
+}
+
+fn poll() {
+    info!("Following the mouse pointer");
+    // The screen size is read once per run, it rarely changes while polling
+    let size = display_size();
+    loop {
+        let last_read = LAST_READ_MS.lock().map_or(0, |t| *t);
+        if get_millis().saturating_sub(last_read) > IDLE_TIMEOUT_MS {
+            break;
+        }
+
+        let position = size.and_then(|(width, height)| {
+            let (x, y) = pointer_location()?;
+            Some(((x / width).clamp(0.0, 1.0), (y / height).clamp(0.0, 1.0)))
+        });
+        if position.is_none() {
+            warn!("Could not read the mouse pointer position, install xdotool (X11)");
+            // Let the next request retry once the idle timeout expired
+            thread::sleep(Duration::from_millis(IDLE_TIMEOUT_MS as u64));
+            break;
+        }
+        if let Ok(mut latest) = LATEST_POSITION.lock() {
+            *latest = position;
+        }
+        thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+    }
+
+    if let Ok(mut latest) = LATEST_POSITION.lock() {
+        *latest = None;
+    }
+    info!("Stopped following the mouse pointer");
+    POLL_RUNNING.store(false, Ordering::SeqCst);
+}
+
+fn xdotool(args: &[&str]) -> Option<String> {
+    let output = Command::new("xdotool")
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    String::from_utf8(output.stdout).ok()
+}
+
+/// Width and height of the whole screen in pixels, "1920 1080"
+fn display_size() -> Option<(f32, f32)> {
+    let output = xdotool(&["getdisplaygeometry"])?;
+    let mut values = output.split_whitespace().filter_map(|value| value.parse::<f32>().ok());
+    let (width, height) = (values.next()?, values.next()?);
+    if width < 1.0 || height < 1.0 {
+        return None;
+    }
+
+    Some((width, height))
+}
+
+/// Pointer position in pixels, from the X= and Y= lines of the shell output
+fn pointer_location() -> Option<(f32, f32)> {
+    let output = xdotool(&["getmouselocation", "--shell"])?;
+    let value = |name: &str| {
+        output
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix('='))
+            .and_then(|value| value.trim().parse::<f32>().ok())
+    };
+
+    Some((value("X")?, value("Y")?))
+}
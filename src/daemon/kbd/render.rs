@@ -0,0 +1,142 @@
+use super::{get_animation_sleep_ms, EffectManager};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Messages sent to the render thread
+enum RenderCommand {
+    /// Runs a closure on the effect manager, to push or pop layers, save them...
+    Run(Box<dyn FnOnce(&mut EffectManager) + Send>),
+    /// Stops the render thread
+    Shutdown,
+}
+
+/// Owns the thread the effects run on. The effect manager lives on that
+/// thread only, everything else reaches it through a channel so a client
+/// never waits for a frame to be drawn and the other way around.
+pub struct Renderer {
+    sender: Mutex<Sender<RenderCommand>>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Renderer {
+    /// Starts the render thread. `draw` is called once per frame and
+    /// `shutdown` right before the thread stops, both on the render thread
+    pub fn start(draw: fn(&mut EffectManager), shutdown: fn(&mut EffectManager)) -> Renderer {
+        let (sender, receiver) = mpsc::channel();
+        let thread = thread::spawn(move || render_loop(receiver, draw, shutdown));
+
+        Renderer {
+            sender: Mutex::new(sender),
+            thread: Mutex::new(Some(thread)),
+        }
+    }
+
+    /// Runs `f` on the render thread and waits for its result, None once the
+    /// thread stopped
+    pub fn run<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut EffectManager) -> T + Send + 'static,
+    ) -> Option<T> {
+        let (reply, result) = mpsc::channel();
+        let command = RenderCommand::Run(Box::new(move |manager| {
+            let _ = reply.send(f(manager));
+        }));
+        self.sender.lock().ok()?.send(command).ok()?;
+
+        result.recv().ok()
+    }
+
+    /// Stops the render thread and waits for it, after its `shutdown` ran
+    pub fn stop(&self) {
+        if let Ok(sender) = self.sender.lock() {
+            let _ = sender.send(RenderCommand::Shutdown);
+        }
+        let thread = self.thread.lock().ok().and_then(|mut thread| thread.take());
+        if let Some(thread) = thread {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn render_loop(
+    receiver: Receiver<RenderCommand>,
+    draw: fn(&mut EffectManager),
+    shutdown: fn(&mut EffectManager),
+) {
+    let mut manager = EffectManager::new();
+    let mut next_frame = Instant::now();
+    loop {
+        let timeout = next_frame.saturating_duration_since(Instant::now());
+        match receiver.recv_timeout(timeout) {
+            Ok(RenderCommand::Run(f)) => f(&mut manager),
+            Ok(RenderCommand::Shutdown) | Err(RecvTimeoutError::Disconnected) => {
+                shutdown(&mut manager);
+                return;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+        // Checked after every command too, so a busy channel doesn't stop the animation
+        if Instant::now() >= next_frame {
+            draw(&mut manager);
+            next_frame = Instant::now() + Duration::from_millis(get_animation_sleep_ms());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kbd::{board, effects, Effect};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static FRAMES: AtomicUsize = AtomicUsize::new(0);
+    static SHUTDOWN_FRAME: Mutex<Option<Vec<u8>>> = Mutex::new(None);
+
+    fn draw(k: &mut EffectManager) {
+        if let Some(frame) = k.render() {
+            k.frame_written(frame);
+        }
+        FRAMES.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Blanks the keyboard like the daemon does on exit
+    fn blank(k: &mut EffectManager) {
+        let mut frame = board::KeyboardData::new();
+        frame.set_kbd_colour(0, 0, 0);
+        k.frame_written(frame);
+        *SHUTDOWN_FRAME.lock().unwrap() = Some(k.get_shown_frame().get_curr_state());
+    }
+
+    #[test]
+    fn commands_from_many_threads_while_drawing() {
+        let _layout = board::test_layout(15, 1);
+        let renderer = Renderer::start(draw, blank);
+        thread::scope(|scope| {
+            for client in 0..4u8 {
+                let renderer = &renderer;
+                scope.spawn(move || {
+                    for _ in 0..50 {
+                        let pushed = renderer.run(move |k| {
+                            k.push_effect(effects::Static::new(vec![client, 0, 0]), vec![true; board::zone_key_count()]);
+                        });
+                        assert!(pushed.is_some());
+                        assert!(renderer.run(|k| k.save()).is_some());
+                        assert!(renderer.run(|k| k.pop_effect()).is_some());
+                    }
+                });
+            }
+        });
+        // Every push was popped again, and the thread still draws frames
+        assert_eq!(renderer.run(|k| k.get_layer_names().len()), Some(0));
+        let frames = FRAMES.load(Ordering::Relaxed);
+        thread::sleep(Duration::from_millis(3 * get_animation_sleep_ms()));
+        assert!(FRAMES.load(Ordering::Relaxed) > frames);
+
+        renderer.stop();
+        assert!(renderer.run(|_| ()).is_none());
+        let blanked = SHUTDOWN_FRAME.lock().unwrap().take().expect("The shutdown didn't run");
+        assert!(blanked.iter().all(|&channel| channel == 0));
+    }
+}
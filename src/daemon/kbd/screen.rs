@@ -0,0 +1,162 @@
+use super::get_millis;
+use lazy_static::lazy_static;
+use log::*;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+/// Time between two screenshots
+const CAPTURE_INTERVAL_MS: u64 = 200;
+
+/// The capture is stopped when no effect asked for a frame for this long
+const IDLE_TIMEOUT_MS: u128 = 5000;
+
+/// Screenshot tools tried in order, each one writes a small PPM image to
+/// stdout: grim for wlroots compositors, ImageMagick's import for X11
+const CAPTURE_COMMANDS: [&[&str]; 2] = [
+    &["grim", "-s", "0.05", "-t", "ppm", "-"],
+    &["import", "-silent", "-window", "root", "-resize", "96x54!", "ppm:-"],
+];
+
+/// A downscaled screenshot, RGB row by row
+#[derive(Clone)]
+pub struct ScreenFrame {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl ScreenFrame {
+    /// Average colour of the part of the screen between the `x` and `y`
+    /// fractions (0 to 1), black for an empty area
+    pub fn average(&self, x: (f32, f32), y: (f32, f32)) -> [u8; 3] {
+        let (x0, x1) = span(x, self.width);
+        let (y0, y1) = span(y, self.height);
+        let mut sum = [0u64; 3];
+        for row in y0..y1 {
+            for col in x0..x1 {
+                let pixel = (row * self.width + col) * 3;
+                for (channel, total) in sum.iter_mut().enumerate() {
+                    *total += self.pixels[pixel + channel] as u64;
+                }
+            }
+        }
+        let count = ((x1 - x0) * (y1 - y0)).max(1) as u64;
+
+        sum.map(|total| (total / count) as u8)
+    }
+}
+
+/// Pixels covered by a (start, end) fraction of `size`, at least one
+fn span(range: (f32, f32), size: usize) -> (usize, usize) {
+    let start = ((range.0.clamp(0.0, 1.0) * size as f32) as usize).min(size.saturating_sub(1));
+    let end = ((range.1.clamp(0.0, 1.0) * size as f32).ceil() as usize).clamp(start + 1, size.max(1));
+    (start, end)
+}
+
+lazy_static! {
+    static ref LATEST_FRAME: Mutex<Option<ScreenFrame>> = Mutex::new(None);
+    static ref LAST_READ_MS: Mutex<u128> = Mutex::new(0);
+}
+
+static CAPTURE_RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Returns the most recent screenshot, None while there is none.
+///
+/// The first call starts taking screenshots in the background. When no tool
+/// can capture the screen (missing, or no access to the display) this keeps
+/// returning None.
+pub fn latest_frame() -> Option<ScreenFrame> {
+    if let Ok(mut last_read) = LAST_READ_MS.lock() {
+        *last_read = get_millis();
+    }
+    if !CAPTURE_RUNNING.swap(true, Ordering::SeqCst) {
+        thread::spawn(capture);
+    }
+    LATEST_FRAME.lock().ok().and_then(|frame| frame.clone())
+}
+
+fn capture() {
+    let mut working: Option<&[&str]> = None;
+    info!("Capturing the screen");
+    loop {
+        let last_read = LAST_READ_MS.lock().map_or(0, |t| *t);
+        if get_millis().saturating_sub(last_read) > IDLE_TIMEOUT_MS {
+            break;
+        }
+
+        // Sticks to the first tool that worked, tries them all again otherwise
+        let frame = match working {
+            Some(command) => screenshot(command),
+            None => CAPTURE_COMMANDS.iter().find_map(|command| {
+                let frame = screenshot(command)?;
+                working = Some(*command);
+                Some(frame)
+            }),
+        };
+        if frame.is_none() && working.take().is_none() {
+            warn!("Could not capture the screen, install grim (wlroots) or ImageMagick (X11)");
+            // Let the next request retry once the idle timeout expired
+            thread::sleep(Duration::from_millis(IDLE_TIMEOUT_MS as u64));
+            break;
+        }
+        if let Ok(mut latest) = LATEST_FRAME.lock() {
+            *latest = frame;
+        }
+        thread::sleep(Duration::from_millis(CAPTURE_INTERVAL_MS));
+    }
+
+    if let Ok(mut latest) = LATEST_FRAME.lock() {
+        *latest = None;
+    }
+    info!("Stopped capturing the screen");
+    CAPTURE_RUNNING.store(false, Ordering::SeqCst);
+}
+
+fn screenshot(command: &[&str]) -> Option<ScreenFrame> {
+    let output = Command::new(command[0])
+        .args(&command[1..])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    parse_ppm(&output.stdout)
+}
+
+/// Reads a binary (P6) PPM image with 8 bit channels
+fn parse_ppm(data: &[u8]) -> Option<ScreenFrame> {
+    // Header: magic, width, height and max value, separated by whitespace
+    // and possibly comments, then a single whitespace before the pixels
+    let mut fields: Vec<&str> = Vec::with_capacity(4);
+    let mut pos = 0;
+    while fields.len() < 4 {
+        while data.get(pos)?.is_ascii_whitespace() {
+            pos += 1;
+        }
+        if data[pos] == b'#' {
+            while *data.get(pos)? != b'\n' {
+                pos += 1;
+            }
+            continue;
+        }
+        let start = pos;
+        while !data.get(pos)?.is_ascii_whitespace() {
+            pos += 1;
+        }
+        fields.push(std::str::from_utf8(&data[start..pos]).ok()?);
+    }
+    let width: usize = fields[1].parse().ok()?;
+    let height: usize = fields[2].parse().ok()?;
+    if fields[0] != "P6" || fields[3] != "255" || width == 0 || height == 0 {
+        return None;
+    }
+    let pixels = data.get(pos + 1..pos + 1 + width * height * 3)?.to_vec();
+
+    Some(ScreenFrame { width, height, pixels })
+}
@@ -0,0 +1,180 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use crate::comms;
+
+/// A request frame of the TCP mode, sent as a single line of JSON
+#[derive(Deserialize)]
+pub(crate) struct RemoteRequest {
+    pub(crate) token: String,
+    pub(crate) command: comms::DaemonCommand,
+}
+
+/// The answer to a request frame, also a single line of JSON
+#[derive(Serialize)]
+pub(crate) enum RemoteResponse {
+    Ok(comms::DaemonResponse),
+    Error(String),
+}
+
+/// Most clients a listener serves at once, others are disconnected right away
+pub(crate) const MAX_CLIENTS: usize = 8;
+/// Longest request line, a longer one ends the connection
+const MAX_REQUEST_BYTES: u64 = 64 * 1024;
+/// How long a client may stay silent before it is disconnected
+const READ_TIMEOUT_MS: u64 = 60_000;
+
+/// Open TCP connections
+static CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Compares a request token with the configured one in a time that doesn't
+/// depend on how many bytes match
+pub(crate) fn token_matches(given: &str, token: &str) -> bool {
+    let (given, token) = (given.as_bytes(), token.as_bytes());
+    let mut diff = given.len() ^ token.len();
+    for (i, byte) in token.iter().enumerate() {
+        diff |= (byte ^ given.get(i).copied().unwrap_or(0)) as usize;
+    }
+    diff == 0
+}
+
+/// A connection counted against `MAX_CLIENTS`, given back when dropped
+pub(crate) struct ClientSlot(&'static AtomicUsize);
+
+impl ClientSlot {
+    /// Takes a slot from `clients`, None if all of them are in use
+    pub(crate) fn take(clients: &'static AtomicUsize) -> Option<ClientSlot> {
+        clients
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| (count < MAX_CLIENTS).then_some(count + 1))
+            .ok()
+            .map(|_| ClientSlot(clients))
+    }
+}
+
+impl Drop for ClientSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Starts listening for JSON commands on `address`. Every request has to
+/// carry `token`, so an empty token refuses to start the listener.
+pub fn start_tcp_listener(
+    address: String,
+    token: String,
+    process: fn(comms::DaemonCommand) -> Option<comms::DaemonResponse>,
+) -> Option<JoinHandle<()>> {
+    if token.is_empty() {
+        error!("TCP mode is enabled but no token is set, not listening on {}", address);
+        return None;
+    }
+    let listener = match TcpListener::bind(&address) {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("Could not listen on {}: {}", address, e);
+            return None;
+        }
+    };
+    info!("Listening for remote commands on {}", address);
+
+    Some(thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let Some(slot) = ClientSlot::take(&CONNECTIONS) else {
+                        warn!("Too many remote clients, dropping a new connection");
+                        continue;
+                    };
+                    let token = token.clone();
+                    thread::spawn(move || {
+                        handle_client(stream, &token, process);
+                        drop(slot);
+                    });
+                }
+                Err(e) => warn!("Remote connection failed: {}", e),
+            }
+        }
+    }))
+}
+
+fn handle_client(
+    stream: TcpStream,
+    token: &str,
+    process: fn(comms::DaemonCommand) -> Option<comms::DaemonResponse>,
+) {
+    let peer = stream.peer_addr().map(|a| a.to_string()).unwrap_or_default();
+    if stream.set_read_timeout(Some(Duration::from_millis(READ_TIMEOUT_MS))).is_err() {
+        return;
+    }
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+    let mut reader = BufReader::new(stream);
+    loop {
+        let mut line = String::new();
+        // Closed, silent for too long or not UTF-8
+        match reader.by_ref().take(MAX_REQUEST_BYTES).read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        if line.len() as u64 >= MAX_REQUEST_BYTES && !line.ends_with('\n') {
+            warn!("Request from {} is too long, disconnecting", peer);
+            if let Ok(json) = serde_json::to_string(&RemoteResponse::Error(String::from("Request too long"))) {
+                let _ = writer.write_all(format!("{}\n", json).as_bytes());
+            }
+            break;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<RemoteRequest>(&line) {
+            Ok(request) if token_matches(&request.token, token) => match process(request.command) {
+                Some(response) => RemoteResponse::Ok(response),
+                None => RemoteResponse::Error(String::from("Command failed")),
+            },
+            Ok(_) => {
+                warn!("Rejected remote command from {} with a wrong token", peer);
+                RemoteResponse::Error(String::from("Invalid token"))
+            }
+            Err(e) => RemoteResponse::Error(format!("Invalid request: {}", e)),
+        };
+        let mut json = match serde_json::to_string(&response) {
+            Ok(json) => json,
+            Err(_) => break,
+        };
+        json.push('\n');
+        if writer.write_all(json.as_bytes()).is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_have_to_match_exactly() {
+        assert!(token_matches("secret", "secret"));
+        assert!(!token_matches("secreT", "secret"));
+        assert!(!token_matches("secret2", "secret"));
+        assert!(!token_matches("secre", "secret"));
+        assert!(!token_matches("", "secret"));
+    }
+
+    #[test]
+    fn client_slots_are_capped_and_given_back() {
+        static CLIENTS: AtomicUsize = AtomicUsize::new(0);
+        let slots: Vec<_> = (0..MAX_CLIENTS).map(|_| ClientSlot::take(&CLIENTS).unwrap()).collect();
+        assert!(ClientSlot::take(&CLIENTS).is_none());
+        drop(slots);
+        assert!(ClientSlot::take(&CLIENTS).is_some());
+    }
+}
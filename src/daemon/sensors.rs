@@ -0,0 +1,253 @@
+use lazy_static::lazy_static;
+use log::*;
+use std::fs;
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Where the kernel exposes the hardware monitoring chips
+const HWMON_DIR: &str = "/sys/class/hwmon";
+
+/// Where the kernel exposes the batteries and chargers
+const POWER_SUPPLY_DIR: &str = "/sys/class/power_supply";
+
+/// hwmon drivers reporting the CPU package temperature
+const CPU_SENSORS: [&str; 3] = ["coretemp", "k10temp", "zenpower"];
+
+/// hwmon drivers reporting the GPU temperature
+const GPU_SENSORS: [&str; 3] = ["nvidia", "nouveau", "amdgpu"];
+
+/// Where the kernel exposes the PCI devices, for the power state of the dGPU
+const PCI_DEVICES_DIR: &str = "/sys/bus/pci/devices";
+
+/// PCI vendor id of NVIDIA
+const NVIDIA_VENDOR: &str = "0x10de";
+
+/// How long a reading of nvidia-smi is reused. Each call keeps the dGPU
+/// awake for a moment, polling it faster would stop it from ever sleeping
+const NVIDIA_SMI_CACHE: Duration = Duration::from_secs(10);
+
+/// Values of `gpu_temperature_source` in the config, anything else is auto
+pub const GPU_SOURCE_NVIDIA_SMI: &str = "nvidia-smi";
+pub const GPU_SOURCE_HWMON: &str = "hwmon";
+
+/// Reported as the source when the dGPU sleeps or no source has a reading
+pub const GPU_SOURCE_POWERED_DOWN: &str = "powered-down";
+pub const GPU_SOURCE_NONE: &str = "none";
+
+lazy_static! {
+    /// The sensors are resolved once, hwmon chips don't come and go while running
+    static ref CPU_HWMON_PATH: Option<String> = find_hwmon(&CPU_SENSORS);
+    static ref GPU_HWMON_PATH: Option<String> = find_hwmon(&GPU_SENSORS);
+    static ref FAN_HWMON_PATH: Option<String> = find_fan_hwmon();
+    /// PCI device directory of the NVIDIA GPU, None without one
+    static ref NVIDIA_GPU_PATH: Option<String> = find_nvidia_gpu();
+    /// Last nvidia-smi reading and when it was taken
+    static ref NVIDIA_SMI_READING: Mutex<Option<(Instant, Option<f32>)>> = Mutex::new(None);
+}
+
+/// Returns the directory of the first hwmon chip whose name is in `names`
+fn find_hwmon(names: &[&str]) -> Option<String> {
+    let entries = fs::read_dir(HWMON_DIR).ok()?;
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        let Ok(name) = fs::read_to_string(path.join("name")) else {
+            continue;
+        };
+        if names.contains(&name.trim()) {
+            info!("Found {} temperature sensor at {}", name.trim(), path.display());
+            return Some(path.to_string_lossy().to_string());
+        }
+    }
+    warn!("No temperature sensor found among {:?}", names);
+
+    None
+}
+
+/// Returns the directory of the hwmon chip reporting the fan tachometers,
+/// preferring the one of the razer driver over ACPI or EC ones
+fn find_fan_hwmon() -> Option<String> {
+    let mut entries: Vec<_> = fs::read_dir(HWMON_DIR)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|path| path.join("fan1_input").exists())
+        .collect();
+    entries.sort();
+    let razer = entries.iter().position(|path| {
+        fs::read_to_string(path.join("name")).is_ok_and(|n| n.to_lowercase().contains("razer"))
+    });
+    let path = match razer {
+        Some(index) => entries.swap_remove(index),
+        None => entries.into_iter().next()?,
+    };
+    info!("Found fan tachometers at {}", path.display());
+
+    Some(path.to_string_lossy().to_string())
+}
+
+/// Reads the first temperature input of a hwmon chip in degrees celsius
+fn read_temperature(hwmon: &Option<String>) -> Option<f32> {
+    let dir = hwmon.as_ref()?;
+    let raw = fs::read_to_string(format!("{}/temp1_input", dir)).ok()?;
+    let millidegrees: i32 = raw.trim().parse().ok()?;
+
+    Some(millidegrees as f32 / 1000.0)
+}
+
+/// Reads the CPU temperature in degrees celsius
+pub fn read_cpu_temperature() -> Option<f32> {
+    read_temperature(&CPU_HWMON_PATH)
+}
+
+/// Returns the PCI device directory of the first NVIDIA display controller
+fn find_nvidia_gpu() -> Option<String> {
+    let mut entries: Vec<_> = fs::read_dir(PCI_DEVICES_DIR)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .collect();
+    entries.sort();
+    let path = entries.into_iter().find(|path| {
+        let vendor = fs::read_to_string(path.join("vendor")).unwrap_or_default();
+        let class = fs::read_to_string(path.join("class")).unwrap_or_default();
+        // Class 0x03 covers VGA and 3D controllers
+        vendor.trim() == NVIDIA_VENDOR && class.trim().starts_with("0x03")
+    })?;
+    info!("Found NVIDIA GPU at {}", path.display());
+
+    Some(path.to_string_lossy().to_string())
+}
+
+/// True when the NVIDIA dGPU is runtime suspended, reading it would wake it
+fn nvidia_gpu_suspended() -> bool {
+    NVIDIA_GPU_PATH.as_ref().is_some_and(|dir| {
+        fs::read_to_string(format!("{}/power/runtime_status", dir)).is_ok_and(|status| status.trim() == "suspended")
+    })
+}
+
+/// Asks nvidia-smi for the temperature of the first GPU, reusing a reading
+/// younger than `NVIDIA_SMI_CACHE`
+fn read_nvidia_smi_temperature() -> Option<f32> {
+    let mut reading = NVIDIA_SMI_READING.lock().ok()?;
+    if let Some((_, celsius)) = reading.filter(|(taken, _)| taken.elapsed() < NVIDIA_SMI_CACHE) {
+        return celsius;
+    }
+    let celsius = Command::new("nvidia-smi")
+        .args(["--query-gpu=temperature.gpu", "--format=csv,noheader,nounits"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8_lossy(&output.stdout).lines().next()?.trim().parse().ok());
+    *reading = Some((Instant::now(), celsius));
+
+    celsius
+}
+
+/// Reads the GPU temperature in degrees celsius from `source`, one of the
+/// `GPU_SOURCE_*` values or auto (nvidia-smi, then hwmon). Returns the
+/// source the reading came from, `GPU_SOURCE_POWERED_DOWN` without a
+/// reading while the NVIDIA dGPU sleeps and `GPU_SOURCE_NONE` otherwise
+pub fn read_gpu_temperature(source: &str) -> (Option<f32>, &'static str) {
+    if source == GPU_SOURCE_HWMON {
+        return match read_temperature(&GPU_HWMON_PATH) {
+            Some(celsius) => (Some(celsius), GPU_SOURCE_HWMON),
+            None => (None, GPU_SOURCE_NONE),
+        };
+    }
+    if nvidia_gpu_suspended() {
+        return (None, GPU_SOURCE_POWERED_DOWN);
+    }
+    // nvidia-smi is only asked when there is an NVIDIA GPU to ask about
+    if let Some(celsius) = NVIDIA_GPU_PATH.as_ref().and_then(|_| read_nvidia_smi_temperature()) {
+        return (Some(celsius), GPU_SOURCE_NVIDIA_SMI);
+    }
+    let hwmon = if source == GPU_SOURCE_NVIDIA_SMI { None } else { read_temperature(&GPU_HWMON_PATH) };
+    match hwmon {
+        Some(celsius) => (Some(celsius), GPU_SOURCE_HWMON),
+        None => (None, GPU_SOURCE_NONE),
+    }
+}
+
+/// Reads the measured speed of a fan (1 or 2) in RPM, -1 when there is no
+/// such fan
+pub fn read_fan_rpm(fan: u8) -> i32 {
+    let Some(dir) = FAN_HWMON_PATH.as_ref() else {
+        return -1;
+    };
+    fs::read_to_string(format!("{}/fan{}_input", dir, fan))
+        .ok()
+        .and_then(|raw| raw.trim().parse().ok())
+        .unwrap_or(-1)
+}
+
+/// Returns the directory of the first battery, which is not always BAT0
+fn find_battery() -> Option<String> {
+    let mut entries: Vec<_> = fs::read_dir(POWER_SUPPLY_DIR)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .collect();
+    entries.sort();
+    entries
+        .into_iter()
+        .find(|path| {
+            fs::read_to_string(path.join("type")).is_ok_and(|t| t.trim() == "Battery")
+        })
+        .map(|path| path.to_string_lossy().to_string())
+}
+
+/// Reads the battery charge percentage and whether it is charging
+pub fn read_battery_state() -> Option<(u8, bool)> {
+    let dir = find_battery()?;
+    let capacity: u8 = fs::read_to_string(format!("{}/capacity", dir)).ok()?.trim().parse().ok()?;
+    let status = fs::read_to_string(format!("{}/status", dir)).ok()?;
+
+    Some((capacity, status.trim() == "Charging"))
+}
+
+/// Wear of the battery. The capacities are in mAh, or in mWh for the
+/// batteries the kernel reports energy for
+pub struct BatteryHealth {
+    /// None when the firmware doesn't count the cycles
+    pub cycle_count: Option<u32>,
+    pub design_capacity: u32,
+    pub full_capacity: u32,
+    pub unit: &'static str,
+}
+
+impl BatteryHealth {
+    /// Full charge capacity in percent of the design capacity
+    pub fn health_percent(&self) -> f32 {
+        self.full_capacity as f32 * 100.0 / self.design_capacity as f32
+    }
+}
+
+fn read_battery_value(dir: &str, node: &str) -> Option<u64> {
+    fs::read_to_string(format!("{}/{}", dir, node)).ok()?.trim().parse().ok()
+}
+
+/// Reads the wear of the first battery, None when it doesn't report its
+/// capacities. Depending on the battery the kernel has charge_* nodes in
+/// µAh or energy_* nodes in µWh
+pub fn read_battery_health() -> Option<BatteryHealth> {
+    let dir = find_battery()?;
+    let (prefix, unit) = match read_battery_value(&dir, "charge_full") {
+        Some(_) => ("charge", "mAh"),
+        None => ("energy", "mWh"),
+    };
+    let design_capacity = read_battery_value(&dir, &format!("{}_full_design", prefix))? / 1000;
+    let full_capacity = read_battery_value(&dir, &format!("{}_full", prefix))? / 1000;
+    if design_capacity == 0 {
+        return None;
+    }
+    // 0 is what most firmwares report when they don't count
+    let cycle_count = read_battery_value(&dir, "cycle_count").filter(|count| *count > 0);
+
+    Some(BatteryHealth {
+        cycle_count: cycle_count.map(|count| count as u32),
+        design_capacity: design_capacity as u32,
+        full_capacity: full_capacity as u32,
+        unit,
+    })
+}
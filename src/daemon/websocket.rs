@@ -0,0 +1,263 @@
+//! Optional WebSocket server for browser control panels. Every text message
+//! is a request frame of the TCP mode and is answered the same way, events
+//! such as plugging in are pushed to the clients that sent a valid token.
+//! Only pages served from this machine can connect, other origins are
+//! refused during the handshake. Needs the `websocket` feature and
+//! `websocket_enabled` in the config.
+
+use serde::Serialize;
+
+use crate::comms;
+
+/// Pushed to the clients without a request, as `{"Event": ...}`
+#[derive(Serialize, Clone, Debug)]
+pub enum Event {
+    /// The settings of this power source are in use now
+    PowerSource { ac: bool },
+    /// New battery charge in percent
+    Battery { capacity: u8 },
+}
+
+#[cfg(feature = "websocket")]
+mod server {
+    use super::*;
+    use crate::remote::{token_matches, ClientSlot, RemoteRequest, RemoteResponse};
+    use log::*;
+    use std::io::ErrorKind;
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::mpsc::{self, Receiver, Sender};
+    use std::sync::Mutex;
+    use std::thread::{self, JoinHandle};
+    use std::time::{Duration, Instant};
+    use tungstenite::handshake::server::{ErrorResponse, Request, Response};
+    use tungstenite::http::StatusCode;
+    use tungstenite::{Message, WebSocket};
+
+    /// How long a client waits for a request before sending the pending events
+    const EVENT_POLL_MS: u64 = 200;
+    /// How long the handshake and the first valid request may take before
+    /// the client is dropped
+    const HANDSHAKE_TIMEOUT_MS: u64 = 10_000;
+
+    /// Event queues of the clients that sent a valid token
+    static CLIENTS: Mutex<Vec<Sender<String>>> = Mutex::new(Vec::new());
+    /// Open connections, authenticated or not
+    static CONNECTIONS: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Serialize)]
+    enum Push {
+        Event(Event),
+    }
+
+    pub fn start(
+        address: String,
+        token: String,
+        process: fn(comms::DaemonCommand) -> Option<comms::DaemonResponse>,
+    ) -> Option<JoinHandle<()>> {
+        if token.is_empty() {
+            error!("WebSocket mode is enabled but no token is set, not listening on {}", address);
+            return None;
+        }
+        let listener = match TcpListener::bind(&address) {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("Could not listen on {}: {}", address, e);
+                return None;
+            }
+        };
+        info!("Listening for WebSocket clients on {}", address);
+
+        Some(thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let Some(slot) = ClientSlot::take(&CONNECTIONS) else {
+                            warn!("Too many WebSocket clients, dropping a new connection");
+                            continue;
+                        };
+                        let token = token.clone();
+                        thread::spawn(move || {
+                            handle_client(stream, &token, process);
+                            drop(slot);
+                        });
+                    }
+                    Err(e) => warn!("WebSocket connection failed: {}", e),
+                }
+            }
+        }))
+    }
+
+    fn handle_client(
+        stream: TcpStream,
+        token: &str,
+        process: fn(comms::DaemonCommand) -> Option<comms::DaemonResponse>,
+    ) {
+        let peer = stream.peer_addr().map(|a| a.to_string()).unwrap_or_default();
+        let handshake_timeout = Some(Duration::from_millis(HANDSHAKE_TIMEOUT_MS));
+        if stream.set_read_timeout(handshake_timeout).is_err() || stream.set_write_timeout(handshake_timeout).is_err() {
+            return;
+        }
+        let mut socket = match tungstenite::accept_hdr(stream, check_origin) {
+            Ok(socket) => socket,
+            Err(e) => {
+                warn!("WebSocket handshake with {} failed: {}", peer, e);
+                return;
+            }
+        };
+        // Lets the loop below wake up for the events between two requests
+        if socket.get_ref().set_read_timeout(Some(Duration::from_millis(EVENT_POLL_MS))).is_err() {
+            return;
+        }
+
+        let mut client = Client { events: None, connected: Instant::now() };
+        while serve(&mut socket, &mut client, token, &peer, process) {}
+        let _ = socket.close(None);
+    }
+
+    /// Refuses the handshake of pages that weren't served from this machine,
+    /// clients without an Origin header aren't browsers and are let through.
+    /// The error type is the one tungstenite expects from the callback
+    #[allow(clippy::result_large_err)]
+    fn check_origin(request: &Request, response: Response) -> Result<Response, ErrorResponse> {
+        let Some(origin) = request.headers().get("Origin") else {
+            return Ok(response);
+        };
+        if origin.to_str().is_ok_and(local_origin) {
+            return Ok(response);
+        }
+        warn!("Refused a WebSocket client from origin {:?}", origin);
+        let mut error = ErrorResponse::new(Some(String::from("Origin not allowed")));
+        *error.status_mut() = StatusCode::FORBIDDEN;
+        Err(error)
+    }
+
+    /// Whether an Origin header names a page on localhost, any scheme or port
+    fn local_origin(origin: &str) -> bool {
+        let Some((_, host)) = origin.split_once("://") else {
+            return false;
+        };
+        let host = match host.strip_prefix('[') {
+            Some(ipv6) => ipv6.split(']').next().unwrap_or_default(),
+            None => host.split([':', '/']).next().unwrap_or_default(),
+        };
+        matches!(host, "localhost" | "127.0.0.1" | "::1")
+    }
+
+    /// State of a connection, it only gets the events once it sent a valid
+    /// token
+    struct Client {
+        events: Option<Receiver<String>>,
+        connected: Instant,
+    }
+
+    /// Answers the next request or sends the pending events, false once the
+    /// client is gone or didn't send a valid request in time
+    fn serve(
+        socket: &mut WebSocket<TcpStream>,
+        client: &mut Client,
+        token: &str,
+        peer: &str,
+        process: fn(comms::DaemonCommand) -> Option<comms::DaemonResponse>,
+    ) -> bool {
+        if let Some(events) = &client.events {
+            for event in events.try_iter() {
+                if socket.send(Message::Text(event)).is_err() {
+                    return false;
+                }
+            }
+        } else if client.connected.elapsed() > Duration::from_millis(HANDSHAKE_TIMEOUT_MS) {
+            warn!("WebSocket client {} sent no valid token in time", peer);
+            return false;
+        }
+        let text = match socket.read() {
+            Ok(Message::Text(text)) => text,
+            Ok(Message::Close(_)) => return false,
+            // Pings are answered by tungstenite, binary frames aren't used
+            Ok(_) => return true,
+            Err(tungstenite::Error::Io(e))
+                if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut =>
+            {
+                return true;
+            }
+            Err(_) => return false,
+        };
+        let response = match serde_json::from_str::<RemoteRequest>(&text) {
+            Ok(request) if token_matches(&request.token, token) => {
+                if client.events.is_none() {
+                    let (sender, events) = mpsc::channel();
+                    if let Ok(mut clients) = CLIENTS.lock() {
+                        clients.push(sender);
+                    }
+                    client.events = Some(events);
+                }
+                match process(request.command) {
+                    Some(response) => RemoteResponse::Ok(response),
+                    None => RemoteResponse::Error(String::from("Command failed")),
+                }
+            }
+            Ok(_) => {
+                warn!("Rejected WebSocket command from {} with a wrong token", peer);
+                RemoteResponse::Error(String::from("Invalid token"))
+            }
+            Err(e) => RemoteResponse::Error(format!("Invalid request: {}", e)),
+        };
+        match serde_json::to_string(&response) {
+            Ok(json) => socket.send(Message::Text(json)).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    pub fn broadcast(event: Event) {
+        let Ok(json) = serde_json::to_string(&Push::Event(event)) else {
+            return;
+        };
+        if let Ok(mut clients) = CLIENTS.lock() {
+            // The queues of the clients that left are dropped here
+            clients.retain(|client| client.send(json.clone()).is_ok());
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn only_local_origins_are_allowed() {
+            for origin in ["http://localhost", "http://localhost:8080", "https://127.0.0.1:3000", "http://[::1]:80"] {
+                assert!(local_origin(origin), "{}", origin);
+            }
+            for origin in ["null", "http://example.com", "http://localhost.example.com", "http://127.0.0.2", "localhost"] {
+                assert!(!local_origin(origin), "{}", origin);
+            }
+        }
+    }
+}
+
+/// Starts the WebSocket server on `address`. Like the TCP mode, every request
+/// has to carry `token` and an empty token refuses to start the server.
+#[cfg(feature = "websocket")]
+pub fn start(
+    address: String,
+    token: String,
+    process: fn(comms::DaemonCommand) -> Option<comms::DaemonResponse>,
+) {
+    server::start(address, token, process);
+}
+
+#[cfg(not(feature = "websocket"))]
+pub fn start(
+    _address: String,
+    _token: String,
+    _process: fn(comms::DaemonCommand) -> Option<comms::DaemonResponse>,
+) {
+    log::warn!("The WebSocket server is enabled but the daemon was built without the websocket feature");
+}
+
+/// Sends an event to every connected WebSocket client
+pub fn broadcast(event: Event) {
+    #[cfg(feature = "websocket")]
+    server::broadcast(event);
+    #[cfg(not(feature = "websocket"))]
+    let _ = event;
+}
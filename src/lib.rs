@@ -11,6 +11,13 @@ pub struct SupportedDevice {
     pub pid: String,
     pub features: Vec<String>,
     pub fan: Vec<u16>,
+    /// Smallest fan speed change in RPM the device accepts
+    #[serde(default = "default_fan_step")]
+    pub fan_step: u16,
+}
+
+fn default_fan_step() -> u16 {
+    100
 }
 
 impl SupportedDevice {
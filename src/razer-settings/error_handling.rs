@@ -19,13 +19,13 @@ impl<T> Crash for Option<T> {
     }
 }
 
-impl<T, E> Crash for Result<T, E> {
+impl<T, E: std::fmt::Display> Crash for Result<T, E> {
     type Value = T;
 
     fn or_crash(self, msg: impl AsRef<str>) -> Self::Value {
         match self {
             Self::Ok(v) => v,
-            Self::Err(_) => crash_with_msg(msg),
+            Self::Err(e) => crash_with_msg(format!("{}: {}", msg.as_ref(), e)),
         }
     }
 }
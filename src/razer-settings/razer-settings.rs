@@ -1,12 +1,14 @@
+use std::cell::RefCell;
 use std::io::ErrorKind;
+use std::rc::Rc;
 
 use adw::prelude::{self, *};
 use adw::{
-    ActionRow, Application, ApplicationWindow, ButtonRow, ComboRow, HeaderBar, PreferencesGroup,
+    ActionRow, Application, ApplicationWindow, ButtonRow, ComboRow, EntryRow, HeaderBar, PreferencesGroup,
     PreferencesPage, SpinRow, SwitchRow, ToolbarView, ViewStack, ViewSwitcher, WindowTitle,
 };
 use dbus::arg::Append;
-use gtk::gdk::Display;
+use gtk::gdk::{Display, RGBA};
 use gtk::{
     Adjustment, Button, ColorDialog, ColorDialogButton, IconTheme, License, Scale, StringList,
 };
@@ -32,122 +34,314 @@ const NAME: &str = env!("CARGO_PKG_NAME");
 const AUTHORS: &str = env!("CARGO_PKG_AUTHORS");
 const PARENT_PATH: &str = env!("CARGO_MANIFEST_DIR");
 
-fn send_data(opt: comms::DaemonCommand) -> Option<comms::DaemonResponse> {
-    match comms::try_bind() {
-        Ok(socket) => comms::send_to_daemon(opt, socket),
+/// Frames an effect runs for before its preview is taken
+const PREVIEW_FRAMES: u8 = 10;
+
+/// Sends a command to the daemon, turning its `Error` response into the `Err`
+/// case so `or_crash` can show what went wrong
+fn send_data(opt: comms::DaemonCommand) -> Result<comms::DaemonResponse, String> {
+    let socket = match comms::try_bind() {
+        Ok(socket) => socket,
         Err(error) if error.kind() == ErrorKind::NotFound => {
             crash_with_msg("Can't connect to the daemon");
         }
-        Err(error) => {
-            println!("Error opening socket: {error}");
-            None
-        }
+        Err(error) => return Err(format!("Error opening socket: {error}")),
+    };
+    match comms::send_to_daemon(opt, socket) {
+        Ok(comms::DaemonResponse::Error { message }) => Err(message),
+        Ok(response) => Ok(response),
+        Err(comms::SendError::Timeout) => Err("The daemon did not answer in time, it may be stuck writing to the laptop".into()),
+        Err(comms::SendError::Failed) => Err("The daemon did not answer".into()),
     }
 }
 
-fn get_device_name() -> Option<String> {
+fn get_device_name() -> Result<String, String> {
     let response = send_data(comms::DaemonCommand::GetDeviceName)?;
 
     use comms::DaemonResponse::*;
     match response {
-        GetDeviceName { name } => Some(name),
+        GetDeviceName { name } => Ok(name),
+        response => {
+            // This should not happen
+            Err(format!("Instead of GetDeviceName got {response:?}"))
+        }
+    }
+}
+
+/// Asks the daemon which features the laptop it matched has, with the USB
+/// ids it matched it by
+fn get_supported_device(name: &str) -> Result<lib::SupportedDevice, String> {
+    let response = send_data(comms::DaemonCommand::GetSupportedFeatures)?;
+    // Older daemons don't know GetMatchInfo
+    let (vid, pid) = match get_match_info().ok().and_then(|info| info.ids) {
+        Some((vendor_id, product_id)) => (format!("{vendor_id:04x}"), format!("{product_id:04x}")),
+        None => (String::new(), String::new()),
+    };
+
+    use comms::DaemonResponse::*;
+    match response {
+        GetSupportedFeatures { features, fan_min, fan_max, fan_step, .. } => Ok(lib::SupportedDevice {
+            name: name.into(),
+            vid,
+            pid,
+            features,
+            fan: vec![fan_min, fan_max],
+            fan_step,
+        }),
+        response => {
+            // This should not happen
+            Err(format!("Instead of GetSupportedFeatures got {response:?}"))
+        }
+    }
+}
+
+/// Name and serial number of the connected devices, with the index of the
+/// one the settings apply to
+fn list_devices() -> Result<(Vec<(String, String)>, usize), String> {
+    let response = send_data(comms::DaemonCommand::ListDevices)?;
+
+    use comms::DaemonResponse::*;
+    match response {
+        ListDevices { devices, selected } => Ok((devices, selected)),
+        response => {
+            // This should not happen
+            Err(format!("Instead of ListDevices got {response:?}"))
+        }
+    }
+}
+
+fn select_device(index: usize) -> Result<bool, String> {
+    let response = send_data(comms::DaemonCommand::SelectDevice { index })?;
+
+    use comms::DaemonResponse::*;
+    match response {
+        SelectDevice { result } => Ok(result),
         response => {
             // This should not happen
-            println!("Instead of GetDeviceName got {response:?}");
-            None
+            Err(format!("Instead of SelectDevice got {response:?}"))
         }
     }
 }
 
-fn get_bho() -> Option<(bool, u8)> {
+/// Power source the daemon reported, None when it doesn't know
+fn get_power_source() -> Result<Option<bool>, String> {
+    let response = send_data(comms::DaemonCommand::GetPowerSource)?;
+
+    use comms::DaemonResponse::*;
+    match response {
+        GetPowerSource { source } => Ok(match source.as_str() {
+            "AC" => Some(true),
+            "BAT" => Some(false),
+            _ => None,
+        }),
+        response => {
+            // This should not happen
+            Err(format!("Instead of GetPowerSource got {response:?}"))
+        }
+    }
+}
+
+fn format_power_source(ac: Option<bool>) -> &'static str {
+    match ac {
+        Some(true) => "On AC power",
+        Some(false) => "On battery",
+        None => "",
+    }
+}
+
+/// Entry of the laptops file the daemon matched the laptop with
+struct MatchInfo {
+    matched: bool,
+    /// USB ids, None when simulated or not matched
+    ids: Option<(u16, u16)>,
+}
+
+fn get_match_info() -> Result<MatchInfo, String> {
+    let response = send_data(comms::DaemonCommand::GetMatchInfo)?;
+
+    use comms::DaemonResponse::*;
+    match response {
+        GetMatchInfo { name, vendor_id, product_id, simulated, .. } => {
+            let ids = vendor_id.zip(product_id).filter(|_| !simulated);
+            Ok(MatchInfo { matched: name.is_some(), ids })
+        }
+        response => {
+            // This should not happen
+            Err(format!("Instead of GetMatchInfo got {response:?}"))
+        }
+    }
+}
+
+/// Details of the device the daemon currently sends the settings to
+fn get_current_device() -> lib::SupportedDevice {
+    let device_name = get_device_name().or_crash("Failed to get device name");
+    get_supported_device(&device_name).or_crash("Failed to get device info")
+}
+
+fn get_bho() -> Result<(bool, u8), String> {
     let response = send_data(comms::DaemonCommand::GetBatteryHealthOptimizer())?;
 
     use comms::DaemonResponse::*;
     match response {
-        GetBatteryHealthOptimizer { is_on, threshold } => Some((is_on, threshold)),
+        GetBatteryHealthOptimizer { is_on, threshold } => Ok((is_on, threshold)),
         response => {
             // This should not happen
-            println!("Instead of GetBatteryHealthOptimizer got {response:?}");
-            None
+            Err(format!("Instead of GetBatteryHealthOptimizer got {response:?}"))
         }
     }
 }
 
-fn set_bho(is_on: bool, threshold: u8) -> Option<bool> {
+fn set_bho(is_on: bool, threshold: u8) -> Result<bool, String> {
     let response = send_data(comms::DaemonCommand::SetBatteryHealthOptimizer { is_on, threshold })?;
 
     use comms::DaemonResponse::*;
     match response {
-        SetBatteryHealthOptimizer { result } => Some(result),
+        SetBatteryHealthOptimizer { result } => Ok(result),
         response => {
             // This should not happen
-            println!("Instead of SetBatteryHealthOptimizer got {response:?}");
-            None
+            Err(format!("Instead of SetBatteryHealthOptimizer got {response:?}"))
         }
     }
 }
 
-fn get_brightness(ac: bool) -> Option<u8> {
+fn get_white_balance() -> Result<[u16; 3], String> {
+    let response = send_data(comms::DaemonCommand::GetWhiteBalance)?;
+
+    use comms::DaemonResponse::*;
+    match response {
+        GetWhiteBalance { r_mul, g_mul, b_mul } => Ok([r_mul, g_mul, b_mul]),
+        response => {
+            // This should not happen
+            Err(format!("Instead of GetWhiteBalance got {response:?}"))
+        }
+    }
+}
+
+fn set_white_balance(balance: [u16; 3]) -> Result<bool, String> {
+    let [r_mul, g_mul, b_mul] = balance;
+    let response = send_data(comms::DaemonCommand::SetWhiteBalance { r_mul, g_mul, b_mul })?;
+
+    use comms::DaemonResponse::*;
+    match response {
+        SetWhiteBalance { result } => Ok(result),
+        response => {
+            // This should not happen
+            Err(format!("Instead of SetWhiteBalance got {response:?}"))
+        }
+    }
+}
+
+fn get_brightness(ac: bool) -> Result<u8, String> {
     let ac = if ac { 1 } else { 0 };
     let response = send_data(comms::DaemonCommand::GetBrightness { ac })?;
 
     use comms::DaemonResponse::*;
     match response {
-        GetBrightness { result } => Some(result),
+        GetBrightness { result } => Ok(result),
         response => {
             // This should not happen
-            println!("Instead of GetBrightness got {response:?}");
-            None
+            Err(format!("Instead of GetBrightness got {response:?}"))
         }
     }
 }
 
-fn set_brightness(ac: bool, val: u8) -> Option<bool> {
+fn set_brightness(ac: bool, val: u8) -> Result<bool, String> {
     let ac = if ac { 1 } else { 0 };
     let response = send_data(comms::DaemonCommand::SetBrightness { ac, val })?;
 
     use comms::DaemonResponse::*;
     match response {
-        SetBrightness { result } => Some(result),
+        SetBrightness { result } => Ok(result),
         response => {
             // This should not happen
-            println!("Instead of SetBrightness got {response:?}");
-            None
+            Err(format!("Instead of SetBrightness got {response:?}"))
         }
     }
 }
 
-fn get_logo(ac: bool) -> Option<u8> {
+fn get_logo(ac: bool) -> Result<u8, String> {
     let ac = if ac { 1 } else { 0 };
     let response = send_data(comms::DaemonCommand::GetLogoLedState { ac })?;
 
     use comms::DaemonResponse::*;
     match response {
-        GetLogoLedState { logo_state } => Some(logo_state),
+        GetLogoLedState { logo_state } => Ok(logo_state),
         response => {
             // This should not happen
-            println!("Instead of GetLogoLedState got {response:?}");
-            None
+            Err(format!("Instead of GetLogoLedState got {response:?}"))
         }
     }
 }
 
-fn set_logo(ac: bool, logo_state: u8) -> Option<bool> {
+fn set_logo(ac: bool, logo_state: u8) -> Result<bool, String> {
     let ac = if ac { 1 } else { 0 };
     let response = send_data(comms::DaemonCommand::SetLogoLedState { ac, logo_state })?;
 
     use comms::DaemonResponse::*;
     match response {
-        SetLogoLedState { result } => Some(result),
+        SetLogoLedState { result } => Ok(result),
         response => {
             // This should not happen
-            println!("Instead of SetLogoLedState got {response:?}");
-            None
+            Err(format!("Instead of SetLogoLedState got {response:?}"))
         }
     }
 }
 
-fn set_effect(name: &str, values: Vec<u8>) -> Option<bool> {
+fn get_logo_breathing_speed(ac: bool) -> Result<u8, String> {
+    let ac = if ac { 1 } else { 0 };
+    let response = send_data(comms::DaemonCommand::GetLogoBreathingSpeed { ac })?;
+
+    use comms::DaemonResponse::*;
+    match response {
+        GetLogoBreathingSpeed { speed } => Ok(speed),
+        response => {
+            // This should not happen
+            Err(format!("Instead of GetLogoBreathingSpeed got {response:?}"))
+        }
+    }
+}
+
+fn get_logo_sync() -> Result<bool, String> {
+    let response = send_data(comms::DaemonCommand::GetLogoSync)?;
+
+    use comms::DaemonResponse::*;
+    match response {
+        GetLogoSync { enabled } => Ok(enabled),
+        response => {
+            // This should not happen
+            Err(format!("Instead of GetLogoSync got {response:?}"))
+        }
+    }
+}
+
+fn set_logo_sync(enabled: bool) -> Result<bool, String> {
+    let response = send_data(comms::DaemonCommand::SetLogoSync { enabled })?;
+
+    use comms::DaemonResponse::*;
+    match response {
+        SetLogoSync { result } => Ok(result),
+        response => {
+            // This should not happen
+            Err(format!("Instead of SetLogoSync got {response:?}"))
+        }
+    }
+}
+
+fn set_logo_breathing_speed(ac: bool, speed: u8) -> Result<bool, String> {
+    let ac = if ac { 1 } else { 0 };
+    let response = send_data(comms::DaemonCommand::SetLogoBreathingSpeed { ac, speed })?;
+
+    use comms::DaemonResponse::*;
+    match response {
+        SetLogoBreathingSpeed { result } => Ok(result),
+        response => {
+            // This should not happen
+            Err(format!("Instead of SetLogoBreathingSpeed got {response:?}"))
+        }
+    }
+}
+
+fn set_effect(name: &str, values: Vec<u8>) -> Result<bool, String> {
     let response = send_data(comms::DaemonCommand::SetEffect {
         name: name.into(),
         params: values,
@@ -155,16 +349,185 @@ fn set_effect(name: &str, values: Vec<u8>) -> Option<bool> {
 
     use comms::DaemonResponse::*;
     match response {
-        SetEffect { result } => Some(result),
+        SetEffect { result, .. } => Ok(result),
+        response => {
+            // This should not happen
+            Err(format!("Instead of SetEffect got {response:?}"))
+        }
+    }
+}
+
+fn get_effect() -> Result<(String, Vec<u8>), String> {
+    let response = send_data(comms::DaemonCommand::GetEffect)?;
+
+    use comms::DaemonResponse::*;
+    match response {
+        GetEffect { name, params } => Ok((name, params)),
+        response => {
+            // This should not happen
+            Err(format!("Instead of GetEffect got {response:?}"))
+        }
+    }
+}
+
+fn push_effect_layer_region(name: &str, params: Vec<u8>, region: &str) -> Result<bool, String> {
+    let response = send_data(comms::DaemonCommand::PushEffectLayerRegion {
+        name: name.into(),
+        params,
+        region: region.into(),
+    })?;
+
+    use comms::DaemonResponse::*;
+    match response {
+        PushEffectLayer { result } => Ok(result),
+        response => {
+            // This should not happen
+            Err(format!("Instead of PushEffectLayer got {response:?}"))
+        }
+    }
+}
+
+fn get_regions() -> Result<Vec<String>, String> {
+    let response = send_data(comms::DaemonCommand::GetRegions)?;
+
+    use comms::DaemonResponse::*;
+    match response {
+        GetRegions { names } => Ok(names),
+        response => {
+            // This should not happen
+            Err(format!("Instead of GetRegions got {response:?}"))
+        }
+    }
+}
+
+fn pop_effect_layer() -> Result<bool, String> {
+    let response = send_data(comms::DaemonCommand::PopEffectLayer)?;
+
+    use comms::DaemonResponse::*;
+    match response {
+        PopEffectLayer { result } => Ok(result),
+        response => {
+            // This should not happen
+            Err(format!("Instead of PopEffectLayer got {response:?}"))
+        }
+    }
+}
+
+fn get_effect_layers() -> Result<Vec<String>, String> {
+    let response = send_data(comms::DaemonCommand::GetEffectLayers)?;
+
+    use comms::DaemonResponse::*;
+    match response {
+        GetEffectLayers { names } => Ok(names),
+        response => {
+            // This should not happen
+            Err(format!("Instead of GetEffectLayers got {response:?}"))
+        }
+    }
+}
+
+fn save_effect_preset(name: &str) -> Result<bool, String> {
+    let response = send_data(comms::DaemonCommand::SaveEffectPreset { name: name.into() })?;
+
+    use comms::DaemonResponse::*;
+    match response {
+        SaveEffectPreset { result } => Ok(result),
+        response => {
+            // This should not happen
+            Err(format!("Instead of SaveEffectPreset got {response:?}"))
+        }
+    }
+}
+
+fn load_effect_preset(name: &str) -> Result<bool, String> {
+    let response = send_data(comms::DaemonCommand::LoadEffectPreset { name: name.into() })?;
+
+    use comms::DaemonResponse::*;
+    match response {
+        LoadEffectPreset { result } => Ok(result),
+        response => {
+            // This should not happen
+            Err(format!("Instead of LoadEffectPreset got {response:?}"))
+        }
+    }
+}
+
+fn list_effect_presets() -> Result<Vec<String>, String> {
+    let response = send_data(comms::DaemonCommand::ListEffectPresets)?;
+
+    use comms::DaemonResponse::*;
+    match response {
+        ListEffectPresets { names } => Ok(names),
+        response => {
+            // This should not happen
+            Err(format!("Instead of ListEffectPresets got {response:?}"))
+        }
+    }
+}
+
+fn switch_profile(name: &str) -> Result<bool, String> {
+    let response = send_data(comms::DaemonCommand::SwitchProfile { name: name.into() })?;
+
+    use comms::DaemonResponse::*;
+    match response {
+        SwitchProfile { result } => Ok(result),
+        response => {
+            // This should not happen
+            Err(format!("Instead of SwitchProfile got {response:?}"))
+        }
+    }
+}
+
+fn list_profiles() -> Result<Vec<String>, String> {
+    let response = send_data(comms::DaemonCommand::ListProfiles)?;
+
+    use comms::DaemonResponse::*;
+    match response {
+        ListProfiles { names } => Ok(names),
+        response => {
+            // This should not happen
+            Err(format!("Instead of ListProfiles got {response:?}"))
+        }
+    }
+}
+
+fn preview_effect(name: &str, params: Vec<u8>) -> Result<Vec<u8>, String> {
+    let response = send_data(comms::DaemonCommand::PreviewEffect {
+        name: name.into(),
+        params,
+        frames: PREVIEW_FRAMES,
+    })?;
+
+    use comms::DaemonResponse::*;
+    match response {
+        PreviewEffect { rgbdata } => Ok(rgbdata),
         response => {
             // This should not happen
-            println!("Instead of SetEffect got {response:?}");
-            None
+            Err(format!("Instead of PreviewEffect got {response:?}"))
         }
     }
 }
 
-fn get_power(ac: bool) -> Option<(u8, u8, u8)> {
+/// Values and names of the power modes of the laptop
+fn get_power_profiles() -> Result<Vec<(u8, String)>, String> {
+    let response = send_data(comms::DaemonCommand::ListPowerProfiles)?;
+
+    use comms::DaemonResponse::*;
+    match response {
+        ListPowerProfiles { profiles } => Ok(profiles),
+        response => {
+            // This should not happen
+            Err(format!("Instead of ListPowerProfiles got {response:?}"))
+        }
+    }
+}
+
+/// Position of a power mode in the dropdown
+fn power_mode_index(modes: &[u8], mode: u8) -> u32 {
+    modes.iter().position(|m| *m == mode).unwrap_or(0) as u32
+}
+
+fn get_power(ac: bool) -> Result<(u8, u8, u8), String> {
     let ac = if ac { 1 } else { 0 };
     let mut result = (0, 0, 0);
 
@@ -176,8 +539,7 @@ fn get_power(ac: bool) -> Option<(u8, u8, u8)> {
         }
         response => {
             // This should not happen
-            println!("Instead of GetPwrLevel got {response:?}");
-            return None;
+            return Err(format!("Instead of GetPwrLevel got {response:?}"));
         }
     }
 
@@ -189,8 +551,7 @@ fn get_power(ac: bool) -> Option<(u8, u8, u8)> {
         }
         response => {
             // This should not happen
-            println!("Instead of GetCPUBoost got {response:?}");
-            return None;
+            return Err(format!("Instead of GetCPUBoost got {response:?}"));
         }
     }
 
@@ -202,15 +563,14 @@ fn get_power(ac: bool) -> Option<(u8, u8, u8)> {
         }
         response => {
             // This should not happen
-            println!("Instead of GetGPUBoost got {response:?}");
-            return None;
+            return Err(format!("Instead of GetGPUBoost got {response:?}"));
         }
     }
 
-    Some(result)
+    Ok(result)
 }
 
-fn set_power(ac: bool, power: (u8, u8, u8)) -> Option<bool> {
+fn set_power(ac: bool, power: (u8, u8, u8)) -> Result<bool, String> {
     let ac = if ac { 1 } else { 0 };
     let response = send_data(comms::DaemonCommand::SetPowerMode {
         ac,
@@ -221,41 +581,219 @@ fn set_power(ac: bool, power: (u8, u8, u8)) -> Option<bool> {
 
     use comms::DaemonResponse::*;
     match response {
-        SetPowerMode { result } => Some(result),
+        SetPowerMode { result } => Ok(result),
         response => {
             // This should not happen
-            println!("Instead of SetPowerMode got {response:?}");
-            None
+            Err(format!("Instead of SetPowerMode got {response:?}"))
         }
     }
 }
 
-fn get_fan_speed(ac: bool) -> Option<i32> {
+fn set_cpu_boost(ac: bool, cpu: u8) -> Result<bool, String> {
+    let ac = if ac { 1 } else { 0 };
+    let response = send_data(comms::DaemonCommand::SetCPUBoost { ac, cpu })?;
+
+    use comms::DaemonResponse::*;
+    match response {
+        SetCPUBoost { result } => Ok(result),
+        response => {
+            // This should not happen
+            Err(format!("Instead of SetCPUBoost got {response:?}"))
+        }
+    }
+}
+
+fn set_gpu_boost(ac: bool, gpu: u8) -> Result<bool, String> {
+    let ac = if ac { 1 } else { 0 };
+    let response = send_data(comms::DaemonCommand::SetGPUBoost { ac, gpu })?;
+
+    use comms::DaemonResponse::*;
+    match response {
+        SetGPUBoost { result } => Ok(result),
+        response => {
+            // This should not happen
+            Err(format!("Instead of SetGPUBoost got {response:?}"))
+        }
+    }
+}
+
+fn get_fan_speed(ac: bool) -> Result<i32, String> {
     let ac = if ac { 1 } else { 0 };
     let response = send_data(comms::DaemonCommand::GetFanSpeed { ac })?;
 
     use comms::DaemonResponse::*;
     match response {
-        GetFanSpeed { rpm } => Some(rpm),
+        GetFanSpeed { rpm } => Ok(rpm),
         response => {
             // This should not happen
-            println!("Instead of GetFanSpeed got {response:?}");
-            None
+            Err(format!("Instead of GetFanSpeed got {response:?}"))
         }
     }
 }
 
-fn set_fan_speed(ac: bool, value: i32) -> Option<bool> {
+fn set_fan_speed(ac: bool, value: i32) -> Result<bool, String> {
     let ac = if ac { 1 } else { 0 };
     let response = send_data(comms::DaemonCommand::SetFanSpeed { ac, rpm: value })?;
 
     use comms::DaemonResponse::*;
     match response {
-        SetFanSpeed { result } => Some(result),
+        SetFanSpeed { result } => Ok(result),
         response => {
             // This should not happen
-            println!("Instead of SetFanSpeed got {response:?}");
-            None
+            Err(format!("Instead of SetFanSpeed got {response:?}"))
+        }
+    }
+}
+
+fn get_fan_curve(ac: bool) -> Result<Vec<(u8, i32)>, String> {
+    let ac = if ac { 1 } else { 0 };
+    let response = send_data(comms::DaemonCommand::GetFanCurve { ac })?;
+
+    use comms::DaemonResponse::*;
+    match response {
+        GetFanCurve { points } => Ok(points),
+        response => {
+            // This should not happen
+            Err(format!("Instead of GetFanCurve got {response:?}"))
+        }
+    }
+}
+
+fn set_fan_curve(ac: bool, points: Vec<(u8, i32)>) -> Result<bool, String> {
+    let ac = if ac { 1 } else { 0 };
+    let response = send_data(comms::DaemonCommand::SetFanCurve { ac, points })?;
+
+    use comms::DaemonResponse::*;
+    match response {
+        SetFanCurve { result } => Ok(result),
+        response => {
+            // This should not happen
+            Err(format!("Instead of SetFanCurve got {response:?}"))
+        }
+    }
+}
+
+fn get_temperature(cpu: bool) -> Result<f32, String> {
+    let command = if cpu {
+        comms::DaemonCommand::GetCpuTemperature
+    } else {
+        comms::DaemonCommand::GetGpuTemperature
+    };
+    let response = send_data(command)?;
+
+    use comms::DaemonResponse::*;
+    match response {
+        GetTemperature { celsius } => Ok(celsius),
+        GetGpuTemperature { celsius: Some(celsius), .. } => Ok(celsius),
+        GetGpuTemperature { source, .. } => Err(source),
+        response => {
+            // This should not happen
+            Err(format!("Instead of GetTemperature got {response:?}"))
+        }
+    }
+}
+
+fn get_battery_state() -> Result<(u8, bool), String> {
+    let response = send_data(comms::DaemonCommand::GetBatteryState)?;
+
+    use comms::DaemonResponse::*;
+    match response {
+        GetBatteryState { capacity, charging } => Ok((capacity, charging)),
+        response => {
+            // This should not happen
+            Err(format!("Instead of GetBatteryState got {response:?}"))
+        }
+    }
+}
+
+/// Wear of the battery as reported by the daemon
+struct BatteryHealth {
+    health_percent: f32,
+    full_capacity: u32,
+    design_capacity: u32,
+    unit: String,
+    cycle_count: Option<u32>,
+}
+
+/// None when the battery doesn't report its capacities
+fn get_battery_health() -> Result<Option<BatteryHealth>, String> {
+    let response = send_data(comms::DaemonCommand::GetBatteryHealth)?;
+
+    use comms::DaemonResponse::*;
+    match response {
+        GetBatteryHealth { available: false, .. } => Ok(None),
+        GetBatteryHealth { cycle_count, design_capacity, full_capacity, unit, health_percent, .. } => {
+            Ok(Some(BatteryHealth { health_percent, full_capacity, design_capacity, unit, cycle_count }))
+        }
+        response => {
+            // This should not happen
+            Err(format!("Instead of GetBatteryHealth got {response:?}"))
+        }
+    }
+}
+
+fn format_battery_state(state: Option<(u8, bool)>) -> String {
+    match state {
+        Some((capacity, true)) => format!("{capacity}% (charging)"),
+        Some((capacity, false)) => format!("{capacity}%"),
+        None => "Unavailable".into(),
+    }
+}
+
+fn format_temperature(celsius: Result<f32, String>) -> String {
+    match celsius {
+        Ok(celsius) => format!("{celsius:.0} °C"),
+        Err(reason) if reason == "powered-down" => "GPU powered down".into(),
+        Err(_) => "Unavailable".into(),
+    }
+}
+
+/// Clamps a fan speed to the device range and rounds it to the nearest
+/// speed the device accepts
+fn snap_fan_speed(value: f64, min: f64, max: f64, step: f64) -> f64 {
+    let value = value.clamp(min, max);
+    let snapped = (value / step).round() * step;
+    if snapped < min {
+        return snapped + step;
+    }
+    if snapped > max {
+        return snapped - step;
+    }
+
+    snapped
+}
+
+/// Formats fan curve points as `temp:rpm, temp:rpm`
+fn format_fan_curve(points: &[(u8, i32)]) -> String {
+    points
+        .iter()
+        .map(|(temp, rpm)| format!("{temp}:{rpm}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Parses the `temp:rpm, temp:rpm` text of the fan curve row
+fn parse_fan_curve(text: &str) -> Option<Vec<(u8, i32)>> {
+    text.split(',')
+        .map(str::trim)
+        .filter(|point| !point.is_empty())
+        .map(|point| {
+            let (temp, rpm) = point.split_once(':')?;
+            Some((temp.trim().parse().ok()?, rpm.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+/// Version of the daemon, the hidraw node of the laptop and its kernel driver
+fn get_version() -> Result<(String, String, String), String> {
+    let response = send_data(comms::DaemonCommand::GetVersion)?;
+
+    use comms::DaemonResponse::*;
+    match response {
+        GetVersion { version, device_path, driver, .. } => Ok((version, device_path, driver)),
+        response => {
+            // This should not happen
+            Err(format!("Instead of GetVersion got {response:?}"))
         }
     }
 }
@@ -263,6 +801,15 @@ fn set_fan_speed(ac: bool, value: i32) -> Option<bool> {
 fn show_about(window: &ApplicationWindow, device: &lib::SupportedDevice) {
     let name = &device.name;
     let features = &device.features.join(",");
+    // Older daemons don't know GetVersion, the version of the GUI is shown instead
+    let (version, mut device_info) = match get_version() {
+        Ok((version, path, driver)) if !path.is_empty() => (version, format!("\n - Device: {path} ({driver})")),
+        Ok((version, _, _)) => (version, String::from("\n - Device: simulated")),
+        Err(_) => (String::from(VERSION), String::new()),
+    };
+    if !device.vid.is_empty() {
+        device_info += &format!("\n - Matched by USB id {}:{}", device.vid, device.pid);
+    }
 
     let about = adw::AboutDialog::builder()
         .application_name("Razer Laptop Control")
@@ -270,26 +817,64 @@ fn show_about(window: &ApplicationWindow, device: &lib::SupportedDevice) {
         .developer_name("Noah Felber")
         .issue_url("https://github.com/no8f/razer-laptop-control/issues/new/choose")
         .website("https://github.com/no8f/razer-laptop-control")
-        .comments(format!("<span size='large' weight='bold' >Laptop Information</span>\n\n - Model: {name} \n - Features: {features}"))
-        .version(VERSION)
+        .comments(format!("<span size='large' weight='bold' >Laptop Information</span>\n\n - Model: {name} \n - Features: {features}{device_info}"))
+        .version(version)
         .developers(AUTHORS.split(":").collect::<Vec<&str>>())
         .copyright("© 2025 Noah Felber")
         .license_type(License::Gpl30)
         .build();
 
-    about.present(Some(window));
+    about.present(Some(window));
+}
+
+/// Shown instead of the settings when the daemon runs without a laptop of
+/// the laptops file
+fn run_unsupported_app() {
+    let app = Application::builder()
+        .application_id("com.no8f.razerLaptopControl")
+        .build();
+
+    app.connect_activate(|app| {
+        let status = adw::StatusPage::builder()
+            .icon_name("dialog-warning-symbolic")
+            .title("Unsupported device")
+            .description(format!(
+                "The daemon found no laptop listed in {}. The daemon log names the Razer devices it saw, \
+                 please open an issue with it to get yours added.",
+                lib::DEVICE_FILE
+            ))
+            .build();
+
+        let toolbar = ToolbarView::new();
+        toolbar.add_top_bar(&HeaderBar::new());
+        toolbar.set_content(Some(&status));
+
+        let window = ApplicationWindow::builder()
+            .application(app)
+            .default_width(640)
+            .default_height(400)
+            .title("Razer Laptop Control")
+            .content(&toolbar)
+            .build();
+        window.present();
+    });
+
+    app.run();
 }
 
 fn main() {
     setup_panic_hook();
     gtk::init().or_crash("Failed to initialize GTK.");
 
-    let device_file =
-        std::fs::read_to_string(lib::DEVICE_FILE).or_crash("Failed to read the device file");
-    let devices: Vec<lib::SupportedDevice> =
-        serde_json::from_str(&device_file).or_crash("Failed to parse the device file");
+    // Older daemons don't know GetMatchInfo, they only run with a matched laptop
+    if get_match_info().is_ok_and(|info| !info.matched) {
+        run_unsupported_app();
+        return;
+    }
 
-    let device_name = get_device_name().or_crash("Failed to get device name");
+    let device = Rc::new(RefCell::new(get_current_device()));
+    // Older daemons only know a single device
+    let devices = list_devices().ok();
 
     let app = Application::builder()
         .application_id("com.no8f.razerLaptopControl")
@@ -297,14 +882,6 @@ fn main() {
 
     app.set_version(VERSION);
     app.connect_activate(move |app| {
-        // For now we get the device from the device name. One is duplicated but
-        // its settings are the same.
-        // TODO: Document this or make it more robust
-        let device = devices
-            .iter()
-            .find(|d| d.name == device_name)
-            .or_crash("Failed to get device info");
-
         let window = ApplicationWindow::builder()
             .application(app)
             .default_width(640)
@@ -313,25 +890,8 @@ fn main() {
             .icon_name(app.application_id().unwrap())
             .build();
 
-        let ac_settings_page = make_page(true, device.clone());
-        let battery_settings_page = make_page(false, device.clone());
-        let general_page = make_general_page();
-
         let stack = ViewStack::new();
-
-        stack.add_titled_with_icon(&ac_settings_page, Some("AC"), "AC", "ac-adapter-symbolic");
-        stack.add_titled_with_icon(
-            &battery_settings_page,
-            Some("Battery"),
-            "Battery",
-            "battery",
-        );
-        stack.add_titled_with_icon(
-            &general_page,
-            Some("General"),
-            "General",
-            "preferences-system-symbolic",
-        );
+        fill_stack(&stack, &device.borrow());
         stack.set_property("enable-transitions", true);
 
         let stack_switcher = ViewSwitcher::builder().build();
@@ -346,7 +906,10 @@ fn main() {
 
         let header_bar = HeaderBar::new();
 
-        header_bar.set_title_widget(Some(&WindowTitle::new("Razer Laptop Control", "")));
+        // Older daemons don't know GetPowerSource, the sysfs file is read once instead
+        let power_source = get_power_source().unwrap_or_else(|_| check_if_running_on_ac_power());
+        let title = WindowTitle::new("Razer Laptop Control", format_power_source(power_source));
+        header_bar.set_title_widget(Some(&title));
 
         // Add a button to the header
         let header_button = Button::from_icon_name("help-about");
@@ -358,10 +921,68 @@ fn main() {
             #[strong]
             device,
             move |button| {
-                show_about(&window, &device);
+                show_about(&window, &device.borrow());
             }
         ));
 
+        // The selector is only worth the space with several devices connected
+        if let Some((devices, selected)) = devices.clone().filter(|(devices, _)| devices.len() > 1) {
+            let names: Vec<String> = devices
+                .iter()
+                .map(|(name, serial)| format!("{name} ({serial})"))
+                .collect();
+            let names: Vec<&str> = names.iter().map(|name| name.as_str()).collect();
+            let selector = gtk::DropDown::from_strings(&names);
+            selector.set_selected(selected as u32);
+            selector.set_tooltip_text(Some("Device the settings apply to"));
+            header_bar.pack_end(&selector);
+
+            selector.connect_selected_notify(clone!(
+                #[strong]
+                stack,
+                #[strong]
+                device,
+                move |selector| {
+                    let index = selector.selected() as usize;
+                    if !select_device(index).or_crash("Error selecting the device") {
+                        return;
+                    }
+                    let current = get_current_device();
+                    fill_stack(&stack, &current);
+                    *device.borrow_mut() = current;
+                }
+            ));
+        }
+
+        // Older daemons don't know the profiles, there is nothing to pick then
+        let profiles = list_profiles().unwrap_or_default();
+        if !profiles.is_empty() {
+            // The first entry is a placeholder, the daemon doesn't tell which profile is in use
+            let mut names = vec!["Profile"];
+            names.extend(profiles.iter().map(|name| name.as_str()));
+            let selector = gtk::DropDown::from_strings(&names);
+            selector.set_tooltip_text(Some("Switch the power, fan, brightness and logo settings and the effects to a saved profile"));
+            header_bar.pack_end(&selector);
+
+            selector.connect_selected_notify(clone!(
+                #[strong]
+                stack,
+                #[strong]
+                device,
+                move |selector| {
+                    let Some(name) = profiles.get((selector.selected() as usize).wrapping_sub(1)) else {
+                        return;
+                    };
+                    if !switch_profile(name).or_crash("Error switching the profile") {
+                        return;
+                    }
+                    let current = get_current_device();
+                    fill_stack(&stack, &current);
+                    *device.borrow_mut() = current;
+                }
+            ));
+        }
+
         let toolbar = ToolbarView::new();
         toolbar.add_top_bar(&header_bar);
 
@@ -374,45 +995,198 @@ fn main() {
 
         // If we know we are not running on AC, we show the battery tab by
         // default
-        match check_if_running_on_ac_power() {
+        match power_source {
             Some(false) => stack.set_visible_child_name("Battery"),
             _ => {}
         }
+
+        // Follows the cable while the window is open, the tab only changes
+        // with the power source so the other one can still be browsed
+        let last_source = Rc::new(RefCell::new(power_source));
+        glib::timeout_add_seconds_local(
+            2,
+            clone!(
+                #[weak]
+                title,
+                #[weak]
+                stack,
+                #[upgrade_or]
+                glib::ControlFlow::Break,
+                move || {
+                    let Ok(source) = get_power_source() else {
+                        return glib::ControlFlow::Continue;
+                    };
+                    if source != *last_source.borrow() {
+                        title.set_subtitle(format_power_source(source));
+                        match source {
+                            Some(true) => stack.set_visible_child_name("AC"),
+                            Some(false) => stack.set_visible_child_name("Battery"),
+                            None => {}
+                        }
+                        *last_source.borrow_mut() = source;
+                    }
+                    glib::ControlFlow::Continue
+                }
+            ),
+        );
     });
 
     app.run();
 }
 
+/// Replaces the pages of the stack with the ones of `device`
+fn fill_stack(stack: &ViewStack, device: &lib::SupportedDevice) {
+    for name in ["AC", "Battery", "General"] {
+        if let Some(page) = stack.child_by_name(name) {
+            stack.remove(&page);
+        }
+    }
+
+    let ac_settings_page = make_page(true, device.clone());
+    let battery_settings_page = make_page(false, device.clone());
+    let general_page = make_general_page();
+
+    stack.add_titled_with_icon(&ac_settings_page, Some("AC"), "AC", "ac-adapter-symbolic");
+    stack.add_titled_with_icon(
+        &battery_settings_page,
+        Some("Battery"),
+        "Battery",
+        "battery",
+    );
+    stack.add_titled_with_icon(
+        &general_page,
+        Some("General"),
+        "General",
+        "preferences-system-symbolic",
+    );
+}
+
 fn make_page(ac: bool, device: lib::SupportedDevice) -> PreferencesPage {
     let fan_speed = get_fan_speed(ac).or_crash("Error reading fan speed");
     let brightness = get_brightness(ac).or_crash("Error reading brightness");
-    let power = get_power(ac);
+    let power = get_power(ac).ok();
 
     let min_fan_speed = *device.fan.get(0).or_crash("Invalid fan values") as f64;
     let max_fan_speed = *device.fan.get(1).or_crash("Invalid fan values") as f64;
+    let fan_step = device.fan_step.max(1) as f64;
 
     let settings_page = PreferencesPage::new();
 
+    // Battery section
+    if !ac {
+        let settings_section = PreferencesGroup::new();
+        settings_section.set_title("Battery");
+        settings_page.add(&settings_section);
+
+        let label = gtk::Label::new(Some(&format_battery_state(get_battery_state().ok())));
+        glib::timeout_add_seconds_local(
+            5,
+            clone!(
+                #[weak]
+                label,
+                #[upgrade_or]
+                glib::ControlFlow::Break,
+                move || {
+                    label.set_text(&format_battery_state(get_battery_state().ok()));
+                    glib::ControlFlow::Continue
+                }
+            ),
+        );
+        let row = ActionRow::new();
+        row.set_title("Charge");
+        row.add_suffix(&label);
+        settings_section.add(&row);
+    }
+
     // Logo section
     if device.has_logo() {
         let logo = get_logo(ac).or_crash("Error reading logo");
+        let speed = get_logo_breathing_speed(ac).or_crash("Error reading logo breathing speed");
 
         let settings_section = PreferencesGroup::new();
         settings_section.set_title("Logo");
         settings_page.add(&settings_section);
 
+        // 0 keeps the breathing rate of the firmware
+        let scale = Scale::with_range(gtk::Orientation::Horizontal, 0f64, 10f64, 1f64);
+        scale.set_value(speed as f64);
+        scale.set_width_request(150);
+        scale.set_draw_value(true);
+        scale.set_sensitive(logo == 2);
+        scale.connect_change_value(move |scale, _stype, value| {
+            let value = value.round().clamp(0f64, 10f64);
+            set_logo_breathing_speed(ac, value as u8).or_crash("Error setting logo breathing speed");
+            let speed = get_logo_breathing_speed(ac).or_crash("Error reading logo breathing speed");
+            scale.set_value(speed as f64);
+            return gtk::glib::Propagation::Stop;
+        });
+
+        // Older daemons can't follow the keyboard, the switch is left out
+        let sync = get_logo_sync().ok();
+        let sync_switch = SwitchRow::new();
+        sync_switch.set_title("Breathe with the keyboard");
+        sync_switch.set_subtitle("Follows a single colour breathing effect");
+        sync_switch.set_active(sync.unwrap_or(false));
+        sync_switch.set_sensitive(logo == 2);
+        sync_switch.connect_active_notify(move |switch| {
+            set_logo_sync(switch.is_active()).or_crash("Error setting logo sync");
+            let enabled = get_logo_sync().or_crash("Error reading logo sync");
+            if switch.is_active() != enabled {
+                switch.set_active(enabled);
+            }
+        });
+
         let logo_options = StringList::new(&["Off", "On", "Breathing"]);
         let logo_options_dropdown = ComboRow::new();
         logo_options_dropdown.set_model(Some(&logo_options));
         logo_options_dropdown.set_title("Turn on logo");
         logo_options_dropdown.set_selected(logo as u32);
-        logo_options_dropdown.connect_selected_notify(move |options| {
-            let logo = options.selected() as u8;
-            set_logo(ac, logo);
-            let logo = get_logo(ac).or_crash("Error reading logo").clamp(0, 2);
-            options.set_selected(logo as u32);
-        });
+        logo_options_dropdown.connect_selected_notify(clone!(
+            #[weak]
+            scale,
+            #[weak]
+            sync_switch,
+            move |options| {
+                let logo = options.selected() as u8;
+                set_logo(ac, logo).or_crash("Error setting logo");
+                let logo = get_logo(ac).or_crash("Error reading logo").clamp(0, 2);
+                options.set_selected(logo as u32);
+                scale.set_sensitive(logo == 2);
+                sync_switch.set_sensitive(logo == 2);
+            }
+        ));
+        // The other tab changes this one too while the settings are synced,
+        // read it again every time the tab is shown
+        logo_options_dropdown.connect_map(clone!(
+            #[weak]
+            scale,
+            #[weak]
+            sync_switch,
+            move |options| {
+                let logo = get_logo(ac).or_crash("Error reading logo").clamp(0, 2);
+                if options.selected() != logo as u32 {
+                    options.set_selected(logo as u32);
+                }
+                scale.set_sensitive(logo == 2);
+                sync_switch.set_sensitive(logo == 2);
+                // Shared by both tabs
+                let enabled = get_logo_sync().unwrap_or(sync_switch.is_active());
+                if sync_switch.is_active() != enabled {
+                    sync_switch.set_active(enabled);
+                }
+            }
+        ));
         settings_section.add(&logo_options_dropdown);
+
+        let row = ActionRow::new();
+        row.set_title("Breathing speed");
+        row.set_subtitle("0 uses the rate of the firmware");
+        row.add_suffix(&scale);
+        settings_section.add(&row);
+
+        if sync.is_some() {
+            settings_section.add(&sync_switch);
+        }
     }
 
     // Power section
@@ -421,10 +1195,13 @@ fn make_page(ac: bool, device: lib::SupportedDevice) -> PreferencesPage {
         settings_section.set_title("Power");
         settings_page.add(&settings_section);
 
-        let power_profile = StringList::new(&["Balanced", "Gaming", "Creator", "Silent", "Custom"]);
+        let profiles = get_power_profiles().or_crash("Error reading the power profiles");
+        let names: Vec<&str> = profiles.iter().map(|(_, name)| name.as_str()).collect();
+        let modes: Vec<u8> = profiles.iter().map(|(mode, _)| *mode).collect();
+        let power_profile = StringList::new(&names);
         let power_profile_dropdown = ComboRow::new();
         power_profile_dropdown.set_model(Some(&power_profile));
-        power_profile_dropdown.set_selected(power.0 as u32);
+        power_profile_dropdown.set_selected(power_mode_index(&modes, power.0));
         power_profile_dropdown.set_title("Power Profile");
 
         settings_section.add(&power_profile_dropdown);
@@ -461,14 +1238,22 @@ fn make_page(ac: bool, device: lib::SupportedDevice) -> PreferencesPage {
             gpu_boost_dropdown,
             #[weak]
             cpu_boost_dropdown,
+            #[strong]
+            modes,
             move |power_profile_dropdown| {
-                let profile = power_profile_dropdown.selected() as u8;
-                let cpu = cpu_boost_dropdown.selected() as u8;
-                let gpu = gpu_boost_dropdown.selected() as u8;
+                let Some(&profile) = modes.get(power_profile_dropdown.selected() as usize) else {
+                    return;
+                };
+                // Only the custom profile takes boosts, the daemon keeps them for it
+                let (cpu, gpu) = if profile == 4 {
+                    (cpu_boost_dropdown.selected() as u8, gpu_boost_dropdown.selected() as u8)
+                } else {
+                    (0, 0)
+                };
                 set_power(ac, (profile, cpu, gpu)).or_crash("Error setting power");
 
                 let power = get_power(ac).or_crash("Error reading power");
-                power_profile_dropdown.set_selected(power.0 as u32);
+                power_profile_dropdown.set_selected(power_mode_index(&modes, power.0));
                 cpu_boost_dropdown.set_selected(power.1 as u32);
                 gpu_boost_dropdown.set_selected(power.2 as u32);
 
@@ -487,14 +1272,14 @@ fn make_page(ac: bool, device: lib::SupportedDevice) -> PreferencesPage {
             power_profile_dropdown,
             #[weak]
             gpu_boost_dropdown,
+            #[strong]
+            modes,
             move |cpu_boost_dropdown| {
-                let profile = power_profile_dropdown.selected() as u8;
                 let cpu = cpu_boost_dropdown.selected() as u8;
-                let gpu = gpu_boost_dropdown.selected() as u8;
-                set_power(ac, (profile, cpu, gpu)).or_crash("Error setting power");
+                set_cpu_boost(ac, cpu).or_crash("Error setting CPU boost");
 
                 let power = get_power(ac).or_crash("Error reading power");
-                power_profile_dropdown.set_selected(power.0 as u32);
+                power_profile_dropdown.set_selected(power_mode_index(&modes, power.0));
                 cpu_boost_dropdown.set_selected(power.1 as u32);
                 gpu_boost_dropdown.set_selected(power.2 as u32);
             }
@@ -505,14 +1290,14 @@ fn make_page(ac: bool, device: lib::SupportedDevice) -> PreferencesPage {
             power_profile_dropdown,
             #[weak]
             cpu_boost_dropdown,
+            #[strong]
+            modes,
             move |gpu_boost_dropdown| {
-                let profile = power_profile_dropdown.selected() as u8;
-                let cpu = cpu_boost_dropdown.selected() as u8;
                 let gpu = gpu_boost_dropdown.selected() as u8;
-                set_power(ac, (profile, cpu, gpu)).or_crash("Error setting power");
+                set_gpu_boost(ac, gpu).or_crash("Error setting GPU boost");
 
                 let power = get_power(ac).or_crash("Error reading power");
-                power_profile_dropdown.set_selected(power.0 as u32);
+                power_profile_dropdown.set_selected(power_mode_index(&modes, power.0));
                 cpu_boost_dropdown.set_selected(power.1 as u32);
                 gpu_boost_dropdown.set_selected(power.2 as u32);
             }
@@ -527,34 +1312,42 @@ fn make_page(ac: bool, device: lib::SupportedDevice) -> PreferencesPage {
     let switch = SwitchRow::new();
     let auto = fan_speed == 0;
     switch.set_active(auto);
-    switch.set_title("Auto");
+    switch.set_title("Automatic");
+    switch.set_subtitle("The firmware controls the fan speed");
 
     settings_section.add(&switch);
     let scale = Scale::with_range(
         gtk::Orientation::Horizontal,
         min_fan_speed,
         max_fan_speed,
-        1f64,
+        fan_step,
     );
     scale.set_value(fan_speed as f64);
-    scale.set_sensitive(fan_speed != 0);
     scale.set_width_request(150);
     scale.set_draw_value(true);
+    scale.set_format_value_func(|_, value| format!("{:.0} RPM", value));
+
+    let row = ActionRow::new();
+    row.set_title("Fan Speed");
+    row.add_suffix(&scale);
+    // Only a fixed speed can be set, hidden while the firmware is in charge
+    row.set_visible(!auto);
+    settings_section.add(&row);
 
-    let update_label = gtk::Label::default();
     scale.connect_change_value(clone!(
         #[weak]
         switch,
+        #[weak]
+        row,
         #[upgrade_or_panic]
-        move |scale, stype, value| {
-            let value = value.clamp(min_fan_speed, max_fan_speed);
+        move |scale, _, value| {
+            let value = snap_fan_speed(value, min_fan_speed, max_fan_speed, fan_step);
             set_fan_speed(ac, value as i32).or_crash("Error setting fan speed");
             let fan_speed = get_fan_speed(ac).or_crash("Error reading fan speed");
             let auto = fan_speed == 0;
             scale.set_value(fan_speed as f64);
-            scale.set_sensitive(!auto);
+            row.set_visible(!auto);
             switch.set_active(auto);
-            update_label.set_text(&format!("Horizontal scale value: {:.2}", value));
             return glib::Propagation::Stop;
         },
     ));
@@ -562,6 +1355,8 @@ fn make_page(ac: bool, device: lib::SupportedDevice) -> PreferencesPage {
     switch.connect_active_notify(clone!(
         #[weak]
         scale,
+        #[weak]
+        row,
         #[upgrade_or_panic]
         move |switch| {
             set_fan_speed(
@@ -569,7 +1364,7 @@ fn make_page(ac: bool, device: lib::SupportedDevice) -> PreferencesPage {
                 if switch.is_active() {
                     0
                 } else {
-                    min_fan_speed as i32
+                    snap_fan_speed(min_fan_speed, min_fan_speed, max_fan_speed, fan_step) as i32
                 },
             )
             .or_crash("Error setting fan speed");
@@ -578,15 +1373,45 @@ fn make_page(ac: bool, device: lib::SupportedDevice) -> PreferencesPage {
             let auto = fan_speed == 0;
 
             scale.set_value(fan_speed as f64);
-            scale.set_sensitive(!auto);
+            row.set_visible(!auto);
             switch.set_active(auto);
         }
     ));
 
-    let row = ActionRow::new();
-    row.set_title("Fan Speed");
-    row.add_suffix(&scale);
-    settings_section.add(&row);
+    let fan_curve = get_fan_curve(ac).or_crash("Error reading fan curve");
+    let curve_row = EntryRow::new();
+    curve_row.set_title("Fan Curve (°C:RPM, empty to disable)");
+    curve_row.set_text(&format_fan_curve(&fan_curve));
+    curve_row.set_show_apply_button(true);
+    curve_row.connect_apply(move |curve_row| {
+        if let Some(points) = parse_fan_curve(&curve_row.text()) {
+            set_fan_curve(ac, points).or_crash("Error setting fan curve");
+        }
+        let fan_curve = get_fan_curve(ac).or_crash("Error reading fan curve");
+        curve_row.set_text(&format_fan_curve(&fan_curve));
+    });
+    settings_section.add(&curve_row);
+
+    for (cpu, title) in [(true, "CPU Temperature"), (false, "GPU Temperature")] {
+        let label = gtk::Label::new(Some(&format_temperature(get_temperature(cpu))));
+        glib::timeout_add_seconds_local(
+            2,
+            clone!(
+                #[weak]
+                label,
+                #[upgrade_or]
+                glib::ControlFlow::Break,
+                move || {
+                    label.set_text(&format_temperature(get_temperature(cpu)));
+                    glib::ControlFlow::Continue
+                }
+            ),
+        );
+        let row = ActionRow::new();
+        row.set_title(title);
+        row.add_suffix(&label);
+        settings_section.add(&row);
+    }
 
     // Keyboard Section
     let settings_section = PreferencesGroup::new(); //settings_page.add_section(Some("Keyboard"));
@@ -612,8 +1437,142 @@ fn make_page(ac: bool, device: lib::SupportedDevice) -> PreferencesPage {
     settings_page
 }
 
+fn rgba_from_params(rgb: &[u8]) -> RGBA {
+    RGBA::new(
+        rgb[0] as f32 / 255.0,
+        rgb[1] as f32 / 255.0,
+        rgb[2] as f32 / 255.0,
+        1.0,
+    )
+}
+
+/// Turns a region name of the daemon (function_row) into a label (Function row)
+fn region_label(region: &str) -> String {
+    match region {
+        "all" => "All keys".into(),
+        "wasd" => "WASD".into(),
+        region => {
+            let label = region.replace('_', " ");
+            let mut chars = label.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => label,
+            }
+        }
+    }
+}
+
+/// Returns the daemon name and params of the effect selected in the dropdown
+fn selected_effect(
+    effect: u32,
+    color: RGBA,
+    color_2: RGBA,
+    extra_colors: &[RGBA],
+    duration: u8,
+    direction: u32,
+) -> Option<(&'static str, Vec<u8>)> {
+    let red = (color.red() * 255.0).round() as u8;
+    let green = (color.green() * 255.0).round() as u8;
+    let blue = (color.blue() * 255.0).round() as u8;
+
+    let red2 = (color_2.red() * 255.0).round() as u8;
+    let green2 = (color_2.green() * 255.0).round() as u8;
+    let blue2 = (color_2.blue() * 255.0).round() as u8;
+
+    // More than 2 colours start with the number of colours
+    let mut gradient = vec![red, green, blue, red2, green2, blue2];
+    if !extra_colors.is_empty() {
+        gradient.insert(0, extra_colors.len() as u8 + 2);
+        for color in extra_colors {
+            gradient.push((color.red() * 255.0).round() as u8);
+            gradient.push((color.green() * 255.0).round() as u8);
+            gradient.push((color.blue() * 255.0).round() as u8);
+        }
+    }
+
+    match effect {
+        0 => Some(("static", vec![red, green, blue])),
+        1 => Some(("static_gradient", gradient)),
+        2 => Some(("wave_gradient", gradient)),
+        3 => Some(("breathing_single", vec![red, green, blue, duration])),
+        4 => Some(("spectrum_cycle", vec![duration])),
+        5 => Some(("wave", vec![red, green, blue, red2, green2, blue2, direction as u8, duration])),
+        _ => None,
+    }
+}
+
+/// Colours of the pickers added after "Color 2"
+fn extra_colors(container: &gtk::Box) -> Vec<RGBA> {
+    let mut colors = vec![];
+    let mut child = container.first_child();
+    while let Some(widget) = child {
+        if let Some(picker) = widget.downcast_ref::<ColorDialogButton>() {
+            colors.push(picker.rgba());
+        }
+        child = widget.next_sibling();
+    }
+    colors
+}
+
+/// Colours of the gradient effects, the 2 colour form for exactly 6
+/// arguments, otherwise they start with the number of colours
+fn gradient_colors(params: &[u8]) -> Vec<RGBA> {
+    let (count, start) = match params.first() {
+        _ if params.len() == 6 => (2, 0),
+        Some(count) => (*count as usize, 1),
+        None => (0, 0),
+    };
+    params.get(start..start + count * 3).unwrap_or_default().chunks(3).map(rgba_from_params).collect()
+}
+
+/// Draws the 15 x 6 keys of a preview, dark keys when there is no data
+fn draw_keyboard_preview(cr: &gtk::cairo::Context, width: i32, height: i32, rgbdata: &[u8]) {
+    let (cols, rows) = (15, 6);
+    let key_width = width as f64 / cols as f64;
+    let key_height = height as f64 / rows as f64;
+    for row in 0..rows {
+        for col in 0..cols {
+            let index = (row * cols + col) * 3;
+            match rgbdata.get(index..index + 3) {
+                Some(rgb) => cr.set_source_rgb(rgb[0] as f64 / 255.0, rgb[1] as f64 / 255.0, rgb[2] as f64 / 255.0),
+                None => cr.set_source_rgb(0.1, 0.1, 0.1),
+            }
+            cr.rectangle(
+                col as f64 * key_width + 1.0,
+                row as f64 * key_height + 1.0,
+                key_width - 2.0,
+                key_height - 2.0,
+            );
+            let _ = cr.fill();
+        }
+    }
+}
+
+/// Shows the layer stack of the daemon, top layer first
+fn refresh_layer_list(list: &gtk::ListBox) {
+    list.remove_all();
+    let names = get_effect_layers().unwrap_or_default();
+    for (index, name) in names.iter().enumerate().rev() {
+        let row = ActionRow::new();
+        row.set_title(name);
+        row.set_subtitle(&format!("Layer {index}"));
+        list.append(&row);
+    }
+}
+
+fn refresh_preset_list(options: &StringList) {
+    options.splice(0, options.n_items(), &[]);
+    for name in list_effect_presets().unwrap_or_default() {
+        options.append(&name);
+    }
+}
+
+/// Gradient colours after the first 2 the general page offers
+const MAX_EXTRA_COLORS: usize = 6;
+
 fn make_general_page() -> PreferencesPage {
-    let bho = get_bho();
+    let bho = get_bho().ok();
+    let effect = get_effect().ok();
 
     let page = PreferencesPage::new();
 
@@ -623,7 +1582,7 @@ fn make_general_page() -> PreferencesPage {
     page.add(&settings_section);
 
     let effect_options =
-        StringList::new(&["Static", "Static Gradient", "Wave Gradient", "Breathing"]);
+        StringList::new(&["Static", "Static Gradient", "Wave Gradient", "Breathing", "Spectrum Cycle", "Wave"]);
     let effect_options_dropdown = ComboRow::new();
     effect_options_dropdown.set_model(Some(&effect_options));
     effect_options_dropdown.set_title("Effect");
@@ -642,16 +1601,119 @@ fn make_general_page() -> PreferencesPage {
     color_picker_row_2.add_suffix(&color_picker_2);
     settings_section.add(&color_picker_row_2);
 
+    // Further gradient stops, evenly spaced after the first 2 colours
+    let extra_colors_box = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+    extra_colors_box.set_valign(gtk::Align::Center);
+    let add_color_button = Button::from_icon_name("list-add-symbolic");
+    add_color_button.set_valign(gtk::Align::Center);
+    add_color_button.set_tooltip_text(Some("Add a color"));
+    let remove_color_button = Button::from_icon_name("list-remove-symbolic");
+    remove_color_button.set_valign(gtk::Align::Center);
+    remove_color_button.set_tooltip_text(Some("Remove the last color"));
+    let extra_colors_row = ActionRow::new();
+    extra_colors_row.set_title("More colors");
+    extra_colors_row.add_suffix(&extra_colors_box);
+    extra_colors_row.add_suffix(&remove_color_button);
+    extra_colors_row.add_suffix(&add_color_button);
+    settings_section.add(&extra_colors_row);
+
+    add_color_button.connect_clicked(clone!(
+        #[weak]
+        extra_colors_box,
+        move |_| {
+            // The row gets too wide for more
+            if extra_colors(&extra_colors_box).len() < MAX_EXTRA_COLORS {
+                extra_colors_box.append(&ColorDialogButton::new(Some(ColorDialog::new())));
+            }
+        }
+    ));
+    remove_color_button.connect_clicked(clone!(
+        #[weak]
+        extra_colors_box,
+        move |_| {
+            if let Some(last) = extra_colors_box.last_child() {
+                extra_colors_box.remove(&last);
+            }
+        }
+    ));
+
     let duration_spinner = SpinRow::with_range(0.0, 1000.0, 1.0);
     duration_spinner.set_title("Animation Duration");
     settings_section.add(&duration_spinner);
 
+    let direction_options =
+        StringList::new(&["Left to right", "Right to left", "Top to bottom", "Bottom to top"]);
+    let direction_dropdown = ComboRow::new();
+    direction_dropdown.set_model(Some(&direction_options));
+    direction_dropdown.set_title("Direction");
+    settings_section.add(&direction_dropdown);
+
+    let preview_data: Rc<RefCell<Vec<u8>>> = Rc::new(RefCell::new(vec![]));
+    let preview = gtk::DrawingArea::new();
+    preview.set_content_width(300);
+    preview.set_content_height(120);
+    preview.set_margin_top(12);
+    preview.set_margin_bottom(12);
+    preview.set_tooltip_text(Some("The effect after its first frames, the animation isn't played"));
+    preview.set_draw_func(clone!(
+        #[strong]
+        preview_data,
+        move |_, cr, width, height| {
+            draw_keyboard_preview(cr, width, height, &preview_data.borrow());
+        }
+    ));
+
+    let preview_button = ButtonRow::new();
+    preview_button.set_title("Preview a still of the effect");
+    preview_button.set_start_icon_name(Some("view-reveal-symbolic"));
+    settings_section.add(&preview_button);
+
+    preview_button.connect_activated(clone!(
+        #[weak]
+        effect_options_dropdown,
+        #[weak]
+        color_picker,
+        #[weak]
+        color_picker_2,
+        #[weak]
+        extra_colors_box,
+        #[weak]
+        duration_spinner,
+        #[weak]
+        direction_dropdown,
+        #[weak]
+        preview,
+        #[strong]
+        preview_data,
+        move |_| {
+            // Same arguments as the Write button, only rendered by the daemon
+            let effect = selected_effect(
+                effect_options_dropdown.selected(),
+                color_picker.rgba(),
+                color_picker_2.rgba(),
+                &extra_colors(&extra_colors_box),
+                duration_spinner.value() as u8,
+                direction_dropdown.selected(),
+            );
+            if let Some((name, params)) = effect {
+                *preview_data.borrow_mut() = preview_effect(name, params).unwrap_or_default();
+            }
+            preview.queue_draw();
+        }
+    ));
+
     let button = ButtonRow::new();
     button.set_title("Write effect");
     button.set_end_icon_name(Some("go-next-symbolic"));
     button.set_activatable(true);
 
     settings_section.add(&button);
+    settings_section.add(&preview);
+
+    let layer_list = gtk::ListBox::new();
+    layer_list.add_css_class("boxed-list");
+    layer_list.set_selection_mode(gtk::SelectionMode::None);
+    layer_list.set_placeholder(Some(&gtk::Label::new(Some("No effect layers"))));
 
     button.connect_activated(clone!(
         #[weak]
@@ -661,73 +1723,278 @@ fn make_general_page() -> PreferencesPage {
         #[weak]
         color_picker_2,
         #[weak]
+        extra_colors_box,
+        #[weak]
         duration_spinner,
+        #[weak]
+        direction_dropdown,
+        #[weak]
+        layer_list,
         #[upgrade_or_panic]
         move |_| {
-            let color = color_picker.rgba();
-            let red = (color.red() * 255.0).round() as u8;
-            let green = (color.green() * 255.0).round() as u8;
-            let blue = (color.blue() * 255.0).round() as u8;
-
-            let color = color_picker_2.rgba();
-            let red2 = (color.red() * 255.0).round() as u8;
-            let green2 = (color.green() * 255.0).round() as u8;
-            let blue2 = (color.blue() * 255.0).round() as u8;
-
-            let effect = effect_options_dropdown.selected();
-            match effect {
-                0 => {
-                    set_effect("static", vec![red, green, blue]).or_crash("Failed to set effect");
-                }
-                1 => {
-                    set_effect(
-                        "static_gradient",
-                        vec![red, green, blue, red2, green2, blue2],
-                    )
-                    .or_crash("Failed to set effect");
-                }
-                2 => {
-                    set_effect("wave_gradient", vec![red, green, blue, red2, green2, blue2])
-                        .or_crash("Failed to set effect");
-                }
-                3 => {
-                    set_effect(
-                        "breathing_single",
-                        vec![red, green, blue, duration_spinner.value() as u8],
-                    )
-                    .or_crash("Failed to set effect");
-                }
-                _ => {}
+            let effect = selected_effect(
+                effect_options_dropdown.selected(),
+                color_picker.rgba(),
+                color_picker_2.rgba(),
+                &extra_colors(&extra_colors_box),
+                duration_spinner.value() as u8,
+                direction_dropdown.selected(),
+            );
+            if let Some((name, params)) = effect {
+                set_effect(name, params).or_crash("Failed to set effect");
             }
+            refresh_layer_list(&layer_list);
         }
     ));
 
     effect_options_dropdown.connect_selected_notify(clone!(
+        #[weak]
+        color_picker_row,
         #[weak]
         color_picker_row_2,
         #[weak]
+        extra_colors_row,
+        #[weak]
         duration_spinner,
+        #[weak]
+        direction_dropdown,
         #[upgrade_or_panic]
         move |options| {
             let logo = options.selected();
+            color_picker_row.set_visible(true);
             color_picker_row_2.set_visible(false);
+            extra_colors_row.set_visible(false);
             duration_spinner.set_visible(false);
+            duration_spinner.set_title("Animation Duration");
+            direction_dropdown.set_visible(false);
 
             match logo {
                 1 | 2 => {
                     color_picker_row_2.set_visible(true);
+                    extra_colors_row.set_visible(true);
                 }
                 3 => {
                     color_picker_row_2.set_visible(true);
                     duration_spinner.set_visible(true);
                 }
+                4 => {
+                    color_picker_row.set_visible(false);
+                    duration_spinner.set_visible(true);
+                }
+                5 => {
+                    color_picker_row_2.set_visible(true);
+                    duration_spinner.set_visible(true);
+                    duration_spinner.set_title("Speed (keys per second)");
+                    direction_dropdown.set_visible(true);
+                }
                 _ => {}
             }
         }
     ));
-    effect_options_dropdown.set_selected(0);
+    // Preselect whatever the daemon is currently running
+    let (selected, params) = match effect {
+        Some((name, params)) => match name.as_str() {
+            "Static Gradient" => (1, params),
+            "Wave Gradient" => (2, params),
+            "Breathing Single" => (3, params),
+            "Spectrum Cycle" => (4, params),
+            "Wave" => (5, params),
+            _ => (0, params),
+        },
+        None => (0, vec![]),
+    };
+    if params.len() >= 3 {
+        color_picker.set_rgba(&rgba_from_params(&params[0..3]));
+    }
+    if selected == 1 || selected == 2 {
+        let colors = gradient_colors(&params);
+        if let [first, second, extra @ ..] = colors.as_slice() {
+            color_picker.set_rgba(first);
+            color_picker_2.set_rgba(second);
+            for color in extra.iter().take(MAX_EXTRA_COLORS) {
+                let picker = ColorDialogButton::new(Some(ColorDialog::new()));
+                picker.set_rgba(color);
+                extra_colors_box.append(&picker);
+            }
+        }
+    }
+    if selected == 5 && params.len() >= 6 {
+        color_picker_2.set_rgba(&rgba_from_params(&params[3..6]));
+    }
+    if selected == 3 && params.len() >= 4 {
+        duration_spinner.set_value(params[3] as f64);
+    }
+    if selected == 4 && !params.is_empty() {
+        duration_spinner.set_value(params[0] as f64);
+    }
+    if selected == 5 && params.len() >= 8 {
+        direction_dropdown.set_selected(params[6] as u32);
+        duration_spinner.set_value(params[7] as f64);
+    }
+    effect_options_dropdown.set_selected(selected);
     effect_options_dropdown.notify("selected");
 
+    // Layers section, stacking the effect above on a part of the keyboard
+    let settings_section = PreferencesGroup::new();
+    settings_section.set_title("Effect Layers");
+    settings_section.set_description(Some("Stack the effect above on top of the current ones"));
+    page.add(&settings_section);
+
+    let regions = get_regions().unwrap_or_else(|_| vec!["all".into()]);
+    let region_options = StringList::new(&[]);
+    for region in &regions {
+        region_options.append(&region_label(region));
+    }
+    let region_dropdown = ComboRow::new();
+    region_dropdown.set_model(Some(&region_options));
+    region_dropdown.set_title("Keys");
+    settings_section.add(&region_dropdown);
+
+    let push_button = ButtonRow::new();
+    push_button.set_title("Push layer");
+    push_button.set_start_icon_name(Some("list-add-symbolic"));
+    settings_section.add(&push_button);
+
+    let pop_button = ButtonRow::new();
+    pop_button.set_title("Remove top layer");
+    pop_button.set_start_icon_name(Some("list-remove-symbolic"));
+    settings_section.add(&pop_button);
+
+    push_button.connect_activated(clone!(
+        #[weak]
+        effect_options_dropdown,
+        #[weak]
+        color_picker,
+        #[weak]
+        color_picker_2,
+        #[weak]
+        extra_colors_box,
+        #[weak]
+        duration_spinner,
+        #[weak]
+        direction_dropdown,
+        #[weak]
+        region_dropdown,
+        #[weak]
+        layer_list,
+        #[upgrade_or_panic]
+        move |_| {
+            let effect = selected_effect(
+                effect_options_dropdown.selected(),
+                color_picker.rgba(),
+                color_picker_2.rgba(),
+                &extra_colors(&extra_colors_box),
+                duration_spinner.value() as u8,
+                direction_dropdown.selected(),
+            );
+            let region = regions.get(region_dropdown.selected() as usize);
+            if let (Some((name, params)), Some(region)) = (effect, region) {
+                push_effect_layer_region(name, params, region)
+                    .or_crash("Failed to push effect layer");
+            }
+            refresh_layer_list(&layer_list);
+        }
+    ));
+
+    pop_button.connect_activated(clone!(
+        #[weak]
+        layer_list,
+        #[upgrade_or_panic]
+        move |_| {
+            pop_effect_layer().or_crash("Failed to remove effect layer");
+            refresh_layer_list(&layer_list);
+        }
+    ));
+
+    refresh_layer_list(&layer_list);
+    let layer_group = PreferencesGroup::new();
+    layer_group.add(&layer_list);
+    page.add(&layer_group);
+
+    // Presets section, named sets of effect layers
+    let settings_section = PreferencesGroup::new();
+    settings_section.set_title("Presets");
+    page.add(&settings_section);
+
+    let preset_options = StringList::new(&[]);
+    refresh_preset_list(&preset_options);
+    let preset_dropdown = ComboRow::new();
+    preset_dropdown.set_model(Some(&preset_options));
+    preset_dropdown.set_title("Preset");
+    settings_section.add(&preset_dropdown);
+
+    let load_button = ButtonRow::new();
+    load_button.set_title("Load preset");
+    load_button.set_start_icon_name(Some("document-open-symbolic"));
+    settings_section.add(&load_button);
+
+    let preset_name = EntryRow::new();
+    preset_name.set_title("Save the layers as (letters, digits, - and _)");
+    preset_name.set_show_apply_button(true);
+    settings_section.add(&preset_name);
+
+    load_button.connect_activated(clone!(
+        #[weak]
+        preset_dropdown,
+        #[weak]
+        layer_list,
+        #[upgrade_or_panic]
+        move |_| {
+            let name = preset_dropdown
+                .selected_item()
+                .and_downcast::<gtk::StringObject>()
+                .map(|item| item.string());
+            if let Some(name) = name {
+                load_effect_preset(&name).or_crash("Failed to load preset");
+            }
+            refresh_layer_list(&layer_list);
+        }
+    ));
+
+    preset_name.connect_apply(clone!(
+        #[weak]
+        preset_options,
+        #[upgrade_or_panic]
+        move |preset_name| {
+            // A rejected name stays in the field to be corrected
+            if save_effect_preset(&preset_name.text()).unwrap_or(false) {
+                preset_name.remove_css_class("error");
+                preset_name.set_text("");
+                refresh_preset_list(&preset_options);
+            } else {
+                preset_name.add_css_class("error");
+            }
+        }
+    ));
+
+    // White balance section, dialled in while the keyboard shows white
+    if let Ok(balance) = get_white_balance() {
+        let settings_section = PreferencesGroup::new();
+        settings_section.set_title("White Balance");
+        settings_section.set_description(Some("With a white effect, lower the colours white leans to until it looks neutral"));
+        page.add(&settings_section);
+
+        for (channel, name) in ["Red", "Green", "Blue"].into_iter().enumerate() {
+            let scale = Scale::with_range(gtk::Orientation::Horizontal, 0f64, 1000f64, 10f64);
+            scale.set_value(balance[channel] as f64);
+            scale.set_width_request(150);
+            scale.set_draw_value(true);
+            scale.connect_change_value(move |scale, _, value| {
+                // The other channels are read back, another client may have changed them
+                let mut balance = get_white_balance().or_crash("Error reading the white balance");
+                balance[channel] = value.clamp(0f64, 1000f64) as u16;
+                set_white_balance(balance).or_crash("Error setting the white balance");
+                scale.set_value(balance[channel] as f64);
+
+                return gtk::glib::Propagation::Stop;
+            });
+            let row = ActionRow::new();
+            row.set_title(name);
+            row.add_suffix(&scale);
+            settings_section.add(&row);
+        }
+    }
+
     // Battery Health Optimizer section
     if let Some(bho) = bho {
         let settings_section = PreferencesGroup::new(); //page.add_section(Some("Battery Health Optimizer"));
@@ -769,7 +2036,7 @@ fn make_general_page() -> PreferencesPage {
             move |switch| {
                 let threshold = scale.value().clamp(50f64, 80f64) as u8;
 
-                set_bho(switch.is_active(), threshold);
+                set_bho(switch.is_active(), threshold).or_crash("Error setting bho");
 
                 let (is_on, threshold) = get_bho().or_crash("Error reading bho");
 
@@ -785,5 +2052,24 @@ fn make_general_page() -> PreferencesPage {
         settings_section.add(&row);
     }
 
+    // Battery health, next to the optimizer that slows the wear down
+    if let Ok(Some(health)) = get_battery_health() {
+        let settings_section = PreferencesGroup::new();
+        settings_section.set_title("Battery Health");
+        page.add(&settings_section);
+
+        let row = ActionRow::new();
+        row.set_title("Health");
+        row.set_subtitle(&format!("{} of {} {}", health.full_capacity, health.design_capacity, health.unit));
+        row.add_suffix(&gtk::Label::new(Some(&format!("{:.0}%", health.health_percent))));
+        settings_section.add(&row);
+
+        let row = ActionRow::new();
+        row.set_title("Charge cycles");
+        let cycles = health.cycle_count.map_or(String::from("Unknown"), |cycles| cycles.to_string());
+        row.add_suffix(&gtk::Label::new(Some(&cycles)));
+        settings_section.add(&row);
+    }
+
     page
 }